@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustdmg::dmg::DMG;
+
+const BOOT_ROM_SIZE: usize = 256;
+const ROM_BANK_SIZE: usize = 0x4000;
+const STEPS: usize = 10_000;
+
+// The first 256 bytes are the boot ROM and the rest the cartridge, padded to whole banks so most
+// inputs get past the size check. Cartridges with a bad header are refused and runs end at the first
+// unimplemented opcode or memory area with an error, which is fine, but any panic is a bug.
+fuzz_target!(|data: &[u8]| {
+    if data.len() < BOOT_ROM_SIZE { return; }
+    let (boot_rom, rom) = data.split_at(BOOT_ROM_SIZE);
+    let mut rom = rom.to_vec();
+    let banks = rom.len().div_ceil(ROM_BANK_SIZE).max(2);
+    rom.resize(banks * ROM_BANK_SIZE, 0);
+    if let Ok(mut dmg) = DMG::new_from_data(boot_rom.to_vec(), &rom) {
+        for _ in 0..STEPS {
+            if dmg.step().is_err() { return; }
+        }
+    }
+});
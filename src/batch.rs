@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use crate::cpu::stats::Opcode;
+use crate::dmg::DMG;
+use crate::error::EmulationError;
+use crate::watchdog::{Watchdog, WatchdogError};
+
+const ROM_EXTENSIONS: [&str; 2] = ["gb", "gbc"];
+
+#[derive(Debug, PartialEq)]
+pub enum BatchOutcome {
+    Completed,
+    LoadFailed(String),
+    UnimplementedOpcode(String),
+    // The ROM ran an opcode the console does not have, a bug in the ROM rather than the emulator
+    IllegalOpcode(String),
+    // Ran into something else the emulator cannot do yet
+    Unsupported(String),
+    Panicked(String),
+    Hung(String),
+}
+
+pub struct BatchResult {
+    pub rom_path: PathBuf,
+    pub outcome: BatchOutcome,
+    pub frames_run: u64,
+    pub frame_hash: Option<u64>,
+    pub unimplemented_opcodes: Vec<Opcode>,
+}
+
+fn classify_error(error: EmulationError) -> BatchOutcome {
+    match error {
+        EmulationError::UnimplementedOpcode { .. } => BatchOutcome::UnimplementedOpcode(error.to_string()),
+        EmulationError::IllegalOpcode { .. } => BatchOutcome::IllegalOpcode(error.to_string()),
+        error => BatchOutcome::Unsupported(error.to_string()),
+    }
+}
+
+pub(crate) fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+// With a watchdog budget, ROMs that stop making progress for that many frames are cut short
+pub fn run_rom(rom_path: &Path, frames: u64, watchdog_frames: Option<u64>) -> BatchResult {
+    let mut dmg = match DMG::new(&rom_path.to_string_lossy()) {
+        Ok(dmg) => dmg,
+        Err(error) => return BatchResult {
+            rom_path: rom_path.to_path_buf(),
+            outcome: BatchOutcome::LoadFailed(error.to_string()),
+            frames_run: 0,
+            frame_hash: None,
+            unimplemented_opcodes: vec![],
+        },
+    };
+
+    let run_result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        match watchdog_frames {
+            Some(budget_frames) => {
+                let mut watchdog = Watchdog::new(&mut dmg, budget_frames);
+                for _frame in 0..frames { watchdog.run_frame(&mut dmg)?; }
+            }
+            None => for _frame in 0..frames { dmg.run_frame()?; },
+        }
+        Ok::<(), WatchdogError>(())
+    }));
+
+    let outcome = match run_result {
+        Ok(Ok(())) => BatchOutcome::Completed,
+        Ok(Err(WatchdogError::Hang(hang))) => BatchOutcome::Hung(hang.to_string()),
+        Ok(Err(WatchdogError::Emulation(error))) => classify_error(error),
+        Err(payload) => BatchOutcome::Panicked(panic_message(payload)),
+    };
+
+    BatchResult {
+        rom_path: rom_path.to_path_buf(),
+        outcome,
+        frames_run: dmg.frame_count(),
+        frame_hash: Some(dmg.frame_hash()),
+        unimplemented_opcodes: dmg.cpu.opcode_stats.unimplemented().collect(),
+    }
+}
+
+pub fn find_roms(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| match path.extension() {
+            Some(extension) => ROM_EXTENSIONS.iter().any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension)),
+            None => false,
+        })
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+pub fn run_directory(directory: &Path, frames: u64, watchdog_frames: Option<u64>) -> io::Result<Vec<BatchResult>> {
+    let roms = find_roms(directory)?;
+
+    // Keep the default hook from spamming the report with backtraces, the message is captured anyway
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results = roms.iter().map(|rom| run_rom(rom, frames, watchdog_frames)).collect();
+    panic::set_hook(default_hook);
+
+    Ok(results)
+}
+
+pub fn unimplemented_opcode_counts(results: &[BatchResult]) -> Vec<(Opcode, usize)> {
+    let mut counts: BTreeMap<Opcode, usize> = BTreeMap::new();
+    for opcode in results.iter().flat_map(|result| result.unimplemented_opcodes.iter()) {
+        *counts.entry(*opcode).or_default() += 1;
+    }
+    let mut counts: Vec<(Opcode, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(_, count_a), (_, count_b)| count_b.cmp(count_a));
+    counts
+}
+
+pub fn print_report(results: &[BatchResult]) {
+    println!();
+    println!("==============");
+    println!("Batch results");
+    for result in results {
+        let name = result.rom_path.file_name().unwrap_or_default().to_string_lossy();
+        let hash = match result.frame_hash {
+            Some(hash) => format!("{:016X}", hash),
+            None => "-".to_string(),
+        };
+        let (status, detail) = match &result.outcome {
+            BatchOutcome::Completed => ("OK", String::new()),
+            BatchOutcome::LoadFailed(message) => ("LOAD", message.clone()),
+            BatchOutcome::UnimplementedOpcode(message) => ("UNIMPL", message.clone()),
+            BatchOutcome::IllegalOpcode(message) => ("ILLEGAL", message.clone()),
+            BatchOutcome::Unsupported(message) => ("UNSUPP", message.clone()),
+            BatchOutcome::Panicked(message) => ("PANIC", message.clone()),
+            BatchOutcome::Hung(report) => ("HUNG", report.lines().take(2).collect::<Vec<_>>().join(". ")),
+        };
+        println!("{:<7}{:<40}frames {:<6} hash {} {}", status, name, result.frames_run, hash, detail);
+    }
+
+    let completed = results.iter().filter(|result| result.outcome == BatchOutcome::Completed).count();
+    println!("{} of {} ROMs ran to completion", completed, results.len());
+
+    let blocking_opcodes = unimplemented_opcode_counts(results);
+    if !blocking_opcodes.is_empty() {
+        println!("Unimplemented opcodes, by number of ROMs hitting them:");
+        for (opcode, rom_count) in blocking_opcodes {
+            println!("  {:<6} {}", opcode.to_string(), rom_count);
+        }
+    }
+    println!("==============");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_bad_opcode_as_unimplemented() {
+        assert_eq!(classify_error(EmulationError::UnimplementedOpcode { opcode: Opcode::Base(0xD3), address: 0x0150 }),
+                   BatchOutcome::UnimplementedOpcode("Opcode D3 at 0150 is not implemented".to_string()));
+        assert_eq!(classify_error(EmulationError::UnimplementedOpcode { opcode: Opcode::CB(0x37), address: 0x0150 }),
+                   BatchOutcome::UnimplementedOpcode("Opcode CB 37 at 0150 is not implemented".to_string()));
+    }
+
+    #[test]
+    fn classify_illegal_opcode() {
+        assert_eq!(classify_error(EmulationError::IllegalOpcode { opcode: 0xDD, address: 0x0150 }),
+                   BatchOutcome::IllegalOpcode("Illegal opcode DD at 0150 locked up the CPU".to_string()));
+    }
+
+    #[test]
+    fn classify_other_errors() {
+        assert_eq!(classify_error(EmulationError::UnemulatedMemory { area: "External RAM", address: 0xA000 }),
+                   BatchOutcome::Unsupported("External RAM is not emulated, accessed at A000".to_string()));
+    }
+
+    fn result_with_unimplemented(opcodes: Vec<Opcode>) -> BatchResult {
+        BatchResult {
+            rom_path: PathBuf::from("test.gb"),
+            outcome: BatchOutcome::Completed,
+            frames_run: 0,
+            frame_hash: None,
+            unimplemented_opcodes: opcodes,
+        }
+    }
+
+    #[test]
+    fn unimplemented_opcodes_ranked_by_rom_count() {
+        let results = vec![
+            result_with_unimplemented(vec![Opcode::Base(0x27)]),
+            result_with_unimplemented(vec![Opcode::CB(0x37)]),
+            result_with_unimplemented(vec![Opcode::CB(0x37)]),
+        ];
+        assert_eq!(unimplemented_opcode_counts(&results), vec![(Opcode::CB(0x37), 2), (Opcode::Base(0x27), 1)]);
+    }
+
+    #[test]
+    fn missing_rom_fails_to_load() {
+        let result = run_rom(Path::new("does/not/exist.gb"), 1, None);
+        assert!(matches!(result.outcome, BatchOutcome::LoadFailed(_)));
+        assert_eq!(result.frames_run, 0);
+    }
+}
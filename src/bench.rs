@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use crate::dmg::DMG;
+use crate::error::EmulationError;
+use crate::ppu::timeline::FRAME_DURATION;
+
+pub const CPU_CLOCK_HZ: u64 = 4_194_304;
+
+// How long a frame takes on the real console, a little under 1/60 s
+pub fn frame_interval() -> Duration {
+    Duration::from_secs_f64(FRAME_DURATION as f64 / CPU_CLOCK_HZ as f64)
+}
+
+pub struct BenchResult {
+    pub real_time: Duration,
+    pub emulated_cycles: u64,
+    pub instructions: u64,
+    pub frames: u64,
+}
+
+impl BenchResult {
+    pub fn emulated_seconds(&self) -> f64 {
+        self.emulated_cycles as f64 / CPU_CLOCK_HZ as f64
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.emulated_seconds() / self.real_time.as_secs_f64()
+    }
+
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.real_time.as_secs_f64()
+    }
+}
+
+pub fn run_bench(dmg: &mut DMG, duration: Duration) -> Result<BenchResult, EmulationError> {
+    let cycles_before = dmg.cpu.cycle_count;
+    let instructions_before = dmg.cpu.instruction_count;
+    let frames_before = dmg.frame_count();
+    let start = Instant::now();
+
+    // Checking the clock once per frame keeps the measurement overhead out of the hot loop
+    while start.elapsed() < duration {
+        dmg.run_frame()?;
+    }
+
+    Ok(BenchResult {
+        real_time: start.elapsed(),
+        emulated_cycles: dmg.cpu.cycle_count - cycles_before,
+        instructions: dmg.cpu.instruction_count - instructions_before,
+        frames: dmg.frame_count() - frames_before,
+    })
+}
+
+pub fn print_report(result: &BenchResult) {
+    println!();
+    println!("==============");
+    println!("Benchmark");
+    println!("Real time: {:.2} s", result.real_time.as_secs_f64());
+    println!("Emulated time: {:.2} s in {} frames", result.emulated_seconds(), result.frames);
+    println!("Speed: {:.2} emulated seconds per real second", result.speed());
+    println!("Instructions: {:.0} per second", result.instructions_per_second());
+    println!("==============");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derived_rates() {
+        let result = BenchResult {
+            real_time: Duration::from_secs(2),
+            emulated_cycles: CPU_CLOCK_HZ * 4,
+            instructions: 1000,
+            frames: 240,
+        };
+        assert_eq!(result.emulated_seconds(), 4.0);
+        assert_eq!(result.speed(), 2.0);
+        assert_eq!(result.instructions_per_second(), 500.0);
+    }
+}
@@ -0,0 +1,246 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::bus::{HardwareMode, InfraredTransceiver};
+use crate::bus::bootrom::{BootROM, BootRomVariant};
+use crate::bus::cartridge::Cartridge;
+use crate::bus::rtc::RtcClock;
+use crate::dmg::DMG;
+use crate::error::EmulationError;
+use crate::ppu::palettes::CompatibilityPalette;
+
+enum RomSource {
+    File(PathBuf),
+    Bytes(Vec<u8>),
+}
+
+// Everything decided before the console is switched on, in one place instead of a constructor
+// per combination:
+//
+//     let dmg = DmgBuilder::new().rom_file("game.gb").skip_boot(true).palette(CompatibilityPalette::Brown).build()?;
+//
+// Without boot ROM bytes, the variant's file is read from the working directory unless the boot
+// is skipped.
+pub struct DmgBuilder {
+    rom: Option<RomSource>,
+    boot_rom: BootRomVariant,
+    boot_rom_data: Option<Vec<u8>>,
+    hardware_mode: Option<HardwareMode>,
+    skip_boot: bool,
+    palette: CompatibilityPalette,
+    audio: bool,
+    state_directory: Option<PathBuf>,
+    random_ram_seed: Option<u64>,
+    cached_interpreter: bool,
+    rtc_clock: RtcClock,
+    oam_access_blocking: bool,
+    profiler: bool,
+    trace: Option<Box<dyn Write>>,
+    infrared: Option<Rc<RefCell<dyn InfraredTransceiver>>>,
+    cheats: Vec<String>,
+}
+
+impl Default for DmgBuilder {
+    fn default() -> DmgBuilder { DmgBuilder::new() }
+}
+
+impl DmgBuilder {
+    pub fn new() -> DmgBuilder {
+        DmgBuilder {
+            rom: None,
+            boot_rom: BootRomVariant::default(),
+            boot_rom_data: None,
+            hardware_mode: None,
+            skip_boot: false,
+            palette: CompatibilityPalette::default(),
+            audio: true,
+            state_directory: None,
+            random_ram_seed: None,
+            cached_interpreter: false,
+            rtc_clock: RtcClock::Emulated,
+            oam_access_blocking: false,
+            profiler: false,
+            trace: None,
+            infrared: None,
+            cheats: vec![],
+        }
+    }
+
+    pub fn rom_file<P: AsRef<Path>>(mut self, path: P) -> DmgBuilder {
+        self.rom = Some(RomSource::File(path.as_ref().to_path_buf()));
+        self
+    }
+
+    pub fn rom_bytes(mut self, rom: Vec<u8>) -> DmgBuilder {
+        self.rom = Some(RomSource::Bytes(rom));
+        self
+    }
+
+    pub fn boot_rom(mut self, variant: BootRomVariant) -> DmgBuilder {
+        self.boot_rom = variant;
+        self
+    }
+
+    // Has to be the size of the boot ROM variant's
+    pub fn boot_rom_bytes(mut self, data: Vec<u8>) -> DmgBuilder {
+        self.boot_rom_data = Some(data);
+        self
+    }
+
+    // The console the boot ROM belongs to unless set
+    pub fn hardware_mode(mut self, mode: HardwareMode) -> DmgBuilder {
+        self.hardware_mode = Some(mode);
+        self
+    }
+
+    pub fn skip_boot(mut self, skip: bool) -> DmgBuilder {
+        self.skip_boot = skip;
+        self
+    }
+
+    pub fn palette(mut self, palette: CompatibilityPalette) -> DmgBuilder {
+        self.palette = palette;
+        self
+    }
+
+    pub fn audio(mut self, enabled: bool) -> DmgBuilder {
+        self.audio = enabled;
+        self
+    }
+
+    // Where numbered save state slots go, the working directory otherwise
+    pub fn state_directory<P: AsRef<Path>>(mut self, directory: P) -> DmgBuilder {
+        self.state_directory = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    // Powers up with reproducible garbage in RAM instead of zeros, see DMG::reset_with_random_ram
+    pub fn random_ram(mut self, seed: u64) -> DmgBuilder {
+        self.random_ram_seed = Some(seed);
+        self
+    }
+
+    pub fn cached_interpreter(mut self, enabled: bool) -> DmgBuilder {
+        self.cached_interpreter = enabled;
+        self
+    }
+
+    // See DMG::set_rtc_clock
+    pub fn rtc_clock(mut self, clock: RtcClock) -> DmgBuilder {
+        self.rtc_clock = clock;
+        self
+    }
+
+    // See DMG::set_oam_access_blocking
+    pub fn oam_access_blocking(mut self, enabled: bool) -> DmgBuilder {
+        self.oam_access_blocking = enabled;
+        self
+    }
+
+    pub fn profiler(mut self, enabled: bool) -> DmgBuilder {
+        self.profiler = enabled;
+        self
+    }
+
+    pub fn trace(mut self, writer: Box<dyn Write>) -> DmgBuilder {
+        self.trace = Some(writer);
+        self
+    }
+
+    pub fn infrared(mut self, infrared: Rc<RefCell<dyn InfraredTransceiver>>) -> DmgBuilder {
+        self.infrared = Some(infrared);
+        self
+    }
+
+    // GameShark or Game Genie code, enabled from the start
+    pub fn cheat(mut self, code: &str) -> DmgBuilder {
+        self.cheats.push(code.to_string());
+        self
+    }
+
+    pub fn build<'a>(self) -> Result<DMG<'a>, EmulationError> {
+        let boot_rom = match self.boot_rom_data {
+            Some(data) => BootROM::from_data_for_variant(data, self.boot_rom).map_err(EmulationError::InvalidBootRom)?,
+            // Never mapped, only its variant matters
+            None if self.skip_boot => BootROM { data: vec![0; self.boot_rom.size()], variant: self.boot_rom },
+            None => BootROM::load(self.boot_rom.file_name(), self.boot_rom)?,
+        };
+        let rom = match self.rom {
+            Some(RomSource::File(path)) => fs::read(path)?,
+            Some(RomSource::Bytes(rom)) => rom,
+            None => return Err(EmulationError::InvalidCartridge("No ROM given".to_string())),
+        };
+        let cartridge = Cartridge::from_data(rom).map_err(EmulationError::InvalidCartridge)?;
+        let mode = self.hardware_mode.unwrap_or(self.boot_rom.hardware_mode());
+        if !cartridge.runs_on(mode) { return Err(EmulationError::CgbOnlyRom(cartridge.header().title)); }
+
+        let mut dmg = DMG::new_from_cartridge(boot_rom, cartridge, mode)?;
+        dmg.set_compatibility_palette(self.palette);
+        dmg.set_audio_enabled(self.audio);
+        if let Some(directory) = &self.state_directory { dmg.set_state_directory(directory); }
+        if let Some(seed) = self.random_ram_seed { dmg.reset_with_random_ram(seed); }
+        if self.skip_boot { dmg.skip_boot_rom(); }
+        if self.cached_interpreter { dmg.enable_cached_interpreter(); }
+        dmg.set_rtc_clock(self.rtc_clock);
+        dmg.set_oam_access_blocking(self.oam_access_blocking);
+        if self.profiler { dmg.enable_profiler(); }
+        if let Some(writer) = self.trace { dmg.set_trace(writer); }
+        dmg.set_infrared_transceiver(self.infrared);
+        for code in &self.cheats {
+            dmg.cheats_mut().add(code).map_err(EmulationError::InvalidCheat)?;
+        }
+        Ok(dmg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::register::DMGRegister;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        rom
+    }
+
+    #[test]
+    fn skips_the_boot_rom() {
+        let mut dmg = DmgBuilder::new().rom_bytes(rom()).skip_boot(true).audio(false).build().unwrap();
+        assert!(!dmg.cpu.bus.boot_rom_active);
+        assert!(!dmg.audio_enabled());
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0100);
+        assert_eq!(dmg.cpu.reg_af.read(), 0x01B0);
+        assert_eq!(dmg.cpu.stack_pointer.read(), 0xFFFE);
+        dmg.run_frame().unwrap();
+        dmg.reset();
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0100);
+        assert!(!dmg.cpu.bus.boot_rom_active);
+    }
+
+    #[test]
+    fn configures_the_console() {
+        let dmg = DmgBuilder::new()
+            .rom_bytes(rom())
+            .boot_rom(BootRomVariant::Cgb)
+            .boot_rom_bytes(vec![0; 0x900])
+            .cheat("0199A2C6")
+            .build().unwrap();
+        assert_eq!(dmg.hardware_mode(), HardwareMode::Cgb);
+        assert!(dmg.cpu.bus.boot_rom_active);
+        assert_eq!(dmg.cheats().list().len(), 1);
+    }
+
+    #[test]
+    fn reports_bad_settings() {
+        assert!(matches!(DmgBuilder::new().skip_boot(true).build(), Err(EmulationError::InvalidCartridge(_))));
+        assert!(matches!(DmgBuilder::new().rom_bytes(rom()).boot_rom_bytes(vec![0; 3]).build(),
+                         Err(EmulationError::InvalidBootRom(_))));
+        assert!(matches!(DmgBuilder::new().rom_bytes(rom()).skip_boot(true).cheat("nonsense").build(),
+                         Err(EmulationError::InvalidCheat(_))));
+    }
+}
@@ -1,16 +1,102 @@
 use super::*;
 
+#[cfg(feature = "std")]
 use std::fs;
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
 use std::io::Read;
 
-pub struct BootROM { pub data: Vec<u8> }
+// The boot ROMs of the different models. They differ in size, in the logo animation and in the
+// register values they leave behind, which is how games tell the models apart.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BootRomVariant {
+    // The first DMG revision
+    Dmg0,
+    #[default]
+    Dmg,
+    // Game Boy Pocket
+    Mgb,
+    Sgb,
+    Cgb,
+}
+
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+// The CGB boot ROM leaves 0x0100-0x01FF to the cartridge so it can read the header
+const CGB_BOOT_ROM_HEADER_GAP: core::ops::Range<u16> = 0x0100..0x0200;
+
+impl BootRomVariant {
+    pub const ALL: [BootRomVariant; 5] = [BootRomVariant::Dmg0, BootRomVariant::Dmg, BootRomVariant::Mgb, BootRomVariant::Sgb, BootRomVariant::Cgb];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BootRomVariant::Dmg0 => "dmg0",
+            BootRomVariant::Dmg => "dmg",
+            BootRomVariant::Mgb => "mgb",
+            BootRomVariant::Sgb => "sgb",
+            BootRomVariant::Cgb => "cgb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BootRomVariant> {
+        BootRomVariant::ALL.iter().copied().find(|variant| variant.name() == name)
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            BootRomVariant::Cgb => CGB_BOOT_ROM_SIZE,
+            _ => BOOT_ROM_SIZE,
+        }
+    }
+
+    // Looked for in the working directory, like DMG_ROM.bin always was
+    pub fn file_name(self) -> &'static str {
+        match self {
+            BootRomVariant::Dmg0 => "DMG0_ROM.bin",
+            BootRomVariant::Dmg => "DMG_ROM.bin",
+            BootRomVariant::Mgb => "MGB_ROM.bin",
+            BootRomVariant::Sgb => "SGB_ROM.bin",
+            BootRomVariant::Cgb => "CGB_ROM.bin",
+        }
+    }
+
+    // AF, BC, DE and HL as the boot ROM leaves them when it jumps to the cartridge at 0x0100
+    pub fn registers_at_handover(self) -> [u16; 4] {
+        match self {
+            BootRomVariant::Dmg0 => [0x0100, 0xFF13, 0x00C1, 0x8403],
+            BootRomVariant::Dmg => [0x01B0, 0x0013, 0x00D8, 0x014D],
+            BootRomVariant::Mgb => [0xFFB0, 0x0013, 0x00D8, 0x014D],
+            BootRomVariant::Sgb => [0x0100, 0x0014, 0x0000, 0xC060],
+            BootRomVariant::Cgb => [0x1180, 0x0000, 0xFF56, 0x000D],
+        }
+    }
+
+    // The console the boot ROM belongs to
+    pub fn hardware_mode(self) -> HardwareMode {
+        match self {
+            BootRomVariant::Cgb => HardwareMode::Cgb,
+            BootRomVariant::Sgb => HardwareMode::Sgb,
+            _ => HardwareMode::Dmg,
+        }
+    }
+}
+
+pub struct BootROM {
+    pub data: Vec<u8>,
+    pub variant: BootRomVariant,
+}
 
 impl BootROM {
+    #[cfg(feature = "std")]
     pub fn new(boot_rom_file_path: &str) -> io::Result<BootROM> {
+        BootROM::load(boot_rom_file_path, BootRomVariant::Dmg)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load(boot_rom_file_path: &str, variant: BootRomVariant) -> io::Result<BootROM> {
         let file_metadata = fs::metadata(boot_rom_file_path)?;
 
-        if file_metadata.len() as usize != BOOT_ROM_SIZE {
+        if file_metadata.len() as usize != variant.size() {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Bad boot ROM file size"));
         }
 
@@ -18,13 +104,31 @@ impl BootROM {
         let mut data: Vec<u8> = Vec::new();
         file.read_to_end(&mut data)?;
 
-        Ok(BootROM{data})
+        Ok(BootROM{data, variant})
+    }
+
+    pub fn from_data(data: Vec<u8>) -> Result<BootROM, String> {
+        BootROM::from_data_for_variant(data, BootRomVariant::Dmg)
+    }
+
+    pub fn from_data_for_variant(data: Vec<u8>, variant: BootRomVariant) -> Result<BootROM, String> {
+        if data.len() != variant.size() {
+            return Err("Bad boot ROM size".to_string());
+        }
+        Ok(BootROM{data, variant})
+    }
+
+    // Whether the boot ROM covers an address while it is mapped
+    pub fn contains(&self, address: u16) -> bool {
+        (address as usize) < self.variant.size()
+            && !(self.variant == BootRomVariant::Cgb && CGB_BOOT_ROM_HEADER_GAP.contains(&address))
     }
 }
 
 impl MemoryZone for BootROM {
     fn read(&self, address: u16) -> u8 { self.data[address as usize] }
-    fn write(&mut self, _address: u16, _value: u8) { panic!("Trying to write to boot ROM"); }
+    // Nothing is there to take the write, like with any other ROM
+    fn write(&mut self, _address: u16, _value: u8) {}
 }
 
 #[cfg(test)]
@@ -32,15 +136,37 @@ mod tests {
     use super::*;
 
     #[test]
-    #[should_panic(expected = "Trying to write to boot ROM")]
-    fn write_panics() {
-        let mut bootrom = BootROM{data:vec![0]};
-        bootrom.write(0, 0);
+    fn writes_are_ignored() {
+        let mut bootrom = BootROM{data:vec![0], variant: BootRomVariant::Dmg};
+        bootrom.write(0, 1);
+        assert_eq!(bootrom.read(0), 0);
     }
 
     #[test]
     fn read() {
-        let bootrom = BootROM{data:vec![123, 234]};
+        let bootrom = BootROM{data:vec![123, 234], variant: BootRomVariant::Dmg};
         assert_eq!(bootrom.read(1), 234);
     }
+
+    #[test]
+    fn from_data_checks_size() {
+        assert!(BootROM::from_data(vec![0; BOOT_ROM_SIZE]).is_ok());
+        assert_eq!(BootROM::from_data(vec![0; 3]).err(), Some("Bad boot ROM size".to_string()));
+        assert!(BootROM::from_data_for_variant(vec![0; BOOT_ROM_SIZE], BootRomVariant::Cgb).is_err());
+        assert!(BootROM::from_data_for_variant(vec![0; 0x900], BootRomVariant::Cgb).is_ok());
+        assert!(BootROM::from_data_for_variant(vec![0; BOOT_ROM_SIZE], BootRomVariant::Mgb).is_ok());
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_to_the_cartridge() {
+        let mut cartridge = vec![0; 0x900];
+        cartridge[0x0134] = 0x42;
+        let mut bus = Bus::new_from_vecs(vec![0; 0x900], cartridge);
+        bus.boot_rom.variant = BootRomVariant::Cgb;
+        bus.boot_rom.data[0x0200] = 0x11;
+        assert_eq!(bus.read(0x0134), 0x42);
+        assert_eq!(bus.read(0x0200), 0x11);
+        assert!(bus.in_boot_rom(0x08FF));
+        assert!(!bus.in_boot_rom(0x0900));
+    }
 }
@@ -1,9 +1,13 @@
 use super::*;
 
+#[cfg(feature = "std")]
 use std::fs;
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
 use std::io::Read;
-use std::str;
+use core::str;
+use super::mbc3::Mbc3;
 
 
 const CARTRIDGE_TYPES: [CartridgeType; 26] = [
@@ -18,11 +22,11 @@ const CARTRIDGE_TYPES: [CartridgeType; 26] = [
     CartridgeType{code: 0x0B, name:"ROM+MMM01", supported: false},
     CartridgeType{code: 0x0C, name:"ROM+MMM01+SRAM", supported: false},
     CartridgeType{code: 0x0D, name:"ROM+MMM01+SRAM+BATT", supported: false},
-    CartridgeType{code: 0x0F, name:"ROM+MBC3+TIMER+BATT", supported: false},
-    CartridgeType{code: 0x10, name:"ROM+MBC3+TIMER+RAM+BATT", supported: false},
-    CartridgeType{code: 0x11, name:"ROM+MBC", supported: false},
-    CartridgeType{code: 0x12, name:"ROM+MBC3+RAM", supported: false},
-    CartridgeType{code: 0x13, name:"ROM+MBC3+RAM+BATT", supported: false},
+    CartridgeType{code: 0x0F, name:"ROM+MBC3+TIMER+BATT", supported: true},
+    CartridgeType{code: 0x10, name:"ROM+MBC3+TIMER+RAM+BATT", supported: true},
+    CartridgeType{code: 0x11, name:"ROM+MBC3", supported: true},
+    CartridgeType{code: 0x12, name:"ROM+MBC3+RAM", supported: true},
+    CartridgeType{code: 0x13, name:"ROM+MBC3+RAM+BATT", supported: true},
     CartridgeType{code: 0x19, name:"ROM+MBC5", supported: false},
     CartridgeType{code: 0x1A, name:"ROM+MBC5+RAM", supported: false},
     CartridgeType{code: 0x1B, name:"ROM+MBC5+RAM+BATT", supported: false},
@@ -48,6 +52,16 @@ const CARTRIDGE_ROM_SIZES: [CartridgeRomSize; 10] = [
     CartridgeRomSize {code: 0x54, name:"12Mbit", num_banks: 96},
 ];
 
+// Header byte 0x0149, in bytes
+const CARTRIDGE_RAM_SIZES: [(u8, usize); 6] = [
+    (0x00, 0),
+    (0x01, 0x800),
+    (0x02, 0x2000),
+    (0x03, 0x8000),
+    (0x04, 0x20000),
+    (0x05, 0x10000),
+];
+
 pub struct CartridgeType<'a> {
     pub name: &'a str,
     pub supported: bool,
@@ -60,83 +74,114 @@ pub struct CartridgeRomSize<'a> {
     pub code: u8,
 }
 
-pub struct RomBank {
-    pub bank_number: u8,
-    pub data: Vec<u8>,
-}
-
-impl MemoryZone for RomBank {
-    fn read(&self, address: u16) -> u8 {
-        self.data[self.global_address_to_local_address(address) as usize]
-    }
-    fn write(&mut self, address: u16, value: u8) {
-        let local_address = self.global_address_to_local_address(address) as usize;
-        self.data[local_address] = value
-    }
+// Header byte 0x0143, older cartridges have the last character of their title there
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CgbSupport {
+    Unsupported,
+    // Colorized on a CGB, still runs on a DMG
+    Enhanced,
+    Required,
 }
 
-impl RomBank {
-    fn global_address_to_local_address(&self, address: u16) -> u16 {
-        if self.bank_number == 0 { return address; } else { return address - 0x4000; }
-    }
+// What the header at 0x0100-0x014F says about a cartridge
+#[derive(Clone, Debug, PartialEq)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cartridge_type: String,
+    pub rom_size: String,
+    pub global_checksum: u16,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
 }
 
 pub struct Cartridge {
     pub name: String,
-    pub rom_banks: Vec<RomBank>,
-    blob: Vec<u8>,
+    // The whole ROM image as loaded, banks are ROM_BANK_SIZE long slices of it
+    rom: Vec<u8>,
+    // None for ROM only cartridges, which have bank 1 at 0x4000-0x7FFF and no RAM
+    pub mbc3: Option<Mbc3>,
+}
+
+// Bank 0 is always mapped at 0x0000-0x3FFF, the MBC picks the bank at 0x4000-0x7FFF and handles
+// the cartridge RAM at 0xA000-0xBFFF
+impl MemoryZone for Cartridge {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => self.rom[address as usize],
+            0x4000..=0x7FFF => {
+                let offset = self.rom_bank_number() * ROM_BANK_SIZE + (address as usize - ROM_BANK_SIZE);
+                self.rom.get(offset).copied().unwrap_or(0xFF)
+            }
+            _ => self.mbc3.as_ref().map_or(0xFF, |mbc3| mbc3.read_ram(address)),
+        }
+    }
+    // Cartridges take writes to ROM as mapper commands, without a mapper they do nothing.
+    // Bus::poke is the way to patch ROM.
+    fn write(&mut self, address: u16, value: u8) {
+        if let Some(mbc3) = &mut self.mbc3 {
+            match address {
+                0x0000..=0x7FFF => mbc3.write_register(address, value),
+                _ => mbc3.write_ram(address, value),
+            }
+        }
+    }
 }
 
 impl Cartridge {
+    // Test programs are usually shorter than a bank, they are taken as they come
     pub fn new_dummy_cartridge(data: Vec<u8>) -> Cartridge {
-        let rom_bank_zero = RomBank {
-            bank_number: 0,
-            data
-        };
-        Cartridge {name: "".to_string(), blob: vec![], rom_banks: vec![rom_bank_zero]}
+        Cartridge {name: "".to_string(), rom: data, mbc3: None}
     }
 
+    #[cfg(feature = "std")]
     pub fn read_cartridge_from_romfile(rom_file_path: &str) -> io::Result<Cartridge> {
         let file_metadata = fs::metadata(rom_file_path)?;
 
-        if file_metadata.len() as usize % ROM_BANK_SIZE != 0 {
+        if !(file_metadata.len() as usize).is_multiple_of(ROM_BANK_SIZE) {
             return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Bad cartridge ROM file size"));
         }
 
         let mut file = fs::File::open(rom_file_path)?;
         let mut file_content: Vec<u8> = Vec::with_capacity(file_metadata.len() as usize);
         file.read_to_end(&mut file_content)?;
-        Ok(Cartridge::parse_cartridge_from_blob(file_content)?)
-    }
-
-    fn parse_cartridge_from_blob(blob: Vec<u8>) -> io::Result<Cartridge> {
-        let num_banks_in_file = blob.len() / ROM_BANK_SIZE;
-        let mut rom_banks: Vec<RomBank> = Vec::with_capacity(num_banks_in_file);
-
-        for bank_index in 0..num_banks_in_file {
-            let bank_start_pos = bank_index * ROM_BANK_SIZE;
-            let bank_end_pos = (bank_index + 1) * ROM_BANK_SIZE;
-            rom_banks.push(
-                RomBank{
-                    bank_number: bank_index as u8,
-                    data: blob[bank_start_pos..bank_end_pos].to_vec()
-                }
-            );
+        Cartridge::parse_cartridge_from_blob(file_content).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_cartridge_from_reader<R: Read>(reader: &mut R) -> io::Result<Cartridge> {
+        let mut content: Vec<u8> = Vec::new();
+        reader.read_to_end(&mut content)?;
+
+        if content.is_empty() || !content.len().is_multiple_of(ROM_BANK_SIZE) {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Bad cartridge ROM size"));
+        }
+
+        Cartridge::parse_cartridge_from_blob(content).map_err(|message| io::Error::new(io::ErrorKind::InvalidData, message))
+    }
+
+    // For ROM images already in memory, which is the only way to load one without std
+    pub fn from_data(blob: Vec<u8>) -> Result<Cartridge, String> {
+        if blob.is_empty() || !blob.len().is_multiple_of(ROM_BANK_SIZE) {
+            return Err("Bad cartridge ROM size".to_string());
         }
+        Cartridge::parse_cartridge_from_blob(blob)
+    }
 
+    fn parse_cartridge_from_blob(blob: Vec<u8>) -> Result<Cartridge, String> {
         let name = match str::from_utf8(&blob[0x0134..0x0142]) {
             Ok(v) => v.to_string(),
-            Err(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid UTF8 in ROM name")),
+            Err(_) => return Err("Invalid UTF8 in ROM name".to_string()),
         };
 
-        let cartridge = Cartridge {
-            blob,
-            rom_banks,
+        let mut cartridge = Cartridge {
+            rom: blob,
             name,
+            mbc3: None,
         };
 
         let cartridge_type = cartridge.get_cartridge_type()?;
         let rom_size = cartridge.get_rom_size()?;
+        let ram_size = cartridge.get_ram_size()?;
 
         println!();
         println!("==============");
@@ -144,39 +189,212 @@ impl Cartridge {
         println!("Name: {}", cartridge.name);
         println!("Type : {}", cartridge_type.name);
         println!("Rom size: {} in {} banks", rom_size.name, rom_size.num_banks);
+        println!("Ram size: {} bytes", ram_size);
+        println!("CGB support: {:?}", cartridge.cgb_support());
+        println!("SGB support: {}", cartridge.sgb_support());
         println!("==============");
 
         if !cartridge_type.supported {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Cartridge type {} unsupported", cartridge_type.name)))
+            return Err(format!("Cartridge type {} unsupported", cartridge_type.name))
+        }
+
+        let cartridge_type_code = cartridge_type.code;
+        if (0x0F..=0x13).contains(&cartridge_type_code) {
+            cartridge.mbc3 = Some(Mbc3::new(ram_size, cartridge_type_code <= 0x10));
         }
 
         Ok(cartridge)
     }
 
-    pub fn get_cartridge_type(&self) -> io::Result<&CartridgeType> {
-        let type_code_in_rom = self.blob[0x0147];
+    // A last bank shorter than ROM_BANK_SIZE only happens with dummy cartridges
+    pub fn bank_count(&self) -> usize {
+        self.rom.len().div_ceil(ROM_BANK_SIZE)
+    }
+
+    // The bank at 0x4000-0x7FFF, banks past the end of the ROM wrap around like on the cartridge
+    pub fn rom_bank_number(&self) -> usize {
+        match &self.mbc3 {
+            Some(mbc3) => mbc3.rom_bank() as usize % self.bank_count().max(1),
+            None => 1,
+        }
+    }
+
+    pub fn rom_bank(&self, bank: usize) -> Option<&[u8]> {
+        self.rom.chunks(ROM_BANK_SIZE).nth(bank)
+    }
+
+    pub fn rom_bank_mut(&mut self, bank: usize) -> Option<&mut [u8]> {
+        self.rom.chunks_mut(ROM_BANK_SIZE).nth(bank)
+    }
+
+    // Big-endian sum of the whole ROM stored in the header, 0 for cartridges without one
+    pub fn global_checksum(&self) -> u16 {
+        match self.rom.get(0x014E..0x0150) {
+            Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+            None => 0,
+        }
+    }
+
+    // Types and sizes missing from the tables only show up for cartridges built in tests
+    pub fn header(&self) -> CartridgeHeader {
+        CartridgeHeader {
+            title: self.name.trim_end_matches('\0').to_string(),
+            cartridge_type: self.get_cartridge_type().map_or("unknown", |cartridge_type| cartridge_type.name).to_string(),
+            rom_size: self.get_rom_size().map_or("unknown", |rom_size| rom_size.name).to_string(),
+            global_checksum: self.global_checksum(),
+            cgb_support: self.cgb_support(),
+            sgb_support: self.sgb_support(),
+        }
+    }
+
+    pub fn cgb_support(&self) -> CgbSupport {
+        match self.rom.get(0x0143).map(|flag| flag & 0xC0) {
+            Some(0xC0) => CgbSupport::Required,
+            Some(0x80) => CgbSupport::Enhanced,
+            _ => CgbSupport::Unsupported,
+        }
+    }
+
+    // Cartridges that require a CGB check for one and refuse to go on, when they do not
+    // misbehave first
+    pub fn runs_on(&self, mode: HardwareMode) -> bool {
+        mode == HardwareMode::Cgb || self.cgb_support() != CgbSupport::Required
+    }
+
+    // The Super Game Boy only takes commands from cartridges with 0x03 at 0x0146 and the old
+    // licensee code 0x33 that says to look for the new one
+    pub fn sgb_support(&self) -> bool {
+        self.rom.get(0x0146) == Some(&0x03) && self.rom.get(0x014B) == Some(&0x33)
+    }
+
+    // Identifies the exact ROM contents, e.g. to tie recordings to the ROM they were made with
+    pub fn rom_hash(&self) -> u64 {
+        crate::hash::fnv1a_64(&self.rom)
+    }
+
+    pub fn get_cartridge_type(&self) -> Result<&CartridgeType<'_>, String> {
+        let type_code_in_rom = *self.rom.get(0x0147).ok_or("No cartridge header")?;
         match CARTRIDGE_TYPES
             .iter()
             .find(|cart_type| cart_type.code == type_code_in_rom) {
-            Some(cartridge_type) => return Ok(cartridge_type),
-            None => return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Cartridge type {:#02X?} unrecognized", type_code_in_rom))),
+            Some(cartridge_type) => Ok(cartridge_type),
+            None => Err(format!("Cartridge type {:#02X?} unrecognized", type_code_in_rom)),
+        }
+    }
+
+    pub fn get_ram_size(&self) -> Result<usize, String> {
+        let ram_size_in_rom = *self.rom.get(0x0149).ok_or("No cartridge header")?;
+        match CARTRIDGE_RAM_SIZES.iter().find(|(code, _)| *code == ram_size_in_rom) {
+            Some((_, size)) => Ok(*size),
+            None => Err(format!("Cartridge RAM size {:#02X?} unrecognized", ram_size_in_rom)),
         }
     }
 
-    pub fn get_rom_size(&self) -> io::Result<&CartridgeRomSize> {
-        let type_size_in_rom = self.blob[0x0148];
+    pub fn get_rom_size(&self) -> Result<&CartridgeRomSize<'_>, String> {
+        let type_size_in_rom = *self.rom.get(0x0148).ok_or("No cartridge header")?;
 
         match CARTRIDGE_ROM_SIZES
             .iter()
             .find(|cart_size| cart_size.code == type_size_in_rom) {
-            Some(cartridge_size) => return Ok(cartridge_size),
-            None => return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Cartridge size {:#02X?} unrecognized", type_size_in_rom))),
+            Some(cartridge_size) => Ok(cartridge_size),
+            None => Err(format!("Cartridge size {:#02X?} unrecognized", type_size_in_rom)),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom_blob(num_banks: usize) -> Vec<u8> {
+        let mut blob = vec![0; num_banks * ROM_BANK_SIZE];
+        blob[0x0134..0x0138].copy_from_slice(b"TEST");
+        blob
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_reader() {
+        let blob = rom_blob(2);
+        let cartridge = Cartridge::read_cartridge_from_reader(&mut blob.as_slice()).unwrap();
+        assert_eq!(cartridge.bank_count(), 2);
+        assert!(cartridge.name.starts_with("TEST"));
+    }
+
+    #[test]
+    fn banks_are_slices_of_the_rom() {
+        let mut blob = rom_blob(2);
+        blob[ROM_BANK_SIZE] = 0xAB;
+        let mut cartridge = Cartridge::from_data(blob).unwrap();
+        assert_eq!(cartridge.rom_bank(0).unwrap()[0x0134], b'T');
+        assert_eq!(cartridge.rom_bank(1).unwrap().len(), ROM_BANK_SIZE);
+        assert_eq!(cartridge.rom_bank(1).unwrap()[0], 0xAB);
+        assert_eq!(cartridge.rom_bank(2), None);
+        cartridge.rom_bank_mut(1).unwrap()[1] = 0xCD;
+        assert_eq!(cartridge.rom_bank(1).unwrap()[1], 0xCD);
+        assert_eq!(Cartridge::new_dummy_cartridge(vec![1, 2, 3]).rom_bank(0), Some(&[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn global_checksum() {
+        let mut blob = rom_blob(2);
+        blob[0x014E] = 0x12;
+        blob[0x014F] = 0x34;
+        let cartridge = Cartridge::from_data(blob).unwrap();
+        assert_eq!(cartridge.global_checksum(), 0x1234);
+        assert_eq!(Cartridge::new_dummy_cartridge(vec![]).global_checksum(), 0);
+    }
+
+    #[test]
+    fn header() {
+        let header = Cartridge::from_data(rom_blob(2)).unwrap().header();
+        assert_eq!(header, CartridgeHeader { title: "TEST".to_string(), cartridge_type: "ROM only".to_string(),
+                                             rom_size: "256Kbit".to_string(), global_checksum: 0,
+                                             cgb_support: CgbSupport::Unsupported, sgb_support: false });
+        assert_eq!(Cartridge::new_dummy_cartridge(vec![]).header().cartridge_type, "unknown");
+    }
+
+    #[test]
+    fn cgb_flag() {
+        let mut blob = rom_blob(2);
+        blob[0x0143] = 0x80;
+        assert_eq!(Cartridge::from_data(blob.clone()).unwrap().cgb_support(), CgbSupport::Enhanced);
+        blob[0x0143] = 0xC0;
+        assert_eq!(Cartridge::from_data(blob.clone()).unwrap().cgb_support(), CgbSupport::Required);
+        blob[0x0143] = b'E';
+        assert_eq!(Cartridge::from_data(blob).unwrap().cgb_support(), CgbSupport::Unsupported);
+    }
+
+    #[test]
+    fn cgb_only_cartridges_need_cgb_mode() {
+        let mut blob = rom_blob(2);
+        blob[0x0143] = 0xC0;
+        let cartridge = Cartridge::from_data(blob).unwrap();
+        assert!(!cartridge.runs_on(HardwareMode::Dmg));
+        assert!(!cartridge.runs_on(HardwareMode::Sgb));
+        assert!(cartridge.runs_on(HardwareMode::Cgb));
+    }
+
+    #[test]
+    fn sgb_flag() {
+        let mut blob = rom_blob(2);
+        blob[0x0146] = 0x03;
+        assert!(!Cartridge::from_data(blob.clone()).unwrap().sgb_support());
+        blob[0x014B] = 0x33;
+        assert!(Cartridge::from_data(blob).unwrap().sgb_support());
+    }
+
+    #[test]
+    fn from_data_bad_size() {
+        assert_eq!(Cartridge::from_data(vec![0; ROM_BANK_SIZE + 1]).err(), Some("Bad cartridge ROM size".to_string()));
+        assert!(Cartridge::from_data(vec![]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn read_from_reader_bad_size() {
+        let blob = vec![0; ROM_BANK_SIZE + 1];
+        assert!(Cartridge::read_cartridge_from_reader(&mut blob.as_slice()).is_err());
+        assert!(Cartridge::read_cartridge_from_reader(&mut io::empty()).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use crate::prelude::*;
+
+const GAME_SHARK_DIGITS: usize = 8;
+const GAME_GENIE_SHORT_DIGITS: usize = 6;
+const GAME_GENIE_DIGITS: usize = 9;
+
+// What a code changes. GameShark codes are written to RAM once per frame, Game Genie codes
+// replace what the CPU reads from ROM, only when the original byte matches if they have a
+// compare value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheatPatch {
+    // The CGB work RAM bank for 0xD000-0xDFFF, None for whichever is mapped
+    GameShark { bank: Option<u8>, address: u16, value: u8 },
+    GameGenie { address: u16, value: u8, compare: Option<u8> },
+}
+
+impl CheatPatch {
+    // GameShark codes are 8 hex digits TTVVLLHH: type, value and the address in little endian.
+    // Game Genie codes are ABC-DEF or ABC-DEF-GHI: value AB, address FCDE with F inverted and,
+    // in the long form, the compare value GI rotated and scrambled.
+    pub fn parse(code: &str) -> Result<CheatPatch, String> {
+        let digits = code.trim().chars().filter(|character| *character != '-')
+            .map(|character| character.to_digit(16).map(|digit| digit as u8))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| format!("Bad cheat code: {}", code))?;
+        let byte = |index: usize| digits[index] << 4 | digits[index + 1];
+        match digits.len() {
+            GAME_SHARK_DIGITS if !code.contains('-') => {
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                let bank = match byte(0) {
+                    0x00 | 0x01 | 0x80 => None,
+                    kind @ 0x90..=0x97 => Some(kind & 0x07),
+                    kind => return Err(format!("Unsupported GameShark code type {:02X}", kind)),
+                };
+                // External RAM is not emulated yet
+                if !(0xC000..=0xDFFF).contains(&address) {
+                    return Err(format!("GameShark code {} patches {:04X}, only work RAM is supported", code, address));
+                }
+                Ok(CheatPatch::GameShark { bank, address, value: byte(2) })
+            }
+            GAME_GENIE_SHORT_DIGITS | GAME_GENIE_DIGITS => {
+                let address = ((digits[5] as u16 ^ 0x0F) << 12) | (digits[2] as u16) << 8 | (digits[3] as u16) << 4 | digits[4] as u16;
+                if address >= 0x8000 {
+                    return Err(format!("Game Genie code {} patches {:04X}, outside ROM", code, address));
+                }
+                let compare = if digits.len() == GAME_GENIE_DIGITS {
+                    Some((digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+                Ok(CheatPatch::GameGenie { address, value: byte(0), compare })
+            }
+            _ => Err(format!("Bad cheat code: {}", code)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub code: String,
+    pub patch: CheatPatch,
+    pub enabled: bool,
+}
+
+// The codes entered, numbered in the order they were added. They are a setting rather than
+// part of the console, so resets and save states keep them.
+#[derive(Default)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+    // Counts changes to the codes, for caches of what the patched memory held
+    revision: u64,
+}
+
+impl Cheats {
+    // Returns the index of the new code, enabled right away
+    pub fn add(&mut self, code: &str) -> Result<usize, String> {
+        let patch = CheatPatch::parse(code)?;
+        self.cheats.push(Cheat { code: code.trim().to_uppercase(), patch, enabled: true });
+        self.revision += 1;
+        Ok(self.cheats.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Result<Cheat, String> {
+        if index >= self.cheats.len() { return Err(format!("No cheat {}", index)); }
+        self.revision += 1;
+        Ok(self.cheats.remove(index))
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> Result<(), String> {
+        let cheat = self.cheats.get_mut(index).ok_or_else(|| format!("No cheat {}", index))?;
+        cheat.enabled = enabled;
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    pub fn patch_rom_read(&self, address: u16, original: u8) -> u8 {
+        self.enabled().find_map(|patch| match patch {
+            CheatPatch::GameGenie { address: patched, value, compare }
+                if patched == address && compare.is_none_or(|compare| compare == original) => Some(value),
+            _ => None,
+        }).unwrap_or(original)
+    }
+
+    pub fn ram_patches(&self) -> impl Iterator<Item = (Option<u8>, u16, u8)> + '_ {
+        self.enabled().filter_map(|patch| match patch {
+            CheatPatch::GameShark { bank, address, value } => Some((bank, address, value)),
+            _ => None,
+        })
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = CheatPatch> + '_ {
+        self.cheats.iter().filter(|cheat| cheat.enabled).map(|cheat| cheat.patch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+
+    #[test]
+    fn parse_codes() {
+        assert_eq!(CheatPatch::parse("010FA2C6"), Ok(CheatPatch::GameShark { bank: None, address: 0xC6A2, value: 0x0F }));
+        assert_eq!(CheatPatch::parse("9263A0D0"), Ok(CheatPatch::GameShark { bank: Some(2), address: 0xD0A0, value: 0x63 }));
+        assert_eq!(CheatPatch::parse("3E1-23F"), Ok(CheatPatch::GameGenie { address: 0x0123, value: 0x3E, compare: None }));
+        assert_eq!(CheatPatch::parse("3E1-23F-2AE"),
+                   Ok(CheatPatch::GameGenie { address: 0x0123, value: 0x3E, compare: Some(0x2Eu8.rotate_right(2) ^ 0xBA) }));
+        assert!(CheatPatch::parse("01FF0080").is_err());
+        assert!(CheatPatch::parse("3E1-230").is_err());
+        assert!(CheatPatch::parse("XYZ").is_err());
+    }
+
+    #[test]
+    fn game_genie_overlays_rom_reads() {
+        let mut rom = vec![0; 0x200];
+        rom[0x0123] = 0x42;
+        let mut bus = Bus::new_from_vecs(vec![], rom);
+        bus.boot_rom_active = false;
+        let index = bus.cheats.add("3E1-23F").unwrap();
+        assert_eq!(bus.read(0x0123), 0x3E);
+        bus.cheats.set_enabled(index, false).unwrap();
+        assert_eq!(bus.read(0x0123), 0x42);
+        // The compare value has to match the original byte
+        let compare = (0x43u8 ^ 0xBA).rotate_left(2);
+        bus.cheats.add(&format!("3E1-23F-{:X}0{:X}", compare >> 4, compare & 0x0F)).unwrap();
+        assert_eq!(bus.read(0x0123), 0x42);
+    }
+
+    #[test]
+    fn game_shark_writes_ram_every_frame() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.cheats.add("0199A2C6").unwrap();
+        bus.write(0xC6A2, 0x01);
+        bus.advance(bus.ppu.cycles_until_vblank());
+        assert_eq!(bus.read(0xC6A2), 0x99);
+    }
+}
@@ -0,0 +1,125 @@
+use serde::{Deserialize, Serialize};
+
+pub const HDMA_BLOCK_SIZE: u16 = 0x10;
+// The CPU is stopped for 8 M-cycles while a block is copied
+pub const HDMA_BLOCK_CYCLES: u64 = 32;
+
+// CGB DMA into video RAM set up through HDMA1-HDMA5. A general transfer copies everything at
+// once, an HBlank transfer copies a block at the start of every HBlank until it is done or
+// stopped.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Hdma {
+    source: u16,
+    // Offset into video RAM
+    destination: u16,
+    remaining_blocks: u8,
+    hblank_active: bool,
+    // HBlanks the PPU had entered when the last block was copied
+    pub last_hblank: u64,
+}
+
+impl Hdma {
+    pub fn set_source_high(&mut self, value: u8) {
+        self.source = (self.source & 0x00FF) | (value as u16) << 8;
+    }
+
+    pub fn set_source_low(&mut self, value: u8) {
+        self.source = (self.source & 0xFF00) | (value & 0xF0) as u16;
+    }
+
+    pub fn set_destination_high(&mut self, value: u8) {
+        self.destination = (self.destination & 0x00FF) | ((value & 0x1F) as u16) << 8;
+    }
+
+    pub fn set_destination_low(&mut self, value: u8) {
+        self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16;
+    }
+
+    // HDMA5 write: bit 7 picks an HBlank transfer, the rest is the length in blocks minus one.
+    // Clearing bit 7 while an HBlank transfer runs stops it. Returns the blocks to copy right away.
+    pub fn start(&mut self, value: u8) -> u8 {
+        if self.hblank_active && value & 0x80 == 0 {
+            self.hblank_active = false;
+            return 0;
+        }
+        self.remaining_blocks = (value & 0x7F) + 1;
+        if value & 0x80 != 0 {
+            self.hblank_active = true;
+            0
+        } else {
+            self.remaining_blocks
+        }
+    }
+
+    pub fn hblank_active(&self) -> bool {
+        self.hblank_active
+    }
+
+    // HDMA5 read: bit 7 clear while an HBlank transfer runs, with the blocks left minus one.
+    // 0xFF once a transfer has finished.
+    pub fn status(&self) -> u8 {
+        match (self.hblank_active, self.remaining_blocks) {
+            (true, blocks) => blocks - 1,
+            (false, 0) => 0xFF,
+            (false, blocks) => 0x80 | (blocks - 1),
+        }
+    }
+
+    // Source address and video RAM offset of the next block, moving past it
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, self.destination);
+        self.source = self.source.wrapping_add(HDMA_BLOCK_SIZE);
+        self.destination = (self.destination + HDMA_BLOCK_SIZE) & 0x1FF0;
+        self.remaining_blocks -= 1;
+        if self.remaining_blocks == 0 { self.hblank_active = false; }
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::{Bus, HardwareMode};
+
+    fn cgb_bus_with_source() -> Bus {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0xFF40, 0x80);
+        for offset in 0..0x40 { bus.write(0xC000 + offset, offset as u8 + 1); }
+        bus.write(0xFF51, 0xC0);
+        bus.write(0xFF52, 0x00);
+        bus.write(0xFF53, 0x81);
+        bus.write(0xFF54, 0x0F);
+        bus
+    }
+
+    #[test]
+    fn general_transfer_copies_at_once() {
+        let mut bus = cgb_bus_with_source();
+        bus.write(0xFF55, 0x01);
+        assert_eq!(bus.video_ram.data[0x0100..0x0120], (1..=0x20).collect::<Vec<u8>>()[..]);
+        assert_eq!(bus.video_ram.data[0x0120], 0);
+        assert_eq!(bus.read(0xFF55), 0xFF);
+        assert_eq!(bus.read(0xFF51), 0xFF);
+        assert_eq!(bus.take_stalled_cycles(), 64);
+    }
+
+    #[test]
+    fn hblank_transfer_copies_a_block_per_hblank() {
+        let mut bus = cgb_bus_with_source();
+        bus.write(0xFF55, 0x82);
+        assert_eq!(bus.read(0xFF55), 0x02);
+        assert_eq!(bus.video_ram.data[0x0100], 0);
+        // Line 0 enters HBlank after OAM search and pixel transfer
+        bus.advance(80 + 172);
+        assert_eq!(bus.video_ram.data[0x0100..0x0110], (1..=0x10).collect::<Vec<u8>>()[..]);
+        assert_eq!(bus.video_ram.data[0x0110], 0);
+        assert_eq!(bus.read(0xFF55), 0x01);
+        bus.advance(456);
+        assert_eq!(bus.video_ram.data[0x011F], 0x20);
+        // Stopped before the last block
+        bus.write(0xFF55, 0x00);
+        assert_eq!(bus.read(0xFF55), 0x80);
+        bus.advance(456);
+        assert_eq!(bus.video_ram.data[0x0120], 0);
+    }
+}
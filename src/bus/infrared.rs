@@ -0,0 +1,58 @@
+// Whatever sits in front of the CGB infrared port: another console, a remote, or nothing,
+// which is what the port sees when no transceiver is set
+pub trait InfraredTransceiver {
+    // The game switched its LED on or off
+    fn set_led(&mut self, _on: bool) {}
+    // Whether light reaches the sensor
+    fn receiving(&mut self) -> bool { false }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+    use alloc::rc::Rc;
+
+    use super::*;
+    use crate::bus::{Bus, HardwareMode};
+    use crate::prelude::*;
+
+    #[derive(Default)]
+    struct Mirror {
+        led_changes: Vec<bool>,
+        lit: bool,
+    }
+
+    impl InfraredTransceiver for Mirror {
+        fn set_led(&mut self, on: bool) {
+            self.led_changes.push(on);
+            self.lit = on;
+        }
+        fn receiving(&mut self) -> bool { self.lit }
+    }
+
+    #[test]
+    fn no_signal_without_a_transceiver() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        assert_eq!(bus.read(0xFF56), 0x3E);
+        bus.write(0xFF56, 0xC1);
+        assert_eq!(bus.read(0xFF56), 0xFF);
+    }
+
+    #[test]
+    fn led_and_sensor_go_through_the_transceiver() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        let mirror = Rc::new(RefCell::new(Mirror::default()));
+        bus.set_infrared_transceiver(Some(mirror.clone()));
+        bus.write(0xFF56, 0x01);
+        bus.write(0xFF56, 0x01);
+        // Reading is only enabled with bits 6 and 7 set
+        assert_eq!(bus.read(0xFF56) & 0x02, 0x02);
+        bus.write(0xFF56, 0xC1);
+        assert_eq!(bus.read(0xFF56) & 0x02, 0x00);
+        bus.write(0xFF56, 0xC0);
+        assert_eq!(bus.read(0xFF56) & 0x02, 0x02);
+        assert_eq!(mirror.borrow().led_changes, vec![true, false]);
+    }
+}
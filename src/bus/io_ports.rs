@@ -1,10 +1,9 @@
-use std::cell::RefCell;
-use std::rc::Rc;
-
 use super::*;
-use crate::ppu::PPU;
 
 
+pub(super) const IO_SERIAL_DATA: u16 = 0xFF01;
+pub(super) const IO_SERIAL_CONTROL: u16 = 0xFF02;
+
 const IO_SOUND_CHANNEL_CONTROL_NR50: u16 = 0xFF24;
 const IO_SOUND_ON_OFF_NR52: u16 = 0xFF26;
 const IO_SOUND_CH1_SOUND_LENGTH_WAVE_PATTERN_DUTY_NR11: u16 = 0xFF11;
@@ -13,29 +12,71 @@ const IO_SOUND_CH1_FREQUENCY_LO_NR13: u16 = 0xFF13;
 const IO_SOUND_CH1_FREQUENCY_HI_NR14: u16 = 0xFF14;
 const IO_SOUND_OUTPUT_TERMINAL_NR51: u16 = 0xFF25;
 
-const IO_LCD_CONTROL: u16 = 0xFF40;
-const IO_LCD_SCROLL_Y: u16 = 0xFF42;
-const IO_LCD_Y_COORDINATE: u16 = 0xFF44;
-const IO_LDC_BG_PALETTE_DATA: u16 = 0xFF47;
+const IO_SOUND_FIRST_REGISTER: u16 = 0xFF10;
+const IO_SOUND_WAVE_RAM_END: u16 = 0xFF3F;
+
+pub(super) const IO_LCD_CONTROL: u16 = 0xFF40;
+pub(super) const IO_JOYPAD: u16 = 0xFF00;
+pub(super) const IO_DIVIDER: u16 = 0xFF04;
+pub(super) const IO_TIMER_COUNTER: u16 = 0xFF05;
+pub(super) const IO_TIMER_MODULO: u16 = 0xFF06;
+pub(super) const IO_TIMER_CONTROL: u16 = 0xFF07;
+pub(super) const IO_INTERRUPT_FLAG: u16 = 0xFF0F;
+pub(super) const IO_LCD_STATUS: u16 = 0xFF41;
+pub(super) const IO_LCD_SCROLL_Y: u16 = 0xFF42;
+pub(super) const IO_LCD_SCROLL_X: u16 = 0xFF43;
+pub(super) const IO_LCD_Y_COORDINATE: u16 = 0xFF44;
+pub(super) const IO_LCD_Y_COMPARE: u16 = 0xFF45;
+pub(super) const IO_OAM_DMA: u16 = 0xFF46;
+pub(super) const IO_LDC_BG_PALETTE_DATA: u16 = 0xFF47;
+pub(super) const IO_LCD_OBJ_PALETTE_0_DATA: u16 = 0xFF48;
+pub(super) const IO_LCD_OBJ_PALETTE_1_DATA: u16 = 0xFF49;
+pub(super) const IO_LCD_WINDOW_Y: u16 = 0xFF4A;
+pub(super) const IO_LCD_WINDOW_X: u16 = 0xFF4B;
 
 const IO_BOOT_ROM_CONTROL: u16 = 0xFF50;
+pub(super) const IO_VRAM_BANK: u16 = 0xFF4F;
+pub(super) const IO_WORK_RAM_BANK: u16 = 0xFF70;
+pub(super) const IO_OBJECT_PRIORITY: u16 = 0xFF6C;
+pub(super) const IO_HDMA_SOURCE_HIGH: u16 = 0xFF51;
+pub(super) const IO_HDMA_SOURCE_LOW: u16 = 0xFF52;
+pub(super) const IO_HDMA_DESTINATION_HIGH: u16 = 0xFF53;
+pub(super) const IO_HDMA_DESTINATION_LOW: u16 = 0xFF54;
+pub(super) const IO_HDMA_CONTROL: u16 = 0xFF55;
+pub(super) const IO_INFRARED_PORT: u16 = 0xFF56;
+pub(super) const IO_BG_PALETTE_INDEX: u16 = 0xFF68;
+pub(super) const IO_BG_PALETTE_DATA: u16 = 0xFF69;
+pub(super) const IO_OBJ_PALETTE_INDEX: u16 = 0xFF6A;
+pub(super) const IO_OBJ_PALETTE_DATA: u16 = 0xFF6B;
 
+// Registers only the CGB has: speed switch, VRAM bank, HDMA, infrared, palettes, object
+// priority and WRAM bank
+const IO_CGB_REGISTERS: [RangeInclusive<u16>; 6] = [0xFF4C..=0xFF4D, 0xFF4F..=0xFF4F, 0xFF51..=0xFF56,
+                                                    0xFF68..=0xFF6C, 0xFF70..=0xFF70, 0xFF74..=0xFF77];
+
+pub(super) fn is_cgb_register(address: u16) -> bool {
+    IO_CGB_REGISTERS.iter().any(|registers| registers.contains(&address))
+}
 
+// Registers the PPU draws with, they are only read out of it
+pub(super) fn is_ppu_register(address: u16) -> bool {
+    matches!(address, IO_LCD_CONTROL | IO_LCD_SCROLL_Y | IO_LCD_SCROLL_X | IO_LDC_BG_PALETTE_DATA | IO_LCD_OBJ_PALETTE_0_DATA
+                      | IO_LCD_OBJ_PALETTE_1_DATA | IO_LCD_WINDOW_Y | IO_LCD_WINDOW_X)
+}
+
+
+// Values written to the IO registers. Registers belonging to a component, like the PPU ones,
+// are routed there by the bus and only mirrored here.
 pub struct IOPorts {
     pub data: Vec<u8>,
-    ppu: Rc<RefCell<PPU>>,
 }
 
-impl MemoryZone for IOPorts {
-    fn read(&self, address: u16) -> u8 {
-        match address {
-            IO_LCD_Y_COORDINATE => { self.ppu.borrow().current_line }
-            IO_LCD_SCROLL_Y => { self.ppu.borrow().bg_scroll_y }
-            _ => {panic!("Reading from IO address {:04X}", address);}
-        }
-        // self.data[self.global_address_to_local_address(address) as usize]
+impl IOPorts {
+    // Registers not routed to a component cannot be read yet
+    pub fn read(&self, address: u16) -> Result<u8, EmulationError> {
+        Err(EmulationError::UnsupportedIoRead(address))
     }
-    fn write(&mut self, address: u16, value: u8) {
+    pub fn write(&mut self, address: u16, value: u8) -> Result<(), EmulationError> {
         match address {
             IO_SOUND_CHANNEL_CONTROL_NR50 => { println!("Not implemented"); }
             IO_SOUND_ON_OFF_NR52 => { println!("Not implemented"); }
@@ -44,24 +85,37 @@ impl MemoryZone for IOPorts {
             IO_SOUND_CH1_FREQUENCY_LO_NR13 => { println!("Not implemented"); }
             IO_SOUND_CH1_FREQUENCY_HI_NR14 => { println!("Not implemented"); }
             IO_SOUND_OUTPUT_TERMINAL_NR51 => { println!("Not implemented"); }
-            IO_LDC_BG_PALETTE_DATA => { println!("Not implemented"); }
-            IO_LCD_SCROLL_Y => { self.ppu.borrow_mut().bg_scroll_y = value; }
-            IO_LCD_CONTROL => { println!("Not implemented"); }
-            IO_BOOT_ROM_CONTROL => { if value != 1 { panic!("0xFF50 only allows writes of 1")} } // HAPPY CASE HANDLED BY BUS
-            _ => {panic!("Writing to IO: address {:04X} value {:02X}", address, value);}
+            IO_SERIAL_DATA | IO_SERIAL_CONTROL => {} // SET ON THE SERIAL PORT BY BUS
+            // There is no APU yet, the values are kept for the debug views
+            IO_SOUND_FIRST_REGISTER..=IO_SOUND_WAVE_RAM_END => {}
+            _ if is_ppu_register(address) => {} // SET ON THE PPU BY BUS
+            IO_LCD_STATUS | IO_LCD_Y_COMPARE => {}
+            IO_DIVIDER..=IO_TIMER_CONTROL => {} // SET ON THE TIMER BY BUS
+            // 0xFF50 only allows writes of 1, the happy case is handled by the bus
+            IO_BOOT_ROM_CONTROL if value == 1 => {}
+            _ => return Err(EmulationError::UnsupportedIoWrite { address, value }),
         }
         let local_address = self.global_address_to_local_address(address) as usize;
         self.data[local_address] = value;
+        Ok(())
     }
-}
 
-impl IOPorts {
     fn global_address_to_local_address(&self, address: u16) -> u16 { address - IO_PORTS_BASE_ADDRESS }
 
-    pub fn new(ppu: Rc<RefCell<PPU>>) -> IOPorts {
+    // Last value written to a register
+    pub fn stored(&self, address: u16) -> u8 {
+        self.data[self.global_address_to_local_address(address) as usize]
+    }
+
+    // Stores a value without the side effects or checks of a CPU write
+    pub fn poke(&mut self, address: u16, value: u8) {
+        let local_address = self.global_address_to_local_address(address) as usize;
+        self.data[local_address] = value;
+    }
+
+    pub fn new() -> IOPorts {
         IOPorts{
-            data: vec![0; IO_PORTS_SIZE as usize], 
-            ppu,
+            data: vec![0; IO_PORTS_SIZE as usize],
         }
     }
 }
@@ -69,25 +123,168 @@ impl IOPorts {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ppu::sprites::ObjectPriority;
 
     #[test]
     fn read_ff44_lcdc_y_coordinate() {
         let mut bus = Bus::new_from_vecs(vec![], vec![]);
-        bus.ppu.borrow_mut().current_line = 123;
+        bus.ppu.current_line = 123;
         assert_eq!(bus.read(0xFF44), 123);
     }
 
     #[test]
     fn read_ff42_scx_scroll_y() {
         let mut bus = Bus::new_from_vecs(vec![], vec![]);
-        bus.ppu.borrow_mut().bg_scroll_y = 123;
+        bus.ppu.bg_scroll_y = 123;
         assert_eq!(bus.read(0xFF42), 123);
     }
 
+    #[test]
+    fn lcd_control() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        assert_eq!(bus.read(0xFF40), 0);
+        bus.write(0xFF40, 0x91);
+        bus.advance(456 * 5);
+        assert_eq!((bus.read(0xFF40), bus.read(0xFF44)), (0x91, 5));
+        bus.write(0xFF40, 0x11);
+        assert_eq!((bus.read(0xFF40), bus.read(0xFF44), bus.read(0xFF41) & 0x03), (0x11, 0, 0));
+        bus.advance(456 * 5);
+        assert_eq!(bus.read(0xFF44), 0);
+        assert!(bus.fault.is_none());
+    }
+
+    #[test]
+    fn window_position() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF4A, 0x40);
+        bus.write(0xFF4B, 0x07);
+        assert_eq!((bus.ppu.window_y, bus.ppu.window_x), (0x40, 0x07));
+        assert_eq!((bus.read(0xFF4A), bus.read(0xFF4B)), (0x40, 0x07));
+        assert!(bus.fault.is_none());
+    }
+
+    #[test]
+    fn object_palettes() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF48, 0xE4);
+        bus.write(0xFF49, 0x1B);
+        assert_eq!((bus.ppu.object_palette_0, bus.ppu.object_palette_1), (0xE4, 0x1B));
+        assert_eq!((bus.read(0xFF48), bus.read(0xFF49)), (0xE4, 0x1B));
+        assert!(bus.fault.is_none());
+    }
+
+    #[test]
+    fn inspect_registers() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF40, 0x80);
+        bus.ppu.current_line = 90;
+        bus.write(0xFF47, 0xFC);
+        assert_eq!(bus.inspect_io(0xFF44), 90);
+        assert_eq!(bus.inspect_io(0xFF47), 0xFC);
+        assert_eq!(bus.inspect_io(0xFF01), 0);
+    }
+
+    #[test]
+    fn poke_ff44_sets_ppu_line() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        assert_eq!(bus.poke(0xFF44, 77), Ok(()));
+        assert_eq!(bus.ppu.current_line, 77);
+        assert_eq!(bus.io_ports.stored(0xFF44), 77);
+    }
+
+    #[test]
+    fn sound_registers_are_stored() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF17, 0xF3);
+        bus.write(0xFF30, 0x01);
+        assert_eq!(bus.inspect_io(0xFF17), 0xF3);
+        assert_eq!(bus.inspect_io(0xFF30), 0x01);
+    }
+
+    #[test]
+    fn cgb_registers_only_exist_in_cgb_mode() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF74, 0x12);
+        assert_eq!(bus.read(0xFF74), 0xFF);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0xFF74, 0x12);
+        assert_eq!(bus.read(0xFF74), 0x12);
+        assert_eq!(bus.inspect_io(0xFF74), 0x12);
+    }
+
+    #[test]
+    fn ff4f_switches_video_ram_banks() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0x8010, 0x11);
+        bus.write(0xFF4F, 0x01);
+        assert_eq!(bus.read(0xFF4F), 0xFF);
+        assert_eq!(bus.read(0x8010), 0x00);
+        bus.write(0x9FFF, 0x22);
+        bus.write(0xFF4F, 0xFE);
+        assert_eq!(bus.read(0xFF4F), 0xFE);
+        assert_eq!(bus.read(0x8010), 0x11);
+        assert_eq!(bus.video_ram_bank_1.data[0x1FFF], 0x22);
+        assert_eq!(bus.video_ram.data[0x1FFF], 0x00);
+    }
+
+    #[test]
+    fn palette_registers() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0xFF68, 0x80 | 0x08);
+        bus.write(0xFF69, 0x1F);
+        bus.write(0xFF69, 0x7C);
+        assert_eq!(bus.read(0xFF68), 0xCA);
+        assert_eq!(bus.ppu.palettes.background.color(1, 0), 0x7C1F);
+        bus.write(0xFF6A, 0x01);
+        bus.write(0xFF6B, 0x12);
+        assert_eq!(bus.read(0xFF6B), 0x12);
+        assert_eq!(bus.ppu.palettes.objects.color(0, 0), 0x12FF);
+        assert_eq!(bus.color_framebuffer().map(|colors| colors.len()), Some(160 * 144));
+    }
+
+    #[test]
+    fn opri_selects_object_priority() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        assert_eq!(bus.ppu.object_priority, ObjectPriority::Coordinate);
+        bus.set_mode(HardwareMode::Cgb);
+        assert_eq!(bus.ppu.object_priority, ObjectPriority::OamIndex);
+        bus.write(0xFF6C, 0x01);
+        assert_eq!(bus.read(0xFF6C), 0xFF);
+        assert_eq!(bus.ppu.object_priority, ObjectPriority::Coordinate);
+        bus.reset(|| 0);
+        assert_eq!(bus.ppu.object_priority, ObjectPriority::OamIndex);
+    }
+
+    #[test]
+    fn unsupported_registers_are_reported() {
+        let mut io_ports = IOPorts::new();
+        assert!(matches!(io_ports.read(0xFF47), Err(EmulationError::UnsupportedIoRead(0xFF47))));
+        assert!(matches!(io_ports.write(0xFF50, 0x00), Err(EmulationError::UnsupportedIoWrite { address: 0xFF50, value: 0x00 })));
+        assert_eq!(io_ports.stored(0xFF50), 0x00);
+        assert!(io_ports.write(0xFF50, 0x01).is_ok());
+    }
+
     #[test]
     fn write_ff42_scx_scroll_y() {
         let mut bus = Bus::new_from_vecs(vec![], vec![]);
         bus.write(0xFF42, 123);
-        assert_eq!(bus.ppu.borrow().bg_scroll_y, 123);
+        assert_eq!(bus.ppu.bg_scroll_y, 123);
+    }
+
+    #[test]
+    fn timer_registers() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF06, 0xAB);
+        bus.write(0xFF05, 0xFF);
+        bus.write(0xFF07, 0x05);
+        assert_eq!(bus.read(0xFF07), 0xFD);
+        bus.advance(600);
+        assert_eq!(bus.read(0xFF04), 2);
+        assert_eq!(bus.read(0xFF05), 0xAB + 36);
+        assert_eq!(bus.read(0xFF0F), 0xE4);
+        bus.write(0xFF04, 0x12);
+        assert_eq!(bus.inspect_io(0xFF04), 0);
     }
 }
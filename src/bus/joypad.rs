@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+use super::INTERRUPT_JOYPAD;
+use crate::input::Buttons;
+
+// P14 low selects the directions, P15 low the action buttons
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_ACTIONS: u8 = 0x20;
+const SELECT_LINES: u8 = SELECT_DIRECTIONS | SELECT_ACTIONS;
+
+// P1. The game picks which half of the buttons to look at through P14 and P15, pressed buttons
+// pull their line in the low nibble down. A line going down requests the joypad interrupt.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Joypad {
+    select: u8,
+    // Input rather than console state, saved states leave the held buttons alone
+    #[serde(skip)]
+    buttons: Buttons,
+    // IF bits requested since the bus last took them
+    requested_interrupts: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Joypad {
+        Joypad { select: SELECT_LINES, buttons: Buttons::empty(), requested_interrupts: 0 }
+    }
+}
+
+impl Joypad {
+    // The top two bits do not exist and read as 1
+    pub fn read(&self) -> u8 {
+        0xC0 | self.select | self.lines()
+    }
+
+    pub fn write(&mut self, value: u8) {
+        let lines = self.lines();
+        self.select = value & SELECT_LINES;
+        self.request_on_press(lines);
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        let lines = self.lines();
+        self.buttons = buttons;
+        self.request_on_press(lines);
+    }
+
+    // Takes the selected lines from a saved state, keeping the buttons held now
+    pub fn restore(&mut self, state: &Joypad) {
+        self.select = state.select;
+        self.requested_interrupts = state.requested_interrupts;
+    }
+
+    // IF bits for the bus to request, cleared once taken
+    pub fn take_interrupts(&mut self) -> u8 {
+        core::mem::take(&mut self.requested_interrupts)
+    }
+
+    // Low nibble of P1, a 0 for every pressed button in the selected halves
+    fn lines(&self) -> u8 {
+        let mut pressed = 0;
+        if self.select & SELECT_DIRECTIONS == 0 { pressed |= self.buttons.bits() & 0x0F; }
+        if self.select & SELECT_ACTIONS == 0 { pressed |= self.buttons.bits() >> 4; }
+        !pressed & 0x0F
+    }
+
+    fn request_on_press(&mut self, lines_before: u8) {
+        if lines_before & !self.lines() != 0 { self.requested_interrupts |= INTERRUPT_JOYPAD; }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selected_half_is_read() {
+        let mut joypad = Joypad::default();
+        joypad.set_buttons(Buttons::A | Buttons::DOWN);
+        assert_eq!(joypad.read(), 0xFF);
+        joypad.write(0x20);
+        assert_eq!(joypad.read(), 0xE7);
+        joypad.write(0x10);
+        assert_eq!(joypad.read(), 0xDE);
+        joypad.write(0x00);
+        assert_eq!(joypad.read(), 0xC6);
+    }
+
+    #[test]
+    fn pressing_a_selected_button_requests_the_interrupt() {
+        let mut joypad = Joypad::default();
+        joypad.set_buttons(Buttons::START);
+        assert_eq!(joypad.take_interrupts(), 0);
+        // Selecting the half with a held button pulls its line down too
+        joypad.write(0x10);
+        assert_eq!(joypad.take_interrupts(), INTERRUPT_JOYPAD);
+        joypad.set_buttons(Buttons::START | Buttons::LEFT);
+        assert_eq!(joypad.take_interrupts(), 0);
+        joypad.set_buttons(Buttons::START | Buttons::B);
+        assert_eq!(joypad.take_interrupts(), INTERRUPT_JOYPAD);
+        joypad.set_buttons(Buttons::empty());
+        assert_eq!(joypad.take_interrupts(), 0);
+    }
+}
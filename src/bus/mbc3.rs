@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use super::rtc::{Rtc, RtcClock, RTC_DAY_HIGH, RTC_SECONDS};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+const RAM_ENABLE: u8 = 0x0A;
+
+// ROM banks 1 to 127 switched in at 0x4000-0x7FFF and up to four RAM banks at 0xA000-0xBFFF,
+// where the RTC registers are selected like one more bank on cartridges that have the clock.
+// Games set it up by writing to ROM:
+//   0x0000-0x1FFF: 0x0A enables RAM and the RTC, anything else disables them
+//   0x2000-0x3FFF: ROM bank, 0 selects 1
+//   0x4000-0x5FFF: RAM bank 0-3 or RTC register 0x08-0x0C
+//   0x6000-0x7FFF: writing 0 and then 1 latches the clock
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Mbc3 {
+    rom_bank: u8,
+    ram_bank: u8,
+    ram_enabled: bool,
+    latch_armed: bool,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+}
+
+impl Mbc3 {
+    pub fn new(ram_size: usize, has_rtc: bool) -> Mbc3 {
+        Mbc3 {
+            rom_bank: 1,
+            ram_bank: 0,
+            ram_enabled: false,
+            latch_armed: false,
+            ram: vec![0; ram_size],
+            rtc: if has_rtc { Some(Rtc::default()) } else { None },
+        }
+    }
+
+    pub fn rom_bank(&self) -> u8 {
+        self.rom_bank
+    }
+
+    // RAM bank or RTC register selected at 0xA000-0xBFFF
+    pub fn ram_bank(&self) -> u8 {
+        self.ram_bank
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    pub fn rtc(&self) -> Option<&Rtc> {
+        self.rtc.as_ref()
+    }
+
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(rtc) = &mut self.rtc { rtc.set_clock(clock); }
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == RAM_ENABLE,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => {
+                if self.latch_armed && value == 1 {
+                    if let Some(rtc) = &mut self.rtc { rtc.latch(); }
+                }
+                self.latch_armed = value == 0;
+            }
+        }
+    }
+
+    // Disabled RAM, missing banks and missing clock registers read as 0xFF
+    pub fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled { return 0xFF; }
+        match (self.ram_bank, &self.rtc) {
+            (RTC_SECONDS..=RTC_DAY_HIGH, Some(rtc)) => rtc.read(self.ram_bank),
+            (0..=3, _) => self.ram.get(self.ram_offset(address)).copied().unwrap_or(0xFF),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled { return; }
+        let offset = self.ram_offset(address);
+        match (self.ram_bank, &mut self.rtc) {
+            (RTC_SECONDS..=RTC_DAY_HIGH, Some(rtc)) => rtc.write(self.ram_bank, value),
+            (0..=3, _) => if let Some(byte) = self.ram.get_mut(offset) { *byte = value; },
+            _ => {}
+        }
+    }
+
+    fn ram_offset(&self, address: u16) -> usize {
+        self.ram_bank as usize * RAM_BANK_SIZE + (address as usize - 0xA000) % RAM_BANK_SIZE
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        if let Some(rtc) = &mut self.rtc { rtc.tick(cycles); }
+    }
+
+    // The registers start over, RAM and the clock are kept by the cartridge battery
+    pub fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = false;
+        self.latch_armed = false;
+    }
+
+    // Takes everything from a saved state but the clock's time source
+    pub fn restore(&mut self, saved: &Mbc3) {
+        let mut rtc = self.rtc.take();
+        if let (Some(rtc), Some(saved_rtc)) = (&mut rtc, &saved.rtc) { rtc.restore(saved_rtc); }
+        *self = Mbc3 { rtc, ..saved.clone() };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::rtc::RTC_MINUTES;
+
+    #[test]
+    fn rom_bank_zero_selects_one() {
+        let mut mbc3 = Mbc3::new(0, false);
+        mbc3.write_register(0x2000, 0x85);
+        assert_eq!(mbc3.rom_bank(), 0x05);
+        mbc3.write_register(0x3FFF, 0);
+        assert_eq!(mbc3.rom_bank(), 1);
+    }
+
+    #[test]
+    fn ram_needs_enabling() {
+        let mut mbc3 = Mbc3::new(4 * RAM_BANK_SIZE, false);
+        mbc3.write_ram(0xA000, 0x12);
+        assert_eq!(mbc3.read_ram(0xA000), 0xFF);
+        mbc3.write_register(0x0000, RAM_ENABLE);
+        mbc3.write_register(0x4000, 2);
+        mbc3.write_ram(0xA001, 0x34);
+        assert_eq!(mbc3.read_ram(0xA001), 0x34);
+        assert_eq!(mbc3.ram()[2 * RAM_BANK_SIZE + 1], 0x34);
+        mbc3.write_register(0x4000, RTC_SECONDS);
+        assert_eq!(mbc3.read_ram(0xA001), 0xFF);
+    }
+
+    #[test]
+    fn rtc_registers_are_latched() {
+        let mut mbc3 = Mbc3::new(0, true);
+        mbc3.write_register(0x0000, RAM_ENABLE);
+        mbc3.write_register(0x4000, RTC_MINUTES);
+        mbc3.write_ram(0xA000, 42);
+        assert_eq!(mbc3.read_ram(0xA000), 0);
+        mbc3.write_register(0x6000, 1);
+        assert_eq!(mbc3.read_ram(0xA000), 0);
+        mbc3.write_register(0x6000, 0);
+        mbc3.write_register(0x6000, 1);
+        assert_eq!(mbc3.read_ram(0xA000), 42);
+    }
+}
@@ -1,16 +1,54 @@
 pub mod cartridge;
+pub mod cheats;
 pub mod bootrom;
+pub mod hdma;
+pub mod infrared;
 pub mod io_ports;
+pub mod joypad;
+pub mod mbc3;
+pub mod oam_dma;
 pub mod ram_bank;
+pub mod ram_search;
+pub mod rtc;
+pub mod scheduler;
+pub mod serial;
+pub mod sgb;
+pub mod timer;
+pub mod work_ram;
 
-use std::cell::RefCell;
-use std::rc::Rc;
+use core::cell::RefCell;
+use core::ops::RangeInclusive;
+use alloc::rc::Rc;
 
-use cartridge::Cartridge;
-use bootrom::BootROM;
-use io_ports::IOPorts;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use crate::error::EmulationError;
+use crate::input::Buttons;
+use cartridge::{Cartridge, CgbSupport};
+use cheats::Cheats;
+use bootrom::{BootROM, BootRomVariant};
+use io_ports::{is_cgb_register, is_ppu_register, IOPorts, IO_INTERRUPT_FLAG, IO_JOYPAD, IO_LCD_CONTROL, IO_LCD_SCROLL_X, IO_LCD_SCROLL_Y, IO_LCD_OBJ_PALETTE_0_DATA, IO_LCD_OBJ_PALETTE_1_DATA, IO_LCD_WINDOW_X, IO_LCD_WINDOW_Y, IO_LDC_BG_PALETTE_DATA, IO_LCD_STATUS, IO_LCD_Y_COMPARE, IO_LCD_Y_COORDINATE, IO_OBJECT_PRIORITY, IO_VRAM_BANK, IO_WORK_RAM_BANK};
+use io_ports::{IO_BG_PALETTE_DATA, IO_BG_PALETTE_INDEX, IO_INFRARED_PORT, IO_OBJ_PALETTE_DATA, IO_OBJ_PALETTE_INDEX};
+use io_ports::{IO_HDMA_CONTROL, IO_HDMA_DESTINATION_HIGH, IO_HDMA_DESTINATION_LOW, IO_HDMA_SOURCE_HIGH, IO_HDMA_SOURCE_LOW};
+use io_ports::{IO_DIVIDER, IO_OAM_DMA, IO_SERIAL_CONTROL, IO_SERIAL_DATA, IO_TIMER_CONTROL, IO_TIMER_COUNTER, IO_TIMER_MODULO};
+use hdma::{Hdma, HDMA_BLOCK_CYCLES, HDMA_BLOCK_SIZE};
 use ram_bank::RAMBank;
+use joypad::Joypad;
+use mbc3::Mbc3;
+use oam_dma::OamDma;
+use rtc::RtcClock;
+use serial::Serial;
+use sgb::Sgb;
+use timer::Timer;
+use work_ram::{WorkRAM, WORK_RAM_SWITCHABLE_BANK_SIZE};
+pub use infrared::InfraredTransceiver;
+pub use serial::SerialSink;
 use crate::ppu::PPU;
+use crate::ppu::palettes::CompatibilityPalette;
+use crate::ppu::sprites::ObjectPriority;
+use crate::ppu::timeline::Timeline;
 
 const ROM_BANK_SIZE: usize = 0x4000;
 const BOOT_ROM_SIZE: usize = 256;
@@ -20,23 +58,139 @@ const WORK_RAM_BANK_SIZE: u16 = 0x2000;
 const WORK_RAM_BASE_ADDRESS: u16 = 0xC000;
 const VIDEO_RAM_SIZE: u16 = 0x2000;
 const VIDEO_RAM_BASE_ADDRESS: u16 = 0x8000;
+const OAM_SIZE: u16 = 0xA0;
+const OAM_BASE_ADDRESS: u16 = 0xFE00;
 const IO_PORTS_SIZE: u16 = 0x80;
 const IO_PORTS_BASE_ADDRESS: u16 = 0xFF00;
+const INTERRUPT_ENABLE_ADDRESS: u16 = 0xFFFF;
+
+// IF and IE bits, in priority order
+pub const INTERRUPT_VBLANK: u8 = 0x01;
+pub const INTERRUPT_LCD_STATUS: u8 = 0x02;
+pub const INTERRUPT_TIMER: u8 = 0x04;
+pub const INTERRUPT_SERIAL: u8 = 0x08;
+pub const INTERRUPT_JOYPAD: u8 = 0x10;
 
 
+// The console being emulated. CGB registers and behaviours only exist in Cgb mode, DMG
+// cartridges run in either. Sgb is a DMG that also takes Super Game Boy commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HardwareMode {
+    Dmg,
+    Cgb,
+    Sgb,
+}
+
 pub trait MemoryZone {
     fn read(&self, address: u16) -> u8;
     fn write(&mut self, address: u16, value: u8);
 }
 
+// Stands in for areas that are not emulated, reads float high and writes are lost
+struct OpenBus;
+
+impl MemoryZone for OpenBus {
+    fn read(&self, _address: u16) -> u8 { 0xFF }
+    fn write(&mut self, _address: u16, _value: u8) {}
+}
+
+// Gets notified of every CPU-visible bus access, used by debugging and analysis tools
+pub trait BusObserver {
+    fn on_read(&mut self, _address: u16, _value: u8) {}
+    fn on_write(&mut self, _address: u16, _old_value: u8, _new_value: u8) {}
+}
+
+// A device answering for a range of addresses instead of the built-in memory map, for embedders
+// experimenting with debug ports or mappers. Ticked with the cycles each instruction took.
+pub trait Peripheral {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn tick(&mut self, _cycles: u64) {}
+}
+
+// Lines of memory holding code cached by the CPU. Writes to them are collected so the blocks
+// decoded from there can be dropped before they run again.
+pub struct CodeWatch {
+    line_shift: u32,
+    watched: Vec<bool>,
+    written: Vec<usize>,
+}
+
+impl CodeWatch {
+    pub fn new(line_shift: u32) -> CodeWatch {
+        CodeWatch { line_shift, watched: vec![false; 0x10000 >> line_shift], written: vec![] }
+    }
+
+    pub fn watch(&mut self, first_line: usize, last_line: usize) {
+        for line in first_line..=last_line { self.watched[line] = true; }
+    }
+
+    fn on_write(&mut self, address: u16) {
+        let line = address as usize >> self.line_shift;
+        if self.watched[line] {
+            self.watched[line] = false;
+            self.written.push(line);
+        }
+    }
+
+    pub fn take_written(&mut self) -> Vec<usize> {
+        core::mem::take(&mut self.written)
+    }
+}
+
+type MappedPeripheral = (RangeInclusive<u16>, Rc<RefCell<dyn Peripheral>>);
+
+// Contents of everything writable on the bus, ROM is left out as it comes with the cartridge
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BusState {
+    pub boot_rom_active: bool,
+    pub work_ram: Vec<u8>,
+    pub video_ram: Vec<u8>,
+    // Only saved in CGB mode
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub video_ram_bank_1: Vec<u8>,
+    pub oam: Vec<u8>,
+    pub io_ports: Vec<u8>,
+    pub high_ram: Vec<u8>,
+    pub ppu: PPU,
+    // Cycles the PPU has not been advanced by yet
+    #[serde(default)]
+    pub ppu_debt: u64,
+    #[serde(default)]
+    pub hdma: Hdma,
+    #[serde(default)]
+    pub sgb: Sgb,
+    #[serde(default)]
+    pub interrupt_enable: u8,
+    #[serde(default)]
+    pub timer: Timer,
+    #[serde(default)]
+    pub joypad: Joypad,
+    #[serde(default)]
+    pub serial: Serial,
+    #[serde(default)]
+    pub oam_dma: OamDma,
+    // Cartridge RAM and registers, the clock included, for cartridges with an MBC3
+    #[serde(default)]
+    pub mbc3: Option<Mbc3>,
+}
+
+// Owns every component of the console besides the CPU. Components are ticked from cycle() and
+// their IO registers are routed to them by address, there is no shared ownership.
 pub struct Bus {
+    mode: HardwareMode,
     pub boot_rom_active: bool,
     pub boot_rom: BootROM,
     pub cartridge: cartridge::Cartridge,
-    pub work_ram: RAMBank,
+    pub work_ram: WorkRAM,
     pub video_ram: RAMBank,
+    // The CGB's second video RAM bank, tile data and the attributes of the tile maps in bank 0
+    pub video_ram_bank_1: RAMBank,
+    pub oam: RAMBank,
     pub io_ports: IOPorts,
     pub high_ram: RAMBank,
+    // IE, interrupt requests are kept in IF among the IO ports
+    pub interrupt_enable: u8,
 //            rom_bank_fixed: MemoryZone,
 //            rom_bank_switchable: MemoryZone,
 //            vram: MemoryZone,
@@ -49,20 +203,733 @@ pub struct Bus {
 //            io_ram: MemoryZone,
 //            hi_ram: MemoryZone,
 //            interrupt_enable_register: MemoryZone,
-    ppu: Rc<RefCell<PPU>>,
+    ppu: PPU,
+    // The PPU only catches up on the cycles it is owed when its registers are accessed or the
+    // frame it is drawing ends
+    ppu_debt: u64,
+    hdma: Hdma,
+    oam_dma: OamDma,
+    timer: Timer,
+    joypad: Joypad,
+    serial: Serial,
+    // Gets the bytes sent through the link port, kept in serial_output when there is none
+    serial_sink: Option<Rc<RefCell<dyn SerialSink>>>,
+    serial_output: Vec<u8>,
+    // Super Game Boy mode only
+    pub sgb: Sgb,
+    pub cheats: Cheats,
+    // Cycles DMA kept the CPU from running, for the CPU to account for
+    stalled_cycles: u64,
+    infrared: Option<Rc<RefCell<dyn InfraredTransceiver>>>,
+    compatibility_palette: CompatibilityPalette,
+    observers: Vec<Rc<RefCell<dyn BusObserver>>>,
+    peripherals: Vec<MappedPeripheral>,
+    pub code_watch: Option<CodeWatch>,
+    pub oam_access_blocking: bool,
+    // Plain 64 KiB of RAM replacing the whole memory map, for CPU tests
+    flat_memory: Option<RAMBank>,
+    open_bus: OpenBus,
+    // The first access the emulator could not handle since the CPU last checked
+    fault: Option<EmulationError>,
 }
 
 impl Bus {
     pub fn read(&mut self, address: u16) -> u8 {
-        self.get_memory_zone_from_address(address).read(address)
+        let value = if self.oam_blocked(address) || self.oam_dma_blocked(address) { 0xFF } else { self.load(address) };
+        for observer in &self.observers {
+            observer.borrow_mut().on_read(address, value);
+        }
+        value
     }
     pub fn write(&mut self, address: u16, value: u8) {
+        let old_value = if self.observers.is_empty() { 0 } else { self.stored_value(address) };
         if address == 0xFF50 && value == 1 { self.boot_rom_active = false };
-        self.get_memory_zone_from_address(address).write(address, value)
+        match self.peripheral_at(address) {
+            Some(peripheral) => peripheral.borrow_mut().write(address, value),
+            None if self.is_io(address) => self.write_io(address, value),
+            None if self.is_interrupt_enable(address) => self.interrupt_enable = value,
+            None if self.oam_blocked(address) || self.oam_dma_blocked(address) => {}
+            // The boot ROM only hides the cartridge from reads, its MBC still gets the writes
+            None if self.in_boot_rom(address) => self.cartridge.write(address, value),
+            None => self.get_memory_zone_from_address(address).write(address, value),
+        }
+        if let Some(code_watch) = &mut self.code_watch { code_watch.on_write(address); }
+        for observer in &self.observers {
+            observer.borrow_mut().on_write(address, old_value, value);
+        }
+    }
+
+    // Reads without notifying observers, for debugging tools inspecting memory. Looking at
+    // areas that are not emulated does not count as a fault.
+    pub fn peek(&mut self, address: u16) -> u8 {
+        let fault = self.fault.take();
+        let value = self.load(address);
+        self.fault = fault;
+        value
+    }
+
+    fn load(&mut self, address: u16) -> u8 {
+        if let Some(peripheral) = self.peripheral_at(address) {
+            return peripheral.borrow_mut().read(address);
+        }
+        if self.is_io(address) { return self.read_io(address); }
+        if self.is_interrupt_enable(address) { return self.interrupt_enable; }
+        let value = self.get_memory_zone_from_address(address).read(address);
+        if address < VIDEO_RAM_BASE_ADDRESS && !self.cheats.is_empty() && !self.in_boot_rom(address) {
+            return self.cheats.patch_rom_read(address, value);
+        }
+        value
+    }
+
+    fn is_io(&self, address: u16) -> bool {
+        self.flat_memory.is_none() && (IO_PORTS_BASE_ADDRESS..IO_PORTS_BASE_ADDRESS + IO_PORTS_SIZE).contains(&address)
+    }
+
+    // The PPU has OAM to itself while it searches it and draws the line, the CPU reads 0xFF and
+    // its writes are lost. Only with oam_access_blocking, games rarely depend on it.
+    fn oam_blocked(&mut self, address: u16) -> bool {
+        if !self.oam_access_blocking || self.flat_memory.is_some() { return false; }
+        if !(OAM_BASE_ADDRESS..OAM_BASE_ADDRESS + OAM_SIZE).contains(&address) { return false; }
+        self.catch_up_ppu();
+        self.ppu.uses_oam()
+    }
+
+    // Only the IO registers and high RAM are reachable during OAM DMA, reads from elsewhere see
+    // 0xFF and writes are lost
+    fn oam_dma_blocked(&self, address: u16) -> bool {
+        self.oam_dma.active() && address < IO_PORTS_BASE_ADDRESS
+    }
+
+    fn run_oam_dma(&mut self, cycles: u64) {
+        for offset in self.oam_dma.advance(cycles) {
+            let value = self.load(self.oam_dma.source() + offset);
+            self.oam.data[offset as usize] = value;
+            if let Some(code_watch) = &mut self.code_watch { code_watch.on_write(OAM_BASE_ADDRESS + offset); }
+        }
+    }
+
+    // Sprites the PPU picks for a line out of OAM, see PPU::sprites_on_line
+    pub fn sprites_on_line(&self, line: u8) -> Vec<usize> {
+        self.ppu.sprites_on_line(&self.oam.data, line)
+    }
+
+    // IE sits right after high RAM, all of its bits can be written and read back
+    fn is_interrupt_enable(&self, address: u16) -> bool {
+        self.flat_memory.is_none() && address == INTERRUPT_ENABLE_ADDRESS
+    }
+
+    // IO registers belonging to a component are routed to it, the rest are kept by io_ports
+    fn read_io(&mut self, address: u16) -> u8 {
+        match address {
+            // Only the five request bits exist, the others read as 1
+            IO_INTERRUPT_FLAG => self.io_ports.stored(address) | 0xE0,
+            IO_LCD_Y_COORDINATE => { self.catch_up_ppu(); self.ppu.current_line }
+            IO_LCD_STATUS => { self.catch_up_ppu(); self.ppu.status() }
+            IO_LCD_Y_COMPARE => self.ppu.line_compare,
+            _ if is_ppu_register(address) => self.ppu_register(address),
+            IO_DIVIDER..=IO_TIMER_CONTROL => self.read_timer(address),
+            IO_SERIAL_DATA => self.serial.data(),
+            IO_SERIAL_CONTROL => self.serial.control(),
+            IO_OAM_DMA => self.io_ports.stored(address),
+            IO_JOYPAD => self.read_joypad(),
+            _ if is_cgb_register(address) => self.read_cgb_register(address),
+            _ => self.io_ports.read(address).unwrap_or_else(|error| {
+                self.fail(error);
+                0xFF
+            }),
+        }
+    }
+
+    fn write_io(&mut self, address: u16, value: u8) {
+        if is_cgb_register(address) {
+            if self.is_cgb() { self.write_cgb_register(address, value); }
+            return;
+        }
+        // The Super Game Boy listens to the joypad lines as well
+        if address == IO_JOYPAD {
+            if self.mode == HardwareMode::Sgb { self.sgb.write_joypad(value); }
+            self.joypad.write(value);
+            self.request_joypad_interrupts();
+            self.io_ports.poke(address, value);
+            return;
+        }
+        if address == IO_INTERRUPT_FLAG {
+            self.io_ports.poke(address, value & 0x1F);
+            return;
+        }
+        if address == IO_OAM_DMA {
+            self.oam_dma.start(value);
+            self.io_ports.poke(address, value);
+            return;
+        }
+        // Lines drawn so far used the old values
+        if is_ppu_register(address) {
+            self.catch_up_ppu();
+            self.set_ppu_register(address, value);
+            // Turning the LCD on with LY matching LYC can raise the STAT interrupt right away
+            self.request_ppu_interrupts();
+        }
+        match address {
+            IO_DIVIDER => self.timer.reset_divider(),
+            IO_TIMER_COUNTER => self.timer.set_counter(value),
+            IO_TIMER_MODULO => self.timer.set_modulo(value),
+            IO_TIMER_CONTROL => self.timer.set_control(value),
+            IO_SERIAL_DATA => self.serial.set_data(value),
+            IO_SERIAL_CONTROL => self.serial.set_control(value),
+            _ => {}
+        }
+        // Changing the STAT sources or LYC can raise the STAT interrupt right away
+        if address == IO_LCD_STATUS || address == IO_LCD_Y_COMPARE {
+            self.catch_up_ppu();
+            if address == IO_LCD_STATUS { self.ppu.set_status(value) } else { self.ppu.set_line_compare(value) }
+            self.request_ppu_interrupts();
+        }
+        if let Err(error) = self.io_ports.write(address, value) { self.fail(error); }
+    }
+
+    // Current value of an IO register without side effects. Registers that cannot be read yet
+    // report the last value written to them.
+    pub fn inspect_io(&self, address: u16) -> u8 {
+        match address {
+            IO_INTERRUPT_FLAG => self.io_ports.stored(address) | 0xE0,
+            IO_LCD_Y_COORDINATE => self.ppu.line_after(self.ppu_debt),
+            IO_LCD_STATUS => self.ppu.status(),
+            IO_LCD_Y_COMPARE => self.ppu.line_compare,
+            _ if is_ppu_register(address) => self.ppu_register(address),
+            IO_DIVIDER..=IO_TIMER_CONTROL => self.read_timer(address),
+            IO_SERIAL_DATA => self.serial.data(),
+            IO_SERIAL_CONTROL => self.serial.control(),
+            IO_JOYPAD => self.read_joypad(),
+            _ if is_cgb_register(address) => self.read_cgb_register(address),
+            _ => self.io_ports.stored(address),
+        }
+    }
+
+    fn ppu_register(&self, address: u16) -> u8 {
+        match address {
+            IO_LCD_CONTROL => self.ppu.lcd_control(),
+            IO_LCD_SCROLL_Y => self.ppu.bg_scroll_y,
+            IO_LCD_SCROLL_X => self.ppu.bg_scroll_x,
+            IO_LDC_BG_PALETTE_DATA => self.ppu.bg_palette,
+            IO_LCD_OBJ_PALETTE_0_DATA => self.ppu.object_palette_0,
+            IO_LCD_OBJ_PALETTE_1_DATA => self.ppu.object_palette_1,
+            IO_LCD_WINDOW_Y => self.ppu.window_y,
+            IO_LCD_WINDOW_X => self.ppu.window_x,
+            _ => 0xFF,
+        }
+    }
+
+    fn set_ppu_register(&mut self, address: u16, value: u8) {
+        match address {
+            IO_LCD_CONTROL => self.ppu.set_lcd_control(value),
+            IO_LCD_SCROLL_Y => self.ppu.bg_scroll_y = value,
+            IO_LCD_SCROLL_X => self.ppu.bg_scroll_x = value,
+            IO_LDC_BG_PALETTE_DATA => self.ppu.bg_palette = value,
+            IO_LCD_OBJ_PALETTE_0_DATA => self.ppu.object_palette_0 = value,
+            IO_LCD_OBJ_PALETTE_1_DATA => self.ppu.object_palette_1 = value,
+            IO_LCD_WINDOW_Y => self.ppu.window_y = value,
+            IO_LCD_WINDOW_X => self.ppu.window_x = value,
+            _ => {}
+        }
+    }
+
+    fn read_joypad(&self) -> u8 {
+        if self.mode == HardwareMode::Sgb { self.sgb.read_joypad(self.joypad.read()) } else { self.joypad.read() }
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.joypad.buttons()
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        self.joypad.set_buttons(buttons);
+        self.request_joypad_interrupts();
+    }
+
+    fn request_joypad_interrupts(&mut self) {
+        let interrupts = self.joypad.take_interrupts();
+        if interrupts != 0 { self.request_interrupt(interrupts); }
+    }
+
+    pub fn set_serial_sink(&mut self, sink: Option<Rc<RefCell<dyn SerialSink>>>) {
+        self.serial_sink = sink;
+    }
+
+    // Bytes sent through the link port while no sink was set
+    pub fn serial_output(&self) -> &[u8] {
+        &self.serial_output
+    }
+
+    fn finish_serial_transfer(&mut self) {
+        if let Some(byte) = self.serial.take_sent() {
+            match &self.serial_sink {
+                Some(sink) => sink.borrow_mut().receive(byte),
+                None => self.serial_output.push(byte),
+            }
+            self.request_interrupt(INTERRUPT_SERIAL);
+        }
+    }
+
+    fn read_timer(&self, address: u16) -> u8 {
+        match address {
+            IO_DIVIDER => self.timer.divider(),
+            IO_TIMER_COUNTER => self.timer.counter(),
+            IO_TIMER_MODULO => self.timer.modulo(),
+            _ => self.timer.control(),
+        }
+    }
+
+    pub fn mode(&self) -> HardwareMode {
+        self.mode
+    }
+
+    // CGB mode brings 32 KiB of work RAM instead of 8, its contents start over
+    pub fn set_mode(&mut self, mode: HardwareMode) {
+        self.mode = mode;
+        self.work_ram = WorkRAM::new(mode);
+        self.apply_mode_to_ppu();
+    }
+
+    // The PPU draws in color and orders sprites as OPRI says in CGB mode, a new or restored PPU
+    // has to be told again. DMG cartridges get the palettes the CGB boot ROM would set up.
+    fn apply_mode_to_ppu(&mut self) {
+        if self.is_cgb() && self.ppu.color_framebuffer().is_none() { self.ppu.enable_color(); }
+        self.ppu.object_priority = self.object_priority();
+        self.ppu.dmg_compatibility = self.is_cgb() && self.cartridge.cgb_support() == CgbSupport::Unsupported;
+        if self.ppu.dmg_compatibility { self.ppu.palettes.load_compatibility(self.compatibility_palette); }
+    }
+
+    pub fn set_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        self.compatibility_palette = palette;
+        self.apply_mode_to_ppu();
+    }
+
+    fn object_priority(&self) -> ObjectPriority {
+        if self.is_cgb() && self.io_ports.stored(IO_OBJECT_PRIORITY) & 1 == 0 {
+            ObjectPriority::OamIndex
+        } else {
+            ObjectPriority::Coordinate
+        }
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.mode == HardwareMode::Cgb
+    }
+
+    // A DMG has nothing at these addresses, reads float high
+    fn read_cgb_register(&self, address: u16) -> u8 {
+        if !self.is_cgb() { return 0xFF; }
+        match address {
+            IO_VRAM_BANK => 0xFE | self.io_ports.stored(address),
+            IO_WORK_RAM_BANK => 0xF8 | self.io_ports.stored(address),
+            IO_OBJECT_PRIORITY => 0xFE | self.io_ports.stored(address),
+            IO_INFRARED_PORT => self.read_infrared_port(),
+            IO_HDMA_CONTROL => self.hdma.status(),
+            IO_HDMA_SOURCE_HIGH..=IO_HDMA_DESTINATION_LOW => 0xFF,
+            IO_BG_PALETTE_INDEX => self.ppu.palettes.background.specification(),
+            IO_BG_PALETTE_DATA => self.ppu.palettes.background.data(),
+            IO_OBJ_PALETTE_INDEX => self.ppu.palettes.objects.specification(),
+            IO_OBJ_PALETTE_DATA => self.ppu.palettes.objects.data(),
+            _ => self.io_ports.stored(address),
+        }
+    }
+
+    fn write_cgb_register(&mut self, address: u16, value: u8) {
+        match address {
+            IO_VRAM_BANK => self.io_ports.poke(address, value & 0x01),
+            IO_WORK_RAM_BANK => {
+                self.io_ports.poke(address, value & 0x07);
+                self.work_ram.select_bank(value);
+            }
+            IO_INFRARED_PORT => {
+                let led_on = value & 0x01 != 0;
+                if led_on != (self.io_ports.stored(address) & 0x01 != 0) {
+                    if let Some(infrared) = &self.infrared { infrared.borrow_mut().set_led(led_on); }
+                }
+                self.io_ports.poke(address, value & 0xC1);
+            }
+            IO_OBJECT_PRIORITY => {
+                self.catch_up_ppu();
+                self.io_ports.poke(address, value & 0x01);
+                self.ppu.object_priority = self.object_priority();
+            }
+            IO_HDMA_SOURCE_HIGH => self.hdma.set_source_high(value),
+            IO_HDMA_SOURCE_LOW => self.hdma.set_source_low(value),
+            IO_HDMA_DESTINATION_HIGH => self.hdma.set_destination_high(value),
+            IO_HDMA_DESTINATION_LOW => self.hdma.set_destination_low(value),
+            IO_HDMA_CONTROL => {
+                self.catch_up_ppu();
+                self.hdma.last_hblank = self.ppu.hblank_count;
+                for _ in 0..self.hdma.start(value) { self.copy_hdma_block(); }
+            }
+            IO_BG_PALETTE_INDEX => self.ppu.palettes.background.set_specification(value),
+            IO_BG_PALETTE_DATA => { self.catch_up_ppu(); self.ppu.palettes.background.write_data(value); }
+            IO_OBJ_PALETTE_INDEX => self.ppu.palettes.objects.set_specification(value),
+            IO_OBJ_PALETTE_DATA => { self.catch_up_ppu(); self.ppu.palettes.objects.write_data(value); }
+            _ => self.io_ports.poke(address, value),
+        }
+    }
+
+    // RP: the LED in bit 0, bits 6 and 7 enable reading and bit 1 clears while light comes in
+    fn read_infrared_port(&self) -> u8 {
+        let stored = self.io_ports.stored(IO_INFRARED_PORT);
+        let reading = stored & 0xC0 == 0xC0;
+        let receiving = reading && self.infrared.as_ref().is_some_and(|infrared| infrared.borrow_mut().receiving());
+        stored | 0x3C | if receiving { 0x00 } else { 0x02 }
+    }
+
+    pub fn set_infrared_transceiver(&mut self, infrared: Option<Rc<RefCell<dyn InfraredTransceiver>>>) {
+        self.infrared = infrared;
+    }
+
+    fn copy_hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for offset in 0..HDMA_BLOCK_SIZE {
+            let value = self.load(source.wrapping_add(offset));
+            let address = VIDEO_RAM_BASE_ADDRESS + destination + offset;
+            self.get_memory_zone_from_address(address).write(address, value);
+            if let Some(code_watch) = &mut self.code_watch { code_watch.on_write(address); }
+        }
+        self.stalled_cycles += HDMA_BLOCK_CYCLES;
+    }
+
+    // HBlank DMA needs the PPU up to date to see each HBlank as it starts
+    fn run_hblank_dma(&mut self) {
+        self.catch_up_ppu();
+        while self.hdma.hblank_active() && self.hdma.last_hblank < self.ppu.hblank_count {
+            self.hdma.last_hblank += 1;
+            self.copy_hdma_block();
+        }
+    }
+
+    pub fn take_stalled_cycles(&mut self) -> u64 {
+        core::mem::take(&mut self.stalled_cycles)
+    }
+
+    // Selected through VBK (0xFF4F), only its lowest bit is there
+    fn video_ram_bank(&self) -> u8 {
+        if self.is_cgb() { self.io_ports.stored(IO_VRAM_BANK) & 1 } else { 0 }
+    }
+
+    // Debugger writes, going straight to the backing storage so ROM can be patched too.
+    // Observers are not notified.
+    pub fn poke(&mut self, address: u16, value: u8) -> Result<(), String> {
+        if let Some(code_watch) = &mut self.code_watch { code_watch.on_write(address); }
+        if let Some(peripheral) = self.peripheral_at(address) {
+            peripheral.borrow_mut().write(address, value);
+            return Ok(());
+        }
+        if let Some(flat_memory) = &mut self.flat_memory {
+            flat_memory.write(address, value);
+            return Ok(());
+        }
+        let outside_rom = || format!("{:04X} is outside the loaded ROM", address);
+        if self.in_boot_rom(address) {
+            *self.boot_rom.data.get_mut(address as usize).ok_or_else(outside_rom)? = value;
+            return Ok(());
+        }
+        if address < 2 * ROM_BANK_SIZE as u16 {
+            let bank = self.rom_bank_at(address) as usize;
+            let local_address = address as usize % ROM_BANK_SIZE;
+            let byte = self.cartridge.rom_bank_mut(bank)
+                .and_then(|bank| bank.get_mut(local_address))
+                .ok_or_else(outside_rom)?;
+            *byte = value;
+            return Ok(());
+        }
+        if self.is_interrupt_enable(address) {
+            self.interrupt_enable = value;
+            return Ok(());
+        }
+        if self.is_io(address) {
+            self.catch_up_ppu();
+            match address {
+                IO_LCD_Y_COORDINATE => self.ppu.current_line = value,
+                _ => self.set_ppu_register(address, value),
+            }
+            self.io_ports.poke(address, value);
+            return Ok(());
+        }
+        let writable = (VIDEO_RAM_BASE_ADDRESS..VIDEO_RAM_BASE_ADDRESS + VIDEO_RAM_SIZE).contains(&address)
+            || (WORK_RAM_BASE_ADDRESS..WORK_RAM_BASE_ADDRESS + WORK_RAM_BANK_SIZE).contains(&address)
+            || (OAM_BASE_ADDRESS..OAM_BASE_ADDRESS + OAM_SIZE).contains(&address)
+            || (HIGH_RAM_BASE_ADDRESS..HIGH_RAM_BASE_ADDRESS + HIGH_RAM_BANK_SIZE).contains(&address);
+        if !writable { return Err(format!("Cannot write to {:04X}, nothing is mapped there", address)); }
+        self.get_memory_zone_from_address(address).write(address, value);
+        Ok(())
+    }
+
+    // IO registers are inspected since many of them cannot be read yet
+    fn stored_value(&mut self, address: u16) -> u8 {
+        if self.is_io(address) { return self.inspect_io(address); }
+        self.peek(address)
+    }
+
+    pub fn add_observer(&mut self, observer: Rc<RefCell<dyn BusObserver>>) {
+        self.observers.push(observer);
+    }
+
+    pub fn remove_observer(&mut self, observer: &Rc<RefCell<dyn BusObserver>>) {
+        self.observers.retain(|registered| !Rc::ptr_eq(registered, observer));
+    }
+
+    // The peripheral takes over the whole range, which cannot overlap one already mapped
+    pub fn map_peripheral(&mut self, range: RangeInclusive<u16>, peripheral: Rc<RefCell<dyn Peripheral>>) -> Result<(), String> {
+        if range.is_empty() { return Err(String::from("Peripheral address range is empty")); }
+        let overlapping = self.peripherals.iter()
+            .find(|(mapped, _)| range.start() <= mapped.end() && mapped.start() <= range.end());
+        if let Some((mapped, _)) = overlapping {
+            return Err(format!("{:04X}-{:04X} overlaps the peripheral at {:04X}-{:04X}",
+                               range.start(), range.end(), mapped.start(), mapped.end()));
+        }
+        self.peripherals.push((range, peripheral));
+        Ok(())
+    }
+
+    pub fn unmap_peripheral(&mut self, peripheral: &Rc<RefCell<dyn Peripheral>>) {
+        self.peripherals.retain(|(_, mapped)| !Rc::ptr_eq(mapped, peripheral));
+    }
+
+    fn peripheral_at(&self, address: u16) -> Option<Rc<RefCell<dyn Peripheral>>> {
+        self.peripherals.iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(_, peripheral)| Rc::clone(peripheral))
+    }
+
+    pub fn in_boot_rom(&self, address: u16) -> bool {
+        self.boot_rom_active && self.boot_rom.contains(address)
+    }
+
+    // What the CPU sees when running code from an address, see block_cache::Mapping
+    pub fn code_mapping(&self, address: u16) -> (bool, u8) {
+        let bank = match address {
+            0x4000..=0x7FFF => self.cartridge.rom_bank_number() as u8,
+            0x8000..=0x9FFF => self.video_ram_bank(),
+            0xA000..=0xBFFF => self.cartridge.mbc3.as_ref().map_or(0, |mbc3| mbc3.ram_bank()),
+            0xD000..=0xDFFF | 0xF000..=0xFDFF => self.work_ram.bank() as u8,
+            _ => 0,
+        };
+        (self.in_boot_rom(address), bank)
+    }
+
+    pub fn rom_bank_at(&self, address: u16) -> u8 {
+        if (ROM_BANK_SIZE as u16..2 * ROM_BANK_SIZE as u16).contains(&address) { self.cartridge.rom_bank_number() as u8 } else { 0 }
+    }
+
+    // Only cartridges with an MBC3 have a clock, the setting is ignored for the others
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        if let Some(mbc3) = &mut self.cartridge.mbc3 { mbc3.set_rtc_clock(clock); }
+    }
+
+    pub fn save_state(&self) -> BusState {
+        BusState {
+            boot_rom_active: self.boot_rom_active,
+            work_ram: self.work_ram.data.clone(),
+            video_ram: self.video_ram.data.clone(),
+            video_ram_bank_1: if self.is_cgb() { self.video_ram_bank_1.data.clone() } else { vec![] },
+            oam: self.oam.data.clone(),
+            io_ports: self.io_ports.data.clone(),
+            high_ram: self.high_ram.data.clone(),
+            ppu: self.ppu.snapshot(),
+            ppu_debt: self.ppu_debt,
+            hdma: self.hdma.clone(),
+            sgb: self.sgb.clone(),
+            interrupt_enable: self.interrupt_enable,
+            timer: self.timer.clone(),
+            joypad: self.joypad.clone(),
+            serial: self.serial.clone(),
+            oam_dma: self.oam_dma.clone(),
+            mbc3: self.cartridge.mbc3.clone(),
+        }
+    }
+
+    pub fn restore_state(&mut self, state: BusState) -> Result<(), String> {
+        let video_ram_bank_1 = if state.video_ram_bank_1.is_empty() { vec![0; VIDEO_RAM_SIZE as usize] } else { state.video_ram_bank_1 };
+        let zones = [
+            (&mut self.work_ram.data, state.work_ram, "work RAM"),
+            (&mut self.video_ram.data, state.video_ram, "video RAM"),
+            (&mut self.video_ram_bank_1.data, video_ram_bank_1, "video RAM bank 1"),
+            (&mut self.oam.data, state.oam, "OAM"),
+            (&mut self.io_ports.data, state.io_ports, "IO ports"),
+            (&mut self.high_ram.data, state.high_ram, "high RAM"),
+        ];
+        for (zone, data, name) in &zones {
+            if zone.len() != data.len() {
+                return Err(format!("Saved {} has {} bytes instead of {}", name, data.len(), zone.len()));
+            }
+        }
+        for (zone, data, _) in zones {
+            *zone = data;
+        }
+        self.boot_rom_active = state.boot_rom_active;
+        self.ppu = state.ppu;
+        self.ppu_debt = state.ppu_debt;
+        self.hdma = state.hdma;
+        self.sgb = state.sgb;
+        self.interrupt_enable = state.interrupt_enable;
+        self.timer = state.timer;
+        self.joypad.restore(&state.joypad);
+        self.serial = state.serial;
+        self.oam_dma = state.oam_dma;
+        self.restore_mbc3(state.mbc3.as_ref());
+        self.work_ram.select_bank(self.io_ports.stored(IO_WORK_RAM_BANK));
+        self.apply_mode_to_ppu();
+        Ok(())
+    }
+
+    // Restores a state taken from this same bus, copying into the existing memory
+    pub fn copy_state_from(&mut self, state: &BusState) {
+        self.work_ram.data.copy_from_slice(&state.work_ram);
+        self.video_ram.data.copy_from_slice(&state.video_ram);
+        if state.video_ram_bank_1.is_empty() {
+            self.video_ram_bank_1.data.fill(0);
+        } else {
+            self.video_ram_bank_1.data.copy_from_slice(&state.video_ram_bank_1);
+        }
+        self.oam.data.copy_from_slice(&state.oam);
+        self.io_ports.data.copy_from_slice(&state.io_ports);
+        self.high_ram.data.copy_from_slice(&state.high_ram);
+        self.boot_rom_active = state.boot_rom_active;
+        self.ppu = state.ppu.snapshot();
+        self.ppu_debt = state.ppu_debt;
+        self.hdma = state.hdma.clone();
+        self.sgb = state.sgb.clone();
+        self.interrupt_enable = state.interrupt_enable;
+        self.timer = state.timer.clone();
+        self.joypad.restore(&state.joypad);
+        self.serial = state.serial.clone();
+        self.oam_dma = state.oam_dma.clone();
+        self.restore_mbc3(state.mbc3.as_ref());
+        self.work_ram.select_bank(self.io_ports.stored(IO_WORK_RAM_BANK));
+        self.apply_mode_to_ppu();
+    }
+
+    // States from before MBCs were emulated have none, the cartridge is left as it is then
+    fn restore_mbc3(&mut self, saved: Option<&Mbc3>) {
+        if let (Some(mbc3), Some(saved)) = (&mut self.cartridge.mbc3, saved) { mbc3.restore(saved); }
+    }
+
+    // Back to power-on: boot ROM mapped, IO registers cleared and RAM filled by the given function.
+    // The cartridge is kept.
+    pub fn reset(&mut self, mut ram_fill: impl FnMut() -> u8) {
+        for zone in [&mut self.work_ram.data, &mut self.video_ram.data, &mut self.video_ram_bank_1.data, &mut self.oam.data, &mut self.high_ram.data] {
+            zone.iter_mut().for_each(|byte| *byte = ram_fill());
+        }
+        self.io_ports.data.iter_mut().for_each(|byte| *byte = 0);
+        self.work_ram.select_bank(0);
+        self.boot_rom_active = true;
+        self.ppu = PPU::new();
+        self.ppu_debt = 0;
+        self.hdma = Hdma::default();
+        self.sgb = Sgb::new();
+        self.interrupt_enable = 0;
+        self.timer = Timer::default();
+        self.joypad = Joypad::default();
+        self.serial = Serial::default();
+        self.serial_output.clear();
+        self.oam_dma = OamDma::default();
+        if let Some(mbc3) = &mut self.cartridge.mbc3 { mbc3.reset(); }
+        self.stalled_cycles = 0;
+        self.fault = None;
+        self.apply_mode_to_ppu();
+    }
+
+    // Bits 0 to 4 of IF: VBlank, STAT, timer, serial and joypad
+    pub fn request_interrupt(&mut self, interrupts: u8) {
+        let requested = self.io_ports.stored(IO_INTERRUPT_FLAG);
+        self.io_ports.poke(IO_INTERRUPT_FLAG, requested | (interrupts & 0x1F));
+    }
+
+    fn request_ppu_interrupts(&mut self) {
+        let interrupts = self.ppu.take_interrupts();
+        if interrupts != 0 { self.request_interrupt(interrupts); }
+    }
+
+    // The CPU took the interrupt, its request is cleared
+    pub fn acknowledge_interrupt(&mut self, interrupt: u8) {
+        let requested = self.io_ports.stored(IO_INTERRUPT_FLAG);
+        self.io_ports.poke(IO_INTERRUPT_FLAG, requested & !interrupt);
+    }
+
+    // Requested interrupts that are also enabled, whether or not the CPU takes them
+    pub fn pending_interrupts(&self) -> u8 {
+        self.io_ports.stored(IO_INTERRUPT_FLAG) & self.interrupt_enable & 0x1F
+    }
+
+    // Cycles until something could happen without the CPU doing anything, the end of the frame
+    // being drawn at the latest
+    pub fn cycles_until_next_event(&self) -> u64 {
+        self.ppu.cycles_until_interrupt().saturating_sub(self.ppu_debt)
+            .min(self.timer.cycles_until_interrupt())
+            .min(self.serial.cycles_until_interrupt())
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.ppu_debt += cycles;
+        // Frames are counted when VBlank starts, so the count is always up to date. The PPU's
+        // interrupts are requested on time the same way.
+        if self.ppu_debt >= self.ppu.cycles_until_interrupt() { self.catch_up_ppu(); }
+        if self.hdma.hblank_active() { self.run_hblank_dma(); }
+        if self.oam_dma.active() { self.run_oam_dma(cycles); }
+        // The timer is cheap to run, so it never owes cycles
+        scheduler::run_for(&mut [&mut self.timer], cycles);
+        let interrupts = self.timer.take_interrupts();
+        if interrupts != 0 { self.request_interrupt(interrupts); }
+        scheduler::run_for(&mut [&mut self.serial], cycles);
+        self.finish_serial_transfer();
+        if let Some(mbc3) = &mut self.cartridge.mbc3 { mbc3.tick(cycles); }
+        for (_, peripheral) in &self.peripherals {
+            peripheral.borrow_mut().tick(cycles);
+        }
+    }
+
+    pub fn catch_up_ppu(&mut self) {
+        let frame = self.ppu.frame_count;
+        self.ppu.run(self.ppu_debt, &self.video_ram.data, &self.video_ram_bank_1.data, &self.oam.data);
+        self.ppu_debt = 0;
+        self.request_ppu_interrupts();
+        if self.ppu.frame_count != frame && !self.cheats.is_empty() { self.apply_ram_cheats(); }
+    }
+
+    // GameShark codes are written at every VBlank, banked ones straight into their work RAM bank
+    fn apply_ram_cheats(&mut self) {
+        let patches: Vec<_> = self.cheats.ram_patches().collect();
+        for (bank, address, value) in patches {
+            match bank {
+                Some(bank) if address >= 0xD000 => {
+                    let offset = bank.max(1) as usize * WORK_RAM_SWITCHABLE_BANK_SIZE + (address - 0xD000) as usize;
+                    if let Some(byte) = self.work_ram.data.get_mut(offset) { *byte = value; }
+                    if let Some(code_watch) = &mut self.code_watch { code_watch.on_write(address); }
+                }
+                _ => self.write(address, value),
+            }
+        }
+    }
+
+    pub fn start_ppu_timeline(&mut self, frames: u64) {
+        self.catch_up_ppu();
+        self.ppu.start_timeline(frames);
+    }
+
+    pub fn take_ppu_timeline(&mut self) -> Option<Timeline> {
+        self.catch_up_ppu();
+        self.ppu.take_timeline()
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        self.ppu.framebuffer()
+    }
+
+    pub fn color_framebuffer(&self) -> Option<&[u16]> {
+        self.ppu.color_framebuffer()
+    }
+
+    pub fn set_skip_drawing(&mut self, skip: bool) {
+        self.ppu.skip_drawing = skip;
     }
 
-    pub fn cycle(&mut self) {
-        self.ppu.borrow_mut().cycle();
+    pub fn frame_count(&self) -> u64 {
+        self.ppu.frame_count
     }
 
     fn new_video_ram() -> RAMBank {
@@ -72,10 +939,10 @@ impl Bus {
         }
     }
 
-    fn new_work_ram() -> RAMBank {
+    fn new_oam() -> RAMBank {
         RAMBank {
-            base_address: WORK_RAM_BASE_ADDRESS,
-            data: vec![0; WORK_RAM_BANK_SIZE as usize]
+            base_address: OAM_BASE_ADDRESS,
+            data: vec![0; OAM_SIZE as usize]
         }
     }
 
@@ -87,51 +954,125 @@ impl Bus {
     }
 
     pub fn new (boot_rom: BootROM, cartridge: Cartridge, ppu: PPU) -> Bus {
-        let ppu_ref = Rc::new(RefCell::new(ppu));
-        let io_ports = IOPorts::new(Rc::clone(&ppu_ref));
         Bus {
+            mode: HardwareMode::Dmg,
             boot_rom_active: true,
             boot_rom,
             cartridge,
-            work_ram: Bus::new_work_ram(),
+            work_ram: WorkRAM::new(HardwareMode::Dmg),
             video_ram: Bus::new_video_ram(),
-            io_ports,
+            video_ram_bank_1: Bus::new_video_ram(),
+            oam: Bus::new_oam(),
+            io_ports: IOPorts::new(),
             high_ram: Bus::new_high_ram(),
-            ppu: Rc::clone(&ppu_ref),
+            interrupt_enable: 0,
+            ppu,
+            ppu_debt: 0,
+            hdma: Hdma::default(),
+            oam_dma: OamDma::default(),
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            serial: Serial::default(),
+            serial_sink: None,
+            serial_output: vec![],
+            sgb: Sgb::new(),
+            cheats: Cheats::default(),
+            stalled_cycles: 0,
+            infrared: None,
+            compatibility_palette: CompatibilityPalette::default(),
+            observers: vec![],
+            peripherals: vec![],
+            code_watch: None,
+            oam_access_blocking: false,
+            flat_memory: None,
+            open_bus: OpenBus,
+            fault: None,
         }
     }
 
     pub fn new_from_vecs(boot_rom_data: Vec<u8>, cart_rom_bank_zero_data: Vec<u8>) -> Bus {
-        let boot_rom = BootROM{data: boot_rom_data};
-        let ppu: PPU = PPU::new();
-        let ppu_ref = Rc::new(RefCell::new(ppu));
-        let io_ports = IOPorts::new(Rc::clone(&ppu_ref));
+        let boot_rom = BootROM{data: boot_rom_data, variant: BootRomVariant::Dmg};
         Bus {
+            mode: HardwareMode::Dmg,
             boot_rom_active: true,
             boot_rom,
             cartridge: Cartridge::new_dummy_cartridge(cart_rom_bank_zero_data),
-            work_ram: Bus::new_work_ram(),
+            work_ram: WorkRAM::new(HardwareMode::Dmg),
             video_ram: Bus::new_video_ram(),
-            io_ports,
+            video_ram_bank_1: Bus::new_video_ram(),
+            oam: Bus::new_oam(),
+            io_ports: IOPorts::new(),
             high_ram: Bus::new_high_ram(),
-            ppu: Rc::clone(&ppu_ref),
+            interrupt_enable: 0,
+            ppu: PPU::new(),
+            ppu_debt: 0,
+            hdma: Hdma::default(),
+            oam_dma: OamDma::default(),
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            serial: Serial::default(),
+            serial_sink: None,
+            serial_output: vec![],
+            sgb: Sgb::new(),
+            cheats: Cheats::default(),
+            stalled_cycles: 0,
+            infrared: None,
+            compatibility_palette: CompatibilityPalette::default(),
+            observers: vec![],
+            peripherals: vec![],
+            code_watch: None,
+            oam_access_blocking: false,
+            flat_memory: None,
+            open_bus: OpenBus,
+            fault: None,
         }
     }
 
-    fn get_memory_zone_from_address(&mut self, address: u16) -> Box<&mut MemoryZone> {
-        if self.boot_rom_active && address < BOOT_ROM_SIZE as u16 { return Box::new(&mut self.boot_rom) };
-        if address < ROM_BANK_SIZE as u16 { return Box::new(&mut self.cartridge.rom_banks[0])};
-        if address < (ROM_BANK_SIZE * 2) as u16 { panic!("Rom banking not implemented"); };
-        if address < 0xA000 { return Box::new(&mut self.video_ram); };
-        if address < 0xC000 { panic!("External ram not implemented"); };
-        if address < 0xE000 { return Box::new(&mut self.work_ram); };
-        if address >= IO_PORTS_BASE_ADDRESS && address < IO_PORTS_BASE_ADDRESS + IO_PORTS_SIZE {
-            return Box::new(&mut self.io_ports);
+    // Every address reads and writes the given RAM, IO registers and ROM included
+    pub fn new_flat(memory: Vec<u8>) -> Bus {
+        assert_eq!(memory.len(), 0x10000, "Flat memory must cover the whole address space");
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.boot_rom_active = false;
+        bus.flat_memory = Some(RAMBank { base_address: 0, data: memory });
+        bus
+    }
+
+    fn get_memory_zone_from_address(&mut self, address: u16) -> &mut dyn MemoryZone {
+        if let Some(area) = self.unemulated_area(address) {
+            self.fail(EmulationError::UnemulatedMemory { area, address });
+            return &mut self.open_bus;
         }
-        if address >= HIGH_RAM_BASE_ADDRESS && address < HIGH_RAM_BASE_ADDRESS + HIGH_RAM_BANK_SIZE {
-            return Box::new(&mut self.high_ram);
+        let video_ram_bank = self.video_ram_bank();
+        if let Some(flat_memory) = &mut self.flat_memory { return flat_memory; }
+        if self.boot_rom_active && self.boot_rom.contains(address) { return &mut self.boot_rom };
+        if address < VIDEO_RAM_BASE_ADDRESS { return &mut self.cartridge};
+        if address < 0xA000 {
+            return if video_ram_bank == 1 { &mut self.video_ram_bank_1 } else { &mut self.video_ram };
+        };
+        if address < WORK_RAM_BASE_ADDRESS { return &mut self.cartridge; }
+        // Echo RAM included
+        if address < OAM_BASE_ADDRESS { return &mut self.work_ram; };
+        if (OAM_BASE_ADDRESS..OAM_BASE_ADDRESS + OAM_SIZE).contains(&address) { return &mut self.oam; }
+        // Nothing answers between OAM and the IO registers, which is harmless
+        if address < IO_PORTS_BASE_ADDRESS { return &mut self.open_bus; }
+        &mut self.high_ram
+    }
+
+    fn unemulated_area(&self, address: u16) -> Option<&'static str> {
+        if self.flat_memory.is_some() { return None; }
+        match address {
+            0xA000..=0xBFFF if self.cartridge.mbc3.is_none() => Some("External RAM"),
+            _ => None,
         }
-        panic!("Invalid bus address {:#02X?}", address);
+    }
+
+    // Keeps the first fault, later ones are usually a consequence of it
+    pub(crate) fn fail(&mut self, error: EmulationError) {
+        if self.fault.is_none() { self.fault = Some(error); }
+    }
+
+    pub fn take_fault(&mut self) -> Option<EmulationError> {
+        self.fault.take()
     }
 }
 
@@ -156,23 +1097,240 @@ mod tests {
         bus.video_ram.data[0x12] = 0xFF;
         assert_eq!(bus.get_memory_zone_from_address(0x8012).read(0x8012), 0xFF);
     }
+    #[test]
+    fn get_oam_zone() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.oam.data[0x9F] = 0xFF;
+        assert_eq!(bus.get_memory_zone_from_address(0xFE9F).read(0xFE9F), 0xFF);
+    }
+    #[test]
+    fn echo_ram_and_unusable_area() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xE123, 0x12);
+        assert_eq!(bus.read(0xC123), 0x12);
+        bus.write(0xDDFF, 0x34);
+        assert_eq!(bus.read(0xFDFF), 0x34);
+        bus.write(0xFEA0, 0x56);
+        assert_eq!(bus.read(0xFEA0), 0xFF);
+        assert_eq!(bus.read(0xFEFF), 0xFF);
+        assert!(bus.take_fault().is_none());
+    }
+
+    #[test]
+    fn unemulated_areas_fault() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        assert_eq!(bus.peek(0xA000), 0xFF);
+        assert!(bus.take_fault().is_none());
+        bus.write(0xA000, 0x12);
+        assert_eq!(bus.read(0xA001), 0xFF);
+        assert!(matches!(bus.take_fault(), Some(EmulationError::UnemulatedMemory { area: "External RAM", address: 0xA000 })));
+        assert!(bus.take_fault().is_none());
+    }
 
     #[test]
     fn read_ff44_lcdc_y_coordinate() {
         let mut bus = Bus::new_from_vecs(vec![], vec![]);
-        bus.ppu.borrow_mut().current_line = 123;
+        bus.ppu.current_line = 123;
         assert_eq!(bus.read(0xFF44), 123);
 
     }
 
+    #[derive(Default)]
+    struct RecordingObserver { reads: Vec<(u16, u8)>, writes: Vec<(u16, u8, u8)> }
+
+    impl BusObserver for RecordingObserver {
+        fn on_read(&mut self, address: u16, value: u8) { self.reads.push((address, value)); }
+        fn on_write(&mut self, address: u16, old_value: u8, new_value: u8) {
+            self.writes.push((address, old_value, new_value));
+        }
+    }
+
+    #[test]
+    fn observers_see_accesses() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        let recorder = Rc::new(RefCell::new(RecordingObserver::default()));
+        let observer: Rc<RefCell<dyn BusObserver>> = recorder.clone();
+        bus.add_observer(Rc::clone(&observer));
+        bus.write(0xC000, 0x12);
+        bus.write(0xC000, 0x34);
+        assert_eq!(bus.read(0xC000), 0x34);
+        assert_eq!(bus.peek(0xC000), 0x34);
+        assert_eq!(recorder.borrow().writes, vec![(0xC000, 0x00, 0x12), (0xC000, 0x12, 0x34)]);
+        assert_eq!(recorder.borrow().reads, vec![(0xC000, 0x34)]);
+
+        bus.remove_observer(&observer);
+        bus.read(0xC000);
+        assert_eq!(recorder.borrow().reads.len(), 1);
+    }
+
+    #[derive(Default)]
+    struct DebugPort { written: Vec<u8>, ticks: u64 }
+
+    impl Peripheral for DebugPort {
+        fn read(&mut self, address: u16) -> u8 { address as u8 }
+        fn write(&mut self, _address: u16, value: u8) { self.written.push(value); }
+        fn tick(&mut self, cycles: u64) { self.ticks += cycles; }
+    }
+
+    #[test]
+    fn peripherals_claim_addresses() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        let port = Rc::new(RefCell::new(DebugPort::default()));
+        let peripheral: Rc<RefCell<dyn Peripheral>> = port.clone();
+        assert_eq!(bus.map_peripheral(0xA000..=0xA0FF, Rc::clone(&peripheral)), Ok(()));
+        assert!(bus.map_peripheral(0xA0FF..=0xA100, Rc::clone(&peripheral)).is_err());
+        assert_eq!(bus.read(0xA012), 0x12);
+        bus.write(0xA000, 0x34);
+        assert_eq!(bus.poke(0xA001, 0x56), Ok(()));
+        bus.advance(4);
+        bus.advance(8);
+        assert_eq!(port.borrow().written, vec![0x34, 0x56]);
+        assert_eq!(port.borrow().ticks, 12);
+        // Addresses outside the range keep going to memory
+        bus.write(0xC000, 0x78);
+        assert_eq!(bus.read(0xC000), 0x78);
+
+        bus.unmap_peripheral(&peripheral);
+        assert!(bus.poke(0xA000, 0).is_err());
+    }
+
+    #[test]
+    fn ppu_catches_up_on_demand() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF40, 0x80);
+        bus.advance(456 * 3 + 10);
+        assert_eq!(bus.ppu.current_line, 0);
+        assert_eq!(bus.inspect_io(0xFF44), 3);
+        assert_eq!(bus.read(0xFF44), 3);
+        assert_eq!(bus.ppu.current_line, 3);
+        // Reaching VBlank always catches up so the frame count is exact
+        bus.advance(456 * 141 - 11);
+        assert_eq!(bus.frame_count(), 0);
+        bus.advance(1);
+        assert_eq!(bus.frame_count(), 1);
+        assert_eq!(bus.ppu.current_line, 144);
+    }
+
+    #[test]
+    fn poke_patches_rom_and_ram() {
+        let mut bus = Bus::new_from_vecs(vec![0x12], vec![0x34, 0x56]);
+        assert_eq!(bus.poke(0x0000, 0xAA), Ok(()));
+        assert_eq!(bus.boot_rom.data[0], 0xAA);
+        assert!(bus.poke(0x0001, 0xBB).is_err());
+        bus.boot_rom_active = false;
+        assert_eq!(bus.poke(0x0001, 0xBB), Ok(()));
+        assert_eq!(bus.peek(0x0001), 0xBB);
+        assert_eq!(bus.poke(0xFF42, 7), Ok(()));
+        assert_eq!(bus.ppu.bg_scroll_y, 7);
+        assert_eq!(bus.poke(0xC000, 9), Ok(()));
+        assert_eq!(bus.peek(0xC000), 9);
+        assert!(bus.poke(0x0100, 0).is_err());
+        assert!(bus.poke(0x4000, 0).is_err());
+        assert!(bus.poke(0xA000, 0).is_err());
+        assert!(bus.poke(0xFEA0, 0).is_err());
+    }
+
+    #[test]
+    fn only_poke_patches_rom() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![0x34, 0x56]);
+        bus.boot_rom_active = false;
+        bus.write(0x0001, 0xBB);
+        assert_eq!(bus.read(0x0001), 0x56);
+        assert_eq!(bus.poke(0x0001, 0xBB), Ok(()));
+        assert_eq!(bus.read(0x0001), 0xBB);
+    }
+
+    // ROM+MBC3+TIMER+RAM+BATT with 4 ROM banks and 32 KiB of RAM, each bank starting with its number
+    fn mbc3_bus() -> Bus {
+        let mut rom = vec![0; 4 * ROM_BANK_SIZE];
+        rom[0x0147] = 0x10;
+        rom[0x0148] = 0x01;
+        rom[0x0149] = 0x03;
+        for bank in 1..4 { rom[bank * ROM_BANK_SIZE] = bank as u8; }
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.cartridge = Cartridge::from_data(rom).unwrap();
+        bus.boot_rom_active = false;
+        bus
+    }
+
+    #[test]
+    fn mbc3_switches_banks() {
+        let mut bus = mbc3_bus();
+        assert_eq!(bus.read(0x4000), 1);
+        bus.write(0x2000, 3);
+        assert_eq!(bus.read(0x4000), 3);
+        assert_eq!(bus.rom_bank_at(0x4000), 3);
+        assert_eq!(bus.code_mapping(0x4000), (false, 3));
+        bus.write(0x2000, 0);
+        assert_eq!(bus.read(0x4000), 1);
+
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, 2);
+        bus.write(0xA000, 0x44);
+        bus.write(0x4000, 0);
+        assert_eq!(bus.read(0xA000), 0);
+        bus.write(0x4000, 2);
+        assert_eq!(bus.read(0xA000), 0x44);
+        assert!(bus.take_fault().is_none());
+
+        // The RAM is kept by the battery, the registers start over
+        bus.reset(|| 0);
+        bus.boot_rom_active = false;
+        assert_eq!(bus.read(0xA000), 0xFF);
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, 2);
+        assert_eq!(bus.read(0xA000), 0x44);
+    }
+
+    #[test]
+    fn mbc3_clock_runs_with_the_bus() {
+        let mut bus = mbc3_bus();
+        bus.write(0x0000, 0x0A);
+        bus.write(0x4000, rtc::RTC_SECONDS);
+        bus.advance(3 * 4_194_304);
+        assert_eq!(bus.read(0xA000), 0);
+        bus.write(0x6000, 0);
+        bus.write(0x6000, 1);
+        assert_eq!(bus.read(0xA000), 3);
+    }
+
+    #[test]
+    fn flat_memory() {
+        let mut memory = vec![0; 0x10000];
+        memory[0x4000] = 0x12;
+        let mut bus = Bus::new_flat(memory);
+        assert_eq!(bus.read(0x4000), 0x12);
+        bus.write(0xFF44, 0x34);
+        assert_eq!(bus.read(0xFF44), 0x34);
+        bus.write(0xFFFF, 0x1F);
+        assert_eq!(bus.peek(0xFFFF), 0x1F);
+    }
+
     #[test]
     fn write_ff50_disable_boot_rom() {
         let mut bus = Bus::new_from_vecs(vec![0x12], vec![0x34]);
         assert_eq!(bus.read(0x0000), 0x12);
-        assert_eq!(bus.boot_rom_active, true);
+        assert!(bus.boot_rom_active);
         bus.write(0xFF50, 1);
-        assert_eq!(bus.boot_rom_active, false);
+        assert!(!bus.boot_rom_active);
         assert_eq!(bus.read(0x0000), 0x34);
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn oam_access_blocking() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        // Turning the LCD on starts with the OAM search
+        bus.write(0xFF40, 0x80);
+        bus.write(0xFE04, 16);
+        assert_eq!(bus.read(0xFE04), 16);
+        bus.oam_access_blocking = true;
+        assert_eq!(bus.read(0xFE04), 0xFF);
+        bus.write(0xFE04, 20);
+        assert_eq!(bus.peek(0xFE04), 16);
+        bus.advance(80 + 172);
+        assert_eq!(bus.read(0xFE04), 16);
+        assert_eq!(bus.sprites_on_line(0), vec![1]);
+        assert!(bus.sprites_on_line(8).is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+use core::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+pub const OAM_DMA_BYTES: u16 = 0xA0;
+// One byte per machine cycle
+const CYCLES_PER_BYTE: u64 = 4;
+
+// Copies 160 bytes from XX00-XX9F into OAM once XX is written to 0xFF46. While it runs the CPU
+// can only get at the IO registers and high RAM, which is why games run the routine starting it
+// from there.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct OamDma {
+    source: u16,
+    active: bool,
+    // Cycles since the transfer started
+    elapsed: u64,
+    copied: u16,
+}
+
+impl OamDma {
+    pub fn start(&mut self, value: u8) {
+        self.source = (value as u16) << 8;
+        self.active = true;
+        self.elapsed = 0;
+        self.copied = 0;
+    }
+
+    pub fn active(&self) -> bool {
+        self.active
+    }
+
+    pub fn source(&self) -> u16 {
+        self.source
+    }
+
+    // Offsets into OAM of the bytes to copy now that more cycles have passed
+    pub fn advance(&mut self, cycles: u64) -> Range<u16> {
+        if !self.active { return 0..0; }
+        self.elapsed += cycles;
+        let first = self.copied;
+        self.copied = (self.elapsed / CYCLES_PER_BYTE).min(OAM_DMA_BYTES as u64) as u16;
+        if self.copied == OAM_DMA_BYTES { self.active = false; }
+        first..self.copied
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+    use crate::cpu::register::DMGRegister;
+
+    #[test]
+    fn copies_a_byte_per_machine_cycle() {
+        let mut dma = OamDma::default();
+        assert_eq!(dma.advance(100), 0..0);
+        dma.start(0xC1);
+        assert_eq!(dma.source(), 0xC100);
+        assert_eq!(dma.advance(6), 0..1);
+        assert_eq!(dma.advance(6), 1..3);
+        assert!(dma.active());
+        assert_eq!(dma.advance(1000), 3..OAM_DMA_BYTES);
+        assert!(!dma.active());
+    }
+
+    #[test]
+    fn only_high_ram_is_reachable_during_the_transfer() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xC000, 0x12);
+        bus.write(0xFF46, 0xC0);
+        assert_eq!(bus.read(0xFF46), 0xC0);
+        assert_eq!(bus.read(0xC000), 0xFF);
+        bus.write(0xFF80, 0x34);
+        assert_eq!(bus.read(0xFF80), 0x34);
+        bus.advance(640);
+        assert_eq!(bus.read(0xC000), 0x12);
+        assert_eq!(bus.read(0xFE00), 0x12);
+    }
+
+    #[test]
+    fn routine_in_high_ram() {
+        // LD A,$C0; CALL $FF80
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x3E, 0xC0, 0xCD, 0x80, 0xFF], vec![]));
+        cpu.stack_pointer.write(0xFFFE);
+        // LDH ($46),A; LD A,$28; DEC A; JR NZ,-3; RET
+        for (offset, byte) in [0xE0, 0x46, 0x3E, 0x28, 0x3D, 0x20, 0xFD, 0xC9].iter().enumerate() {
+            cpu.bus.write(0xFF80 + offset as u16, *byte);
+        }
+        for offset in 0..OAM_DMA_BYTES { cpu.bus.write(0xC000 + offset, offset as u8 ^ 0x5A); }
+        while cpu.program_counter.read() != 0x0005 { cpu.step().unwrap(); }
+        assert!(!cpu.bus.oam_dma.active());
+        assert!((0..OAM_DMA_BYTES).all(|offset| cpu.bus.oam.data[offset as usize] == offset as u8 ^ 0x5A));
+    }
+}
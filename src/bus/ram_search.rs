@@ -0,0 +1,92 @@
+use core::ops::RangeInclusive;
+
+use crate::prelude::*;
+
+// Where games keep their variables: work RAM and high RAM
+const SEARCHED_RANGES: [RangeInclusive<u16>; 2] = [0xC000..=0xDFFF, 0xFF80..=0xFFFE];
+
+// How a candidate's value has to compare to the one it had at the previous snapshot, or to a
+// given value, to stay a candidate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchFilter {
+    Equal(u8),
+    Greater,
+    Less,
+    Changed,
+    Unchanged,
+}
+
+impl SearchFilter {
+    fn keeps(self, previous: u8, current: u8) -> bool {
+        match self {
+            SearchFilter::Equal(value) => current == value,
+            SearchFilter::Greater => current > previous,
+            SearchFilter::Less => current < previous,
+            SearchFilter::Changed => current != previous,
+            SearchFilter::Unchanged => current == previous,
+        }
+    }
+}
+
+// Narrows RAM down to the addresses behaving like a value in the game, e.g. one that went down
+// every time a life was lost. Every filter takes a new snapshot to compare the next one with.
+pub struct RamSearch {
+    // Address and value at the last snapshot
+    candidates: Vec<(u16, u8)>,
+}
+
+impl RamSearch {
+    pub fn start(mut read: impl FnMut(u16) -> u8) -> RamSearch {
+        let candidates = SEARCHED_RANGES.iter().cloned().flatten().map(|address| (address, read(address))).collect();
+        RamSearch { candidates }
+    }
+
+    // Returns how many candidates are left
+    pub fn filter(&mut self, mut read: impl FnMut(u16) -> u8, filter: SearchFilter) -> usize {
+        self.candidates.retain_mut(|(address, value)| {
+            let current = read(*address);
+            let keep = filter.keeps(*value, current);
+            *value = current;
+            keep
+        });
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[(u16, u8)] {
+        &self.candidates
+    }
+}
+
+// GameShark code holding an address at a value, for freezing what a search found
+pub fn game_shark_code(address: u16, value: u8) -> String {
+    let [low, high] = address.to_le_bytes();
+    format!("01{:02X}{:02X}{:02X}", value, low, high)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrows_down_to_a_counter() {
+        let mut memory = vec![0u8; 0x10000];
+        memory[0xC123] = 3;
+        memory[0xD000] = 3;
+        let mut search = RamSearch::start(|address| memory[address as usize]);
+        assert_eq!(search.candidates().len(), 0x2000 + 0x7F);
+        assert_eq!(search.filter(|address| memory[address as usize], SearchFilter::Equal(3)), 2);
+        memory[0xC123] = 2;
+        memory[0xD000] = 4;
+        assert_eq!(search.filter(|address| memory[address as usize], SearchFilter::Less), 1);
+        assert_eq!(search.filter(|address| memory[address as usize], SearchFilter::Unchanged), 1);
+        memory[0xC123] = 1;
+        assert_eq!(search.filter(|address| memory[address as usize], SearchFilter::Changed), 1);
+        assert_eq!(search.candidates(), &[(0xC123, 1)]);
+        assert_eq!(search.filter(|address| memory[address as usize], SearchFilter::Greater), 0);
+    }
+
+    #[test]
+    fn freezing_code() {
+        assert_eq!(game_shark_code(0xC6A2, 0x09), "0109A2C6");
+    }
+}
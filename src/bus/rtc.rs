@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+
+// The RTC runs from its own 32768 Hz crystal, a second is as long as this many CPU cycles
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+const DAY_COUNTER_LIMIT: u16 = 512;
+
+pub const RTC_SECONDS: u8 = 0x08;
+pub const RTC_MINUTES: u8 = 0x09;
+pub const RTC_HOURS: u8 = 0x0A;
+pub const RTC_DAY_LOW: u8 = 0x0B;
+pub const RTC_DAY_HIGH: u8 = 0x0C;
+
+const DAY_HIGH_BIT_8: u8 = 0x01;
+const DAY_HIGH_HALT: u8 = 0x40;
+const DAY_HIGH_CARRY: u8 = 0x80;
+
+// What makes the clock tick. Emulated time follows the CPU cycles, so it runs fast while fast
+// forwarding and goes back with save states. The host clock follows the wall clock instead, given
+// as seconds since some fixed point, so the game's clock stays right whatever the emulator does.
+#[derive(Clone, Copy, Default)]
+pub enum RtcClock {
+    #[default]
+    Emulated,
+    Host(fn() -> u64),
+}
+
+#[cfg(feature = "std")]
+impl RtcClock {
+    pub fn host() -> RtcClock {
+        RtcClock::Host(|| {
+            std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+        })
+    }
+}
+
+// The MBC3 real-time clock: seconds, minutes, hours and a 9 bit day counter that sets a carry
+// when it overflows. Games read a copy of the counters taken when they latch the clock.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    latched: [u8; 5],
+    // Cycles toward the next second with the emulated clock
+    cycles: u64,
+    // Host clock reading the counters were last brought up to, None until the host clock is used
+    host_time: Option<u64>,
+    // A setting of the frontend rather than part of the cartridge, kept by save states
+    #[serde(skip)]
+    clock: RtcClock,
+}
+
+impl Rtc {
+    pub fn clock(&self) -> RtcClock {
+        self.clock
+    }
+
+    // The counters carry on from where they are, only the time source changes
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.clock = clock;
+        self.host_time = match clock {
+            RtcClock::Host(now) => Some(now()),
+            RtcClock::Emulated => None,
+        };
+    }
+
+    // Takes the counters of a saved clock, keeping the time source. With the host clock they
+    // catch up with the time that passed since the save on the next access.
+    pub fn restore(&mut self, saved: &Rtc) {
+        *self = Rtc { clock: self.clock, ..saved.clone() };
+    }
+
+    pub fn tick(&mut self, cycles: u64) {
+        if !matches!(self.clock, RtcClock::Emulated) || self.halted { return; }
+        self.cycles += cycles;
+        if self.cycles >= CYCLES_PER_SECOND {
+            self.add_seconds(self.cycles / CYCLES_PER_SECOND);
+            self.cycles %= CYCLES_PER_SECOND;
+        }
+    }
+
+    // Copies the counters to the registers games read
+    pub fn latch(&mut self) {
+        self.catch_up_with_host();
+        self.latched = [self.seconds, self.minutes, self.hours, self.days as u8, self.day_high()];
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        match register {
+            RTC_SECONDS..=RTC_DAY_HIGH => self.latched[(register - RTC_SECONDS) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    // Writes go to the counters themselves, setting the seconds starts the second over
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.catch_up_with_host();
+        match register {
+            RTC_SECONDS => {
+                self.seconds = value & 0x3F;
+                self.cycles = 0;
+            }
+            RTC_MINUTES => self.minutes = value & 0x3F,
+            RTC_HOURS => self.hours = value & 0x1F,
+            RTC_DAY_LOW => self.days = (self.days & 0x100) | value as u16,
+            RTC_DAY_HIGH => {
+                self.days = (self.days & 0xFF) | ((value & DAY_HIGH_BIT_8) as u16) << 8;
+                self.halted = value & DAY_HIGH_HALT != 0;
+                self.day_carry = value & DAY_HIGH_CARRY != 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn day_high(&self) -> u8 {
+        (self.days >> 8) as u8 | if self.halted { DAY_HIGH_HALT } else { 0 } | if self.day_carry { DAY_HIGH_CARRY } else { 0 }
+    }
+
+    // Time the host clock moved on while the RTC was halted is lost, like on a real cartridge
+    fn catch_up_with_host(&mut self) {
+        let now = match self.clock {
+            RtcClock::Host(now) => now(),
+            RtcClock::Emulated => return,
+        };
+        let elapsed = self.host_time.map_or(0, |host_time| now.saturating_sub(host_time));
+        if !self.halted { self.add_seconds(elapsed); }
+        self.host_time = Some(now);
+    }
+
+    fn add_seconds(&mut self, seconds: u64) {
+        let seconds = self.seconds as u64 + seconds;
+        let minutes = self.minutes as u64 + seconds / 60;
+        let hours = self.hours as u64 + minutes / 60;
+        let days = self.days as u64 + hours / 24;
+        self.seconds = (seconds % 60) as u8;
+        self.minutes = (minutes % 60) as u8;
+        self.hours = (hours % 24) as u8;
+        if days >= DAY_COUNTER_LIMIT as u64 { self.day_carry = true; }
+        self.days = (days % DAY_COUNTER_LIMIT as u64) as u16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU64, Ordering};
+
+    static HOST_TIME: AtomicU64 = AtomicU64::new(1_000_000);
+
+    fn host_time() -> u64 {
+        HOST_TIME.load(Ordering::Relaxed)
+    }
+
+    fn latched(rtc: &mut Rtc) -> [u8; 5] {
+        rtc.latch();
+        [RTC_SECONDS, RTC_MINUTES, RTC_HOURS, RTC_DAY_LOW, RTC_DAY_HIGH].map(|register| rtc.read(register))
+    }
+
+    #[test]
+    fn emulated_clock_counts_cycles() {
+        let mut rtc = Rtc::default();
+        rtc.tick(CYCLES_PER_SECOND - 1);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, 0]);
+        rtc.tick(1);
+        assert_eq!(latched(&mut rtc), [1, 0, 0, 0, 0]);
+        rtc.tick(CYCLES_PER_SECOND * (59 + 59 * 60 + 23 * 3600));
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn latched_registers_hold_still() {
+        let mut rtc = Rtc::default();
+        rtc.latch();
+        rtc.tick(CYCLES_PER_SECOND * 5);
+        assert_eq!(rtc.read(RTC_SECONDS), 0);
+        rtc.latch();
+        assert_eq!(rtc.read(RTC_SECONDS), 5);
+    }
+
+    #[test]
+    fn halt_and_day_carry() {
+        let mut rtc = Rtc::default();
+        rtc.write(RTC_DAY_LOW, 0xFF);
+        rtc.write(RTC_DAY_HIGH, DAY_HIGH_BIT_8 | DAY_HIGH_HALT);
+        rtc.write(RTC_HOURS, 23);
+        rtc.write(RTC_MINUTES, 59);
+        rtc.write(RTC_SECONDS, 59);
+        rtc.tick(CYCLES_PER_SECOND * 10);
+        assert_eq!(latched(&mut rtc), [59, 59, 23, 0xFF, DAY_HIGH_BIT_8 | DAY_HIGH_HALT]);
+        rtc.write(RTC_DAY_HIGH, DAY_HIGH_BIT_8);
+        rtc.tick(CYCLES_PER_SECOND);
+        assert_eq!(latched(&mut rtc), [0, 0, 0, 0, DAY_HIGH_CARRY]);
+    }
+
+    #[test]
+    fn host_clock_ignores_cycles() {
+        let mut rtc = Rtc::default();
+        rtc.set_clock(RtcClock::Host(host_time));
+        rtc.tick(CYCLES_PER_SECOND * 100);
+        assert_eq!(latched(&mut rtc)[0], 0);
+        HOST_TIME.fetch_add(61, Ordering::Relaxed);
+        assert_eq!(latched(&mut rtc), [1, 1, 0, 0, 0]);
+
+        // A restored copy catches up with the time that passed since it was taken
+        let saved = rtc.clone();
+        let mut restored = Rtc::default();
+        restored.set_clock(RtcClock::Host(host_time));
+        restored.restore(&saved);
+        HOST_TIME.fetch_add(3600, Ordering::Relaxed);
+        assert_eq!(latched(&mut rtc), [1, 1, 1, 0, 0]);
+        assert_eq!(latched(&mut restored), [1, 1, 1, 0, 0]);
+    }
+}
@@ -0,0 +1,49 @@
+// Components are no longer ticked one T-cycle at a time. Each one reports how far away its next
+// event is (a PPU mode or line change, later timer overflows or the end of a DMA transfer) and
+// the scheduler moves all of them forward in chunks that never step over any of those events.
+pub trait Component {
+    // Cycles until the component next changes state in a way the rest of the console can see
+    fn cycles_until_event(&self) -> u64;
+    // Moves the component forward, never past its next event
+    fn advance(&mut self, cycles: u64);
+}
+
+pub fn run_for(components: &mut [&mut dyn Component], cycles: u64) {
+    let mut remaining = cycles;
+    while remaining > 0 {
+        let step = components.iter()
+            .map(|component| component.cycles_until_event())
+            .fold(remaining, u64::min);
+        for component in components.iter_mut() {
+            component.advance(step);
+        }
+        remaining -= step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    // Has an event every period cycles and remembers the chunks it was advanced by
+    struct Periodic { period: u64, elapsed: u64, steps: Vec<u64> }
+
+    impl Component for Periodic {
+        fn cycles_until_event(&self) -> u64 { self.period - self.elapsed % self.period }
+        fn advance(&mut self, cycles: u64) {
+            assert!(cycles <= self.cycles_until_event());
+            self.elapsed += cycles;
+            self.steps.push(cycles);
+        }
+    }
+
+    #[test]
+    fn stops_at_every_event() {
+        let mut three = Periodic { period: 3, elapsed: 0, steps: vec![] };
+        let mut five = Periodic { period: 5, elapsed: 0, steps: vec![] };
+        run_for(&mut [&mut three, &mut five], 11);
+        assert_eq!(three.steps, vec![3, 2, 1, 3, 1, 1]);
+        assert_eq!((three.elapsed, five.elapsed), (11, 11));
+    }
+}
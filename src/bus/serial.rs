@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+
+use super::scheduler::Component;
+
+// Eight bits at 8192 Hz with the internal clock
+const TRANSFER_CYCLES: u64 = 8 * 512;
+const SC_START: u8 = 0x80;
+const SC_INTERNAL_CLOCK: u8 = 0x01;
+
+// Whatever is plugged into the link port. There is never another console on the other end, so
+// the game always receives 0xFF, the sink only gets to see what was sent.
+pub trait SerialSink {
+    fn receive(&mut self, byte: u8);
+}
+
+// SB and SC. Only transfers clocked by this console ever finish, with the external clock they
+// wait for a partner that is not there.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Serial {
+    data: u8,
+    control: u8,
+    // Cycles until the byte in SB is sent, 0 when no transfer runs
+    remaining_cycles: u64,
+    // A transfer finished since the bus last took the byte sent
+    finished: bool,
+}
+
+impl Serial {
+    pub fn data(&self) -> u8 {
+        self.data
+    }
+
+    // Bits 1 to 6 do not exist on the DMG and read as 1
+    pub fn control(&self) -> u8 {
+        self.control | 0x7E
+    }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.data = value;
+    }
+
+    pub fn set_control(&mut self, value: u8) {
+        self.control = value & (SC_START | SC_INTERNAL_CLOCK);
+        self.remaining_cycles = if self.control == SC_START | SC_INTERNAL_CLOCK { TRANSFER_CYCLES } else { 0 };
+    }
+
+    // The byte sent by a transfer that just finished, for the bus to hand to the sink and request
+    // the serial interrupt
+    pub fn take_sent(&mut self) -> Option<u8> {
+        if !core::mem::take(&mut self.finished) { return None; }
+        let sent = self.data;
+        self.data = 0xFF;
+        Some(sent)
+    }
+
+    pub fn cycles_until_interrupt(&self) -> u64 {
+        if self.remaining_cycles > 0 { self.remaining_cycles } else { u64::MAX }
+    }
+}
+
+impl Component for Serial {
+    fn cycles_until_event(&self) -> u64 {
+        self.cycles_until_interrupt()
+    }
+
+    fn advance(&mut self, cycles: u64) {
+        if self.remaining_cycles == 0 { return; }
+        self.remaining_cycles -= cycles;
+        if self.remaining_cycles == 0 {
+            self.control &= !SC_START;
+            self.finished = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::scheduler::run_for;
+
+    #[test]
+    fn internal_clock_transfer() {
+        let mut serial = Serial::default();
+        serial.set_data(b'P');
+        serial.set_control(0x81);
+        assert_eq!(serial.control(), 0xFF);
+        run_for(&mut [&mut serial], TRANSFER_CYCLES - 1);
+        assert_eq!(serial.take_sent(), None);
+        run_for(&mut [&mut serial], 1);
+        assert_eq!(serial.control(), 0x7F);
+        assert_eq!(serial.take_sent(), Some(b'P'));
+        assert_eq!(serial.data(), 0xFF);
+        assert_eq!(serial.take_sent(), None);
+    }
+
+    #[test]
+    fn external_clock_never_finishes() {
+        let mut serial = Serial::default();
+        serial.set_control(0x80);
+        assert_eq!(serial.cycles_until_interrupt(), u64::MAX);
+        run_for(&mut [&mut serial], TRANSFER_CYCLES * 10);
+        assert_eq!(serial.take_sent(), None);
+        assert_eq!(serial.control(), 0xFE);
+    }
+}
@@ -0,0 +1,381 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+pub const SGB_PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = SGB_PACKET_SIZE * 8;
+const SCREEN_TILES_WIDE: usize = 20;
+const SCREEN_TILES_HIGH: usize = 18;
+const SGB_PALETTES: usize = 4;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const MLT_REQ: u8 = 0x11;
+
+// A rectangle of ATTR_BLK, each area gets its palette if its bit is set in the control code
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttributeBlock {
+    pub inside: Option<u8>,
+    pub border: Option<u8>,
+    pub outside: Option<u8>,
+    pub left: u8,
+    pub top: u8,
+    pub right: u8,
+    pub bottom: u8,
+}
+
+// A command decoded from one or more packets, the first byte of which holds the command code
+// in its upper five bits and the number of packets in the lower three
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SgbCommand {
+    // PAL01, PAL23, PAL03 and PAL12: color 0 shared by every palette, then colors 1-3 of two of them
+    Palettes { first: usize, second: usize, color0: u16, colors: [[u16; 3]; 2] },
+    AttributeBlocks(Vec<AttributeBlock>),
+    // Whole rows (horizontal) or columns, as (horizontal, index, palette)
+    AttributeLines(Vec<(bool, u8, u8)>),
+    // Splits the screen at a row (horizontal) or column
+    AttributeDivide { horizontal: bool, coordinate: u8, before: u8, on: u8, after: u8 },
+    // Palettes for consecutive tiles starting at a tile, along rows or down columns (vertical)
+    AttributeCharacters { x: u8, y: u8, vertical: bool, palettes: Vec<u8> },
+    MultiplayerRequest { players: u8 },
+    Unsupported { code: u8 },
+}
+
+impl SgbCommand {
+    pub fn decode(data: &[u8]) -> SgbCommand {
+        let byte = |index: usize| data.get(index).copied().unwrap_or(0);
+        let color = |index: usize| u16::from_le_bytes([byte(index), byte(index + 1)]);
+        let code = byte(0) >> 3;
+        match code {
+            PAL01 | PAL23 | PAL03 | PAL12 => {
+                let (first, second) = [(0, 1), (2, 3), (0, 3), (1, 2)][code as usize];
+                let colors = [[color(3), color(5), color(7)], [color(9), color(11), color(13)]];
+                SgbCommand::Palettes { first, second, color0: color(1), colors }
+            }
+            ATTR_BLK => {
+                let count = (byte(1) as usize).min(0x12);
+                let blocks = (0..count).map(|set| {
+                    let offset = 2 + set * 6;
+                    let (control, palettes) = (byte(offset), byte(offset + 1));
+                    let palette = |bit: u8, shift: u8| if control & bit != 0 { Some((palettes >> shift) & 0x03) } else { None };
+                    let (inside, mut border, outside) = (palette(0x01, 0), palette(0x02, 2), palette(0x04, 4));
+                    // With only the inside or the outside changed, the border goes along with it
+                    if border.is_none() && inside.is_some() != outside.is_some() { border = inside.or(outside); }
+                    AttributeBlock {
+                        inside, border, outside,
+                        left: byte(offset + 2) & 0x1F, top: byte(offset + 3) & 0x1F,
+                        right: byte(offset + 4) & 0x1F, bottom: byte(offset + 5) & 0x1F,
+                    }
+                }).collect();
+                SgbCommand::AttributeBlocks(blocks)
+            }
+            ATTR_LIN => {
+                let count = (byte(1) as usize).min(110);
+                let lines = (0..count).map(|line| {
+                    let line = byte(2 + line);
+                    (line & 0x80 != 0, line & 0x1F, (line >> 5) & 0x03)
+                }).collect();
+                SgbCommand::AttributeLines(lines)
+            }
+            ATTR_DIV => SgbCommand::AttributeDivide {
+                horizontal: byte(1) & 0x40 != 0,
+                coordinate: byte(2) & 0x1F,
+                before: (byte(1) >> 2) & 0x03,
+                on: (byte(1) >> 4) & 0x03,
+                after: byte(1) & 0x03,
+            },
+            ATTR_CHR => {
+                let count = (color(3) as usize).min(SCREEN_TILES_WIDE * SCREEN_TILES_HIGH);
+                let palettes = (0..count).map(|tile| (byte(6 + tile / 4) >> (6 - 2 * (tile % 4))) & 0x03).collect();
+                SgbCommand::AttributeCharacters { x: byte(1) & 0x1F, y: byte(2) & 0x1F, vertical: byte(5) & 1 != 0, palettes }
+            }
+            MLT_REQ => SgbCommand::MultiplayerRequest { players: [1, 2, 1, 4][(byte(1) & 0x03) as usize] },
+            _ => SgbCommand::Unsupported { code },
+        }
+    }
+}
+
+// The Super Game Boy listens to the joypad register: games pulse P14 and P15 low together to
+// start a packet, then send its 128 bits and a zero stop bit one at a time, P14 low for a 0
+// and P15 low for a 1, with both high in between. It answers MLT_REQ by reporting the joypad
+// being read in the low nibble of P1 while neither line is selected, which is how games
+// detect it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Sgb {
+    receiving: bool,
+    // A bit is only taken after both lines went high again
+    bit_ready: bool,
+    bits: usize,
+    packet: [u8; SGB_PACKET_SIZE],
+    // Packets of a command sent over several, the first one says how many
+    packets: Vec<u8>,
+    pub palettes: [[u16; 4]; SGB_PALETTES],
+    // Palette of every tile of the screen, row by row
+    pub attributes: Vec<u8>,
+    players: u8,
+    player: u8,
+    last_joypad_write: u8,
+    // Commands the game sent that are decoded but not acted on, for tools to report
+    #[serde(default)]
+    unsupported_commands: u64,
+    #[serde(default)]
+    last_unsupported_command: Option<u8>,
+}
+
+impl Default for Sgb {
+    fn default() -> Sgb { Sgb::new() }
+}
+
+impl Sgb {
+    pub fn new() -> Sgb {
+        Sgb {
+            receiving: false,
+            bit_ready: false,
+            bits: 0,
+            packet: [0; SGB_PACKET_SIZE],
+            packets: vec![],
+            palettes: [[0; 4]; SGB_PALETTES],
+            attributes: vec![0; SCREEN_TILES_WIDE * SCREEN_TILES_HIGH],
+            players: 1,
+            player: 0,
+            last_joypad_write: 0x30,
+            unsupported_commands: 0,
+            last_unsupported_command: None,
+        }
+    }
+
+    // Watches the P14 and P15 lines written to P1
+    pub fn write_joypad(&mut self, value: u8) {
+        let lines = value & 0x30;
+        let previous = self.last_joypad_write;
+        self.last_joypad_write = lines;
+        match lines {
+            0x00 => {
+                self.receiving = true;
+                self.bit_ready = false;
+                self.bits = 0;
+                self.packet = [0; SGB_PACKET_SIZE];
+            }
+            0x30 => {
+                self.bit_ready = true;
+                // The next joypad is read every time P15 goes back up
+                if !self.receiving && previous & 0x20 == 0 && self.players > 1 {
+                    self.player = (self.player + 1) % self.players;
+                }
+            }
+            _ if self.receiving && self.bit_ready => {
+                self.bit_ready = false;
+                self.receive_bit(lines == 0x10);
+            }
+            _ => {}
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) {
+        if self.bits == PACKET_BITS {
+            self.receiving = false;
+            // A packet without its zero stop bit is dropped
+            if !bit { self.receive_packet(); }
+            return;
+        }
+        if bit { self.packet[self.bits / 8] |= 1 << (self.bits % 8); }
+        self.bits += 1;
+    }
+
+    fn receive_packet(&mut self) {
+        self.packets.extend_from_slice(&self.packet);
+        let length = (self.packets[0] & 0x07).max(1) as usize;
+        if self.packets.len() >= length * SGB_PACKET_SIZE {
+            let command = SgbCommand::decode(&self.packets);
+            self.packets.clear();
+            self.apply(command);
+        }
+    }
+
+    pub fn apply(&mut self, command: SgbCommand) {
+        match command {
+            SgbCommand::Palettes { first, second, color0, colors } => {
+                for palette in self.palettes.iter_mut() { palette[0] = color0; }
+                self.palettes[first][1..].copy_from_slice(&colors[0]);
+                self.palettes[second][1..].copy_from_slice(&colors[1]);
+            }
+            SgbCommand::AttributeBlocks(blocks) => {
+                for block in blocks {
+                    for y in 0..SCREEN_TILES_HIGH as u8 {
+                        for x in 0..SCREEN_TILES_WIDE as u8 {
+                            let within = x >= block.left && x <= block.right && y >= block.top && y <= block.bottom;
+                            let on_edge = x == block.left || x == block.right || y == block.top || y == block.bottom;
+                            let palette = match (within, on_edge) {
+                                (true, true) => block.border,
+                                (true, false) => block.inside,
+                                (false, _) => block.outside,
+                            };
+                            if let Some(palette) = palette { self.set_attribute(x, y, palette); }
+                        }
+                    }
+                }
+            }
+            SgbCommand::AttributeLines(lines) => {
+                for (horizontal, index, palette) in lines {
+                    if horizontal {
+                        for x in 0..SCREEN_TILES_WIDE as u8 { self.set_attribute(x, index, palette); }
+                    } else {
+                        for y in 0..SCREEN_TILES_HIGH as u8 { self.set_attribute(index, y, palette); }
+                    }
+                }
+            }
+            SgbCommand::AttributeDivide { horizontal, coordinate, before, on, after } => {
+                for y in 0..SCREEN_TILES_HIGH as u8 {
+                    for x in 0..SCREEN_TILES_WIDE as u8 {
+                        let position = if horizontal { y } else { x };
+                        let palette = match position.cmp(&coordinate) {
+                            core::cmp::Ordering::Less => before,
+                            core::cmp::Ordering::Equal => on,
+                            core::cmp::Ordering::Greater => after,
+                        };
+                        self.set_attribute(x, y, palette);
+                    }
+                }
+            }
+            SgbCommand::AttributeCharacters { x, y, vertical, palettes } => {
+                let (mut x, mut y) = (x as usize % SCREEN_TILES_WIDE, y as usize % SCREEN_TILES_HIGH);
+                for palette in palettes {
+                    self.set_attribute(x as u8, y as u8, palette);
+                    if vertical {
+                        y += 1;
+                        if y == SCREEN_TILES_HIGH { y = 0; x = (x + 1) % SCREEN_TILES_WIDE; }
+                    } else {
+                        x += 1;
+                        if x == SCREEN_TILES_WIDE { x = 0; y = (y + 1) % SCREEN_TILES_HIGH; }
+                    }
+                }
+            }
+            SgbCommand::MultiplayerRequest { players } => {
+                self.players = players;
+                self.player = 0;
+            }
+            SgbCommand::Unsupported { code } => {
+                self.unsupported_commands += 1;
+                self.last_unsupported_command = Some(code);
+            }
+        }
+    }
+
+    fn set_attribute(&mut self, x: u8, y: u8, palette: u8) {
+        if (x as usize) < SCREEN_TILES_WIDE && (y as usize) < SCREEN_TILES_HIGH {
+            self.attributes[y as usize * SCREEN_TILES_WIDE + x as usize] = palette;
+        }
+    }
+
+    pub fn unsupported_commands(&self) -> u64 {
+        self.unsupported_commands
+    }
+
+    pub fn last_unsupported_command(&self) -> Option<u8> {
+        self.last_unsupported_command
+    }
+
+    // With neither line selected the low nibble holds 0xF for the first joypad, 0xE for the second...
+    pub fn read_joypad(&self, stored: u8) -> u8 {
+        if stored & 0x30 == 0x30 { (stored & 0xF0) | (0x0F - self.player) } else { stored }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::{Bus, HardwareMode};
+
+    fn send_packet(bus: &mut Bus, packet: &[u8; SGB_PACKET_SIZE]) {
+        bus.write(0xFF00, 0x00);
+        bus.write(0xFF00, 0x30);
+        for index in 0..PACKET_BITS {
+            let bit = packet[index / 8] >> (index % 8) & 1;
+            bus.write(0xFF00, if bit == 1 { 0x10 } else { 0x20 });
+            bus.write(0xFF00, 0x30);
+        }
+        bus.write(0xFF00, 0x20);
+        bus.write(0xFF00, 0x30);
+    }
+
+    fn sgb_bus() -> Bus {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Sgb);
+        bus
+    }
+
+    #[test]
+    fn multiplayer_request_changes_the_reported_joypad() {
+        let mut bus = sgb_bus();
+        bus.write(0xFF00, 0x30);
+        assert_eq!(bus.read(0xFF00) & 0x0F, 0x0F);
+        let mut packet = [0; SGB_PACKET_SIZE];
+        packet[0] = MLT_REQ << 3 | 1;
+        packet[1] = 0x01;
+        send_packet(&mut bus, &packet);
+        // Pulsing P15 low moves on to the second joypad
+        bus.write(0xFF00, 0x10);
+        bus.write(0xFF00, 0x30);
+        assert_eq!(bus.read(0xFF00) & 0x0F, 0x0E);
+        bus.write(0xFF00, 0x10);
+        bus.write(0xFF00, 0x30);
+        assert_eq!(bus.read(0xFF00) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn palettes() {
+        let mut bus = sgb_bus();
+        let mut packet = [0; SGB_PACKET_SIZE];
+        packet[0] = PAL12 << 3 | 1;
+        packet[1..15].copy_from_slice(&[0xFF, 0x7F, 1, 0, 2, 0, 3, 0, 4, 0, 5, 0, 6, 0]);
+        send_packet(&mut bus, &packet);
+        assert_eq!(bus.sgb.palettes[1], [0x7FFF, 1, 2, 3]);
+        assert_eq!(bus.sgb.palettes[2], [0x7FFF, 4, 5, 6]);
+        assert_eq!(bus.sgb.palettes[0], [0x7FFF, 0, 0, 0]);
+    }
+
+    #[test]
+    fn attribute_commands() {
+        let mut sgb = Sgb::new();
+        // Inside only, the border follows
+        sgb.apply(SgbCommand::decode(&[ATTR_BLK << 3 | 1, 1, 0x01, 0x02, 1, 1, 3, 3]));
+        assert_eq!(&sgb.attributes[SCREEN_TILES_WIDE..SCREEN_TILES_WIDE + 5], &[0, 2, 2, 2, 0]);
+        sgb.apply(SgbCommand::decode(&[ATTR_LIN << 3 | 1, 1, 0x80 | 0x60 | 17]));
+        assert!(sgb.attributes[17 * SCREEN_TILES_WIDE..].iter().all(|palette| *palette == 3));
+        sgb.apply(SgbCommand::decode(&[ATTR_DIV << 3 | 1, 0x01 | 0x08 | 0x30, 10]));
+        assert_eq!((sgb.attributes[9], sgb.attributes[10], sgb.attributes[11]), (2, 3, 1));
+        sgb.apply(SgbCommand::decode(&[ATTR_CHR << 3 | 1, 19, 0, 2, 0, 0, 0b0111_0000]));
+        assert_eq!((sgb.attributes[19], sgb.attributes[20]), (1, 3));
+    }
+
+    #[test]
+    fn unsupported_commands_are_counted() {
+        let mut sgb = Sgb::new();
+        assert_eq!((sgb.unsupported_commands(), sgb.last_unsupported_command()), (0, None));
+        sgb.apply(SgbCommand::decode(&[0x19 << 3 | 1]));
+        sgb.apply(SgbCommand::decode(&[0x17 << 3 | 1]));
+        assert_eq!((sgb.unsupported_commands(), sgb.last_unsupported_command()), (2, Some(0x17)));
+    }
+
+    #[test]
+    fn commands_spanning_packets() {
+        let mut bus = sgb_bus();
+        let mut first = [0; SGB_PACKET_SIZE];
+        first[0] = ATTR_LIN << 3 | 2;
+        first[1] = 15;
+        for (line, byte) in first[2..].iter_mut().enumerate() { *byte = 0x20 | line as u8; }
+        send_packet(&mut bus, &first);
+        assert_eq!(bus.sgb.attributes[0], 0);
+        let mut second = [0; SGB_PACKET_SIZE];
+        second[0] = 0x20 | 14;
+        send_packet(&mut bus, &second);
+        assert!((0..15).all(|x| bus.sgb.attributes[x] == 1));
+        assert_eq!(bus.sgb.attributes[15], 0);
+    }
+}
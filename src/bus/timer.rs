@@ -0,0 +1,228 @@
+use serde::{Deserialize, Serialize};
+
+use super::scheduler::Component;
+use super::INTERRUPT_TIMER;
+
+// TIMA only starts over from TMA, and requests its interrupt, 4 cycles after overflowing. It
+// reads as 0 in between.
+const RELOAD_DELAY: u8 = 4;
+const TAC_ENABLE: u8 = 0x04;
+
+// DIV, TIMA, TMA and TAC. DIV is the upper byte of a counter running every cycle, TIMA goes up
+// whenever the counter bit picked by TAC falls. Writing DIV or TAC can make that bit fall too,
+// which games notice as an extra TIMA increment.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Timer {
+    counter: u16,
+    counter_register: u8,
+    modulo: u8,
+    control: u8,
+    // Cycles left until TIMA is reloaded after overflowing, 0 when no reload is pending
+    reload_delay: u8,
+    // IF bits requested since the bus last took them
+    requested_interrupts: u8,
+}
+
+impl Timer {
+    pub fn divider(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    pub fn counter(&self) -> u8 {
+        self.counter_register
+    }
+
+    pub fn modulo(&self) -> u8 {
+        self.modulo
+    }
+
+    // Only the lower 3 bits exist, the others read as 1
+    pub fn control(&self) -> u8 {
+        self.control | 0xF8
+    }
+
+    pub fn reset_divider(&mut self) {
+        let signal = self.signal();
+        self.counter = 0;
+        if signal { self.increment(); }
+    }
+
+    // Writing TIMA while a reload is pending cancels it
+    pub fn set_counter(&mut self, value: u8) {
+        self.counter_register = value;
+        self.reload_delay = 0;
+    }
+
+    pub fn set_modulo(&mut self, value: u8) {
+        self.modulo = value;
+    }
+
+    pub fn set_control(&mut self, value: u8) {
+        let signal = self.signal();
+        self.control = value & 0x07;
+        if signal && !self.signal() { self.increment(); }
+    }
+
+    // IF bits for the bus to request, cleared once taken
+    pub fn take_interrupts(&mut self) -> u8 {
+        core::mem::take(&mut self.requested_interrupts)
+    }
+
+    // Cycles until TIMA overflows and the timer interrupt is requested, u64::MAX while stopped
+    pub fn cycles_until_interrupt(&self) -> u64 {
+        if self.reload_delay > 0 { return self.reload_delay as u64; }
+        if !self.enabled() { return u64::MAX; }
+        let increments_left = 0xFF - self.counter_register as u64;
+        self.cycles_until_increment() + increments_left * self.period() + RELOAD_DELAY as u64
+    }
+
+    fn enabled(&self) -> bool {
+        self.control & TAC_ENABLE != 0
+    }
+
+    // TIMA goes up when this counter bit falls: 4096, 262144, 65536 or 16384 Hz
+    fn period(&self) -> u64 {
+        match self.control & 0x03 {
+            0 => 1024,
+            1 => 16,
+            2 => 64,
+            _ => 256,
+        }
+    }
+
+    fn signal(&self) -> bool {
+        self.enabled() && self.counter as u64 & (self.period() / 2) != 0
+    }
+
+    fn cycles_until_increment(&self) -> u64 {
+        self.period() - self.counter as u64 % self.period()
+    }
+
+    fn increment(&mut self) {
+        if self.counter_register == 0xFF {
+            self.counter_register = 0;
+            self.reload_delay = RELOAD_DELAY;
+        } else {
+            self.counter_register += 1;
+        }
+    }
+}
+
+impl Component for Timer {
+    fn cycles_until_event(&self) -> u64 {
+        let reload = if self.reload_delay > 0 { self.reload_delay as u64 } else { u64::MAX };
+        let increment = if self.enabled() { self.cycles_until_increment() } else { u64::MAX };
+        reload.min(increment)
+    }
+
+    fn advance(&mut self, cycles: u64) {
+        self.counter = self.counter.wrapping_add(cycles as u16);
+        if self.reload_delay > 0 {
+            self.reload_delay -= cycles as u8;
+            if self.reload_delay == 0 {
+                self.counter_register = self.modulo;
+                self.requested_interrupts |= INTERRUPT_TIMER;
+            }
+        }
+        if self.enabled() && (self.counter as u64).is_multiple_of(self.period()) { self.increment(); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::scheduler::run_for;
+
+    fn timer(control: u8) -> Timer {
+        let mut timer = Timer::default();
+        timer.set_control(control);
+        timer
+    }
+
+    #[test]
+    fn divider_counts_every_256_cycles() {
+        let mut timer = Timer::default();
+        run_for(&mut [&mut timer], 255);
+        assert_eq!(timer.divider(), 0);
+        run_for(&mut [&mut timer], 1);
+        assert_eq!(timer.divider(), 1);
+        run_for(&mut [&mut timer], 256 * 255);
+        assert_eq!(timer.divider(), 0);
+        assert_eq!(timer.counter(), 0);
+    }
+
+    #[test]
+    fn counter_frequencies() {
+        for (control, period) in [(0x04, 1024), (0x05, 16), (0x06, 64), (0x07, 256)] {
+            let mut timer = timer(control);
+            run_for(&mut [&mut timer], period * 10 - 1);
+            assert_eq!(timer.counter(), 9);
+            run_for(&mut [&mut timer], 1);
+            assert_eq!(timer.counter(), 10);
+        }
+    }
+
+    #[test]
+    fn overflow_reloads_after_a_delay() {
+        let mut timer = timer(0x05);
+        timer.set_modulo(0xF0);
+        timer.set_counter(0xFF);
+        run_for(&mut [&mut timer], 16);
+        assert_eq!(timer.counter(), 0);
+        assert_eq!(timer.take_interrupts(), 0);
+        assert_eq!(timer.cycles_until_interrupt(), 4);
+        run_for(&mut [&mut timer], 3);
+        assert_eq!(timer.counter(), 0);
+        run_for(&mut [&mut timer], 1);
+        assert_eq!(timer.counter(), 0xF0);
+        assert_eq!(timer.take_interrupts(), INTERRUPT_TIMER);
+        assert_eq!(timer.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn writing_the_counter_cancels_the_reload() {
+        let mut timer = timer(0x05);
+        timer.set_modulo(0xF0);
+        timer.set_counter(0xFF);
+        run_for(&mut [&mut timer], 17);
+        timer.set_counter(0x42);
+        run_for(&mut [&mut timer], 3);
+        assert_eq!(timer.counter(), 0x42);
+        assert_eq!(timer.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn resetting_the_divider_can_increment_the_counter() {
+        let mut timer = timer(0x05);
+        run_for(&mut [&mut timer], 7);
+        timer.reset_divider();
+        assert_eq!(timer.counter(), 0);
+        run_for(&mut [&mut timer], 8);
+        timer.reset_divider();
+        assert_eq!(timer.counter(), 1);
+        assert_eq!(timer.divider(), 0);
+    }
+
+    #[test]
+    fn disabling_can_increment_the_counter() {
+        let mut timer = timer(0x05);
+        run_for(&mut [&mut timer], 8);
+        timer.set_control(0x01);
+        assert_eq!(timer.counter(), 1);
+        assert_eq!(timer.control(), 0xF9);
+        run_for(&mut [&mut timer], 1024);
+        assert_eq!(timer.counter(), 1);
+    }
+
+    #[test]
+    fn cycles_until_interrupt() {
+        let mut timer = timer(0x04);
+        assert_eq!(timer.cycles_until_interrupt(), 256 * 1024 + 4);
+        timer.set_counter(0xFE);
+        run_for(&mut [&mut timer], 1000);
+        assert_eq!(timer.cycles_until_interrupt(), 24 + 1024 + 4);
+        run_for(&mut [&mut timer], 24 + 1024 + 4);
+        assert_eq!(timer.take_interrupts(), INTERRUPT_TIMER);
+        assert_eq!(Timer::default().cycles_until_interrupt(), u64::MAX);
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+
+pub const WORK_RAM_SWITCHABLE_BANK_SIZE: usize = 0x1000;
+const DMG_WORK_RAM_BANKS: usize = 2;
+const CGB_WORK_RAM_BANKS: usize = 8;
+
+// 0xC000-0xCFFF always holds bank 0, 0xD000-0xDFFF holds bank 1 on the DMG and the bank selected
+// through SVBK on the CGB. Echo RAM addresses map onto the same banks.
+pub struct WorkRAM {
+    pub data: Vec<u8>,
+    bank: usize,
+}
+
+impl MemoryZone for WorkRAM {
+    fn read(&self, address: u16) -> u8 {
+        self.data[self.global_address_to_local_address(address)]
+    }
+    fn write(&mut self, address: u16, value: u8) {
+        let local_address = self.global_address_to_local_address(address);
+        self.data[local_address] = value;
+    }
+}
+
+impl WorkRAM {
+    pub fn new(mode: HardwareMode) -> WorkRAM {
+        let banks = match mode {
+            HardwareMode::Dmg | HardwareMode::Sgb => DMG_WORK_RAM_BANKS,
+            HardwareMode::Cgb => CGB_WORK_RAM_BANKS,
+        };
+        WorkRAM { data: vec![0; banks * WORK_RAM_SWITCHABLE_BANK_SIZE], bank: 1 }
+    }
+
+    // SVBK holds the bank in its lowest three bits, 0 selects bank 1 as well
+    pub fn select_bank(&mut self, svbk: u8) {
+        let bank = (svbk & 0x07).max(1) as usize;
+        if bank * WORK_RAM_SWITCHABLE_BANK_SIZE < self.data.len() { self.bank = bank; }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+
+    fn global_address_to_local_address(&self, address: u16) -> usize {
+        let offset = (address - WORK_RAM_BASE_ADDRESS) as usize % WORK_RAM_BANK_SIZE as usize;
+        if offset < WORK_RAM_SWITCHABLE_BANK_SIZE {
+            offset
+        } else {
+            self.bank * WORK_RAM_SWITCHABLE_BANK_SIZE + offset - WORK_RAM_SWITCHABLE_BANK_SIZE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn svbk_switches_the_upper_bank() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0xC000, 0x10);
+        bus.write(0xD000, 0x11);
+        bus.write(0xFF70, 0x03);
+        assert_eq!(bus.read(0xFF70), 0xFB);
+        assert_eq!(bus.read(0xC000), 0x10);
+        assert_eq!(bus.read(0xD000), 0x00);
+        bus.write(0xDFFF, 0x33);
+        assert_eq!(bus.work_ram.data[3 * WORK_RAM_SWITCHABLE_BANK_SIZE + 0xFFF], 0x33);
+        bus.write(0xFF70, 0x00);
+        assert_eq!(bus.read(0xD000), 0x11);
+    }
+
+    #[test]
+    fn echo_ram_follows_the_selected_bank() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        bus.write(0xFF70, 0x05);
+        bus.write(0xD123, 0x55);
+        assert_eq!(bus.read(0xF123), 0x55);
+        bus.write(0xE010, 0x66);
+        assert_eq!(bus.read(0xC010), 0x66);
+    }
+
+    #[test]
+    fn dmg_has_two_fixed_banks() {
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.write(0xFF70, 0x03);
+        bus.write(0xD000, 0x11);
+        assert_eq!(bus.work_ram.data.len(), 0x2000);
+        assert_eq!(bus.work_ram.data[0x1000], 0x11);
+    }
+}
@@ -0,0 +1,69 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
+use alloc::sync::Arc;
+
+// Handle for stopping an emulator running in an async task, clones share the same state
+#[derive(Clone, Debug, Default)]
+pub struct Controller {
+    stopped: Arc<AtomicBool>,
+}
+
+impl Controller {
+    pub fn new() -> Controller {
+        Controller::default()
+    }
+
+    // The run returns after the frame in progress
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+// Pending once so the executor gets to run other tasks, then ready
+pub(crate) struct YieldNow {
+    yielded: bool,
+}
+
+pub(crate) fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context) -> Poll<()> {
+        if self.yielded { return Poll::Ready(()); }
+        self.yielded = true;
+        context.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::task::Waker;
+
+    #[test]
+    fn yields_once() {
+        let mut context = Context::from_waker(Waker::noop());
+        let mut future = yield_now();
+        assert_eq!(Pin::new(&mut future).poll(&mut context), Poll::Pending);
+        assert_eq!(Pin::new(&mut future).poll(&mut context), Poll::Ready(()));
+    }
+
+    #[test]
+    fn clones_share_stop() {
+        let controller = Controller::new();
+        let handle = controller.clone();
+        assert!(!controller.is_stopped());
+        handle.stop();
+        assert!(controller.is_stopped());
+    }
+}
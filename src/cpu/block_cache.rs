@@ -0,0 +1,301 @@
+use alloc::collections::BTreeMap;
+
+use crate::prelude::*;
+use super::CPU;
+
+// Code is watched for writes in lines of 64 bytes, small enough that IO registers, the stack
+// and a routine copied to high RAM rarely share one
+pub const LINE_SHIFT: u32 = 6;
+// Blocks are never longer than this, so a long run of straight-line code still gets split
+const MAX_BLOCK_LENGTH: usize = 64;
+// Past this many invalidated blocks the whole cache is dropped instead of keeping their slots
+const MAX_DEAD_BLOCKS: usize = 1024;
+
+// JR, JP, CALL, RET, RETI, RST, HALT and STOP end a block
+const CONTROL_FLOW_OPCODES: [u8; 30] = [
+    0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0x76,
+    0xC0, 0xC2, 0xC3, 0xC4, 0xC7, 0xC8, 0xC9, 0xCA, 0xCC, 0xCD, 0xCF,
+    0xD0, 0xD2, 0xD4, 0xD7, 0xD8, 0xD9, 0xDA, 0xDC, 0xDF, 0xE7, 0xE9, 0xEF,
+];
+
+// The bytes following an opcode, read when the instruction is recorded and handed back to it
+// instead of reading them through the bus again. The CB opcode counts as an operand.
+#[derive(Clone, Copy, Default)]
+pub struct Operands {
+    bytes: [u8; 2],
+    length: u8,
+    position: u8,
+}
+
+impl Operands {
+    pub fn new(bytes: &[u8]) -> Operands {
+        let mut operands = Operands { length: bytes.len() as u8, ..Operands::default() };
+        operands.bytes[..bytes.len()].copy_from_slice(bytes);
+        operands
+    }
+
+    // Which of the captured bytes comes next, None once they are used up
+    #[inline]
+    pub fn next(&mut self) -> Option<u8> {
+        if self.position == self.length { return None; }
+        self.position += 1;
+        Some(self.bytes[self.position as usize - 1])
+    }
+
+}
+
+// First and last line holding an instruction of the given length
+pub fn lines(address: u16, length: u16) -> (usize, usize) {
+    let last_byte = address.wrapping_add(length.max(1) - 1);
+    (address as usize >> LINE_SHIFT, last_byte as usize >> LINE_SHIFT)
+}
+
+#[derive(Clone, Copy)]
+pub struct CachedOp {
+    pub opcode: u8,
+    pub implementation: fn(&mut CPU),
+    pub operands: Operands,
+    address: u16,
+}
+
+impl CachedOp {
+    pub fn new(address: u16, opcode: u8, implementation: fn(&mut CPU), operands: Operands) -> CachedOp {
+        CachedOp { opcode, implementation, operands, address }
+    }
+}
+
+struct Block {
+    mapping: Mapping,
+    ops: Vec<CachedOp>,
+    // Where the next instruction would be if the block grows
+    next_address: u16,
+    finished: bool,
+    live: bool,
+    first_line: usize,
+    last_line: usize,
+}
+
+// Whether the boot ROM is mapped over an address and the video or work RAM bank selected there.
+// Blocks decoded under one mapping never run under another, so a bank switch ends a block.
+pub type Mapping = (bool, u8);
+type BlockKey = (Mapping, u16);
+
+// Straight-line runs of instructions as they were decoded the first time they executed, so
+// later runs skip fetching the opcode and operands through the bus and decoding them. Blocks grow as execution moves through
+// them and end at a branch, every cached instruction is checked against the PC before use.
+pub struct BlockCache {
+    blocks: Vec<Block>,
+    starts: BTreeMap<BlockKey, usize>,
+    // Block and position of the instruction expected next
+    cursor: Option<(usize, usize)>,
+    dead_blocks: usize,
+    // Cheats patch ROM reads, blocks decoded before the codes changed may be stale
+    cheats_revision: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache { blocks: vec![], starts: BTreeMap::new(), cursor: None, dead_blocks: 0, cheats_revision: 0, hits: 0, misses: 0 }
+    }
+
+    pub fn clear(&mut self) {
+        self.blocks.clear();
+        self.starts.clear();
+        self.cursor = None;
+        self.dead_blocks = 0;
+    }
+
+    pub fn clear_on_cheats_change(&mut self, cheats_revision: u64) {
+        if cheats_revision == self.cheats_revision { return; }
+        self.clear();
+        self.cheats_revision = cheats_revision;
+    }
+
+    pub fn lookup(&mut self, mapping: Mapping, address: u16) -> Option<CachedOp> {
+        if let Some((block, position)) = self.cursor.filter(|&(block, _)| self.blocks[block].mapping == mapping) {
+            if let Some(op) = self.blocks[block].ops.get(position).filter(|op| op.address == address) {
+                self.cursor = Some((block, position + 1));
+                self.hits += 1;
+                return Some(*op);
+            }
+        }
+        if let Some(&block) = self.starts.get(&(mapping, address)) {
+            self.cursor = Some((block, 1));
+            self.hits += 1;
+            return Some(self.blocks[block].ops[0]);
+        }
+        // Keep the cursor at the end of a block still growing into this address, so the
+        // instruction gets recorded there
+        self.cursor = self.cursor.filter(|&(block, position)| {
+            let block = &self.blocks[block];
+            !block.finished && block.mapping == mapping && position == block.ops.len() && block.next_address == address
+        });
+        self.misses += 1;
+        None
+    }
+
+    // Adds an instruction that just ran without the cache, length covers the bytes it was decoded
+    // from. Those lines have to be watched for writes from before it ran.
+    pub fn record(&mut self, mapping: Mapping, op: CachedOp, length: u16, next_address: u16) {
+        let op_address = op.address;
+        let growing = self.cursor
+            .filter(|&(block, position)| {
+                let block = &self.blocks[block];
+                !block.finished && block.mapping == mapping && position == block.ops.len() && block.next_address == op_address
+            })
+            .map(|(block, _)| block);
+        let block = match growing {
+            Some(block) => block,
+            None => {
+                self.blocks.push(Block {
+                    mapping, ops: vec![], next_address: op_address, finished: false, live: true,
+                    first_line: op_address as usize >> LINE_SHIFT, last_line: op_address as usize >> LINE_SHIFT,
+                });
+                self.starts.insert((mapping, op_address), self.blocks.len() - 1);
+                self.blocks.len() - 1
+            }
+        };
+        let (_, last_line) = lines(op_address, length);
+        let entry = &mut self.blocks[block];
+        entry.ops.push(op);
+        entry.next_address = next_address;
+        entry.last_line = entry.last_line.max(last_line);
+        entry.finished = CONTROL_FLOW_OPCODES.contains(&op.opcode) || entry.ops.len() >= MAX_BLOCK_LENGTH;
+        self.cursor = if entry.finished { None } else { Some((block, entry.ops.len())) };
+    }
+
+    // Drops every block decoded from the given line
+    pub fn invalidate_line(&mut self, line: usize) {
+        for (index, block) in self.blocks.iter_mut().enumerate() {
+            if !block.live || line < block.first_line || line > block.last_line { continue; }
+            block.live = false;
+            block.ops.clear();
+            self.starts.retain(|_, &mut start| start != index);
+            self.dead_blocks += 1;
+        }
+        self.cursor = None;
+        if self.dead_blocks > MAX_DEAD_BLOCKS { self.clear(); }
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> BlockCache { BlockCache::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARTRIDGE: Mapping = (false, 0);
+
+    fn nop(_cpu: &mut CPU) {}
+
+    #[test]
+    fn blocks_grow_until_a_branch() {
+        let mut cache = BlockCache::new();
+        assert!(cache.lookup(CARTRIDGE, 0x0150).is_none());
+        cache.record(CARTRIDGE, CachedOp::new(0x0150, 0x3C, nop, Operands::default()), 1, 0x0151);
+        assert!(cache.lookup(CARTRIDGE, 0x0151).is_none());
+        cache.record(CARTRIDGE, CachedOp::new(0x0151, 0x06, nop, Operands::default()), 2, 0x0153);
+        cache.record(CARTRIDGE, CachedOp::new(0x0153, 0x18, nop, Operands::default()), 2, 0x0150);
+        assert_eq!(cache.block_count(), 1);
+
+        for address in [0x0150, 0x0151, 0x0153] {
+            assert_eq!(cache.lookup(CARTRIDGE, address).map(|op| op.address), Some(address));
+        }
+        assert_eq!(cache.hits, 3);
+        // The boot ROM mapping keeps its own blocks
+        assert!(cache.lookup((true, 0), 0x0050).is_none());
+    }
+
+    #[test]
+    fn cached_code_is_rewritten() {
+        use crate::bus::Bus;
+        use crate::cpu::register::DMGRegister;
+
+        let mut memory = vec![0; 0x10000];
+        // INC A; JR -3
+        memory[0..3].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        let mut cpu = CPU::new(Bus::new_flat(memory));
+        cpu.enable_block_cache();
+        for _ in 0..20 { cpu.step().unwrap(); }
+        assert_eq!(cpu.reg_af.read_a(), 10);
+        assert!(cpu.block_cache().unwrap().hits > 0);
+        // INC B
+        cpu.bus.write(0x0000, 0x04);
+        for _ in 0..20 { cpu.step().unwrap(); }
+        assert_eq!(cpu.reg_af.read_a(), 10);
+        assert_eq!(cpu.reg_bc.read_higher(), 10);
+    }
+
+    #[test]
+    fn work_ram_banks_keep_their_own_blocks() {
+        use crate::bus::{Bus, HardwareMode};
+        use crate::cpu::register::DMGRegister;
+
+        let mut bus = Bus::new_from_vecs(vec![], vec![]);
+        bus.set_mode(HardwareMode::Cgb);
+        // INC A; JR -3 in bank 2, INC B; JR -3 in bank 3
+        for (bank, increment) in [(2, 0x3C), (3, 0x04)] {
+            bus.write(0xFF70, bank);
+            for (offset, byte) in [increment, 0x18, 0xFD].iter().enumerate() { bus.write(0xD000 + offset as u16, *byte); }
+        }
+        let mut cpu = CPU::new(bus);
+        cpu.enable_block_cache();
+        cpu.bus.write(0xFF70, 2);
+        cpu.program_counter.write(0xD000);
+        for _ in 0..20 { cpu.step().unwrap(); }
+        assert_eq!(cpu.reg_af.read_a(), 10);
+        cpu.bus.write(0xFF70, 3);
+        for _ in 0..20 { cpu.step().unwrap(); }
+        assert_eq!(cpu.reg_af.read_a(), 10);
+        assert_eq!(cpu.reg_bc.read_higher(), 10);
+    }
+
+    #[test]
+    fn cheat_changes_drop_blocks() {
+        use crate::bus::Bus;
+        use crate::cpu::register::DMGRegister;
+
+        let mut memory = vec![0; 0x10000];
+        // INC A; JR -3
+        memory[0..3].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        let mut cpu = CPU::new(Bus::new_flat(memory));
+        cpu.enable_block_cache();
+        for _ in 0..20 { cpu.step().unwrap(); }
+        // INC B at 0x0000
+        cpu.bus.cheats.add("040-00F").unwrap();
+        for _ in 0..20 { cpu.step().unwrap(); }
+        assert_eq!(cpu.reg_af.read_a(), 10);
+        assert_eq!(cpu.reg_bc.read_higher(), 10);
+    }
+
+    #[test]
+    fn operands_come_in_order() {
+        assert_eq!(Operands::default().next(), None);
+        let mut operands = Operands::new(&[0x34, 0x12]);
+        assert_eq!(operands.next(), Some(0x34));
+        assert_eq!(operands.next(), Some(0x12));
+        assert_eq!(operands.next(), None);
+    }
+
+    #[test]
+    fn writes_drop_blocks() {
+        let mut cache = BlockCache::new();
+        cache.record(CARTRIDGE, CachedOp::new(0xFF80, 0x3C, nop, Operands::default()), 1, 0xFF81);
+        cache.record(CARTRIDGE, CachedOp::new(0xFF81, 0xC9, nop, Operands::default()), 1, 0x0000);
+        assert_eq!(lines(0xFF80, 1), (0x3FE, 0x3FE));
+        assert_eq!(lines(0x003F, 3), (0x000, 0x001));
+        cache.invalidate_line(0x3FF);
+        assert!(cache.lookup(CARTRIDGE, 0xFF80).is_some());
+        cache.invalidate_line(0x3FE);
+        assert!(cache.lookup(CARTRIDGE, 0xFF80).is_none());
+        assert_eq!(cache.block_count(), 0);
+    }
+}
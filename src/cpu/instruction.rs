@@ -44,6 +44,22 @@ macro_rules! pop {
     )
 }
 
+macro_rules! rst {
+    ($opcode:literal, $vector:literal, $vector_name:expr) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!("RST ", $vector_name),
+            description: concat!("Call ", $vector_name),
+            length_in_bytes: 1, cycles: "16", flags_changed: "",
+            implementation: |cpu| {
+                cpu.push_u16_to_stack(cpu.program_counter.read());
+                cpu.program_counter.write($vector);
+                cpu.cycle_count += 16;
+            }
+        }
+    )
+}
+
 macro_rules! inc_u8 {
     ($opcode:literal, $register:ident, $write_method:ident, $read_method:ident, $register_name:expr) => (
         Instruction{
@@ -54,12 +70,25 @@ macro_rules! inc_u8 {
             implementation: |cpu| {
                 let target_value = cpu.$register.$read_method().overflowing_add(1).0;
                 cpu.$register.$write_method(target_value);
-                cpu.reg_af.flags.remove(Flags::N);
-                cpu.reg_af.flags.set(Flags::Z, target_value == 0);
-                cpu.reg_af.flags.set(Flags::H, target_value & 0x0F == 0);
+                cpu.reg_af.flags.set_znh(target_value == 0, false, target_value & 0x0F == 0);
                 cpu.cycle_count += 4;
             }
         }
+    );
+    ($opcode:literal, hl) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: "INC (HL)",
+            description: "Increment (HL)",
+            length_in_bytes: 1, cycles: "12", flags_changed: "Z0H-",
+            implementation: |cpu| {
+                let address = cpu.reg_hl.read();
+                let target_value = cpu.bus.read(address).overflowing_add(1).0;
+                cpu.bus.write(address, target_value);
+                cpu.reg_af.flags.set_znh(target_value == 0, false, target_value & 0x0F == 0);
+                cpu.cycle_count += 12;
+            }
+        }
     )
 }
 
@@ -88,12 +117,25 @@ macro_rules! dec_u8 {
             implementation: |cpu| {
                 let target_value = cpu.$register.$read_method().overflowing_add(0xFF).0;
                 cpu.$register.$write_method(target_value);
-                cpu.reg_af.flags.insert(Flags::N);
-                cpu.reg_af.flags.set(Flags::Z, target_value == 0);
-                cpu.reg_af.flags.set(Flags::H, target_value & 0x0F == 0x0F);
+                cpu.reg_af.flags.set_znh(target_value == 0, true, target_value & 0x0F == 0x0F);
                 cpu.cycle_count += 4;
             }
         }
+    );
+    ($opcode:literal, hl) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: "DEC (HL)",
+            description: "Decrement (HL)",
+            length_in_bytes: 1, cycles: "12", flags_changed: "Z1H-",
+            implementation: |cpu| {
+                let address = cpu.reg_hl.read();
+                let target_value = cpu.bus.read(address).overflowing_add(0xFF).0;
+                cpu.bus.write(address, target_value);
+                cpu.reg_af.flags.set_znh(target_value == 0, true, target_value & 0x0F == 0x0F);
+                cpu.cycle_count += 12;
+            }
+        }
     )
 }
 
@@ -112,6 +154,39 @@ macro_rules! dec_u16 {
     )
 }
 
+// Half carry out of bit 11 and carry out of bit 15, Z is left alone
+macro_rules! add_hl {
+    ($opcode:literal, $register:ident, $register_name:expr) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!("ADD HL,", $register_name),
+            description: concat!("Add ", $register_name, " to HL"),
+            length_in_bytes: 1, cycles: "8", flags_changed: "-0HC",
+            implementation: |cpu| {
+                let hl = cpu.reg_hl.read();
+                let addend = cpu.$register.read();
+                let (result, carry) = hl.overflowing_add(addend);
+                let half_carry = (hl & 0x0FFF) + (addend & 0x0FFF) > 0x0FFF;
+                let zero = cpu.reg_af.flags.contains(Flags::Z);
+                cpu.reg_af.flags = Flags::from_conditions(zero, false, half_carry, carry);
+                cpu.reg_hl.write(result);
+                cpu.cycle_count += 8;
+            }
+        }
+    )
+}
+
+// SP plus a signed immediate, for ADD SP,r8 and LD HL,SP+r8. The flags come from adding the
+// immediate as an unsigned byte to the low byte of SP.
+fn stack_pointer_plus_immediate(cpu: &mut CPU) -> u16 {
+    let sp = cpu.stack_pointer.read();
+    let offset = cpu.pop_u8_from_pc();
+    let half_carry = (sp & 0x000F) + (offset as u16 & 0x000F) > 0x000F;
+    let carry = (sp & 0x00FF) + offset as u16 > 0x00FF;
+    cpu.reg_af.flags = Flags::from_conditions(false, false, half_carry, carry);
+    sp.wrapping_add(offset as i8 as u16)
+}
+
 macro_rules! ld_8bit_register_immediate {
     ($opcode:literal, $register:ident, $write_method:ident, $register_name:expr) => (
         Instruction{
@@ -225,40 +300,149 @@ macro_rules! ld_pointer_register {
 }
 
 
-macro_rules! rotate_left_trough_carry {
-    ($opcode: literal,
-     $register:ident, $read_method:ident, $write_method:ident, $register_name:expr, regular) => (
+// The CB rotates and shifts, with one of the functions below doing the work. Z is set when the
+// result is 0, the bit shifted out goes to the carry.
+macro_rules! shift {
+    ($opcode:literal, $mnemonic:literal, $operation:ident, $register:ident, $read_method:ident, $write_method:ident, $register_name:expr) => (
         Instruction{opcode: $opcode,
-            mnemonic: concat!("RL ", $register_name),
-            description: concat!("Rotate ", $register_name, " left trough carry"),
+            mnemonic: concat!($mnemonic, " ", $register_name),
+            description: concat!($mnemonic, " ", $register_name),
             length_in_bytes: 2, cycles: "8", flags_changed: "Z00C",
             implementation: |cpu| {
                 cpu.cycle_count += 8;
-                cpu.reg_af.flags.remove(Flags::N);
-                cpu.reg_af.flags.remove(Flags::H);
-                let set_carry = (cpu.$register.$read_method() & 0b10000000) != 0;
-                let mut new_value = cpu.$register.$read_method() << 1;
-                if cpu.reg_af.flags.contains(Flags::C) { new_value += 1; }
-                cpu.$register.$write_method(new_value);
-                cpu.reg_af.flags.set(Flags::C, set_carry);
-                cpu.reg_af.flags.set(Flags::Z, new_value == 0);
+                let (result, carry) = $operation(cpu.$register.$read_method(), cpu.reg_af.flags.carry_bit());
+                cpu.$register.$write_method(result);
+                cpu.reg_af.flags = Flags::from_conditions(result == 0, false, false, carry);
             }
         }
     );
-    ($opcode: literal,
-     $register:ident, $read_method:ident, $write_method:ident, $register_name:expr, fast) => (
+    ($opcode:literal, $mnemonic:literal, $operation:ident, hl) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!($mnemonic, " (HL)"),
+            description: concat!($mnemonic, " (HL)"),
+            length_in_bytes: 2, cycles: "16", flags_changed: "Z00C",
+            implementation: |cpu| {
+                cpu.cycle_count += 16;
+                let address = cpu.reg_hl.read();
+                let (result, carry) = $operation(cpu.bus.read(address), cpu.reg_af.flags.carry_bit());
+                cpu.bus.write(address, result);
+                cpu.reg_af.flags = Flags::from_conditions(result == 0, false, false, carry);
+            }
+        }
+    )
+}
+
+// The one byte rotates of A, same as their CB counterparts except that Z is always cleared
+macro_rules! rotate_a {
+    ($opcode:literal, $mnemonic:literal, $operation:ident) => (
         Instruction{opcode: $opcode,
-            mnemonic: concat!("RL", $register_name),
-            description: concat!("Rotate ", $register_name, " left trough carry (fast)"),
+            mnemonic: $mnemonic,
+            description: concat!($mnemonic, " (fast)"),
             length_in_bytes: 1, cycles: "4", flags_changed: "000C",
             implementation: |cpu| {
                 cpu.cycle_count += 4;
-                let set_carry = (cpu.$register.$read_method() & 0b10000000) != 0;
-                let mut new_value = cpu.$register.$read_method() << 1;
-                if cpu.reg_af.flags.contains(Flags::C) { new_value += 1; }
-                cpu.$register.$write_method(new_value);
-                cpu.reg_af.flags.clear();
-                cpu.reg_af.flags.set(Flags::C, set_carry);
+                let (result, carry) = $operation(cpu.reg_af.read_a(), cpu.reg_af.flags.carry_bit());
+                cpu.reg_af.write_a(result);
+                cpu.reg_af.flags = Flags::from_conditions(false, false, false, carry);
+            }
+        }
+    )
+}
+
+// Z is set when the bit is clear, C is left alone
+macro_rules! bit {
+    ($opcode:literal, $bit:literal, $register:ident, $read_method:ident, $register_name:expr) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("BIT ", $bit, ",", $register_name),
+            description: concat!("Test bit ", $bit, " of ", $register_name),
+            length_in_bytes: 2, cycles: "8", flags_changed: "Z01-",
+            implementation: |cpu| {
+                cpu.cycle_count += 8;
+                let value = cpu.$register.$read_method();
+                cpu.reg_af.flags.set_znh(value & (1 << $bit) == 0, false, true);
+            }
+        }
+    );
+    ($opcode:literal, $bit:literal, hl) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("BIT ", $bit, ",(HL)"),
+            description: concat!("Test bit ", $bit, " of (HL)"),
+            length_in_bytes: 2, cycles: "12", flags_changed: "Z01-",
+            implementation: |cpu| {
+                cpu.cycle_count += 12;
+                let value = cpu.bus.read(cpu.reg_hl.read());
+                cpu.reg_af.flags.set_znh(value & (1 << $bit) == 0, false, true);
+            }
+        }
+    )
+}
+
+macro_rules! res {
+    ($opcode:literal, $bit:literal, $register:ident, $read_method:ident, $write_method:ident, $register_name:expr) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("RES ", $bit, ",", $register_name),
+            description: concat!("Reset bit ", $bit, " of ", $register_name),
+            length_in_bytes: 2, cycles: "8", flags_changed: "",
+            implementation: |cpu| {
+                cpu.cycle_count += 8;
+                let value = cpu.$register.$read_method();
+                cpu.$register.$write_method(value & !(1 << $bit));
+            }
+        }
+    );
+    ($opcode:literal, $bit:literal, hl) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("RES ", $bit, ",(HL)"),
+            description: concat!("Reset bit ", $bit, " of (HL)"),
+            length_in_bytes: 2, cycles: "16", flags_changed: "",
+            implementation: |cpu| {
+                cpu.cycle_count += 16;
+                let address = cpu.reg_hl.read();
+                let value = cpu.bus.read(address);
+                cpu.bus.write(address, value & !(1 << $bit));
+            }
+        }
+    )
+}
+
+macro_rules! set {
+    ($opcode:literal, $bit:literal, $register:ident, $read_method:ident, $write_method:ident, $register_name:expr) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("SET ", $bit, ",", $register_name),
+            description: concat!("Set bit ", $bit, " of ", $register_name),
+            length_in_bytes: 2, cycles: "8", flags_changed: "",
+            implementation: |cpu| {
+                cpu.cycle_count += 8;
+                let value = cpu.$register.$read_method();
+                cpu.$register.$write_method(value | (1 << $bit));
+            }
+        }
+    );
+    ($opcode:literal, $bit:literal, hl) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("SET ", $bit, ",(HL)"),
+            description: concat!("Set bit ", $bit, " of (HL)"),
+            length_in_bytes: 2, cycles: "16", flags_changed: "",
+            implementation: |cpu| {
+                cpu.cycle_count += 16;
+                let address = cpu.reg_hl.read();
+                let value = cpu.bus.read(address);
+                cpu.bus.write(address, value | (1 << $bit));
+            }
+        }
+    )
+}
+
+// Opcodes the SM83 does not have. Running one locks up the CPU.
+macro_rules! illegal {
+    ($opcode:literal) => (
+        Instruction{opcode: $opcode,
+            mnemonic: "ILLEGAL",
+            description: "Illegal opcode, locks up the CPU",
+            length_in_bytes: 1, cycles: "4", flags_changed: "",
+            implementation: |cpu| {
+                cpu.cycle_count += 4;
+                cpu.illegal_opcode();
             }
         }
     )
@@ -283,8 +467,8 @@ macro_rules! jump_relative {
     );
     ($opcode: literal) => (
         Instruction{opcode: $opcode,
-            mnemonic: concat!("JR r8"),
-            description: concat!("Jump relative"),
+            mnemonic: "JR r8",
+            description: "Jump relative",
             length_in_bytes: 2, cycles: "12", flags_changed: "----",
             implementation: |cpu| {
                 let jump_distance = cpu.pop_u8_from_pc() as i8;
@@ -314,8 +498,8 @@ macro_rules! jump {
     );
     ($opcode: literal) => (
         Instruction{opcode: $opcode,
-            mnemonic: concat!("JP d16"),
-            description: concat!("Jump"),
+            mnemonic: "JP d16",
+            description: "Jump",
             length_in_bytes: 3, cycles: "12", flags_changed: "----",
             implementation: |cpu| {
                 let jump_address = cpu.pop_u16_from_pc();
@@ -326,8 +510,8 @@ macro_rules! jump {
     );
     ($opcode: literal, hl) => (
         Instruction{opcode: $opcode,
-            mnemonic: concat!("JP (HL))"),
-            description: concat!("Jump (HL)"),
+            mnemonic: "JP (HL))",
+            description: "Jump (HL)",
             length_in_bytes: 1, cycles: "4", flags_changed: "----",
             implementation: |cpu| {
                 cpu.cycle_count += 4;
@@ -337,12 +521,49 @@ macro_rules! jump {
     )
 }
 
+macro_rules! call {
+    ($opcode: literal, $flag:expr, $true_or_false:literal, $condition_text:literal) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("CALL ", $condition_text, ", a16"),
+            description: concat!("Call if ", $condition_text),
+            length_in_bytes: 3, cycles: "24/12", flags_changed: "----",
+            implementation: |cpu| {
+                let new_pc = cpu.pop_u16_from_pc();
+                if cpu.reg_af.flags.contains($flag) == $true_or_false {
+                    cpu.cycle_count += 24;
+                    cpu.push_u16_to_stack(cpu.program_counter.read());
+                    cpu.program_counter.write(new_pc);
+                } else {
+                    cpu.cycle_count += 12;
+                }
+            }
+        }
+    )
+}
+
+macro_rules! ret {
+    ($opcode: literal, $flag:expr, $true_or_false:literal, $condition_text:literal) => (
+        Instruction{opcode: $opcode,
+            mnemonic: concat!("RET ", $condition_text),
+            description: concat!("Return if ", $condition_text),
+            length_in_bytes: 1, cycles: "20/8", flags_changed: "----",
+            implementation: |cpu| {
+                if cpu.reg_af.flags.contains($flag) == $true_or_false {
+                    cpu.cycle_count += 20;
+                    let new_pc = cpu.pop_u16_from_stack();
+                    cpu.program_counter.write(new_pc);
+                } else {
+                    cpu.cycle_count += 8;
+                }
+            }
+        }
+    )
+}
+
 fn set_cpu_flags_for_add(cpu: &mut CPU, value: u8) {
     let a = cpu.reg_af.read_a();
-    cpu.reg_af.flags.set(Flags::Z, a.overflowing_add(value).0 == 0);
-    cpu.reg_af.flags.set(Flags::C, a.overflowing_add(value).1);
-    cpu.reg_af.flags.set(Flags::H, (a & 0x0F) + (value & 0x0F) > 0x0F);
-    cpu.reg_af.flags.remove(Flags::N);
+    let (result, carry) = a.overflowing_add(value);
+    cpu.reg_af.flags = Flags::from_conditions(result == 0, false, (a & 0x0F) + (value & 0x0F) > 0x0F, carry);
 }
 
 macro_rules! add {
@@ -364,8 +585,8 @@ macro_rules! add {
     ($opcode:literal, hl) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("ADD (HL)"),
-            description: concat!("ADD (HL) to A"),
+            mnemonic: "ADD (HL)",
+            description: "ADD (HL) to A",
             length_in_bytes: 1, cycles: "8", flags_changed: "Z0HC",
             implementation: |cpu| {
                 let addend = cpu.bus.read(cpu.reg_hl.read());
@@ -379,8 +600,8 @@ macro_rules! add {
     ($opcode:literal, immediate) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("ADD d8"),
-            description: concat!("Add immediate to A"),
+            mnemonic: "ADD d8",
+            description: "Add immediate to A",
             length_in_bytes: 2, cycles: "8", flags_changed: "Z0HC",
             implementation: |cpu| {
                 let addend = cpu.pop_u8_from_pc();
@@ -395,10 +616,7 @@ macro_rules! add {
 
 fn set_cpu_flags_for_sub_or_cp(cpu: &mut CPU, value: u8) {
     let a = cpu.reg_af.read_a();
-    cpu.reg_af.flags.set(Flags::Z, a == value);
-    cpu.reg_af.flags.set(Flags::C, a < value);
-    cpu.reg_af.flags.set(Flags::H, (a & 0x0F) < (value & 0x0F));
-    cpu.reg_af.flags.insert(Flags::N);
+    cpu.reg_af.flags = Flags::from_conditions(a == value, true, (a & 0x0F) < (value & 0x0F), a < value);
 }
 
 macro_rules! sub {
@@ -420,8 +638,8 @@ macro_rules! sub {
     ($opcode:literal, hl) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("SUB (HL)"),
-            description: concat!("Substract (HL) from A"),
+            mnemonic: "SUB (HL)",
+            description: "Substract (HL) from A",
             length_in_bytes: 1, cycles: "8", flags_changed: "Z1HC",
             implementation: |cpu| {
                 let subtrahend = cpu.bus.read(cpu.reg_hl.read());
@@ -435,8 +653,8 @@ macro_rules! sub {
     ($opcode:literal, immediate) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("SUB d8"),
-            description: concat!("Substract immediate from A"),
+            mnemonic: "SUB d8",
+            description: "Substract immediate from A",
             length_in_bytes: 2, cycles: "8", flags_changed: "Z1HC",
             implementation: |cpu| {
                 let subtrahend = cpu.pop_u8_from_pc();
@@ -466,8 +684,8 @@ macro_rules! cp {
     ($opcode:literal, hl) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("CP (HL)"),
-            description: concat!("Compare (HL) with A"),
+            mnemonic: "CP (HL)",
+            description: "Compare (HL) with A",
             length_in_bytes: 1, cycles: "8", flags_changed: "Z1HC",
             implementation: |cpu| {
                 let subtrahend = cpu.bus.read(cpu.reg_hl.read());
@@ -479,8 +697,8 @@ macro_rules! cp {
     ($opcode:literal, immediate) => (
         Instruction{
             opcode: $opcode,
-            mnemonic: concat!("CP d8"),
-            description: concat!("Compare immediate with A"),
+            mnemonic: "CP d8",
+            description: "Compare immediate with A",
             length_in_bytes: 2, cycles: "8", flags_changed: "Z1HC",
             implementation: |cpu| {
                 let subtrahend = cpu.pop_u8_from_pc();
@@ -491,39 +709,246 @@ macro_rules! cp {
     )
 }
 
+// Each takes the value and the carry bit and returns the result and the bit shifted out
+fn rotate_left(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value.rotate_left(1), value & 0x80 != 0)
+}
+
+fn rotate_right(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value.rotate_right(1), value & 0x01 != 0)
+}
+
+fn rotate_left_through_carry(value: u8, carry_in: u8) -> (u8, bool) {
+    (value << 1 | carry_in, value & 0x80 != 0)
+}
+
+fn rotate_right_through_carry(value: u8, carry_in: u8) -> (u8, bool) {
+    (value >> 1 | carry_in << 7, value & 0x01 != 0)
+}
+
+fn shift_left_arithmetic(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value << 1, value & 0x80 != 0)
+}
+
+// Bit 7 stays as it was
+fn shift_right_arithmetic(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value >> 1 | value & 0x80, value & 0x01 != 0)
+}
+
+fn shift_right_logical(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value >> 1, value & 0x01 != 0)
+}
+
+// Nothing is shifted out, the carry is always cleared
+fn swap_nibbles(value: u8, _carry_in: u8) -> (u8, bool) {
+    (value.rotate_left(4), false)
+}
+
+// Turns A back into two BCD digits after adding or substracting BCD numbers, going by N, H and C
+// as the previous operation left them
+fn decimal_adjust_a(cpu: &mut CPU) {
+    let mut a = cpu.reg_af.read_a();
+    let flags = cpu.reg_af.flags;
+    let mut carry = flags.contains(Flags::C);
+    if flags.contains(Flags::N) {
+        if carry { a = a.wrapping_sub(0x60); }
+        if flags.contains(Flags::H) { a = a.wrapping_sub(0x06); }
+    } else {
+        if carry || a > 0x99 { a = a.wrapping_add(0x60); carry = true; }
+        if flags.contains(Flags::H) || (a & 0x0F) > 0x09 { a = a.wrapping_add(0x06); }
+    }
+    cpu.reg_af.write_a(a);
+    cpu.reg_af.flags = Flags::from_conditions(a == 0, flags.contains(Flags::N), false, carry);
+}
+
+// The carry coming in counts towards both the half carry and the carry going out
+fn add_with_carry_to_a(cpu: &mut CPU, value: u8) {
+    let a = cpu.reg_af.read_a();
+    let carry = cpu.reg_af.flags.contains(Flags::C) as u8;
+    let result = a.wrapping_add(value).wrapping_add(carry);
+    let half_carry = (a & 0x0F) + (value & 0x0F) + carry > 0x0F;
+    let full_carry = a as u16 + value as u16 + carry as u16 > 0xFF;
+    cpu.reg_af.write_a(result);
+    cpu.reg_af.flags = Flags::from_conditions(result == 0, false, half_carry, full_carry);
+}
+
+fn sub_with_carry_from_a(cpu: &mut CPU, value: u8) {
+    let a = cpu.reg_af.read_a();
+    let carry = cpu.reg_af.flags.contains(Flags::C) as u8;
+    let result = a.wrapping_sub(value).wrapping_sub(carry);
+    let half_carry = (a & 0x0F) < (value & 0x0F) + carry;
+    let full_carry = (a as u16) < value as u16 + carry as u16;
+    cpu.reg_af.write_a(result);
+    cpu.reg_af.flags = Flags::from_conditions(result == 0, true, half_carry, full_carry);
+}
+
+macro_rules! with_carry {
+    ($opcode:literal, $mnemonic:literal, $operation:ident, $description:literal, $flags:literal, $register:ident, $read_method:ident, $register_name:expr) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " ", $register_name),
+            description: concat!($description, " ", $register_name, " and carry"),
+            length_in_bytes: 1, cycles: "4", flags_changed: $flags,
+            implementation: |cpu| {
+                let value = cpu.$register.$read_method();
+                $operation(cpu, value);
+                cpu.cycle_count += 4;
+            }
+        }
+    );
+    ($opcode:literal, $mnemonic:literal, $operation:ident, $description:literal, $flags:literal, hl) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " (HL)"),
+            description: concat!($description, " (HL) and carry"),
+            length_in_bytes: 1, cycles: "8", flags_changed: $flags,
+            implementation: |cpu| {
+                let value = cpu.bus.read(cpu.reg_hl.read());
+                $operation(cpu, value);
+                cpu.cycle_count += 8;
+            }
+        }
+    );
+    ($opcode:literal, $mnemonic:literal, $operation:ident, $description:literal, $flags:literal, immediate) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " d8"),
+            description: concat!($description, " immediate and carry"),
+            length_in_bytes: 2, cycles: "8", flags_changed: $flags,
+            implementation: |cpu| {
+                let value = cpu.pop_u8_from_pc();
+                $operation(cpu, value);
+                cpu.cycle_count += 8;
+            }
+        }
+    )
+}
+
+macro_rules! adc {
+    ($opcode:literal, $($operand:tt)*) => (with_carry!($opcode, "ADC", add_with_carry_to_a, "Add to A", "Z0HC", $($operand)*));
+}
+
+macro_rules! sbc {
+    ($opcode:literal, $($operand:tt)*) => (with_carry!($opcode, "SBC", sub_with_carry_from_a, "Substract from A", "Z1HC", $($operand)*));
+}
+
+// AND sets H, OR and XOR clear it, carry is always cleared
+fn write_a_after_logical(cpu: &mut CPU, result: u8, half_carry: bool) {
+    cpu.reg_af.write_a(result);
+    cpu.reg_af.flags = Flags::from_conditions(result == 0, false, half_carry, false);
+}
+
+macro_rules! logical {
+    ($opcode:literal, $mnemonic:literal, $operator:tt, $half_carry:literal, $flags:literal, $register:ident, $read_method:ident, $register_name:expr) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " ", $register_name),
+            description: concat!($mnemonic, " ", $register_name, " with A"),
+            length_in_bytes: 1, cycles: "4", flags_changed: $flags,
+            implementation: |cpu| {
+                let result = cpu.reg_af.read_a() $operator cpu.$register.$read_method();
+                write_a_after_logical(cpu, result, $half_carry);
+                cpu.cycle_count += 4;
+            }
+        }
+    );
+    ($opcode:literal, $mnemonic:literal, $operator:tt, $half_carry:literal, $flags:literal, hl) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " (HL)"),
+            description: concat!($mnemonic, " (HL) with A"),
+            length_in_bytes: 1, cycles: "8", flags_changed: $flags,
+            implementation: |cpu| {
+                let result = cpu.reg_af.read_a() $operator cpu.bus.read(cpu.reg_hl.read());
+                write_a_after_logical(cpu, result, $half_carry);
+                cpu.cycle_count += 8;
+            }
+        }
+    );
+    ($opcode:literal, $mnemonic:literal, $operator:tt, $half_carry:literal, $flags:literal, immediate) => (
+        Instruction{
+            opcode: $opcode,
+            mnemonic: concat!($mnemonic, " d8"),
+            description: concat!($mnemonic, " immediate with A"),
+            length_in_bytes: 2, cycles: "8", flags_changed: $flags,
+            implementation: |cpu| {
+                let result = cpu.reg_af.read_a() $operator cpu.pop_u8_from_pc();
+                write_a_after_logical(cpu, result, $half_carry);
+                cpu.cycle_count += 8;
+            }
+        }
+    )
+}
+
+macro_rules! and {
+    ($opcode:literal, $($operand:tt)*) => (logical!($opcode, "AND", &, true, "Z010", $($operand)*));
+}
+
+macro_rules! or {
+    ($opcode:literal, $($operand:tt)*) => (logical!($opcode, "OR", |, false, "Z000", $($operand)*));
+}
+
+macro_rules! xor {
+    ($opcode:literal, $($operand:tt)*) => (logical!($opcode, "XOR", ^, false, "Z000", $($operand)*));
+}
+
 
-pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
+pub static INSTRUCTIONS_NOCB: [Instruction; 256] = [
     Instruction{opcode: 0x00, mnemonic: "NOP", description: "No operation",
         length_in_bytes: 1, cycles: "4", flags_changed: "",
         implementation: |cpu| cpu.cycle_count += 4 },
-    Instruction{opcode: 0x01, mnemonic: "LD BC,d16", description: "Load immediate to BC",
-        length_in_bytes: 3, cycles: "12", flags_changed: "",
-        implementation: |_cpu| panic!("Not implemented") },
+    ld_16bit_register_immediate!(0x01, reg_bc, "BC"),
     ld_pointer_register!(0x02, reg_bc, "BC", reg_af, read_higher, "A"),
     inc_u16!(0x03, reg_bc, "BC"),
     inc_u8!(0x04, reg_bc, write_higher, read_higher, "B"),
     dec_u8!(0x05, reg_bc, write_higher, read_higher, "B"),
 
     ld_8bit_register_immediate!(0x06, reg_bc, write_higher, "B"),
+    rotate_a!(0x07, "RLCA", rotate_left),
+
+    Instruction{opcode: 0x08, mnemonic: "LD (a16), SP", description: "Store SP to immediate pointer",
+        length_in_bytes: 3, cycles: "20", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 20;
+            let address = cpu.pop_u16_from_pc();
+            let sp = cpu.stack_pointer.read();
+            // Little endian, low byte first
+            cpu.bus.write(address, sp as u8);
+            cpu.bus.write(address.wrapping_add(1), (sp >> 8) as u8);
+        } },
 
+    add_hl!(0x09, reg_bc, "BC"),
     ld_register_pointer!(0x0A, reg_af, write_a, "A", reg_bc, "BC"),
     dec_u16!(0x0B, reg_bc, "BC"),
     inc_u8!(0x0C, reg_bc, write_lower, read_lower, "C"),
     dec_u8!(0x0D, reg_bc, write_lower, read_lower, "C"),
     ld_8bit_register_immediate!(0x0E, reg_bc, write_lower, "C"),
+    rotate_a!(0x0F, "RRCA", rotate_right),
+
+    Instruction{opcode: 0x10, mnemonic: "STOP", description: "Stop until a button is pressed",
+        length_in_bytes: 2, cycles: "4", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            // The byte after STOP is skipped
+            cpu.pop_u8_from_pc();
+            cpu.stopped = true;
+        } },
+
     ld_16bit_register_immediate!(0x11, reg_de, "DE"),
     ld_pointer_register!(0x12, reg_de, "DE", reg_af, read_higher, "A"),
     inc_u16!(0x13, reg_de, "DE"),
     inc_u8!(0x14, reg_de, write_higher, read_higher, "D"),
     dec_u8!(0x15, reg_de, write_higher, read_higher, "D"),
     ld_8bit_register_immediate!(0x16, reg_de, write_higher, "D"),
-    rotate_left_trough_carry!(0x17, reg_af, read_higher, write_higher, "A", fast),
+    rotate_a!(0x17, "RLA", rotate_left_through_carry),
     jump_relative!(0x18),
+    add_hl!(0x19, reg_de, "DE"),
     ld_register_pointer!(0x1A, reg_af, write_a, "A", reg_de, "DE"),
     dec_u16!(0x1B, reg_de, "DE"),
     inc_u8!(0x1C, reg_de, write_lower, read_lower, "E"),
     dec_u8!(0x1D, reg_de, write_lower, read_lower, "E"),
     ld_8bit_register_immediate!(0x1E, reg_de, write_lower, "E"),
+    rotate_a!(0x1F, "RRA", rotate_right_through_carry),
 
     jump_relative!(0x20, Flags::Z, false, "NZ"),
 
@@ -534,14 +959,30 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
     dec_u8!(0x25, reg_hl, write_higher, read_higher, "H"),
     ld_8bit_register_immediate!(0x26, reg_hl, write_higher, "H"),
 
+    Instruction{opcode: 0x27, mnemonic: "DAA", description: "Decimal adjust A",
+        length_in_bytes: 1, cycles: "4", flags_changed: "Z-0C",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            decimal_adjust_a(cpu);
+        } },
+
     jump_relative!(0x28, Flags::Z, true, "Z"),
 
+    add_hl!(0x29, reg_hl, "HL"),
     ld_register_pointer!(0x2A, reg_af, write_a, "A", reg_hl, "HL", 0x0001, "+"),
     dec_u16!(0x2B, reg_hl, "HL"),
     inc_u8!(0x2C, reg_hl, write_lower, read_lower, "L"),
     dec_u8!(0x2D, reg_hl, write_lower, read_lower, "L"),
     ld_8bit_register_immediate!(0x2E, reg_hl, write_lower, "L"),
 
+    Instruction{opcode: 0x2F, mnemonic: "CPL", description: "Complement A",
+        length_in_bytes: 1, cycles: "4", flags_changed: "-11-",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            cpu.reg_af.write_a(!cpu.reg_af.read_a());
+            cpu.reg_af.flags.insert(Flags::N | Flags::H);
+        } },
+
     jump_relative!(0x30, Flags::C, false, "NC"),
 
     Instruction{opcode: 0x31, mnemonic: "LD SP,d16", description: "Load immediate to SP",
@@ -554,9 +995,28 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
 
     ld_pointer_register!(0x32, reg_hl, "HL", reg_af, read_higher, "A", 0xFFFF, "-"),
     inc_u16!(0x33, stack_pointer, "SP"),
+    inc_u8!(0x34, hl),
+    dec_u8!(0x35, hl),
+
+    Instruction{opcode: 0x36, mnemonic: "LD (HL), d8", description: "Load immediate to (HL)",
+        length_in_bytes: 2, cycles: "12", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 12;
+            let immediate = cpu.pop_u8_from_pc();
+            cpu.bus.write(cpu.reg_hl.read(), immediate);
+        } },
+
+    Instruction{opcode: 0x37, mnemonic: "SCF", description: "Set carry flag",
+        length_in_bytes: 1, cycles: "4", flags_changed: "-001",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            cpu.reg_af.flags.remove(Flags::N | Flags::H);
+            cpu.reg_af.flags.insert(Flags::C);
+        } },
 
     jump_relative!(0x38, Flags::C, true, "C"),
 
+    add_hl!(0x39, stack_pointer, "SP"),
     ld_register_pointer!(0x3A, reg_af, write_a, "A", reg_hl, "HL", 0xFFFF, "-"),
     dec_u16!(0x3B, stack_pointer, "SP"),
     inc_u8!(0x3C, reg_af, write_higher, read_higher, "A"),
@@ -564,6 +1024,14 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
 
     ld_8bit_register_immediate!(0x3E, reg_af, write_higher, "A"),
 
+    Instruction{opcode: 0x3F, mnemonic: "CCF", description: "Complement carry flag",
+        length_in_bytes: 1, cycles: "4", flags_changed: "-00C",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            cpu.reg_af.flags.remove(Flags::N | Flags::H);
+            cpu.reg_af.flags.toggle(Flags::C);
+        } },
+
     ld_8bit_register_register!(0x40, reg_bc, write_higher, "B",  reg_bc, read_higher, "B"),
     ld_8bit_register_register!(0x41, reg_bc, write_higher, "B",  reg_bc, read_lower, "C"),
     ld_8bit_register_register!(0x42, reg_bc, write_higher, "B",  reg_de, read_higher, "D"),
@@ -624,6 +1092,14 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
     ld_pointer_register!(0x73, reg_hl, "HL", reg_de, read_lower, "E"),
     ld_pointer_register!(0x74, reg_hl, "HL", reg_hl, read_higher, "H"),
     ld_pointer_register!(0x75, reg_hl, "HL", reg_hl, read_lower, "L"),
+
+    Instruction{opcode: 0x76, mnemonic: "HALT", description: "Halt until an interrupt is requested",
+        length_in_bytes: 1, cycles: "4", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 4;
+            cpu.halt();
+        } },
+
     ld_pointer_register!(0x77, reg_hl, "HL", reg_af, read_higher, "A"),
 
     ld_8bit_register_register!(0x78, reg_af, write_a, "A",  reg_bc, read_higher, "B"),
@@ -644,6 +1120,15 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
     add!(0x86, hl),
     add!(0x87, reg_af, read_a, "A"),
 
+    adc!(0x88, reg_bc, read_higher, "B"),
+    adc!(0x89, reg_bc, read_lower, "C"),
+    adc!(0x8A, reg_de, read_higher, "D"),
+    adc!(0x8B, reg_de, read_lower, "E"),
+    adc!(0x8C, reg_hl, read_higher, "H"),
+    adc!(0x8D, reg_hl, read_lower, "L"),
+    adc!(0x8E, hl),
+    adc!(0x8F, reg_af, read_a, "A"),
+
     sub!(0x90, reg_bc, read_higher, "B"),
     sub!(0x91, reg_bc, read_lower, "C"),
     sub!(0x92, reg_de, read_higher, "D"),
@@ -653,14 +1138,41 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
     sub!(0x96, hl),
     sub!(0x97, reg_af, read_a, "A"),
 
-    Instruction{opcode: 0xAF, mnemonic: "XOR A", description: "XOR A with A (zeroes A)",
-        length_in_bytes: 1, cycles: "4", flags_changed: "Z000",
-        implementation: |cpu| {
-            cpu.cycle_count += 4;
-            cpu.reg_af.write_a(0);
-            cpu.reg_af.flags.insert(Flags::Z);
-        } },
-
+    sbc!(0x98, reg_bc, read_higher, "B"),
+    sbc!(0x99, reg_bc, read_lower, "C"),
+    sbc!(0x9A, reg_de, read_higher, "D"),
+    sbc!(0x9B, reg_de, read_lower, "E"),
+    sbc!(0x9C, reg_hl, read_higher, "H"),
+    sbc!(0x9D, reg_hl, read_lower, "L"),
+    sbc!(0x9E, hl),
+    sbc!(0x9F, reg_af, read_a, "A"),
+
+    and!(0xA0, reg_bc, read_higher, "B"),
+    and!(0xA1, reg_bc, read_lower, "C"),
+    and!(0xA2, reg_de, read_higher, "D"),
+    and!(0xA3, reg_de, read_lower, "E"),
+    and!(0xA4, reg_hl, read_higher, "H"),
+    and!(0xA5, reg_hl, read_lower, "L"),
+    and!(0xA6, hl),
+    and!(0xA7, reg_af, read_a, "A"),
+
+    xor!(0xA8, reg_bc, read_higher, "B"),
+    xor!(0xA9, reg_bc, read_lower, "C"),
+    xor!(0xAA, reg_de, read_higher, "D"),
+    xor!(0xAB, reg_de, read_lower, "E"),
+    xor!(0xAC, reg_hl, read_higher, "H"),
+    xor!(0xAD, reg_hl, read_lower, "L"),
+    xor!(0xAE, hl),
+    xor!(0xAF, reg_af, read_a, "A"),
+
+    or!(0xB0, reg_bc, read_higher, "B"),
+    or!(0xB1, reg_bc, read_lower, "C"),
+    or!(0xB2, reg_de, read_higher, "D"),
+    or!(0xB3, reg_de, read_lower, "E"),
+    or!(0xB4, reg_hl, read_higher, "H"),
+    or!(0xB5, reg_hl, read_lower, "L"),
+    or!(0xB6, hl),
+    or!(0xB7, reg_af, read_a, "A"),
 
     cp!(0xB8, reg_bc, read_higher, "B"),
     cp!(0xB9, reg_bc, read_lower, "C"),
@@ -671,11 +1183,15 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
     cp!(0xBE, hl),
     cp!(0xBF, reg_af, read_a, "A"),
 
+    ret!(0xC0, Flags::Z, false, "NZ"),
     pop!(0xC1, reg_bc, "BC"),
     jump!(0xC2, Flags::Z, false, "NZ"),
     jump!(0xC3),
+    call!(0xC4, Flags::Z, false, "NZ"),
     push!(0xC5, reg_bc, "BC"),
     add!(0xC6, immediate),
+    rst!(0xC7, 0x0000, "00H"),
+    ret!(0xC8, Flags::Z, true, "Z"),
 
     Instruction{opcode: 0xC9, mnemonic: "RET", description: "Return",
         length_in_bytes: 1, cycles: "16", flags_changed: "",
@@ -691,6 +1207,8 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
         length_in_bytes: 0, cycles: "0", flags_changed: "",
         implementation: |cpu| cpu.run_cb_op() },
 
+    call!(0xCC, Flags::Z, true, "Z"),
+
     Instruction{opcode: 0xCD, mnemonic: "CALL", description: "Call",
         length_in_bytes: 3, cycles: "24", flags_changed: "",
         implementation: |cpu| {
@@ -700,11 +1218,35 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
             cpu.program_counter.write(new_pc);
         } },
 
+    adc!(0xCE, immediate),
+    rst!(0xCF, 0x0008, "08H"),
+
+    ret!(0xD0, Flags::C, false, "NC"),
     pop!(0xD1, reg_de, "DE"),
     jump!(0xD2, Flags::C, false, "NC"),
+    illegal!(0xD3),
+    call!(0xD4, Flags::C, false, "NC"),
     push!(0xD5, reg_de, "DE"),
     sub!(0xD6, immediate),
+    rst!(0xD7, 0x0010, "10H"),
+    ret!(0xD8, Flags::C, true, "C"),
+
+    Instruction{opcode: 0xD9, mnemonic: "RETI", description: "Return and enable interrupts",
+        length_in_bytes: 1, cycles: "16", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 16;
+            let new_pc = cpu.pop_u16_from_stack();
+            cpu.program_counter.write(new_pc);
+            // Unlike EI, without waiting for another instruction
+            cpu.interrupts_enabled = true;
+        } },
+
     jump!(0xDA, Flags::C, true, "C"),
+    illegal!(0xDB),
+    call!(0xDC, Flags::C, true, "C"),
+    illegal!(0xDD),
+    sbc!(0xDE, immediate),
+    rst!(0xDF, 0x0018, "18H"),
 
     Instruction{opcode: 0xE0, mnemonic: "LD ($FF00+imm), A", description: "Put A to pointer 0xFF00 + immediate",
         length_in_bytes: 2, cycles: "12", flags_changed: "",
@@ -724,7 +1266,19 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
             cpu.bus.write(address, cpu.reg_af.read_a());
         } },
 
+    illegal!(0xE3),
+    illegal!(0xE4),
     push!(0xE5, reg_hl, "HL"),
+    and!(0xE6, immediate),
+    rst!(0xE7, 0x0020, "20H"),
+
+    Instruction{opcode: 0xE8, mnemonic: "ADD SP,r8", description: "Add signed immediate to SP",
+        length_in_bytes: 2, cycles: "16", flags_changed: "00HC",
+        implementation: |cpu| {
+            cpu.cycle_count += 16;
+            let result = stack_pointer_plus_immediate(cpu);
+            cpu.stack_pointer.write(result);
+        } },
 
     jump!(0xE9, hl),
 
@@ -736,6 +1290,12 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
             cpu.bus.write(immediate, cpu.reg_af.read_a());
         } },
 
+    illegal!(0xEB),
+    illegal!(0xEC),
+    illegal!(0xED),
+    xor!(0xEE, immediate),
+    rst!(0xEF, 0x0028, "28H"),
+
     Instruction{opcode: 0xF0, mnemonic: "LD A, ($FF00+imm)", description: "Put pointer 0xFF00 + immediate to A",
         length_in_bytes: 2, cycles: "12", flags_changed: "",
         implementation: |cpu| {
@@ -746,45 +1306,351 @@ pub const INSTRUCTIONS_NOCB: [Instruction; 162] = [
 
     pop!(0xF1, reg_af, "AF"),
 
+    Instruction{opcode: 0xF2, mnemonic: "LD A, ($FF00+C)", description: "Put pointer 0xFF00 + C to A",
+        length_in_bytes: 1, cycles: "8", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 8;
+            let address = 0xFF00 + (cpu.reg_bc.read_lower() as u16);
+            cpu.reg_af.write_a(cpu.bus.read(address));
+        } },
+
     Instruction{opcode: 0xF3, mnemonic: "DI", description: "Disable interrupts",
         length_in_bytes: 1, cycles: "4", flags_changed: "",
         implementation: |cpu| {
             cpu.cycle_count += 4;
             cpu.interrupts_enabled = false;
+            cpu.enabling_interrupts = false;
         } },
 
+    illegal!(0xF4),
     push!(0xF5, reg_af, "AF"),
+    or!(0xF6, immediate),
+    rst!(0xF7, 0x0030, "30H"),
+
+    Instruction{opcode: 0xF8, mnemonic: "LD HL,SP+r8", description: "Load SP plus signed immediate to HL",
+        length_in_bytes: 2, cycles: "12", flags_changed: "00HC",
+        implementation: |cpu| {
+            cpu.cycle_count += 12;
+            let result = stack_pointer_plus_immediate(cpu);
+            cpu.reg_hl.write(result);
+        } },
+
+    Instruction{opcode: 0xF9, mnemonic: "LD SP,HL", description: "Load HL to SP",
+        length_in_bytes: 1, cycles: "8", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 8;
+            cpu.stack_pointer.write(cpu.reg_hl.read());
+        } },
+
+    Instruction{opcode: 0xFA, mnemonic: "LD A, (a16)", description: "Load immediate pointer to A",
+        length_in_bytes: 3, cycles: "16", flags_changed: "",
+        implementation: |cpu| {
+            cpu.cycle_count += 16;
+            let immediate = cpu.pop_u16_from_pc();
+            cpu.reg_af.write_a(cpu.bus.read(immediate));
+        } },
 
     Instruction{opcode: 0xFB, mnemonic: "EI", description: "Enable interrupts",
         length_in_bytes: 1, cycles: "4", flags_changed: "",
         implementation: |cpu| {
             cpu.cycle_count += 4;
-            cpu.interrupts_enabled = true;
+            cpu.enabling_interrupts = true;
         } },
 
+    illegal!(0xFC),
+    illegal!(0xFD),
     cp!(0xFE, immediate),
+    rst!(0xFF, 0x0038, "38H"),
 ];
 
-pub const INSTRUCTIONS_CB: [Instruction; 8] = [
-
-    rotate_left_trough_carry!(0x10, reg_bc, read_higher, write_higher, "B", regular),
-    rotate_left_trough_carry!(0x11, reg_bc, read_lower, write_lower, "C", regular),
-    rotate_left_trough_carry!(0x12, reg_de, read_higher, write_higher, "D", regular),
-    rotate_left_trough_carry!(0x13, reg_de, read_lower, write_lower, "E", regular),
-    rotate_left_trough_carry!(0x14, reg_hl, read_higher, write_higher, "H", regular),
-    rotate_left_trough_carry!(0x15, reg_hl, read_lower, write_lower, "L", regular),
-
-    rotate_left_trough_carry!(0x17, reg_af, read_lower, write_lower, "A", regular),
-
-
-    Instruction{opcode: 0x7C, mnemonic: "BIT 7,H", description: "Test bit 7 of H",
-        length_in_bytes: 2, cycles: "8", flags_changed: "Z01-",
-        implementation: |cpu| {
-            cpu.cycle_count += 8;
-            cpu.reg_af.flags.remove(Flags::N);
-            cpu.reg_af.flags.insert(Flags::H);
-            cpu.reg_af.flags.set(Flags::Z, (cpu.reg_hl.read_higher() & (1 << 7)) == 0)
-        } },
+pub static INSTRUCTIONS_CB: [Instruction; 256] = [
+    shift!(0x00, "RLC", rotate_left, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x01, "RLC", rotate_left, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x02, "RLC", rotate_left, reg_de, read_higher, write_higher, "D"),
+    shift!(0x03, "RLC", rotate_left, reg_de, read_lower, write_lower, "E"),
+    shift!(0x04, "RLC", rotate_left, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x05, "RLC", rotate_left, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x06, "RLC", rotate_left, hl),
+    shift!(0x07, "RLC", rotate_left, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x08, "RRC", rotate_right, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x09, "RRC", rotate_right, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x0A, "RRC", rotate_right, reg_de, read_higher, write_higher, "D"),
+    shift!(0x0B, "RRC", rotate_right, reg_de, read_lower, write_lower, "E"),
+    shift!(0x0C, "RRC", rotate_right, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x0D, "RRC", rotate_right, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x0E, "RRC", rotate_right, hl),
+    shift!(0x0F, "RRC", rotate_right, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x10, "RL", rotate_left_through_carry, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x11, "RL", rotate_left_through_carry, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x12, "RL", rotate_left_through_carry, reg_de, read_higher, write_higher, "D"),
+    shift!(0x13, "RL", rotate_left_through_carry, reg_de, read_lower, write_lower, "E"),
+    shift!(0x14, "RL", rotate_left_through_carry, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x15, "RL", rotate_left_through_carry, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x16, "RL", rotate_left_through_carry, hl),
+    shift!(0x17, "RL", rotate_left_through_carry, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x18, "RR", rotate_right_through_carry, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x19, "RR", rotate_right_through_carry, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x1A, "RR", rotate_right_through_carry, reg_de, read_higher, write_higher, "D"),
+    shift!(0x1B, "RR", rotate_right_through_carry, reg_de, read_lower, write_lower, "E"),
+    shift!(0x1C, "RR", rotate_right_through_carry, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x1D, "RR", rotate_right_through_carry, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x1E, "RR", rotate_right_through_carry, hl),
+    shift!(0x1F, "RR", rotate_right_through_carry, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x20, "SLA", shift_left_arithmetic, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x21, "SLA", shift_left_arithmetic, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x22, "SLA", shift_left_arithmetic, reg_de, read_higher, write_higher, "D"),
+    shift!(0x23, "SLA", shift_left_arithmetic, reg_de, read_lower, write_lower, "E"),
+    shift!(0x24, "SLA", shift_left_arithmetic, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x25, "SLA", shift_left_arithmetic, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x26, "SLA", shift_left_arithmetic, hl),
+    shift!(0x27, "SLA", shift_left_arithmetic, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x28, "SRA", shift_right_arithmetic, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x29, "SRA", shift_right_arithmetic, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x2A, "SRA", shift_right_arithmetic, reg_de, read_higher, write_higher, "D"),
+    shift!(0x2B, "SRA", shift_right_arithmetic, reg_de, read_lower, write_lower, "E"),
+    shift!(0x2C, "SRA", shift_right_arithmetic, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x2D, "SRA", shift_right_arithmetic, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x2E, "SRA", shift_right_arithmetic, hl),
+    shift!(0x2F, "SRA", shift_right_arithmetic, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x30, "SWAP", swap_nibbles, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x31, "SWAP", swap_nibbles, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x32, "SWAP", swap_nibbles, reg_de, read_higher, write_higher, "D"),
+    shift!(0x33, "SWAP", swap_nibbles, reg_de, read_lower, write_lower, "E"),
+    shift!(0x34, "SWAP", swap_nibbles, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x35, "SWAP", swap_nibbles, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x36, "SWAP", swap_nibbles, hl),
+    shift!(0x37, "SWAP", swap_nibbles, reg_af, read_higher, write_higher, "A"),
+
+    shift!(0x38, "SRL", shift_right_logical, reg_bc, read_higher, write_higher, "B"),
+    shift!(0x39, "SRL", shift_right_logical, reg_bc, read_lower, write_lower, "C"),
+    shift!(0x3A, "SRL", shift_right_logical, reg_de, read_higher, write_higher, "D"),
+    shift!(0x3B, "SRL", shift_right_logical, reg_de, read_lower, write_lower, "E"),
+    shift!(0x3C, "SRL", shift_right_logical, reg_hl, read_higher, write_higher, "H"),
+    shift!(0x3D, "SRL", shift_right_logical, reg_hl, read_lower, write_lower, "L"),
+    shift!(0x3E, "SRL", shift_right_logical, hl),
+    shift!(0x3F, "SRL", shift_right_logical, reg_af, read_higher, write_higher, "A"),
+
+    bit!(0x40, 0, reg_bc, read_higher, "B"),
+    bit!(0x41, 0, reg_bc, read_lower, "C"),
+    bit!(0x42, 0, reg_de, read_higher, "D"),
+    bit!(0x43, 0, reg_de, read_lower, "E"),
+    bit!(0x44, 0, reg_hl, read_higher, "H"),
+    bit!(0x45, 0, reg_hl, read_lower, "L"),
+    bit!(0x46, 0, hl),
+    bit!(0x47, 0, reg_af, read_higher, "A"),
+
+    bit!(0x48, 1, reg_bc, read_higher, "B"),
+    bit!(0x49, 1, reg_bc, read_lower, "C"),
+    bit!(0x4A, 1, reg_de, read_higher, "D"),
+    bit!(0x4B, 1, reg_de, read_lower, "E"),
+    bit!(0x4C, 1, reg_hl, read_higher, "H"),
+    bit!(0x4D, 1, reg_hl, read_lower, "L"),
+    bit!(0x4E, 1, hl),
+    bit!(0x4F, 1, reg_af, read_higher, "A"),
+
+    bit!(0x50, 2, reg_bc, read_higher, "B"),
+    bit!(0x51, 2, reg_bc, read_lower, "C"),
+    bit!(0x52, 2, reg_de, read_higher, "D"),
+    bit!(0x53, 2, reg_de, read_lower, "E"),
+    bit!(0x54, 2, reg_hl, read_higher, "H"),
+    bit!(0x55, 2, reg_hl, read_lower, "L"),
+    bit!(0x56, 2, hl),
+    bit!(0x57, 2, reg_af, read_higher, "A"),
+
+    bit!(0x58, 3, reg_bc, read_higher, "B"),
+    bit!(0x59, 3, reg_bc, read_lower, "C"),
+    bit!(0x5A, 3, reg_de, read_higher, "D"),
+    bit!(0x5B, 3, reg_de, read_lower, "E"),
+    bit!(0x5C, 3, reg_hl, read_higher, "H"),
+    bit!(0x5D, 3, reg_hl, read_lower, "L"),
+    bit!(0x5E, 3, hl),
+    bit!(0x5F, 3, reg_af, read_higher, "A"),
+
+    bit!(0x60, 4, reg_bc, read_higher, "B"),
+    bit!(0x61, 4, reg_bc, read_lower, "C"),
+    bit!(0x62, 4, reg_de, read_higher, "D"),
+    bit!(0x63, 4, reg_de, read_lower, "E"),
+    bit!(0x64, 4, reg_hl, read_higher, "H"),
+    bit!(0x65, 4, reg_hl, read_lower, "L"),
+    bit!(0x66, 4, hl),
+    bit!(0x67, 4, reg_af, read_higher, "A"),
+
+    bit!(0x68, 5, reg_bc, read_higher, "B"),
+    bit!(0x69, 5, reg_bc, read_lower, "C"),
+    bit!(0x6A, 5, reg_de, read_higher, "D"),
+    bit!(0x6B, 5, reg_de, read_lower, "E"),
+    bit!(0x6C, 5, reg_hl, read_higher, "H"),
+    bit!(0x6D, 5, reg_hl, read_lower, "L"),
+    bit!(0x6E, 5, hl),
+    bit!(0x6F, 5, reg_af, read_higher, "A"),
+
+    bit!(0x70, 6, reg_bc, read_higher, "B"),
+    bit!(0x71, 6, reg_bc, read_lower, "C"),
+    bit!(0x72, 6, reg_de, read_higher, "D"),
+    bit!(0x73, 6, reg_de, read_lower, "E"),
+    bit!(0x74, 6, reg_hl, read_higher, "H"),
+    bit!(0x75, 6, reg_hl, read_lower, "L"),
+    bit!(0x76, 6, hl),
+    bit!(0x77, 6, reg_af, read_higher, "A"),
+
+    bit!(0x78, 7, reg_bc, read_higher, "B"),
+    bit!(0x79, 7, reg_bc, read_lower, "C"),
+    bit!(0x7A, 7, reg_de, read_higher, "D"),
+    bit!(0x7B, 7, reg_de, read_lower, "E"),
+    bit!(0x7C, 7, reg_hl, read_higher, "H"),
+    bit!(0x7D, 7, reg_hl, read_lower, "L"),
+    bit!(0x7E, 7, hl),
+    bit!(0x7F, 7, reg_af, read_higher, "A"),
+
+    res!(0x80, 0, reg_bc, read_higher, write_higher, "B"),
+    res!(0x81, 0, reg_bc, read_lower, write_lower, "C"),
+    res!(0x82, 0, reg_de, read_higher, write_higher, "D"),
+    res!(0x83, 0, reg_de, read_lower, write_lower, "E"),
+    res!(0x84, 0, reg_hl, read_higher, write_higher, "H"),
+    res!(0x85, 0, reg_hl, read_lower, write_lower, "L"),
+    res!(0x86, 0, hl),
+    res!(0x87, 0, reg_af, read_higher, write_higher, "A"),
+
+    res!(0x88, 1, reg_bc, read_higher, write_higher, "B"),
+    res!(0x89, 1, reg_bc, read_lower, write_lower, "C"),
+    res!(0x8A, 1, reg_de, read_higher, write_higher, "D"),
+    res!(0x8B, 1, reg_de, read_lower, write_lower, "E"),
+    res!(0x8C, 1, reg_hl, read_higher, write_higher, "H"),
+    res!(0x8D, 1, reg_hl, read_lower, write_lower, "L"),
+    res!(0x8E, 1, hl),
+    res!(0x8F, 1, reg_af, read_higher, write_higher, "A"),
+
+    res!(0x90, 2, reg_bc, read_higher, write_higher, "B"),
+    res!(0x91, 2, reg_bc, read_lower, write_lower, "C"),
+    res!(0x92, 2, reg_de, read_higher, write_higher, "D"),
+    res!(0x93, 2, reg_de, read_lower, write_lower, "E"),
+    res!(0x94, 2, reg_hl, read_higher, write_higher, "H"),
+    res!(0x95, 2, reg_hl, read_lower, write_lower, "L"),
+    res!(0x96, 2, hl),
+    res!(0x97, 2, reg_af, read_higher, write_higher, "A"),
+
+    res!(0x98, 3, reg_bc, read_higher, write_higher, "B"),
+    res!(0x99, 3, reg_bc, read_lower, write_lower, "C"),
+    res!(0x9A, 3, reg_de, read_higher, write_higher, "D"),
+    res!(0x9B, 3, reg_de, read_lower, write_lower, "E"),
+    res!(0x9C, 3, reg_hl, read_higher, write_higher, "H"),
+    res!(0x9D, 3, reg_hl, read_lower, write_lower, "L"),
+    res!(0x9E, 3, hl),
+    res!(0x9F, 3, reg_af, read_higher, write_higher, "A"),
+
+    res!(0xA0, 4, reg_bc, read_higher, write_higher, "B"),
+    res!(0xA1, 4, reg_bc, read_lower, write_lower, "C"),
+    res!(0xA2, 4, reg_de, read_higher, write_higher, "D"),
+    res!(0xA3, 4, reg_de, read_lower, write_lower, "E"),
+    res!(0xA4, 4, reg_hl, read_higher, write_higher, "H"),
+    res!(0xA5, 4, reg_hl, read_lower, write_lower, "L"),
+    res!(0xA6, 4, hl),
+    res!(0xA7, 4, reg_af, read_higher, write_higher, "A"),
+
+    res!(0xA8, 5, reg_bc, read_higher, write_higher, "B"),
+    res!(0xA9, 5, reg_bc, read_lower, write_lower, "C"),
+    res!(0xAA, 5, reg_de, read_higher, write_higher, "D"),
+    res!(0xAB, 5, reg_de, read_lower, write_lower, "E"),
+    res!(0xAC, 5, reg_hl, read_higher, write_higher, "H"),
+    res!(0xAD, 5, reg_hl, read_lower, write_lower, "L"),
+    res!(0xAE, 5, hl),
+    res!(0xAF, 5, reg_af, read_higher, write_higher, "A"),
+
+    res!(0xB0, 6, reg_bc, read_higher, write_higher, "B"),
+    res!(0xB1, 6, reg_bc, read_lower, write_lower, "C"),
+    res!(0xB2, 6, reg_de, read_higher, write_higher, "D"),
+    res!(0xB3, 6, reg_de, read_lower, write_lower, "E"),
+    res!(0xB4, 6, reg_hl, read_higher, write_higher, "H"),
+    res!(0xB5, 6, reg_hl, read_lower, write_lower, "L"),
+    res!(0xB6, 6, hl),
+    res!(0xB7, 6, reg_af, read_higher, write_higher, "A"),
+
+    res!(0xB8, 7, reg_bc, read_higher, write_higher, "B"),
+    res!(0xB9, 7, reg_bc, read_lower, write_lower, "C"),
+    res!(0xBA, 7, reg_de, read_higher, write_higher, "D"),
+    res!(0xBB, 7, reg_de, read_lower, write_lower, "E"),
+    res!(0xBC, 7, reg_hl, read_higher, write_higher, "H"),
+    res!(0xBD, 7, reg_hl, read_lower, write_lower, "L"),
+    res!(0xBE, 7, hl),
+    res!(0xBF, 7, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xC0, 0, reg_bc, read_higher, write_higher, "B"),
+    set!(0xC1, 0, reg_bc, read_lower, write_lower, "C"),
+    set!(0xC2, 0, reg_de, read_higher, write_higher, "D"),
+    set!(0xC3, 0, reg_de, read_lower, write_lower, "E"),
+    set!(0xC4, 0, reg_hl, read_higher, write_higher, "H"),
+    set!(0xC5, 0, reg_hl, read_lower, write_lower, "L"),
+    set!(0xC6, 0, hl),
+    set!(0xC7, 0, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xC8, 1, reg_bc, read_higher, write_higher, "B"),
+    set!(0xC9, 1, reg_bc, read_lower, write_lower, "C"),
+    set!(0xCA, 1, reg_de, read_higher, write_higher, "D"),
+    set!(0xCB, 1, reg_de, read_lower, write_lower, "E"),
+    set!(0xCC, 1, reg_hl, read_higher, write_higher, "H"),
+    set!(0xCD, 1, reg_hl, read_lower, write_lower, "L"),
+    set!(0xCE, 1, hl),
+    set!(0xCF, 1, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xD0, 2, reg_bc, read_higher, write_higher, "B"),
+    set!(0xD1, 2, reg_bc, read_lower, write_lower, "C"),
+    set!(0xD2, 2, reg_de, read_higher, write_higher, "D"),
+    set!(0xD3, 2, reg_de, read_lower, write_lower, "E"),
+    set!(0xD4, 2, reg_hl, read_higher, write_higher, "H"),
+    set!(0xD5, 2, reg_hl, read_lower, write_lower, "L"),
+    set!(0xD6, 2, hl),
+    set!(0xD7, 2, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xD8, 3, reg_bc, read_higher, write_higher, "B"),
+    set!(0xD9, 3, reg_bc, read_lower, write_lower, "C"),
+    set!(0xDA, 3, reg_de, read_higher, write_higher, "D"),
+    set!(0xDB, 3, reg_de, read_lower, write_lower, "E"),
+    set!(0xDC, 3, reg_hl, read_higher, write_higher, "H"),
+    set!(0xDD, 3, reg_hl, read_lower, write_lower, "L"),
+    set!(0xDE, 3, hl),
+    set!(0xDF, 3, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xE0, 4, reg_bc, read_higher, write_higher, "B"),
+    set!(0xE1, 4, reg_bc, read_lower, write_lower, "C"),
+    set!(0xE2, 4, reg_de, read_higher, write_higher, "D"),
+    set!(0xE3, 4, reg_de, read_lower, write_lower, "E"),
+    set!(0xE4, 4, reg_hl, read_higher, write_higher, "H"),
+    set!(0xE5, 4, reg_hl, read_lower, write_lower, "L"),
+    set!(0xE6, 4, hl),
+    set!(0xE7, 4, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xE8, 5, reg_bc, read_higher, write_higher, "B"),
+    set!(0xE9, 5, reg_bc, read_lower, write_lower, "C"),
+    set!(0xEA, 5, reg_de, read_higher, write_higher, "D"),
+    set!(0xEB, 5, reg_de, read_lower, write_lower, "E"),
+    set!(0xEC, 5, reg_hl, read_higher, write_higher, "H"),
+    set!(0xED, 5, reg_hl, read_lower, write_lower, "L"),
+    set!(0xEE, 5, hl),
+    set!(0xEF, 5, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xF0, 6, reg_bc, read_higher, write_higher, "B"),
+    set!(0xF1, 6, reg_bc, read_lower, write_lower, "C"),
+    set!(0xF2, 6, reg_de, read_higher, write_higher, "D"),
+    set!(0xF3, 6, reg_de, read_lower, write_lower, "E"),
+    set!(0xF4, 6, reg_hl, read_higher, write_higher, "H"),
+    set!(0xF5, 6, reg_hl, read_lower, write_lower, "L"),
+    set!(0xF6, 6, hl),
+    set!(0xF7, 6, reg_af, read_higher, write_higher, "A"),
+
+    set!(0xF8, 7, reg_bc, read_higher, write_higher, "B"),
+    set!(0xF9, 7, reg_bc, read_lower, write_lower, "C"),
+    set!(0xFA, 7, reg_de, read_higher, write_higher, "D"),
+    set!(0xFB, 7, reg_de, read_lower, write_lower, "E"),
+    set!(0xFC, 7, reg_hl, read_higher, write_higher, "H"),
+    set!(0xFD, 7, reg_hl, read_lower, write_lower, "L"),
+    set!(0xFE, 7, hl),
+    set!(0xFF, 7, reg_af, read_higher, write_higher, "A"),
 
 ];
 
@@ -800,17 +1666,139 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0xAF], vec![]));
         cpu.reg_af.write_a(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_a(), 0);
         assert_eq!(cpu.reg_af.flags, Flags::Z)
     }
 
+    #[test]
+    fn xor_a_clears_the_other_flags() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xAF], vec![]));
+        cpu.reg_af.flags = Flags::N | Flags::H | Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::Z)
+    }
+
+    #[test]
+    fn xor_b() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xA8], vec![]));
+        cpu.reg_af.write_higher(0xF0);
+        cpu.reg_bc.write_higher(0x3C);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+        assert_eq!(cpu.reg_af.read_higher(), 0xCC);
+    }
+
+    #[test]
+    fn xor_hl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xAE, 0x5A], vec![]));
+        cpu.reg_af.write_higher(0x5A);
+        cpu.reg_hl.write(0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::Z);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn xor_immediate() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xEE, 0xFF], vec![]));
+        cpu.reg_af.write_higher(0x0F);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+        assert_eq!(cpu.reg_af.read_higher(), 0xF0);
+    }
+
+    #[test]
+    fn and_c() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xA1], vec![]));
+        cpu.reg_af.write_higher(0xF0);
+        cpu.reg_bc.write_lower(0x3C);
+        cpu.reg_af.flags = Flags::N | Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0x30);
+    }
+
+    #[test]
+    fn and_a_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xA7], vec![]));
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn and_hl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xA6, 0x0F], vec![]));
+        cpu.reg_af.write_higher(0x3C);
+        cpu.reg_hl.write(0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0x0C);
+    }
+
+    #[test]
+    fn and_immediate_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xE6, 0x0F], vec![]));
+        cpu.reg_af.write_higher(0xF0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn or_l() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xB5], vec![]));
+        cpu.reg_af.write_higher(0x81);
+        cpu.reg_hl.write_lower(0x18);
+        cpu.reg_af.flags = Flags::H | Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+        assert_eq!(cpu.reg_af.read_higher(), 0x99);
+    }
+
+    #[test]
+    fn or_hl_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xB6, 0x00], vec![]));
+        cpu.reg_hl.write(0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.reg_af.flags, Flags::Z);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn or_immediate() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF6, 0x0F], vec![]));
+        cpu.reg_af.write_higher(0x30);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+        assert_eq!(cpu.reg_af.read_higher(), 0x3F);
+    }
+
     #[test]
     fn inc_b() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x04], vec![]));
         cpu.reg_bc.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -822,7 +1810,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x0C], vec![]));
         cpu.reg_bc.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_lower(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -834,7 +1822,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x14], vec![]));
         cpu.reg_de.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_higher(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -846,7 +1834,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x1C], vec![]));
         cpu.reg_de.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_lower(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -858,7 +1846,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x24], vec![]));
         cpu.reg_hl.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_higher(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -870,7 +1858,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x2C], vec![]));
         cpu.reg_hl.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_lower(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -882,7 +1870,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x3C], vec![]));
         cpu.reg_af.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_higher(), 0x50);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(!cpu.reg_af.flags.contains(Flags::N));
@@ -894,7 +1882,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x05], vec![]));
         cpu.reg_bc.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -906,7 +1894,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x0D], vec![]));
         cpu.reg_bc.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_lower(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -918,7 +1906,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x15], vec![]));
         cpu.reg_de.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_higher(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -930,7 +1918,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x1D], vec![]));
         cpu.reg_de.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_lower(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -942,7 +1930,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x25], vec![]));
         cpu.reg_hl.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_higher(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -954,7 +1942,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x2D], vec![]));
         cpu.reg_hl.write_lower(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_lower(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
@@ -966,19 +1954,55 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x3D], vec![]));
         cpu.reg_af.write_higher(0x4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_higher(), 0x4E);
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
         assert!(cpu.reg_af.flags.contains(Flags::N));
         assert!(!cpu.reg_af.flags.contains(Flags::H));
     }
 
+    #[test]
+    fn inc_pointer_hl() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0x34], vec![]));
+        cpu.reg_af.flags = Flags::C;
+        cpu.reg_hl.write(0xC000);
+        cpu.bus.write(0xC000, 0xFF);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.bus.read(0xC000), 0x00);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H | Flags::C);
+    }
+
+    #[test]
+    fn dec_pointer_hl() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0x35], vec![]));
+        cpu.reg_hl.write(0xC000);
+        cpu.bus.write(0xC000, 0x10);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.bus.read(0xC000), 0x0F);
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
+    }
+
+    #[test]
+    fn ld_pointer_hl_immediate() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0x36, 0xA5], vec![]));
+        cpu.reg_hl.write(0xC000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.bus.read(0xC000), 0xA5);
+    }
+
     #[test]
     fn inc_bc() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x03], vec![]));
         cpu.reg_bc.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read(), 0x4F50);
     }
 
@@ -987,7 +2011,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x13], vec![]));
         cpu.reg_de.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read(), 0x4F50);
     }
 
@@ -996,7 +2020,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x23], vec![]));
         cpu.reg_hl.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read(), 0x4F50);
     }
 
@@ -1005,7 +2029,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x33], vec![]));
         cpu.stack_pointer.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.stack_pointer.read(), 0x4F50);
     }
 
@@ -1014,7 +2038,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x0B], vec![]));
         cpu.reg_bc.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read(), 0x4F4E);
     }
 
@@ -1023,7 +2047,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x1B], vec![]));
         cpu.reg_de.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read(), 0x4F4E);
     }
 
@@ -1032,7 +2056,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x2B], vec![]));
         cpu.reg_hl.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read(), 0x4F4E);
     }
 
@@ -1041,15 +2065,25 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x3B], vec![]));
         cpu.stack_pointer.write(0x4F4F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.stack_pointer.read(), 0x4F4E);
     }
 
+    #[test]
+    fn ld_bc_d16() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0x01, 0x34, 0x12], vec![]));
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.reg_bc.read(), 0x1234);
+    }
+
     #[test]
     fn ld_de_d16() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x11, 0x34, 0x12], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read(), 0x1234);
     }
 
@@ -1057,7 +2091,7 @@ mod tests {
     fn ld_hl_d16() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x21, 0x34, 0x12], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read(), 0x1234);
     }
 
@@ -1065,7 +2099,7 @@ mod tests {
     fn ld_sp_d16() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x31, 0x34, 0x12], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.stack_pointer.read(), 0x1234);
     }
 
@@ -1075,7 +2109,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x32], vec![]));
         cpu.reg_af.write_a(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
         assert_eq!(cpu.reg_hl.read(), 0xC122);
     }
@@ -1086,7 +2120,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x22], vec![]));
         cpu.reg_af.write_a(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
         assert_eq!(cpu.reg_hl.read(), 0xC124);
     }
@@ -1097,7 +2131,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x02], vec![]));
         cpu.reg_af.write_a(0xF0);
         cpu.reg_bc.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1107,7 +2141,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x12], vec![]));
         cpu.reg_af.write_a(0xF0);
         cpu.reg_de.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1117,7 +2151,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x70], vec![]));
         cpu.reg_bc.write_higher(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1127,7 +2161,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x71], vec![]));
         cpu.reg_bc.write_lower(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1137,7 +2171,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x72], vec![]));
         cpu.reg_de.write_higher(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1147,7 +2181,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x73], vec![]));
         cpu.reg_de.write_lower(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1156,7 +2190,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x74], vec![]));
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xC1);
     }
 
@@ -1165,7 +2199,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x75], vec![]));
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0x23);
     }
 
@@ -1175,7 +2209,7 @@ mod tests {
             Bus::new_from_vecs(vec![0x77], vec![]));
         cpu.reg_af.write_higher(0xF0);
         cpu.reg_hl.write(0xC123);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.bus.read(0xC123), 0xF0);
     }
 
@@ -1184,18 +2218,40 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0xEA, 0xC0, 0xC1], vec![]));
         cpu.reg_af.write_higher(0xF0);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.bus.read(0xC1C0), 0xF0);
     }
 
+    #[test]
+    fn ld_a_pointer_immediate() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0xFA, 0x03, 0x00, 0x5A], vec![]));
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 16);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.reg_af.read_a(), 0x5A);
+    }
+
+    #[test]
+    fn ld_pointer_immediate_sp() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0x08, 0x00, 0xC1], vec![]));
+        cpu.stack_pointer.write(0xBEEF);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 20);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.bus.read(0xC100), 0xEF);
+        assert_eq!(cpu.bus.read(0xC101), 0xBE);
+    }
+
     #[test]
     fn ld_a_pointer_de() {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x1A, 0x55], vec![]));
         cpu.reg_de.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_a(), 0x55);
     }
 
@@ -1204,7 +2260,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x0A, 0x55], vec![]));
         cpu.reg_bc.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_a(), 0x55);
     }
 
@@ -1213,7 +2269,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x2A, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_a(), 0x55);
         assert_eq!(cpu.reg_hl.read(), 0x0002);
     }
@@ -1223,7 +2279,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x3A, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_af.read_a(), 0x55);
         assert_eq!(cpu.reg_hl.read(), 0x0000);
     }
@@ -1233,7 +2289,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x46, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0x55);
     }
 
@@ -1242,7 +2298,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x4E, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_lower(), 0x55);
     }
 
@@ -1251,7 +2307,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x56, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_higher(), 0x55);
     }
 
@@ -1260,7 +2316,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x5E, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_de.read_lower(), 0x55);
     }
 
@@ -1269,7 +2325,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x66, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_higher(), 0x55);
     }
 
@@ -1278,7 +2334,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0x6E, 0x55], vec![]));
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_hl.read_lower(), 0x55);
     }
 
@@ -1287,7 +2343,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0xE0, 0xF5], vec![]));
         cpu.reg_af.write_a(0xF0);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.bus.read(0xFFF5), 0xF0);
     }
@@ -1297,7 +2353,7 @@ mod tests {
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0xF0, 0xF5], vec![]));
         cpu.bus.write(0xFFF5, 0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.reg_af.read_a(), 0x12);
     }
@@ -1308,16 +2364,29 @@ mod tests {
             Bus::new_from_vecs(vec![0xE2], vec![]));
         cpu.reg_af.write_a(0xCC);
         cpu.reg_bc.write_lower(0xF0);
-        cpu.step();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.bus.read(0xFFF0), 0xCC);
+    }
+
+    #[test]
+    fn ld_a_pointer_c() {
+        let mut cpu = CPU::new(
+            Bus::new_from_vecs(vec![0xF2], vec![]));
+        // SCY
+        cpu.bus.write(0xFF42, 0x3C);
+        cpu.reg_bc.write_lower(0x42);
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
-        assert_eq!(cpu.bus.read(0xFFF0), 0xCC);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.read_a(), 0x3C);
     }
 
     #[test]
     fn bit_7_h_to_one() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, 0x7C], vec![]));
         cpu.reg_hl.write(0xF000);
-        cpu.step();
+        cpu.step().unwrap();
         assert!(!cpu.reg_af.flags.contains(Flags::N));
         assert!(cpu.reg_af.flags.contains(Flags::H));
         assert!(!cpu.reg_af.flags.contains(Flags::Z));
@@ -1327,16 +2396,110 @@ mod tests {
     fn bit_7_h_to_zero() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, 0x7C], vec![]));
         cpu.reg_hl.write(0x0F00);
-        cpu.step();
+        cpu.step().unwrap();
         assert!(!cpu.reg_af.flags.contains(Flags::N));
         assert!(cpu.reg_af.flags.contains(Flags::H));
         assert!(cpu.reg_af.flags.contains(Flags::Z));
     }
 
+    // The low three bits of a CB opcode pick the operand: B, C, D, E, H, L, (HL), A
+    fn write_cb_operand(cpu: &mut CPU, operand: u8, value: u8) {
+        match operand {
+            0 => cpu.reg_bc.write_higher(value),
+            1 => cpu.reg_bc.write_lower(value),
+            2 => cpu.reg_de.write_higher(value),
+            3 => cpu.reg_de.write_lower(value),
+            4 => cpu.reg_hl.write_higher(value),
+            5 => cpu.reg_hl.write_lower(value),
+            6 => { cpu.reg_hl.write(0xC000); cpu.bus.write(0xC000, value) },
+            _ => cpu.reg_af.write_higher(value),
+        }
+    }
+
+    #[test]
+    fn bit_all_opcodes() {
+        for opcode in 0x40..=0x7F {
+            let bit = (opcode >> 3) & 0x07;
+            let operand = opcode & 0x07;
+            let cycles = if operand == 6 { 12 } else { 8 };
+            for (value, zero) in [(1 << bit, false), (!(1 << bit), true)] {
+                let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, opcode], vec![]));
+                cpu.reg_af.flags = Flags::N | Flags::C;
+                write_cb_operand(&mut cpu, operand, value);
+                cpu.step().unwrap();
+                assert_eq!(cpu.cycle_count, cycles, "CB {:02X}", opcode);
+                assert_eq!(cpu.program_counter.read(), 0x0002, "CB {:02X}", opcode);
+                let expected = if zero { Flags::Z | Flags::H | Flags::C } else { Flags::H | Flags::C };
+                assert_eq!(cpu.reg_af.flags, expected, "CB {:02X} with {:08b}", opcode, value);
+            }
+        }
+    }
+
+    fn read_cb_operand(cpu: &mut CPU, operand: u8) -> u8 {
+        match operand {
+            0 => cpu.reg_bc.read_higher(),
+            1 => cpu.reg_bc.read_lower(),
+            2 => cpu.reg_de.read_higher(),
+            3 => cpu.reg_de.read_lower(),
+            4 => cpu.reg_hl.read_higher(),
+            5 => cpu.reg_hl.read_lower(),
+            6 => cpu.bus.read(0xC000),
+            _ => cpu.reg_af.read_higher(),
+        }
+    }
+
+    #[test]
+    fn res_and_set_all_opcodes() {
+        for opcode in 0x80..=0xFF {
+            let bit = (opcode >> 3) & 0x07;
+            let operand = opcode & 0x07;
+            let set = opcode >= 0xC0;
+            let cycles = if operand == 6 { 16 } else { 8 };
+            let (value, expected) = if set { (0x00, 1 << bit) } else { (0xFF, !(1 << bit)) };
+            let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, opcode], vec![]));
+            cpu.reg_af.flags = Flags::Z | Flags::C;
+            write_cb_operand(&mut cpu, operand, value);
+            cpu.step().unwrap();
+            assert_eq!(cpu.cycle_count, cycles, "CB {:02X}", opcode);
+            assert_eq!(cpu.program_counter.read(), 0x0002, "CB {:02X}", opcode);
+            assert_eq!(read_cb_operand(&mut cpu, operand), expected, "CB {:02X}", opcode);
+            assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::C, "CB {:02X}", opcode);
+        }
+    }
+
+    #[test]
+    fn rotates_and_shifts_all_opcodes() {
+        // Input, carry in, result and flags for each group of eight, in opcode order
+        let cases: [[(u8, bool, u8, Flags); 2]; 8] = [
+            [(0x85, false, 0x0B, Flags::C), (0x00, true, 0x00, Flags::Z)],
+            [(0x01, false, 0x80, Flags::C), (0x00, true, 0x00, Flags::Z)],
+            [(0x80, false, 0x00, Flags::Z | Flags::C), (0x01, true, 0x03, Flags::empty())],
+            [(0x01, false, 0x00, Flags::Z | Flags::C), (0x00, true, 0x80, Flags::empty())],
+            [(0x81, false, 0x02, Flags::C), (0x80, false, 0x00, Flags::Z | Flags::C)],
+            [(0x81, false, 0xC0, Flags::C), (0x01, false, 0x00, Flags::Z | Flags::C)],
+            [(0xF1, true, 0x1F, Flags::empty()), (0x00, false, 0x00, Flags::Z)],
+            [(0x81, false, 0x40, Flags::C), (0x01, true, 0x00, Flags::Z | Flags::C)],
+        ];
+        for opcode in 0x00..=0x3F {
+            let operand = opcode & 0x07;
+            let cycles = if operand == 6 { 16 } else { 8 };
+            for &(value, carry_in, expected, flags) in &cases[opcode as usize >> 3] {
+                let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, opcode], vec![]));
+                cpu.reg_af.flags = if carry_in { Flags::N | Flags::H | Flags::C } else { Flags::N | Flags::H };
+                write_cb_operand(&mut cpu, operand, value);
+                cpu.step().unwrap();
+                assert_eq!(cpu.cycle_count, cycles, "CB {:02X}", opcode);
+                assert_eq!(cpu.program_counter.read(), 0x0002, "CB {:02X}", opcode);
+                assert_eq!(read_cb_operand(&mut cpu, operand), expected, "CB {:02X} with {:08b}", opcode, value);
+                assert_eq!(cpu.reg_af.flags, flags, "CB {:02X} with {:08b}", opcode, value);
+            }
+        }
+    }
+
     #[test]
     fn jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC3, 0x12, 0x34], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x3412);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1345,7 +2508,7 @@ mod tests {
     fn jpnz() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC2, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::Z, false);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x3412);
         assert_eq!(cpu.cycle_count, 16);
     }
@@ -1354,7 +2517,7 @@ mod tests {
     fn jpnz_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC2, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::Z, true);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1363,7 +2526,7 @@ mod tests {
     fn jpz() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCA, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::Z, true);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x3412);
         assert_eq!(cpu.cycle_count, 16);
     }
@@ -1372,7 +2535,7 @@ mod tests {
     fn jpz_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCA, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::Z, false);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1381,7 +2544,7 @@ mod tests {
     fn jpnc() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD2, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::C, false);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x3412);
         assert_eq!(cpu.cycle_count, 16);
     }
@@ -1390,7 +2553,7 @@ mod tests {
     fn jpnc_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD2, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::C, true);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1399,7 +2562,7 @@ mod tests {
     fn jpc() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xDA, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::C, true);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x3412);
         assert_eq!(cpu.cycle_count, 16);
     }
@@ -1408,7 +2571,7 @@ mod tests {
     fn jpc_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xDA, 0x12, 0x34], vec![]));
         cpu.reg_af.flags.set(Flags::C, false);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1417,7 +2580,7 @@ mod tests {
     fn jp_hl() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xE9, 0x12, 0x34], vec![]));
         cpu.reg_hl.write(0x0002);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x34);
         assert_eq!(cpu.cycle_count, 4);
     }
@@ -1425,7 +2588,7 @@ mod tests {
     #[test]
     fn jr() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x18, 0x33], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x35);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1434,7 +2597,7 @@ mod tests {
     fn jrnz_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x20, 0x33], vec![]));
         cpu.reg_af.flags.insert(Flags::Z);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x02);
         assert_eq!(cpu.cycle_count, 8);
     }
@@ -1443,7 +2606,7 @@ mod tests {
     fn jrnz_jump_positive() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x20, 0x33], vec![]));
         cpu.reg_af.flags.remove(Flags::Z);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x35);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1453,7 +2616,7 @@ mod tests {
         // Jump -3
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x20, 0xFD], vec![]));
         cpu.reg_af.flags.remove(Flags::Z);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0xFFFF);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1462,7 +2625,7 @@ mod tests {
     fn jrz_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x28, 0x33], vec![]));
         cpu.reg_af.flags.remove(Flags::Z);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x02);
         assert_eq!(cpu.cycle_count, 8);
     }
@@ -1471,7 +2634,7 @@ mod tests {
     fn jrz_jump_positive() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x28, 0x33], vec![]));
         cpu.reg_af.flags.insert(Flags::Z);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x35);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1480,7 +2643,7 @@ mod tests {
     fn jrnc_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x30, 0x33], vec![]));
         cpu.reg_af.flags.insert(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x02);
         assert_eq!(cpu.cycle_count, 8);
     }
@@ -1489,7 +2652,7 @@ mod tests {
     fn jrnc_jump_positive() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x30, 0x33], vec![]));
         cpu.reg_af.flags.remove(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x35);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1498,7 +2661,7 @@ mod tests {
     fn jrc_no_jump() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x38, 0x33], vec![]));
         cpu.reg_af.flags.remove(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x02);
         assert_eq!(cpu.cycle_count, 8);
     }
@@ -1507,7 +2670,7 @@ mod tests {
     fn jrc_jump_positive() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x38, 0x33], vec![]));
         cpu.reg_af.flags.insert(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x35);
         assert_eq!(cpu.cycle_count, 12);
     }
@@ -1516,7 +2679,19 @@ mod tests {
     fn call() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCD, 0x34, 0x12], vec![]));
         cpu.stack_pointer.write(0xD000);
-        cpu.step();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 24);
+        assert_eq!(cpu.program_counter.read(), 0x1234);
+        assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
+        assert_eq!(cpu.bus.read(0xCFFF), 0x03);
+        assert_eq!(cpu.bus.read(0xCFFE), 0x00);
+    }
+
+    #[test]
+    fn call_nz_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC4, 0x34, 0x12], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 24);
         assert_eq!(cpu.program_counter.read(), 0x1234);
         assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
@@ -1524,12 +2699,80 @@ mod tests {
         assert_eq!(cpu.bus.read(0xCFFE), 0x00);
     }
 
+    #[test]
+    fn call_z_not_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCC, 0x34, 0x12], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.bus.write(0xCFFF, 0xAA);
+        cpu.bus.write(0xCFFE, 0xBB);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.stack_pointer.read(), 0xD000);
+        assert_eq!(cpu.bus.read(0xCFFF), 0xAA);
+        assert_eq!(cpu.bus.read(0xCFFE), 0xBB);
+    }
+
+    #[test]
+    fn call_nc_not_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD4, 0x34, 0x12], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.stack_pointer.read(), 0xD000);
+    }
+
+    #[test]
+    fn call_c_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x00, 0xDC, 0x34, 0x12], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4 + 24);
+        assert_eq!(cpu.program_counter.read(), 0x1234);
+        assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
+        assert_eq!(cpu.bus.read(0xCFFF), 0x04);
+        assert_eq!(cpu.bus.read(0xCFFE), 0x00);
+    }
+
+    #[test]
+    fn rst_38() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x00, 0xFF], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4 + 16);
+        assert_eq!(cpu.program_counter.read(), 0x0038);
+        assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
+        assert_eq!(cpu.bus.read(0xCFFF), 0x02);
+        assert_eq!(cpu.bus.read(0xCFFE), 0x00);
+    }
+
+    #[test]
+    fn rst_vectors() {
+        for (opcode, vector) in [(0xC7, 0x00), (0xCF, 0x08), (0xD7, 0x10), (0xDF, 0x18),
+                                 (0xE7, 0x20), (0xEF, 0x28), (0xF7, 0x30), (0xFF, 0x38)] {
+            let mut cpu = CPU::new(Bus::new_from_vecs(vec![opcode], vec![]));
+            cpu.stack_pointer.write(0xD000);
+            cpu.step().unwrap();
+            assert_eq!(cpu.program_counter.read(), vector, "RST {:02X}", opcode);
+            // RET comes back after the RST
+            cpu.program_counter.write(0xC000);
+            cpu.bus.write(0xC000, 0xC9);
+            cpu.step().unwrap();
+            assert_eq!(cpu.program_counter.read(), 0x0001);
+        }
+    }
+
     #[test]
     fn ret() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC9], vec![]));
         cpu.stack_pointer.write(0xD000);
         cpu.push_u16_to_stack(0x1234);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x1234);
         assert_eq!(cpu.stack_pointer.read(), 0xD000);
@@ -1539,7 +2782,7 @@ mod tests {
     fn ld_b_b() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x40], vec![]));
         cpu.reg_bc.write_higher(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5);
     }
 
@@ -1547,7 +2790,7 @@ mod tests {
     fn ld_b_c() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x41], vec![]));
         cpu.reg_bc.write_lower(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5);
     }
 
@@ -1555,7 +2798,7 @@ mod tests {
     fn ld_b_d() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x42], vec![]));
         cpu.reg_de.write_higher(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5);
     }
 
@@ -1563,7 +2806,7 @@ mod tests {
     fn ld_b_e() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x43], vec![]));
         cpu.reg_de.write_lower(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5);
     }
 
@@ -1571,7 +2814,7 @@ mod tests {
     fn ld_b_h() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x44], vec![]));
         cpu.reg_hl.write_higher(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5)
     }
 
@@ -1579,7 +2822,7 @@ mod tests {
     fn ld_b_l() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x45], vec![]));
         cpu.reg_hl.write_lower(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5)
     }
 
@@ -1587,14 +2830,14 @@ mod tests {
     fn ld_b_a() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x47], vec![]));
         cpu.reg_af.write_higher(0xF5);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.reg_bc.read_higher(), 0xF5)
     }
 
     #[test]
     fn ld_b_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x06, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_bc.read_higher(), 0xBB);
     }
@@ -1602,7 +2845,7 @@ mod tests {
     #[test]
     fn ld_c_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x0E, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_bc.read_lower(), 0xBB);
     }
@@ -1610,7 +2853,7 @@ mod tests {
     #[test]
     fn ld_d_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x16, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_de.read_higher(), 0xBB);
     }
@@ -1618,7 +2861,7 @@ mod tests {
     #[test]
     fn ld_e_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x1E, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_de.read_lower(), 0xBB);
     }
@@ -1626,7 +2869,7 @@ mod tests {
     #[test]
     fn ld_h_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x26, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_hl.read_higher(), 0xBB);
     }
@@ -1634,7 +2877,7 @@ mod tests {
     #[test]
     fn ld_l_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x2E, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_hl.read_lower(), 0xBB);
     }
@@ -1642,7 +2885,7 @@ mod tests {
     #[test]
     fn ld_a_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x3E, 0xBB], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.reg_af.read_higher(), 0xBB);
     }
@@ -1652,7 +2895,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC5], vec![]));
         cpu.stack_pointer.write(0xD000);
         cpu.reg_bc.write(0x1234);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
@@ -1665,7 +2908,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD5], vec![]));
         cpu.stack_pointer.write(0xD000);
         cpu.reg_de.write(0x1234);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
@@ -1678,7 +2921,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xE5], vec![]));
         cpu.stack_pointer.write(0xD000);
         cpu.reg_hl.write(0x1234);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
@@ -1691,7 +2934,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF5], vec![]));
         cpu.stack_pointer.write(0xD000);
         cpu.reg_af.write(0x1234);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 16);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
@@ -1705,7 +2948,7 @@ mod tests {
         cpu.stack_pointer.write(0xCFFE);
         cpu.bus.write(0xCFFF, 0x34);
         cpu.bus.write(0xCFFE, 0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xD000);
@@ -1718,7 +2961,7 @@ mod tests {
         cpu.stack_pointer.write(0xCFFE);
         cpu.bus.write(0xCFFF, 0x34);
         cpu.bus.write(0xCFFE, 0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xD000);
@@ -1731,7 +2974,7 @@ mod tests {
         cpu.stack_pointer.write(0xCFFE);
         cpu.bus.write(0xCFFF, 0x34);
         cpu.bus.write(0xCFFE, 0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xD000);
@@ -1744,7 +2987,7 @@ mod tests {
         cpu.stack_pointer.write(0xCFFE);
         cpu.bus.write(0xCFFF, 0x34);
         cpu.bus.write(0xCFFE, 0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 12);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.stack_pointer.read(), 0xD000);
@@ -1755,7 +2998,7 @@ mod tests {
     fn rl_c_no_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, 0x11], vec![]));
         cpu.reg_bc.write_lower(0b01010010);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_bc.read_lower(), 0b10100100);
@@ -1766,7 +3009,7 @@ mod tests {
     fn rl_c_to_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, 0x11], vec![]));
         cpu.reg_bc.write_lower(0b11010010);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_bc.read_lower(), 0b10100100);
@@ -1778,7 +3021,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCB, 0x11], vec![]));
         cpu.reg_bc.write_lower(0);
         cpu.reg_af.flags.insert(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_bc.read_lower(), 1);
@@ -1789,7 +3032,7 @@ mod tests {
     fn rla_no_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x17], vec![]));
         cpu.reg_af.write_higher(0b01010010);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.read_higher(), 0b10100100);
@@ -1800,7 +3043,7 @@ mod tests {
     fn rla_to_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x17], vec![]));
         cpu.reg_af.write_higher(0b11010010);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.read_higher(), 0b10100100);
@@ -1812,18 +3055,70 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x17], vec![]));
         cpu.reg_af.write_higher(0);
         cpu.reg_af.flags.insert(Flags::C);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.read_higher(), 1);
         assert_eq!(cpu.reg_af.flags, Flags::empty());
     }
 
+    #[test]
+    fn rlca() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x07], vec![]));
+        cpu.reg_af.write_a(0b10000101);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.read_a(), 0b00001011);
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+    }
+
+    #[test]
+    fn rrca_clears_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x0F], vec![]));
+        cpu.reg_af.write_a(0);
+        cpu.reg_af.flags = Flags::Z | Flags::N | Flags::H | Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.reg_af.read_a(), 0);
+        assert_eq!(cpu.reg_af.flags, Flags::empty());
+    }
+
+    #[test]
+    fn rrca_to_carry() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x0F], vec![]));
+        cpu.reg_af.write_a(0b00000011);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_a(), 0b10000001);
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+    }
+
+    #[test]
+    fn rra_from_carry() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x1F], vec![]));
+        cpu.reg_af.write_a(0b00000010);
+        cpu.reg_af.flags.insert(Flags::C);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.read_a(), 0b10000001);
+        assert_eq!(cpu.reg_af.flags, Flags::empty());
+    }
+
+    #[test]
+    fn rra_to_carry_without_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x1F], vec![]));
+        cpu.reg_af.write_a(0b00000001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_a(), 0);
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+    }
+
     #[test]
     fn cp_a_zero() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBF], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::N);
@@ -1834,7 +3129,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xB8], vec![]));
         cpu.reg_af.write_higher(0x13);
         cpu.reg_bc.write_higher(0x04);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
@@ -1845,7 +3140,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xB9], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_bc.write_lower(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N);
@@ -1856,7 +3151,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBA], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_de.write_higher(0x06);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -1867,7 +3162,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBB], vec![]));
         cpu.reg_af.write_higher(0x13);
         cpu.reg_de.write_lower(0x04);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
@@ -1878,7 +3173,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBC], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_hl.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N);
@@ -1889,7 +3184,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBD], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_hl.write_lower(0x06);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -1900,7 +3195,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xBE, 0x06], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -1910,7 +3205,7 @@ mod tests {
     fn cp_immediate_zero() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFE, 0x10], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::N);
@@ -1920,7 +3215,7 @@ mod tests {
     fn cp_immediate_half_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFE, 0x9], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
@@ -1930,7 +3225,7 @@ mod tests {
     fn cp_immediate_no_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFE, 0x1], vec![]));
         cpu.reg_af.write_higher(0x11);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::N);
@@ -1940,7 +3235,7 @@ mod tests {
     fn cp_immediate_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFE, 0x11], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -1950,7 +3245,7 @@ mod tests {
     fn sub_a_zero() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x97], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::N);
@@ -1962,7 +3257,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x90], vec![]));
         cpu.reg_af.write_higher(0x13);
         cpu.reg_bc.write_higher(0x04);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
@@ -1974,7 +3269,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x91], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_bc.write_lower(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N);
@@ -1986,7 +3281,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x92], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_de.write_higher(0x06);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -1998,7 +3293,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x93], vec![]));
         cpu.reg_af.write_higher(0x13);
         cpu.reg_de.write_lower(0x04);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
@@ -2010,7 +3305,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x94], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_hl.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::N);
@@ -2022,7 +3317,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x95], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_hl.write_lower(0x06);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -2034,7 +3329,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x96, 0x06], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -2045,7 +3340,7 @@ mod tests {
     fn sub_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD6, 0x06], vec![]));
         cpu.reg_af.write_higher(0x05);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H | Flags::N);
@@ -2056,7 +3351,7 @@ mod tests {
     fn add_a_no_carry() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x87], vec![]));
         cpu.reg_af.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::default());
@@ -2068,7 +3363,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x80], vec![]));
         cpu.reg_af.write_higher(0x13);
         cpu.reg_bc.write_higher(0x0F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::H);
@@ -2080,7 +3375,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x81], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_bc.write_lower(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::default());
@@ -2092,7 +3387,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x82], vec![]));
         cpu.reg_af.write_higher(0xFF);
         cpu.reg_de.write_higher(0x01);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::C | Flags::H);
@@ -2104,7 +3399,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x83], vec![]));
         cpu.reg_af.write_higher(0x5F);
         cpu.reg_de.write_lower(0x12);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::H);
@@ -2116,7 +3411,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x84], vec![]));
         cpu.reg_af.write_higher(0x11);
         cpu.reg_hl.write_higher(0x10);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::default());
@@ -2128,7 +3423,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x85], vec![]));
         cpu.reg_af.write_higher(0xF5);
         cpu.reg_hl.write_lower(0x0F);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H);
@@ -2140,7 +3435,7 @@ mod tests {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x86, 0xFF], vec![]));
         cpu.reg_af.write_higher(0x05);
         cpu.reg_hl.write(0x0001);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.reg_af.flags, Flags::C | Flags::H);
@@ -2151,31 +3446,403 @@ mod tests {
     fn add_immediate() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC6, 0x06], vec![]));
         cpu.reg_af.write_higher(0x05);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 8);
         assert_eq!(cpu.program_counter.read(), 0x0002);
         assert_eq!(cpu.reg_af.flags, Flags::default());
         assert_eq!(cpu.reg_af.read_higher(), 0x0B);
     }
 
+    #[test]
+    fn adc_b_without_carry_in() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x88], vec![]));
+        cpu.reg_af.write_higher(0x05);
+        cpu.reg_bc.write_higher(0x06);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+        assert_eq!(cpu.reg_af.read_higher(), 0x0B);
+    }
+
+    #[test]
+    fn adc_c_carry_in_causes_half_carry() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x89], vec![]));
+        cpu.reg_af.write_higher(0x08);
+        cpu.reg_bc.write_lower(0x07);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0x10);
+    }
+
+    #[test]
+    fn adc_d_carry_in_causes_carry_and_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x8A], vec![]));
+        cpu.reg_af.write_higher(0xF0);
+        cpu.reg_de.write_higher(0x0F);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H | Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn adc_a_with_carry_in() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x8F], vec![]));
+        cpu.reg_af.write_higher(0x80);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0x01);
+    }
+
+    #[test]
+    fn adc_hl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x8E, 0xFF], vec![]));
+        cpu.reg_af.write_higher(0x00);
+        cpu.reg_hl.write(0x0001);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H | Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn adc_immediate() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCE, 0x3A], vec![]));
+        cpu.reg_af.write_higher(0xE1);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0x1C);
+    }
+
+    #[test]
+    fn sbc_e_without_carry_in() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x9B], vec![]));
+        cpu.reg_af.write_higher(0x13);
+        cpu.reg_de.write_lower(0x04);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0x0F);
+    }
+
+    #[test]
+    fn sbc_h_carry_in_causes_half_carry() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x9C], vec![]));
+        cpu.reg_af.write_higher(0x10);
+        cpu.reg_hl.write_higher(0x00);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0x0F);
+    }
+
+    #[test]
+    fn sbc_l_carry_in_causes_carry() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x9D], vec![]));
+        cpu.reg_af.write_higher(0x05);
+        cpu.reg_hl.write_lower(0x05);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H | Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0xFF);
+    }
+
+    #[test]
+    fn sbc_a_with_carry_in() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x9F], vec![]));
+        cpu.reg_af.write_higher(0x42);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::H | Flags::C);
+        assert_eq!(cpu.reg_af.read_higher(), 0xFF);
+    }
+
+    #[test]
+    fn sbc_hl_zero() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x9E, 0x0F], vec![]));
+        cpu.reg_af.write_higher(0x10);
+        cpu.reg_hl.write(0x0001);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::N | Flags::H);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn sbc_immediate() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xDE, 0x2A], vec![]));
+        cpu.reg_af.write_higher(0x3B);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.flags, Flags::N);
+        assert_eq!(cpu.reg_af.read_higher(), 0x10);
+    }
+
+    #[test]
+    fn daa_after_add() {
+        // 0x38 + 0x45 = 0x7D, which is 83 in BCD
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC6, 0x45, 0x27], vec![]));
+        cpu.reg_af.write_higher(0x38);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.reg_af.read_higher(), 0x83);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+    }
+
+    #[test]
+    fn daa_after_add_with_half_carry() {
+        // 0x09 + 0x09 = 0x12 with H, which is 18 in BCD
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC6, 0x09, 0x27], vec![]));
+        cpu.reg_af.write_higher(0x09);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_higher(), 0x18);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+    }
+
+    #[test]
+    fn daa_after_add_overflowing_to_zero() {
+        // 99 + 1 = 100, leaving 00 and the carry
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC6, 0x01, 0x27], vec![]));
+        cpu.reg_af.write_higher(0x99);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_higher(), 0x00);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::C);
+    }
+
+    #[test]
+    fn daa_after_sub_with_half_carry() {
+        // 42 - 15 = 27
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD6, 0x15, 0x27], vec![]));
+        cpu.reg_af.write_higher(0x42);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_higher(), 0x27);
+        assert_eq!(cpu.reg_af.flags, Flags::N);
+    }
+
+    #[test]
+    fn daa_after_sub_with_carry() {
+        // 10 - 20 = -10, which wraps to 90 with the carry
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD6, 0x20, 0x27], vec![]));
+        cpu.reg_af.write_higher(0x10);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_higher(), 0x90);
+        assert_eq!(cpu.reg_af.flags, Flags::N | Flags::C);
+    }
+
+    #[test]
+    fn cpl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x2F], vec![]));
+        cpu.reg_af.write_higher(0x35);
+        cpu.reg_af.flags = Flags::Z | Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.reg_af.read_higher(), 0xCA);
+        assert_eq!(cpu.reg_af.flags, Flags::all());
+    }
+
+    #[test]
+    fn scf() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x37], vec![]));
+        cpu.reg_af.flags = Flags::Z | Flags::N | Flags::H;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::C);
+    }
+
+    #[test]
+    fn ccf() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x3F, 0x3F], vec![]));
+        cpu.reg_af.flags = Flags::N | Flags::H;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4);
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+    }
+
+    #[test]
+    fn ret_nz_taken() {
+        // CALL 0005; NOP; NOP; RET NZ
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCD, 0x05, 0x00, 0x00, 0x00, 0xC0], vec![]));
+        cpu.stack_pointer.write(0xFFFE);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 24 + 20);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.stack_pointer.read(), 0xFFFE);
+    }
+
+    #[test]
+    fn ret_z_not_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xC8], vec![]));
+        cpu.stack_pointer.write(0xFFFC);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.stack_pointer.read(), 0xFFFC);
+    }
+
+    #[test]
+    fn ret_nc_not_taken() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xD0], vec![]));
+        cpu.stack_pointer.write(0xFFFC);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.program_counter.read(), 0x0001);
+        assert_eq!(cpu.stack_pointer.read(), 0xFFFC);
+    }
+
+    #[test]
+    fn ret_c_taken() {
+        // CALL 0005; NOP; NOP; RET C
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xCD, 0x05, 0x00, 0x00, 0x00, 0xD8], vec![]));
+        cpu.stack_pointer.write(0xFFFE);
+        cpu.reg_af.flags = Flags::C;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 24 + 20);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.stack_pointer.read(), 0xFFFE);
+    }
+
+    #[test]
+    fn reti_enables_interrupts() {
+        // DI; CALL 0006; NOP; NOP; RETI
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF3, 0xCD, 0x06, 0x00, 0x00, 0x00, 0xD9], vec![]));
+        cpu.stack_pointer.write(0xFFFE);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(!cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 4 + 24 + 16);
+        assert_eq!(cpu.program_counter.read(), 0x0004);
+        assert_eq!(cpu.stack_pointer.read(), 0xFFFE);
+        assert!(cpu.interrupts_enabled);
+    }
+
+    #[test]
+    fn add_hl_bc_half_carry_from_bit_11() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x09], vec![]));
+        cpu.reg_hl.write(0x0FFF);
+        cpu.reg_bc.write(0x0001);
+        cpu.reg_af.flags = Flags::Z | Flags::N;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.reg_hl.read(), 0x1000);
+        assert_eq!(cpu.reg_af.flags, Flags::Z | Flags::H);
+    }
+
+    #[test]
+    fn add_hl_de_carry_from_bit_15() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x19], vec![]));
+        cpu.reg_hl.write(0x8000);
+        cpu.reg_de.write(0x8000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_hl.read(), 0x0000);
+        // Z stays clear even though the result is zero
+        assert_eq!(cpu.reg_af.flags, Flags::C);
+    }
+
+    #[test]
+    fn add_hl_hl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x29], vec![]));
+        cpu.reg_hl.write(0x8A23);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_hl.read(), 0x1446);
+        assert_eq!(cpu.reg_af.flags, Flags::H | Flags::C);
+    }
+
+    #[test]
+    fn add_hl_sp_no_carry_from_bit_7() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x39], vec![]));
+        cpu.reg_hl.write(0x00FF);
+        cpu.stack_pointer.write(0x0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_hl.read(), 0x0100);
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+    }
+
+    #[test]
+    fn add_sp_positive() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xE8, 0x08], vec![]));
+        cpu.stack_pointer.write(0xFFF8);
+        cpu.reg_af.flags = Flags::Z | Flags::N;
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 16);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.stack_pointer.read(), 0x0000);
+        assert_eq!(cpu.reg_af.flags, Flags::H | Flags::C);
+    }
+
+    #[test]
+    fn add_sp_negative() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xE8, 0xFE], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
+        // 0x00 + 0xFE carries out of neither bit 3 nor bit 7
+        assert_eq!(cpu.reg_af.flags, Flags::default());
+    }
+
+    #[test]
+    fn ld_hl_sp_plus_offset() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF8, 0xFF], vec![]));
+        cpu.stack_pointer.write(0xD001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 12);
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_hl.read(), 0xD000);
+        assert_eq!(cpu.stack_pointer.read(), 0xD001);
+        assert_eq!(cpu.reg_af.flags, Flags::H | Flags::C);
+    }
+
+    #[test]
+    fn ld_sp_hl() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF9], vec![]));
+        cpu.reg_hl.write(0xDFF0);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 8);
+        assert_eq!(cpu.stack_pointer.read(), 0xDFF0);
+    }
+
     #[test]
     fn disable_interrupts() {
         let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF3], vec![]));
         cpu.interrupts_enabled = true;
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
-        assert_eq!(cpu.interrupts_enabled, false);
+        assert!(!cpu.interrupts_enabled);
     }
 
     #[test]
     fn enable_interrupts() {
-        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFB], vec![]));
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFB, 0x00], vec![]));
         cpu.interrupts_enabled = false;
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.cycle_count, 4);
         assert_eq!(cpu.program_counter.read(), 0x0001);
-        assert_eq!(cpu.interrupts_enabled, true);
+        assert!(!cpu.interrupts_enabled);
+        cpu.step().unwrap();
+        assert!(cpu.interrupts_enabled);
     }
 
 }
@@ -1,10 +1,48 @@
 pub mod register;
 pub mod instruction;
+pub mod stats;
+pub mod block_cache;
 
-use super::bus::Bus;
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+use crate::error::EmulationError;
+use super::bus::{Bus, CodeWatch};
 use register::*;
 use instruction::*;
+use stats::{Opcode, OpcodeStats};
+use block_cache::{BlockCache, CachedOp, Operands};
+
+pub const NOT_IMPLEMENTED_MNEMONIC: &str = "NOT IMPLEMENTED";
+// VBlank's handler, the others follow every 8 bytes in priority order
+const FIRST_INTERRUPT_VECTOR: u16 = 0x0040;
+const INTERRUPT_DISPATCH_CYCLES: u64 = 20;
+
 
+// Everything about the CPU that carries over from one instruction to the next
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CpuState {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+    pub sp: u16,
+    pub pc: u16,
+    pub interrupts_enabled: bool,
+    pub cycle_count: u64,
+    pub instruction_count: u64,
+    #[serde(default)]
+    pub halted: bool,
+    #[serde(default)]
+    pub halt_bug: bool,
+    #[serde(default)]
+    pub stopped: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub enabling_interrupts: bool,
+}
 
 pub struct CPU <'a> {
     pub reg_af: AFRegister,
@@ -15,6 +53,8 @@ pub struct CPU <'a> {
     pub program_counter: Register16bit,
     pub bus: Bus,
     pub cycle_count: u64,
+    pub instruction_count: u64,
+    pub opcode_stats: OpcodeStats,
     pub instruction_vector: Vec<Instruction<'a>>, // FIXME this should be removed when all instructions are implemented
     pub cb_instruction_vector: Vec<Instruction<'a>>, // FIXME this should be removed when all instructions are implemented
     pub debug: bool,
@@ -22,36 +62,55 @@ pub struct CPU <'a> {
     reg_instruction_is_cb: bool,
     instruction_address: u16,
     interrupts_enabled: bool,
+    // Set by EI, interrupts are only enabled once the instruction after it has run
+    enabling_interrupts: bool,
+    // Set by HALT until an enabled interrupt is requested
+    halted: bool,
+    // HALT with interrupts disabled but one already pending does not halt, the next opcode is
+    // read without moving the PC so the byte after HALT runs twice
+    halt_bug: bool,
+    // Set by STOP until a button is pressed
+    stopped: bool,
+    // Set by an illegal opcode, only a reset gets the CPU going again
+    locked: bool,
+    block_cache: Option<BlockCache>,
+    // Operands of the instruction running from the block cache
+    operands: Operands,
+}
+
+fn not_implemented_instruction<'a>(opcode: u8, implementation: fn(&mut CPU)) -> Instruction<'a> {
+    Instruction{opcode, mnemonic: NOT_IMPLEMENTED_MNEMONIC, description: NOT_IMPLEMENTED_MNEMONIC,
+        length_in_bytes: 1, cycles: "0", flags_changed: "",
+        implementation
+    }
 }
 
 impl<'a> CPU<'a> {
     pub fn new(bus: Bus) -> CPU<'a> {
         let mut instruction_vector = vec!();
         let mut cb_instruction_vector = vec!();
+        let bad_opcode: fn(&mut CPU) = |cpu| cpu.unimplemented_opcode();
+        let bad_cb_opcode: fn(&mut CPU) = |cpu| cpu.unimplemented_opcode();
 
         for i in INSTRUCTIONS_NOCB.iter() {
-            while (instruction_vector.len() as u8) < i.opcode {
-                instruction_vector.push(
-                    Instruction{opcode: instruction_vector.len() as u8, mnemonic: "NOT IMPLEMENTED", description: "NOT IMPLEMENTED",
-                        length_in_bytes: 1, cycles: "0", flags_changed: "",
-                        implementation: |cpu| { cpu.dump(); panic!("Bad opcode!") }
-                    }
-                )
+            while instruction_vector.len() < i.opcode as usize {
+                instruction_vector.push(not_implemented_instruction(instruction_vector.len() as u8, bad_opcode))
             }
             instruction_vector.push(i.clone());
         }
+        while instruction_vector.len() < 0x100 {
+            instruction_vector.push(not_implemented_instruction(instruction_vector.len() as u8, bad_opcode))
+        }
 
         for i in INSTRUCTIONS_CB.iter() {
-            while (cb_instruction_vector.len() as u8) < i.opcode {
-                cb_instruction_vector.push(
-                    Instruction{opcode: cb_instruction_vector.len() as u8, mnemonic: "NOT IMPLEMENTED", description: "NOT IMPLEMENTED",
-                        length_in_bytes: 1, cycles: "0", flags_changed: "",
-                        implementation: |cpu| { cpu.dump(); panic!("Bad CB opcode!") }
-                    }
-                )
+            while cb_instruction_vector.len() < i.opcode as usize {
+                cb_instruction_vector.push(not_implemented_instruction(cb_instruction_vector.len() as u8, bad_cb_opcode))
             }
             cb_instruction_vector.push(i.clone());
         }
+        while cb_instruction_vector.len() < 0x100 {
+            cb_instruction_vector.push(not_implemented_instruction(cb_instruction_vector.len() as u8, bad_cb_opcode))
+        }
 
         CPU {
             reg_af: AFRegister::new(),
@@ -62,22 +121,159 @@ impl<'a> CPU<'a> {
             program_counter: Register16bit::new(),
             bus,
             cycle_count: 0,
+            instruction_count: 0,
+            opcode_stats: OpcodeStats::new(),
             instruction_vector,
             cb_instruction_vector,
             debug: false,
             reg_instruction: 0,
             reg_instruction_is_cb: false,
             instruction_address: 0,
-            interrupts_enabled: true,
+            interrupts_enabled: false,
+            enabling_interrupts: false,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+            block_cache: None,
+            operands: Operands::default(),
         }
     }
 
+    // Re-executes instructions from straight-line blocks decoded the first time they ran. Opcode
+    // and operand fetches of cached instructions do not go through the bus, so observers do not
+    // see them.
+    pub fn enable_block_cache(&mut self) {
+        self.block_cache = Some(BlockCache::new());
+        self.bus.code_watch = Some(CodeWatch::new(block_cache::LINE_SHIFT));
+    }
+
+    pub fn block_cache(&self) -> Option<&BlockCache> {
+        self.block_cache.as_ref()
+    }
+
+    pub fn save_state(&self) -> CpuState {
+        CpuState {
+            af: self.reg_af.read(),
+            bc: self.reg_bc.read(),
+            de: self.reg_de.read(),
+            hl: self.reg_hl.read(),
+            sp: self.stack_pointer.read(),
+            pc: self.program_counter.read(),
+            interrupts_enabled: self.interrupts_enabled,
+            cycle_count: self.cycle_count,
+            instruction_count: self.instruction_count,
+            halted: self.halted,
+            halt_bug: self.halt_bug,
+            stopped: self.stopped,
+            locked: self.locked,
+            enabling_interrupts: self.enabling_interrupts,
+        }
+    }
+
+    // Registers as they are at power-on, the boot ROM sets them up from there
+    pub fn reset(&mut self) {
+        self.restore_state(&CpuState {
+            af: 0, bc: 0, de: 0, hl: 0, sp: 0, pc: 0,
+            interrupts_enabled: false,
+            cycle_count: 0,
+            instruction_count: 0,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+            enabling_interrupts: false,
+        });
+    }
+
+    pub fn restore_state(&mut self, state: &CpuState) {
+        self.reg_af.write(state.af);
+        self.reg_bc.write(state.bc);
+        self.reg_de.write(state.de);
+        self.reg_hl.write(state.hl);
+        self.stack_pointer.write(state.sp);
+        self.program_counter.write(state.pc);
+        self.interrupts_enabled = state.interrupts_enabled;
+        self.cycle_count = state.cycle_count;
+        self.instruction_count = state.instruction_count;
+        self.halted = state.halted;
+        self.halt_bug = state.halt_bug;
+        self.stopped = state.stopped;
+        self.locked = state.locked;
+        self.enabling_interrupts = state.enabling_interrupts;
+        // Memory may have been replaced along with the registers
+        if self.block_cache.is_some() { self.enable_block_cache(); }
+    }
+
     fn pop_u8_from_pc(&mut self) -> u8 {
-        let result = self.bus.read(self.program_counter.read());
+        let result = match self.operands.next() {
+            Some(operand) => operand,
+            None => self.bus.read(self.program_counter.read()),
+        };
         self.program_counter.inc();
         result
     }
 
+    fn fetch_opcode(&mut self) -> u8 {
+        let opcode = self.pop_u8_from_pc();
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.program_counter.overflowing_add(0xFFFF);
+        }
+        opcode
+    }
+
+    fn halt(&mut self) {
+        if self.bus.pending_interrupts() == 0 {
+            self.halted = true;
+        } else if !self.interrupts_enabled {
+            self.halt_bug = true;
+        }
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    // The next step jumps to an interrupt handler instead of running an instruction
+    pub fn takes_interrupt(&self) -> bool {
+        self.interrupts_enabled && self.bus.pending_interrupts() != 0
+    }
+
+    // The pending interrupt with the lowest bit wins. Its request is cleared, interrupts are
+    // disabled and the PC is pushed before jumping to the handler.
+    fn dispatch_interrupt(&mut self) {
+        let interrupt = self.bus.pending_interrupts().trailing_zeros() as u16;
+        self.bus.acknowledge_interrupt(1 << interrupt);
+        self.interrupts_enabled = false;
+        self.enabling_interrupts = false;
+        self.push_u16_to_stack(self.program_counter.read());
+        self.program_counter.write(FIRST_INTERRUPT_VECTOR + 8 * interrupt);
+        self.cycle_count += INTERRUPT_DISPATCH_CYCLES;
+        self.bus.advance(INTERRUPT_DISPATCH_CYCLES);
+    }
+
+    // A button press ends STOP
+    pub fn resume(&mut self) {
+        self.stopped = false;
+    }
+
+    // Nothing but an interrupt can end HALT, so the clock jumps straight to the next moment one
+    // could be requested instead of going through it instruction by instruction
+    fn idle(&mut self) {
+        let cycles = self.bus.cycles_until_next_event().next_multiple_of(4).max(4);
+        self.cycle_count += cycles;
+        self.bus.advance(cycles);
+    }
+
     fn pop_u16_from_pc(&mut self) -> u16 {
         let mut result: u16;
         result = self.pop_u8_from_pc() as u16;
@@ -105,18 +301,34 @@ impl<'a> CPU<'a> {
         ((self.pop_u8_from_stack() as u16) << 8) | (self.pop_u8_from_stack() as u16)
     }
 
-    fn dump(&mut self) {
-        println!("### DUMP ###");
-        println!("Cycles ran {}", self.cycle_count);
-        println!("AF {:04X}", self.reg_af.read());
-        println!("BC {:04X}", self.reg_bc.read());
-        println!("DE {:04X}", self.reg_de.read());
-        println!("HL {:04X}", self.reg_hl.read());
-        println!("SP {:04X}", self.stack_pointer.read());
-        println!("PC {:04X}", self.program_counter.read());
-        self.print_instruction();
-        println!("### END ###");
-        println!();
+    // Reported by step() once the instruction is over
+    fn unimplemented_opcode(&mut self) {
+        let (opcode, address) = if self.reg_instruction_is_cb {
+            // Past the CB prefix
+            (Opcode::CB(self.reg_instruction), self.instruction_address.wrapping_sub(1))
+        } else {
+            (Opcode::Base(self.reg_instruction), self.instruction_address)
+        };
+        self.bus.fail(EmulationError::UnimplementedOpcode { opcode, address });
+    }
+
+    // Unlike an unimplemented opcode this is the program's fault, and the CPU does nothing more
+    fn illegal_opcode(&mut self) {
+        self.locked = true;
+        self.bus.fail(EmulationError::IllegalOpcode { opcode: self.reg_instruction, address: self.instruction_address });
+    }
+
+    // One line of CPU state in the Gameboy Doctor log format, taken before the next instruction runs
+    pub fn trace_line(&mut self) -> String {
+        let pc = self.program_counter.read();
+        format!("A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+                self.reg_af.read_higher(), self.reg_af.read_lower(),
+                self.reg_bc.read_higher(), self.reg_bc.read_lower(),
+                self.reg_de.read_higher(), self.reg_de.read_lower(),
+                self.reg_hl.read_higher(), self.reg_hl.read_lower(),
+                self.stack_pointer.read(), pc,
+                self.bus.peek(pc), self.bus.peek(pc.wrapping_add(1)),
+                self.bus.peek(pc.wrapping_add(2)), self.bus.peek(pc.wrapping_add(3)))
     }
 
     // FIXME makes assumptions on PC
@@ -147,18 +359,70 @@ impl<'a> CPU<'a> {
 
     fn run_op(&mut self) {
         self.instruction_address = self.program_counter.read();
-        self.reg_instruction = self.pop_u8_from_pc();
-        self.reg_instruction_is_cb = false;
+        let opcode = self.fetch_opcode();
+        let implementation = self.instruction_vector[opcode as usize].implementation;
+        self.execute_op(opcode, implementation);
+    }
 
-        let instruction = &self.instruction_vector[self.reg_instruction as usize];
+    fn run_cached_op(&mut self) {
+        let address = self.program_counter.read();
+        let mapping = self.bus.code_mapping(address);
+        let cache = self.block_cache.as_mut().unwrap();
+        cache.clear_on_cheats_change(self.bus.cheats.revision());
+        if let Some(code_watch) = &mut self.bus.code_watch {
+            for line in code_watch.take_written() { cache.invalidate_line(line); }
+        }
+        if let Some(op) = cache.lookup(mapping, address) {
+            self.instruction_address = address;
+            self.program_counter.write(address.wrapping_add(1));
+            self.operands = op.operands;
+            self.execute_op(op.opcode, op.implementation);
+            self.operands = Operands::default();
+            return;
+        }
+
+        self.instruction_address = address;
+        let opcode = self.pop_u8_from_pc();
+        let instruction = &self.instruction_vector[opcode as usize];
         let implementation = instruction.implementation;
+        // The CB prefix has no length of its own, the CB opcode follows it
+        let length = if opcode == 0xCB { 2 } else { instruction.length_in_bytes as u16 };
+        // Read before the instruction runs, as it may write over them
+        let mut operand_bytes = [0; 2];
+        for offset in 1..length.max(1) { operand_bytes[offset as usize - 1] = self.bus.peek(address.wrapping_add(offset)); }
+        let operands = Operands::new(&operand_bytes[..length.max(1) as usize - 1]);
+        // Watched already, so the block is dropped if the instruction writes over itself
+        let (first_line, last_line) = block_cache::lines(address, length);
+        if let Some(code_watch) = &mut self.bus.code_watch { code_watch.watch(first_line, last_line); }
+        self.execute_op(opcode, implementation);
+        let next_address = self.program_counter.read();
+        let op = CachedOp::new(address, opcode, implementation, operands);
+        self.block_cache.as_mut().unwrap().record(mapping, op, length, next_address);
+    }
+
+    fn execute_op(&mut self, opcode: u8, implementation: fn(&mut CPU)) {
+        self.reg_instruction = opcode;
+        self.reg_instruction_is_cb = false;
+
         let cycles_before_op = self.cycle_count;
+        // The CB prefix is accounted for by the CB opcode that follows it
+        if opcode != 0xCB {
+            let implemented = self.instruction_vector[opcode as usize].mnemonic != NOT_IMPLEMENTED_MNEMONIC;
+            self.opcode_stats.record(Opcode::Base(opcode), implemented);
+        }
 
-        if self.debug && self.reg_instruction != 0xCB { self.print_instruction() };
+        if self.debug && opcode != 0xCB { self.print_instruction() };
+        // A match over the table positions calling each implementation directly, so it could be
+        // inlined, measured slower than this indirect call: 20000 frames of an ALU-bound loop took
+        // 5.22 s at best against 4.67 s, probably from the extra lookup and the huge function.
         implementation(self);
 
-        for _i in cycles_before_op..self.cycle_count {
-            self.bus.cycle();
+        self.bus.advance(self.cycle_count - cycles_before_op);
+        // DMA transfers stop the CPU while they copy
+        let stalled = self.bus.take_stalled_cycles();
+        if stalled > 0 {
+            self.cycle_count += stalled;
+            self.bus.advance(stalled);
         }
     }
 
@@ -169,38 +433,281 @@ impl<'a> CPU<'a> {
 
         let instruction = &self.cb_instruction_vector[self.reg_instruction as usize];
         let implementation = instruction.implementation;
+        let implemented = instruction.mnemonic != NOT_IMPLEMENTED_MNEMONIC;
+        self.opcode_stats.record(Opcode::CB(self.reg_instruction), implemented);
 
         if self.debug { self.print_instruction() };
         implementation(self);
     }
 
-    pub fn step(&mut self) {
-        self.run_op()
+    pub fn mnemonic(&self, opcode: Opcode) -> &str {
+        match opcode {
+            Opcode::Base(value) => self.instruction_vector[value as usize].mnemonic,
+            Opcode::CB(value) => self.cb_instruction_vector[value as usize].mnemonic,
+        }
+    }
+
+    // Most executed opcodes first, followed by the unimplemented ones the program tried to run
+    pub fn opcode_report(&self, top: usize) -> String {
+        let mut lines = vec![format!("{} distinct opcodes executed", self.opcode_stats.distinct_executed())];
+        for (opcode, count) in self.opcode_stats.most_executed(top) {
+            lines.push(format!("{:>6} {:<20}{}", opcode.to_string(), self.mnemonic(opcode), count));
+        }
+        let unimplemented: Vec<String> = self.opcode_stats.unimplemented().map(|opcode| opcode.to_string()).collect();
+        if !unimplemented.is_empty() {
+            lines.push(format!("Unimplemented opcodes attempted: {}", unimplemented.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    // Errors leave the CPU after the instruction that ran into something not emulated. While
+    // halted a step lets time pass until the next event instead of running an instruction, and a
+    // step taking an interrupt only jumps to its handler.
+    pub fn step(&mut self) -> Result<(), EmulationError> {
+        // The screen keeps showing the last frame, which the LCD being off would not
+        if self.stopped || self.locked {
+            self.idle();
+            return self.bus.take_fault().map_or(Ok(()), Err);
+        }
+        if self.halted {
+            if self.bus.pending_interrupts() == 0 {
+                self.idle();
+                return self.bus.take_fault().map_or(Ok(()), Err);
+            }
+            self.halted = false;
+        }
+        if self.takes_interrupt() {
+            self.dispatch_interrupt();
+            return self.bus.take_fault().map_or(Ok(()), Err);
+        }
+        let enabling_interrupts = self.enabling_interrupts;
+        self.instruction_count += 1;
+        // The HALT bug replays a byte, which cached blocks know nothing about
+        if self.block_cache.is_some() && !self.halt_bug { self.run_cached_op() } else { self.run_op() }
+        // DI right after EI cancels it
+        if enabling_interrupts && self.enabling_interrupts {
+            self.enabling_interrupts = false;
+            self.interrupts_enabled = true;
+        }
+        match self.bus.take_fault() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::CPU;
+    use super::stats::Opcode;
+    use crate::error::EmulationError;
     use crate::bus::Bus;
     use crate::cpu::register::DMGRegister;
 
+    #[test]
+    fn trace_line_format() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x00, 0xAF, 0x31, 0xFE, 0xFF], vec![]));
+        cpu.step().unwrap();
+        cpu.reg_af.write(0x01B0);
+        cpu.reg_hl.write(0x014D);
+        cpu.stack_pointer.write(0xFFFE);
+        assert_eq!(cpu.trace_line(),
+                   "A:01 F:B0 B:00 C:00 D:00 E:00 H:01 L:4D SP:FFFE PC:0001 PCMEM:AF,31,FE,FF");
+    }
+
+    #[test]
+    fn opcode_stats_count_executed_opcodes() {
+        // NOP; NOP; RL C
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x00, 0x00, 0xCB, 0x11], vec![]));
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.opcode_stats.count(Opcode::Base(0x00)), 2);
+        assert_eq!(cpu.opcode_stats.count(Opcode::Base(0xCB)), 0);
+        assert_eq!(cpu.opcode_stats.count(Opcode::CB(0x11)), 1);
+        assert!(cpu.opcode_report(2).starts_with("2 distinct opcodes executed\n    00 NOP"));
+    }
+
+    #[test]
+    fn illegal_opcodes_lock_the_cpu() {
+        // NOP; an opcode the CPU does not have; INC A
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x00, 0xD3, 0x3C], vec![]));
+        cpu.step().unwrap();
+        assert!(matches!(cpu.step(), Err(EmulationError::IllegalOpcode { opcode: 0xD3, address: 0x0001 })));
+        assert!(cpu.is_locked());
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        assert_eq!(cpu.reg_af.read_a(), 0);
+        assert!(cpu.save_state().locked);
+        cpu.reset();
+        assert!(!cpu.is_locked());
+    }
+
+    #[test]
+    fn halt_waits_for_an_interrupt() {
+        // DI; HALT; INC A
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF3, 0x76, 0x3C], vec![]));
+        cpu.bus.write(0xFF40, 0x80);
+        cpu.bus.interrupt_enable = 0x01;
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        // Interrupts that are not enabled do not wake it up
+        cpu.bus.request_interrupt(0x02);
+        // Straight to the end of the frame instead of one NOP worth of cycles at a time, where
+        // the PPU requests VBlank
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        assert_eq!(cpu.bus.frame_count(), 1);
+        assert_eq!(cpu.instruction_count, 2);
+        assert_eq!(cpu.bus.read(0xFF0F), 0xE3);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.reg_af.read_higher(), 1);
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+    }
+
+    #[test]
+    fn interrupts_are_dispatched_by_priority() {
+        // EI; NOP; NOP
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFB, 0x00, 0x00], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.bus.write(0xFFFF, 0x1F);
+        cpu.bus.write(0xFF0F, 0x14);
+        assert_eq!(cpu.bus.read(0xFF0F), 0xF4);
+        cpu.step().unwrap();
+        // Not until the instruction after EI has run
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        cpu.step().unwrap();
+        assert_eq!(cpu.cycle_count, 28);
+        assert_eq!(cpu.program_counter.read(), 0x0050);
+        assert_eq!(cpu.stack_pointer.read(), 0xCFFE);
+        assert_eq!(cpu.pop_u16_from_stack(), 0x0002);
+        assert_eq!(cpu.bus.read(0xFF0F), 0xF0);
+        assert!(!cpu.interrupts_enabled);
+    }
+
+    #[test]
+    fn di_right_after_ei_keeps_interrupts_disabled() {
+        // EI; DI; NOP
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xFB, 0xF3, 0x00], vec![]));
+        cpu.bus.interrupt_enable = 0x01;
+        cpu.bus.request_interrupt(0x01);
+        for _ in 0..3 { cpu.step().unwrap(); }
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert!(!cpu.interrupts_enabled);
+    }
+
+    #[test]
+    fn halt_wakes_into_the_handler() {
+        // HALT; INC A
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x76, 0x3C], vec![]));
+        cpu.stack_pointer.write(0xD000);
+        cpu.interrupts_enabled = true;
+        cpu.bus.interrupt_enable = 0x04;
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        cpu.bus.request_interrupt(0x04);
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.program_counter.read(), 0x0050);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+    }
+
+    #[test]
+    fn halt_wakes_on_timer_overflow() {
+        // HALT; INC A
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x76, 0x3C], vec![]));
+        cpu.bus.interrupt_enable = 0x04;
+        cpu.bus.write(0xFF05, 0xFE);
+        cpu.bus.write(0xFF07, 0x04);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(cpu.is_halted());
+        // Idles right up to the reload instead of the end of the frame
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        assert_eq!(cpu.cycle_count, 2 * 1024 + 4 + 4);
+        assert_eq!(cpu.reg_af.read_higher(), 1);
+    }
+
+    #[test]
+    fn halt_bug_runs_the_next_byte_twice() {
+        // DI; HALT; INC A; NOP
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0xF3, 0x76, 0x3C, 0x00], vec![]));
+        cpu.bus.interrupt_enable = 0x04;
+        cpu.bus.request_interrupt(0x04);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert!(!cpu.is_halted());
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        cpu.step().unwrap();
+        assert_eq!(cpu.program_counter.read(), 0x0003);
+        assert_eq!(cpu.reg_af.read_higher(), 2);
+    }
+
+    #[test]
+    fn stop_waits_for_resume() {
+        // STOP; INC A
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x10, 0x00, 0x3C], vec![]));
+        cpu.step().unwrap();
+        assert!(cpu.is_stopped());
+        assert_eq!(cpu.program_counter.read(), 0x0002);
+        cpu.step().unwrap();
+        assert!(cpu.is_stopped());
+        assert_eq!(cpu.bus.frame_count(), 1);
+        assert_eq!(cpu.reg_af.read_higher(), 0);
+        cpu.resume();
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_af.read_higher(), 1);
+    }
+
+    #[test]
+    fn halted_state_is_saved() {
+        let mut cpu = CPU::new(Bus::new_from_vecs(vec![0x76], vec![]));
+        cpu.step().unwrap();
+        let state = cpu.save_state();
+        assert!(state.halted);
+        cpu.reset();
+        assert!(!cpu.is_halted());
+        cpu.restore_state(&state);
+        assert!(cpu.is_halted());
+    }
+
+    #[test]
+    fn instruction_tables_cover_all_opcodes() {
+        let cpu = CPU::new(Bus::new_from_vecs(vec![], vec![]));
+        assert_eq!(cpu.instruction_vector.len(), 0x100);
+        assert_eq!(cpu.cb_instruction_vector.len(), 0x100);
+        let illegal = [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD];
+        for opcode in 0..=0xFF {
+            let mnemonic = cpu.mnemonic(Opcode::Base(opcode));
+            assert_ne!(mnemonic, super::NOT_IMPLEMENTED_MNEMONIC, "{:02X}", opcode);
+            assert_eq!(mnemonic == "ILLEGAL", illegal.contains(&opcode), "{:02X}", opcode);
+            assert_ne!(cpu.mnemonic(Opcode::CB(opcode)), super::NOT_IMPLEMENTED_MNEMONIC, "CB {:02X}", opcode);
+        }
+    }
+
     #[test]
     fn cpu_internal_registers() {
         // XOR A
         // BIT 7,H
         let mut cpu = CPU::new(
             Bus::new_from_vecs(vec![0xAF, 0xCB, 0x7C], vec![]));
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0001);
         assert_eq!(cpu.instruction_address, 0x0000);
-        assert_eq!(cpu.reg_instruction_is_cb, false);
+        assert!(!cpu.reg_instruction_is_cb);
         assert_eq!(cpu.reg_instruction, 0xAF);
-        cpu.step();
+        cpu.step().unwrap();
         assert_eq!(cpu.program_counter.read(), 0x0003);
         assert_eq!(cpu.instruction_address, 0x0002);
-        assert_eq!(cpu.reg_instruction_is_cb, true);
+        assert!(cpu.reg_instruction_is_cb);
         assert_eq!(cpu.reg_instruction, 0x7C);
+        assert_eq!(cpu.instruction_count, 2);
     }
 
 }
\ No newline at end of file
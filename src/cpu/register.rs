@@ -1,7 +1,5 @@
 use bitflags::bitflags;
 
-pub enum Subregister { Higher, Lower }
-
 pub trait DMGRegister {
     fn read(&self) -> u16;
     fn write(&mut self, value: u16);
@@ -11,8 +9,6 @@ pub trait DMGRegister {
     fn write_lower(&mut self, value: u8);
     fn read_higher(&self) -> u8;
     fn write_higher(&mut self, value: u8);
-    fn read_subreg(&self, subregister: Subregister) -> u8;
-    fn write_subreg(&mut self, subregister: Subregister, value: u8);
 }
 
 pub struct Register16bit { value: u16 }
@@ -32,18 +28,6 @@ impl DMGRegister for Register16bit {
     fn write_higher(&mut self, value: u8) {
         self.value = (self.value & 0x00FF) + ((value as u16) << 8);
     }
-    fn read_subreg(&self, subregister: Subregister) -> u8 {
-        match subregister {
-            Subregister::Higher => self.read_higher(),
-            Subregister::Lower => self.read_lower(),
-        }
-    }
-    fn write_subreg(&mut self, subregister: Subregister, value: u8) {
-        match subregister {
-            Subregister::Higher => self.write_higher(value),
-            Subregister::Lower => self.write_lower(value)
-        }
-    }
 }
 
 bitflags! {
@@ -60,6 +44,25 @@ impl Flags {
     pub fn clear(&mut self) {
         self.bits = 0;
     }
+
+    // The whole register built with shifts instead of a branch per flag, for the hot
+    // arithmetic instructions that decide every flag at once
+    #[inline]
+    pub fn from_conditions(z: bool, n: bool, h: bool, c: bool) -> Flags {
+        Flags { bits: (z as u8) << 7 | (n as u8) << 6 | (h as u8) << 5 | (c as u8) << 4 }
+    }
+
+    // Z, N and H as above, leaving the carry alone like INC and DEC do
+    #[inline]
+    pub fn set_znh(&mut self, z: bool, n: bool, h: bool) {
+        self.bits = (self.bits & Flags::C.bits) | Flags::from_conditions(z, n, h, false).bits;
+    }
+
+    // 1 when the carry is set, to shift into rotated values
+    #[inline]
+    pub fn carry_bit(&self) -> u8 {
+        (self.bits & Flags::C.bits) >> 4
+    }
 }
 
 pub struct AFRegister {
@@ -85,18 +88,6 @@ impl DMGRegister for AFRegister {
     fn write_lower(&mut self, value: u8) { self.flags.bits = value; }
     fn read_higher(&self) -> u8 { self.a }
     fn write_higher(&mut self, value: u8) { self.a = value; }
-    fn read_subreg(&self, subregister: Subregister) -> u8 {
-        match subregister {
-            Subregister::Higher => self.read_higher(),
-            Subregister::Lower => self.read_lower(),
-        }
-    }
-    fn write_subreg(&mut self, subregister: Subregister, value: u8) {
-        match subregister {
-            Subregister::Higher => self.write_higher(value),
-            Subregister::Lower => self.write_lower(value)
-        }
-    }
 }
 
 
@@ -104,26 +95,38 @@ impl DMGRegister for AFRegister {
 mod tests {
     use super::*;
 
+    #[test]
+    fn flags_from_conditions() {
+        assert_eq!(Flags::from_conditions(true, false, true, false), Flags::Z | Flags::H);
+        assert_eq!(Flags::from_conditions(false, true, false, true), Flags::N | Flags::C);
+        let mut flags = Flags::C | Flags::Z;
+        flags.set_znh(false, true, true);
+        assert_eq!(flags, Flags::N | Flags::H | Flags::C);
+        assert_eq!(flags.carry_bit(), 1);
+        flags.clear();
+        assert_eq!(flags.carry_bit(), 0);
+    }
+
     #[test]
     fn write_16_bit() {
         let mut reg = Register16bit{value: 0};
         reg.write(0x1234);
-        assert_eq!(reg.read_subreg(Subregister::Higher), 0x12);
-        assert_eq!(reg.read_subreg(Subregister::Lower), 0x34);
+        assert_eq!(reg.read_higher(), 0x12);
+        assert_eq!(reg.read_lower(), 0x34);
         assert_eq!(reg.read(), 0x1234);
     }
 
     #[test]
     fn write_8_bit() {
         let mut reg = Register16bit{value: 0};
-        reg.write_subreg(Subregister::Higher, 0x12);
+        reg.write_higher(0x12);
         assert_eq!(reg.read(), 0x1200);
-        assert_eq!(reg.read_subreg(Subregister::Higher), 0x12);
-        assert_eq!(reg.read_subreg(Subregister::Lower), 0x00);
-        reg.write_subreg(Subregister::Lower, 0x34);
+        assert_eq!(reg.read_higher(), 0x12);
+        assert_eq!(reg.read_lower(), 0x00);
+        reg.write_lower(0x34);
         assert_eq!(reg.read(), 0x1234);
-        assert_eq!(reg.read_subreg(Subregister::Higher), 0x12);
-        assert_eq!(reg.read_subreg(Subregister::Lower), 0x34);
+        assert_eq!(reg.read_higher(), 0x12);
+        assert_eq!(reg.read_lower(), 0x34);
     }
 
     #[test]
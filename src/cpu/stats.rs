@@ -0,0 +1,89 @@
+use alloc::collections::BTreeSet;
+use core::fmt;
+use crate::prelude::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Opcode {
+    Base(u8),
+    CB(u8),
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Opcode::Base(value) => write!(f, "{:02X}", value),
+            Opcode::CB(value) => write!(f, "CB {:02X}", value),
+        }
+    }
+}
+
+pub struct OpcodeStats {
+    counts: Vec<u64>,
+    cb_counts: Vec<u64>,
+    unimplemented: BTreeSet<Opcode>,
+}
+
+impl Default for OpcodeStats {
+    fn default() -> OpcodeStats { OpcodeStats::new() }
+}
+
+impl OpcodeStats {
+    pub fn new() -> OpcodeStats {
+        OpcodeStats { counts: vec![0; 0x100], cb_counts: vec![0; 0x100], unimplemented: BTreeSet::new() }
+    }
+
+    pub fn record(&mut self, opcode: Opcode, implemented: bool) {
+        match opcode {
+            Opcode::Base(value) => self.counts[value as usize] += 1,
+            Opcode::CB(value) => self.cb_counts[value as usize] += 1,
+        }
+        if !implemented { self.unimplemented.insert(opcode); }
+    }
+
+    pub fn count(&self, opcode: Opcode) -> u64 {
+        match opcode {
+            Opcode::Base(value) => self.counts[value as usize],
+            Opcode::CB(value) => self.cb_counts[value as usize],
+        }
+    }
+
+    fn all_counts(&self) -> impl Iterator<Item = (Opcode, u64)> + '_ {
+        let base = self.counts.iter().enumerate().map(|(value, count)| (Opcode::Base(value as u8), *count));
+        let cb = self.cb_counts.iter().enumerate().map(|(value, count)| (Opcode::CB(value as u8), *count));
+        base.chain(cb).filter(|(_, count)| *count > 0)
+    }
+
+    pub fn distinct_executed(&self) -> usize {
+        self.all_counts().count()
+    }
+
+    pub fn most_executed(&self, top: usize) -> Vec<(Opcode, u64)> {
+        let mut counts: Vec<(Opcode, u64)> = self.all_counts().collect();
+        counts.sort_by(|(opcode_a, count_a), (opcode_b, count_b)| count_b.cmp(count_a).then(opcode_a.cmp(opcode_b)));
+        counts.truncate(top);
+        counts
+    }
+
+    // Opcodes the program tried to execute that have no implementation yet
+    pub fn unimplemented(&self) -> impl Iterator<Item = Opcode> + '_ {
+        self.unimplemented.iter().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn most_executed_sorted_by_count() {
+        let mut stats = OpcodeStats::new();
+        stats.record(Opcode::Base(0x00), true);
+        stats.record(Opcode::CB(0x7C), true);
+        stats.record(Opcode::CB(0x7C), true);
+        stats.record(Opcode::Base(0xD3), false);
+        assert_eq!(stats.distinct_executed(), 3);
+        assert_eq!(stats.most_executed(2), vec![(Opcode::CB(0x7C), 2), (Opcode::Base(0x00), 1)]);
+        assert_eq!(stats.unimplemented().collect::<Vec<_>>(), vec![Opcode::Base(0xD3)]);
+        assert_eq!(Opcode::CB(0x7C).to_string(), "CB 7C");
+    }
+}
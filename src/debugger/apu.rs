@@ -0,0 +1,197 @@
+// Sound channel state decoded from the sound registers. There is no APU yet, so this shows what
+// the game asked for rather than what is being played.
+
+const NR10: u16 = 0xFF10;
+const NR21: u16 = 0xFF16;
+const NR30: u16 = 0xFF1A;
+const NR41: u16 = 0xFF20;
+const NR52: u16 = 0xFF26;
+const WAVE_RAM: u16 = 0xFF30;
+const WAVE_SAMPLES: usize = 32;
+const NOISE_SAMPLES: usize = 32;
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 0, 0, 1],
+    [1, 0, 0, 0, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 0],
+];
+const DUTY_PERCENTAGES: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Envelope {
+    pub increase: bool,
+    pub period: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChannelState {
+    pub number: u8,
+    pub dac_enabled: bool,
+    pub volume: u8,
+    pub envelope: Option<Envelope>,
+    pub length: u16,
+    pub length_enabled: bool,
+    pub frequency_hz: f64,
+    // One period of output samples in the 0-15 range
+    pub waveform: Vec<u8>,
+    pub details: String,
+}
+
+fn envelope(nrx2: u8) -> (u8, Option<Envelope>) {
+    let period = nrx2 & 0b111;
+    let envelope = if period == 0 { None } else { Some(Envelope { increase: nrx2 & 0x08 != 0, period }) };
+    (nrx2 >> 4, envelope)
+}
+
+fn period(low: u8, high: u8) -> u16 {
+    ((high as u16 & 0b111) << 8) | low as u16
+}
+
+fn pulse_channel<F: Fn(u16) -> u8>(read: &F, number: u8, base: u16) -> ChannelState {
+    let (nrx1, nrx2, nrx3, nrx4) = (read(base), read(base + 1), read(base + 2), read(base + 3));
+    let duty = (nrx1 >> 6) as usize;
+    let (volume, envelope) = envelope(nrx2);
+    ChannelState {
+        number,
+        dac_enabled: nrx2 & 0xF8 != 0,
+        volume,
+        envelope,
+        length: 64 - (nrx1 & 0x3F) as u16,
+        length_enabled: nrx4 & 0x40 != 0,
+        frequency_hz: 131_072.0 / (2048 - period(nrx3, nrx4)) as f64,
+        waveform: DUTY_PATTERNS[duty].iter().map(|high| high * volume).collect(),
+        details: format!("duty {}", DUTY_PERCENTAGES[duty]),
+    }
+}
+
+fn sweep_details(nr10: u8) -> String {
+    match (nr10 >> 4) & 0b111 {
+        0 => "no sweep".to_string(),
+        pace => format!("sweep {} pace {} shift {}", if nr10 & 0x08 != 0 { "down" } else { "up" }, pace, nr10 & 0b111),
+    }
+}
+
+fn wave_channel<F: Fn(u16) -> u8>(read: &F) -> ChannelState {
+    let (nr30, nr31, nr32, nr33, nr34) = (read(NR30), read(NR30 + 1), read(NR30 + 2), read(NR30 + 3), read(NR30 + 4));
+    let output_level = (nr32 >> 5) & 0b11;
+    // Output level 0 mutes the channel, 1 plays samples as they are, 2 and 3 shift them right
+    let shift = if output_level == 0 { 4 } else { output_level - 1 };
+    let waveform = (0..WAVE_SAMPLES)
+        .map(|index| {
+            let byte = read(WAVE_RAM + (index / 2) as u16);
+            let sample = if index % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+            sample >> shift
+        })
+        .collect();
+    ChannelState {
+        number: 3,
+        dac_enabled: nr30 & 0x80 != 0,
+        volume: 15 >> shift,
+        envelope: None,
+        length: 256 - nr31 as u16,
+        length_enabled: nr34 & 0x40 != 0,
+        frequency_hz: 65_536.0 / (2048 - period(nr33, nr34)) as f64,
+        waveform,
+        details: format!("output {}", ["mute", "100%", "50%", "25%"][output_level as usize]),
+    }
+}
+
+// The first samples of the noise generator, which always starts from all ones when triggered
+fn noise_samples(short_mode: bool, volume: u8) -> Vec<u8> {
+    let mut lfsr: u16 = 0x7FFF;
+    (0..NOISE_SAMPLES).map(|_| {
+        let feedback = (lfsr ^ (lfsr >> 1)) & 1;
+        lfsr = (lfsr >> 1) | (feedback << 14);
+        if short_mode { lfsr = (lfsr & !(1 << 6)) | (feedback << 6); }
+        if lfsr & 1 == 0 { volume } else { 0 }
+    }).collect()
+}
+
+fn noise_channel<F: Fn(u16) -> u8>(read: &F) -> ChannelState {
+    let (nr41, nr42, nr43, nr44) = (read(NR41), read(NR41 + 1), read(NR41 + 2), read(NR41 + 3));
+    let (volume, envelope) = envelope(nr42);
+    let divider = match nr43 & 0b111 { 0 => 0.5, divider => divider as f64 };
+    let short_mode = nr43 & 0x08 != 0;
+    ChannelState {
+        number: 4,
+        dac_enabled: nr42 & 0xF8 != 0,
+        volume,
+        envelope,
+        length: 64 - (nr41 & 0x3F) as u16,
+        length_enabled: nr44 & 0x40 != 0,
+        frequency_hz: 262_144.0 / (divider * (1 << (nr43 >> 4)) as f64),
+        waveform: noise_samples(short_mode, volume),
+        details: format!("{} bit LFSR", if short_mode { 7 } else { 15 }),
+    }
+}
+
+pub fn sound_enabled<F: Fn(u16) -> u8>(read: F) -> bool {
+    read(NR52) & 0x80 != 0
+}
+
+pub fn decode_channels<F: Fn(u16) -> u8>(read: F) -> Vec<ChannelState> {
+    let mut channel_1 = pulse_channel(&read, 1, NR10 + 1);
+    channel_1.details = format!("{}, {}", channel_1.details, sweep_details(read(NR10)));
+    vec![channel_1, pulse_channel(&read, 2, NR21), wave_channel(&read), noise_channel(&read)]
+}
+
+pub fn describe_channel(channel: &ChannelState) -> String {
+    let envelope = match channel.envelope {
+        Some(envelope) => format!("envelope {} every {}", if envelope.increase { "up" } else { "down" }, envelope.period),
+        None => "no envelope".to_string(),
+    };
+    let length = if channel.length_enabled { format!("length {}", channel.length) } else { "no length".to_string() };
+    format!("CH{} DAC {:<3} volume {:>2} {:>9.1} Hz  {}, {}, {}",
+            channel.number, if channel.dac_enabled { "on" } else { "off" }, channel.volume,
+            channel.frequency_hz, envelope, length, channel.details)
+}
+
+pub fn describe_all<F: Fn(u16) -> u8>(read: F) -> String {
+    let mut lines = vec![format!("Sound {}", if sound_enabled(&read) { "on" } else { "off" })];
+    lines.extend(decode_channels(&read).iter().map(describe_channel));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn registers(values: &[(u16, u8)]) -> impl Fn(u16) -> u8 {
+        let values: HashMap<u16, u8> = values.iter().copied().collect();
+        move |address| *values.get(&address).unwrap_or(&0)
+    }
+
+    #[test]
+    fn pulse_channel_from_boot_sound() {
+        // What the boot ROM writes for its chime on channel 1
+        let read = registers(&[(0xFF11, 0x80), (0xFF12, 0xF3), (0xFF13, 0x83), (0xFF14, 0x87)]);
+        let channel = &decode_channels(read)[0];
+        assert!(channel.dac_enabled);
+        assert_eq!(channel.volume, 15);
+        assert_eq!(channel.envelope, Some(Envelope { increase: false, period: 3 }));
+        assert_eq!(channel.waveform, vec![15, 0, 0, 0, 0, 15, 15, 15]);
+        assert!((channel.frequency_hz - 131_072.0 / (2048.0 - 0x783 as f64)).abs() < 0.001);
+        assert!(describe_channel(channel).contains("duty 50%, no sweep"));
+    }
+
+    #[test]
+    fn wave_channel_output_level() {
+        let read = registers(&[(0xFF1A, 0x80), (0xFF1C, 0x20), (0xFF30, 0xF1)]);
+        let channel = &decode_channels(&read)[2];
+        assert_eq!(&channel.waveform[0..3], &[15, 1, 0]);
+        let read = registers(&[(0xFF1C, 0x60), (0xFF30, 0xF1)]);
+        assert_eq!(&decode_channels(&read)[2].waveform[0..2], &[3, 0]);
+    }
+
+    #[test]
+    fn noise_channel_frequency() {
+        let read = registers(&[(0xFF21, 0xF0), (0xFF22, 0x29)]);
+        let channel = &decode_channels(read)[3];
+        assert_eq!(channel.frequency_hz, 262_144.0 / (1.0 * 4.0));
+        assert_eq!(channel.details, "7 bit LFSR");
+        assert!(channel.waveform.contains(&15));
+        assert!(channel.waveform.contains(&0));
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+
+use crate::cpu::register::DMGRegister;
+use crate::dmg::DMG;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Register { A, F, B, C, D, E, H, L, AF, BC, DE, HL, SP, PC }
+
+const REGISTER_NAMES: [(&str, Register); 14] = [
+    ("A", Register::A), ("F", Register::F), ("B", Register::B), ("C", Register::C),
+    ("D", Register::D), ("E", Register::E), ("H", Register::H), ("L", Register::L),
+    ("AF", Register::AF), ("BC", Register::BC), ("DE", Register::DE), ("HL", Register::HL),
+    ("SP", Register::SP), ("PC", Register::PC),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOperator {
+    Or, And,
+    Equal, NotEqual, Less, Greater, LessOrEqual, GreaterOrEqual,
+    Add, Subtract, BitAnd, BitOr, BitXor,
+}
+
+impl BinaryOperator {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOperator::Or => "||",
+            BinaryOperator::And => "&&",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::LessOrEqual => "<=",
+            BinaryOperator::GreaterOrEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::BitAnd => "&",
+            BinaryOperator::BitOr => "|",
+            BinaryOperator::BitXor => "^",
+        }
+    }
+
+    fn apply(self, left: i64, right: i64) -> i64 {
+        match self {
+            BinaryOperator::Or => ((left != 0) || (right != 0)) as i64,
+            BinaryOperator::And => ((left != 0) && (right != 0)) as i64,
+            BinaryOperator::Equal => (left == right) as i64,
+            BinaryOperator::NotEqual => (left != right) as i64,
+            BinaryOperator::Less => (left < right) as i64,
+            BinaryOperator::Greater => (left > right) as i64,
+            BinaryOperator::LessOrEqual => (left <= right) as i64,
+            BinaryOperator::GreaterOrEqual => (left >= right) as i64,
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            BinaryOperator::BitAnd => left & right,
+            BinaryOperator::BitOr => left | right,
+            BinaryOperator::BitXor => left ^ right,
+        }
+    }
+}
+
+// Operators grouped by binding strength, loosest first. Bitwise operators bind like + and -,
+// tighter than comparisons, so "F & 0x80 != 0" does what it reads like.
+const PRECEDENCE_LEVELS: [&[BinaryOperator]; 4] = [
+    &[BinaryOperator::Or],
+    &[BinaryOperator::And],
+    &[BinaryOperator::Equal, BinaryOperator::NotEqual, BinaryOperator::LessOrEqual,
+      BinaryOperator::GreaterOrEqual, BinaryOperator::Less, BinaryOperator::Greater],
+    &[BinaryOperator::Add, BinaryOperator::Subtract, BinaryOperator::BitAnd,
+      BinaryOperator::BitOr, BinaryOperator::BitXor],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Constant(i64),
+    Register(Register),
+    Memory(Box<Expression>),
+    // Little-endian 16-bit read, written as w[address]
+    MemoryWord(Box<Expression>),
+    Binary(Box<Expression>, BinaryOperator, Box<Expression>),
+}
+
+pub trait EvaluationContext {
+    fn read_register(&self, register: Register) -> u16;
+    fn read_memory(&mut self, address: u16) -> u8;
+}
+
+impl<'a> EvaluationContext for DMG<'a> {
+    fn read_register(&self, register: Register) -> u16 {
+        let cpu = &self.cpu;
+        match register {
+            Register::A => cpu.reg_af.read_higher() as u16,
+            Register::F => cpu.reg_af.read_lower() as u16,
+            Register::B => cpu.reg_bc.read_higher() as u16,
+            Register::C => cpu.reg_bc.read_lower() as u16,
+            Register::D => cpu.reg_de.read_higher() as u16,
+            Register::E => cpu.reg_de.read_lower() as u16,
+            Register::H => cpu.reg_hl.read_higher() as u16,
+            Register::L => cpu.reg_hl.read_lower() as u16,
+            Register::AF => cpu.reg_af.read(),
+            Register::BC => cpu.reg_bc.read(),
+            Register::DE => cpu.reg_de.read(),
+            Register::HL => cpu.reg_hl.read(),
+            Register::SP => cpu.stack_pointer.read(),
+            Register::PC => cpu.program_counter.read(),
+        }
+    }
+
+    fn read_memory(&mut self, address: u16) -> u8 {
+        self.cpu.bus.peek(address)
+    }
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.parse_level(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("Unexpected {:?} in expression", token)),
+        }
+    }
+
+    pub fn evaluate<C: EvaluationContext>(&self, context: &mut C) -> i64 {
+        match self {
+            Expression::Constant(value) => *value,
+            Expression::Register(register) => context.read_register(*register) as i64,
+            Expression::Memory(address) => {
+                let address = address.evaluate(context) as u16;
+                context.read_memory(address) as i64
+            }
+            Expression::MemoryWord(address) => {
+                let address = address.evaluate(context) as u16;
+                let low = context.read_memory(address) as i64;
+                let high = context.read_memory(address.wrapping_add(1)) as i64;
+                high << 8 | low
+            }
+            Expression::Binary(left, operator, right) => {
+                let left = left.evaluate(context);
+                let right = right.evaluate(context);
+                operator.apply(left, right)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Constant(value) if *value > 9 => write!(f, "0x{:X}", value),
+            Expression::Constant(value) => write!(f, "{}", value),
+            Expression::Register(register) => write!(f, "{:?}", register),
+            Expression::Memory(address) => write!(f, "[{}]", address),
+            Expression::MemoryWord(address) => write!(f, "w[{}]", address),
+            Expression::Binary(left, operator, right) => {
+                for (index, side) in [left, right].iter().enumerate() {
+                    if index == 1 { write!(f, " {} ", operator.symbol())?; }
+                    match side.as_ref() {
+                        Expression::Binary(..) => write!(f, "({})", side)?,
+                        _ => write!(f, "{}", side)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Identifier(String),
+    Operator(BinaryOperator),
+    OpenBracket,
+    CloseBracket,
+    OpenParenthesis,
+    CloseParenthesis,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let characters: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut position = 0;
+
+    while position < characters.len() {
+        let character = characters[position];
+
+        if character.is_whitespace() { position += 1; continue; }
+
+        let single = match character {
+            '[' => Some(Token::OpenBracket),
+            ']' => Some(Token::CloseBracket),
+            '(' => Some(Token::OpenParenthesis),
+            ')' => Some(Token::CloseParenthesis),
+            _ => None,
+        };
+        if let Some(token) = single {
+            tokens.push(token);
+            position += 1;
+            continue;
+        }
+
+        let two_characters: String = characters[position..(position + 2).min(characters.len())].iter().collect();
+        let operator = match two_characters.as_str() {
+            "||" => Some((BinaryOperator::Or, 2)),
+            "&&" => Some((BinaryOperator::And, 2)),
+            "==" => Some((BinaryOperator::Equal, 2)),
+            "!=" => Some((BinaryOperator::NotEqual, 2)),
+            "<=" => Some((BinaryOperator::LessOrEqual, 2)),
+            ">=" => Some((BinaryOperator::GreaterOrEqual, 2)),
+            _ => match character {
+                '<' => Some((BinaryOperator::Less, 1)),
+                '>' => Some((BinaryOperator::Greater, 1)),
+                '+' => Some((BinaryOperator::Add, 1)),
+                '-' => Some((BinaryOperator::Subtract, 1)),
+                '&' => Some((BinaryOperator::BitAnd, 1)),
+                '|' => Some((BinaryOperator::BitOr, 1)),
+                '^' => Some((BinaryOperator::BitXor, 1)),
+                _ => None,
+            }
+        };
+        if let Some((operator, length)) = operator {
+            tokens.push(Token::Operator(operator));
+            position += length;
+            continue;
+        }
+
+        if character.is_ascii_alphanumeric() || character == '$' || character == '_' {
+            let start = position;
+            position += 1;
+            while position < characters.len() && (characters[position].is_ascii_alphanumeric() || characters[position] == '_') {
+                position += 1;
+            }
+            let word: String = characters[start..position].iter().collect();
+            tokens.push(word_to_token(&word)?);
+            continue;
+        }
+
+        return Err(format!("Unexpected character '{}' in expression", character));
+    }
+    Ok(tokens)
+}
+
+fn word_to_token(word: &str) -> Result<Token, String> {
+    let first = word.chars().next().unwrap();
+    if first == '$' {
+        return i64::from_str_radix(&word[1..], 16).map(Token::Number).map_err(|_| format!("Bad number: {}", word));
+    }
+    if let Some(digits) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        return i64::from_str_radix(digits, 16).map(Token::Number).map_err(|_| format!("Bad number: {}", word));
+    }
+    if first.is_ascii_digit() {
+        return word.parse().map(Token::Number).map_err(|_| format!("Bad number: {}", word));
+    }
+    Ok(Token::Identifier(word.to_string()))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            Some(token) => Err(format!("Expected {:?}, found {:?}", expected, token)),
+            None => Err(format!("Expected {:?} at end of expression", expected)),
+        }
+    }
+
+    fn parse_level(&mut self, level: usize) -> Result<Expression, String> {
+        if level == PRECEDENCE_LEVELS.len() { return self.parse_primary(); }
+
+        let mut expression = self.parse_level(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            let operator = *operator;
+            if !PRECEDENCE_LEVELS[level].contains(&operator) { break; }
+            self.position += 1;
+            let right = self.parse_level(level + 1)?;
+            expression = Expression::Binary(Box::new(expression), operator, Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(Expression::Constant(value)),
+            Some(Token::Identifier(name)) => self.parse_identifier(&name),
+            Some(Token::OpenBracket) => Ok(Expression::Memory(Box::new(self.parse_bracketed()?))),
+            Some(Token::OpenParenthesis) => {
+                let inner = self.parse_level(0)?;
+                self.expect(Token::CloseParenthesis)?;
+                Ok(inner)
+            }
+            Some(token) => Err(format!("Unexpected {:?} in expression", token)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_bracketed(&mut self) -> Result<Expression, String> {
+        let address = self.parse_level(0)?;
+        self.expect(Token::CloseBracket)?;
+        Ok(address)
+    }
+
+    fn parse_identifier(&mut self, name: &str) -> Result<Expression, String> {
+        if name.eq_ignore_ascii_case("w") && self.tokens.get(self.position) == Some(&Token::OpenBracket) {
+            self.position += 1;
+            return Ok(Expression::MemoryWord(Box::new(self.parse_bracketed()?)));
+        }
+        match REGISTER_NAMES.iter().find(|(register_name, _)| register_name.eq_ignore_ascii_case(name)) {
+            Some((_, register)) => Ok(Expression::Register(*register)),
+            None => Err(format!("Unknown register {}", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestContext { a: u8, memory: [u8; 0x10000] }
+
+    impl EvaluationContext for TestContext {
+        fn read_register(&self, register: Register) -> u16 {
+            match register {
+                Register::A => self.a as u16,
+                _ => 0,
+            }
+        }
+        fn read_memory(&mut self, address: u16) -> u8 { self.memory[address as usize] }
+    }
+
+    fn evaluate(text: &str, context: &mut TestContext) -> i64 {
+        Expression::parse(text).unwrap().evaluate(context)
+    }
+
+    #[test]
+    fn register_and_memory_condition() {
+        let mut context = TestContext { a: 0x3E, memory: [0; 0x10000] };
+        context.memory[0xFF44] = 0x91;
+        assert_eq!(evaluate("A == 0x3E && [0xFF44] > 0x90", &mut context), 1);
+        context.memory[0xFF44] = 0x90;
+        assert_eq!(evaluate("A == 0x3E && [0xFF44] > 0x90", &mut context), 0);
+    }
+
+    #[test]
+    fn number_formats() {
+        let mut context = TestContext { a: 0, memory: [0; 0x10000] };
+        assert_eq!(evaluate("$10 + 0x10 + 10", &mut context), 42);
+    }
+
+    #[test]
+    fn precedence() {
+        let mut context = TestContext { a: 0x81, memory: [0; 0x10000] };
+        assert_eq!(evaluate("a & 0x80 != 0", &mut context), 1);
+        assert_eq!(evaluate("0 || 1 && 0", &mut context), 0);
+        assert_eq!(evaluate("(0 || 1) && 1", &mut context), 1);
+    }
+
+    #[test]
+    fn nested_memory() {
+        let mut context = TestContext { a: 0, memory: [0; 0x10000] };
+        context.memory[0xC000] = 0x10;
+        context.memory[0x0010] = 0x55;
+        assert_eq!(evaluate("[[0xC000]]", &mut context), 0x55);
+    }
+
+    #[test]
+    fn memory_word() {
+        let mut context = TestContext { a: 0, memory: [0; 0x10000] };
+        context.memory[0xC000] = 0x34;
+        context.memory[0xC001] = 0x12;
+        assert_eq!(evaluate("w[0xC000] + 1", &mut context), 0x1235);
+        assert_eq!(Expression::parse("W[$C000]").unwrap().to_string(), "w[0xC000]");
+        assert!(Expression::parse("w").is_err());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expression::parse("A ==").is_err());
+        assert!(Expression::parse("[0xC000").is_err());
+        assert!(Expression::parse("Q == 1").is_err());
+        assert!(Expression::parse("A = 1").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let expression = Expression::parse("A == 0x3E && [0xFF44] > 0x90").unwrap();
+        assert_eq!(expression.to_string(), "(A == 0x3E) && ([0xFF44] > 0x90)");
+        assert_eq!(Expression::parse(&expression.to_string()).unwrap(), expression);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::dmg::DMG;
+
+pub struct IoRegister {
+    pub address: u16,
+    pub name: &'static str,
+}
+
+const fn register(address: u16, name: &'static str) -> IoRegister {
+    IoRegister { address, name }
+}
+
+pub const IO_REGISTERS: [IoRegister; 42] = [
+    register(0xFF00, "P1"),
+    register(0xFF01, "SB"),
+    register(0xFF02, "SC"),
+    register(0xFF04, "DIV"),
+    register(0xFF05, "TIMA"),
+    register(0xFF06, "TMA"),
+    register(0xFF07, "TAC"),
+    register(0xFF0F, "IF"),
+    register(0xFF10, "NR10"),
+    register(0xFF11, "NR11"),
+    register(0xFF12, "NR12"),
+    register(0xFF13, "NR13"),
+    register(0xFF14, "NR14"),
+    register(0xFF16, "NR21"),
+    register(0xFF17, "NR22"),
+    register(0xFF18, "NR23"),
+    register(0xFF19, "NR24"),
+    register(0xFF1A, "NR30"),
+    register(0xFF1B, "NR31"),
+    register(0xFF1C, "NR32"),
+    register(0xFF1D, "NR33"),
+    register(0xFF1E, "NR34"),
+    register(0xFF20, "NR41"),
+    register(0xFF21, "NR42"),
+    register(0xFF22, "NR43"),
+    register(0xFF23, "NR44"),
+    register(0xFF24, "NR50"),
+    register(0xFF25, "NR51"),
+    register(0xFF26, "NR52"),
+    register(0xFF40, "LCDC"),
+    register(0xFF41, "STAT"),
+    register(0xFF42, "SCY"),
+    register(0xFF43, "SCX"),
+    register(0xFF44, "LY"),
+    register(0xFF45, "LYC"),
+    register(0xFF46, "DMA"),
+    register(0xFF47, "BGP"),
+    register(0xFF48, "OBP0"),
+    register(0xFF49, "OBP1"),
+    register(0xFF4A, "WY"),
+    register(0xFF4B, "WX"),
+    register(0xFF50, "BOOT"),
+];
+
+const INTERRUPTS: [&str; 5] = ["VBlank", "STAT", "Timer", "Serial", "Joypad"];
+const SHADES: [&str; 4] = ["white", "light", "dark", "black"];
+
+fn bit(value: u8, index: u8) -> bool {
+    value & (1 << index) != 0
+}
+
+fn on_off(value: u8, index: u8) -> &'static str {
+    if bit(value, index) { "on" } else { "off" }
+}
+
+fn palette(value: u8) -> Vec<String> {
+    (0..4).map(|color| format!("{}={}", color, SHADES[((value >> (color * 2)) & 0b11) as usize])).collect()
+}
+
+fn interrupt_flags(value: u8) -> Vec<String> {
+    let set: Vec<&str> = (0..5).filter(|&index| bit(value, index)).map(|index| INTERRUPTS[index as usize]).collect();
+    if set.is_empty() { vec!["none".to_string()] } else { vec![set.join(" ")] }
+}
+
+// Human readable meaning of the bit fields of a register value
+pub fn decode_fields(name: &str, value: u8) -> Vec<String> {
+    match name {
+        "P1" => vec![
+            format!("buttons {}", if bit(value, 5) { "off" } else { "selected" }),
+            format!("d-pad {}", if bit(value, 4) { "off" } else { "selected" }),
+            format!("pressed lines {:04b}", !value & 0x0F),
+        ],
+        "SC" => vec![
+            format!("transfer {}", if bit(value, 7) { "running" } else { "idle" }),
+            format!("{} clock", if bit(value, 0) { "internal" } else { "external" }),
+        ],
+        "TAC" => vec![
+            format!("timer {}", on_off(value, 2)),
+            format!("{} Hz", [4096, 262_144, 65536, 16384][(value & 0b11) as usize]),
+        ],
+        "IF" | "IE" => interrupt_flags(value),
+        "NR52" => {
+            let mut fields = vec![format!("sound {}", on_off(value, 7))];
+            fields.extend((0..4).map(|channel| format!("ch{} {}", channel + 1, on_off(value, channel))));
+            fields
+        }
+        "NR50" => vec![
+            format!("left volume {}", (value >> 4) & 0b111),
+            format!("right volume {}", value & 0b111),
+        ],
+        "NR51" => {
+            let outputs = |shift: u8| -> String {
+                let channels: Vec<String> = (0..4).filter(|&channel| bit(value, channel + shift))
+                    .map(|channel| format!("ch{}", channel + 1)).collect();
+                if channels.is_empty() { "none".to_string() } else { channels.join(" ") }
+            };
+            vec![format!("left {}", outputs(4)), format!("right {}", outputs(0))]
+        }
+        "LCDC" => vec![
+            format!("LCD {}", on_off(value, 7)),
+            format!("window map {}", if bit(value, 6) { "9C00" } else { "9800" }),
+            format!("window {}", on_off(value, 5)),
+            format!("tile data {}", if bit(value, 4) { "8000" } else { "8800" }),
+            format!("BG map {}", if bit(value, 3) { "9C00" } else { "9800" }),
+            format!("OBJ {}", if bit(value, 2) { "8x16" } else { "8x8" }),
+            format!("OBJ {}", on_off(value, 1)),
+            format!("BG {}", on_off(value, 0)),
+        ],
+        "STAT" => vec![
+            format!("LYC interrupt {}", on_off(value, 6)),
+            format!("OAM interrupt {}", on_off(value, 5)),
+            format!("VBlank interrupt {}", on_off(value, 4)),
+            format!("HBlank interrupt {}", on_off(value, 3)),
+            format!("LYC=LY {}", bit(value, 2)),
+            format!("mode {}", ["HBlank", "VBlank", "OAM", "transfer"][(value & 0b11) as usize]),
+        ],
+        "BGP" | "OBP0" | "OBP1" => palette(value),
+        "DMA" => vec![format!("source {:02X}00", value)],
+        "BOOT" => vec![format!("boot ROM {}", if value == 0 { "mapped" } else { "unmapped" })],
+        _ => vec![],
+    }
+}
+
+pub fn find_register(name_or_address: &str) -> Option<&'static IoRegister> {
+    let address = super::parse_address(name_or_address).ok();
+    IO_REGISTERS.iter().find(|register| {
+        register.name.eq_ignore_ascii_case(name_or_address) || Some(register.address) == address
+    })
+}
+
+pub fn describe_register(dmg: &DMG, register: &IoRegister) -> String {
+    let value = dmg.cpu.bus.inspect_io(register.address);
+    let fields = decode_fields(register.name, value);
+    let line = format!("{:04X} {:<5}{:02X}  {}", register.address, register.name, value, fields.join(", "));
+    line.trim_end().to_string()
+}
+
+pub fn describe_all(dmg: &DMG) -> String {
+    IO_REGISTERS.iter().map(|register| describe_register(dmg, register)).collect::<Vec<_>>().join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_lcdc() {
+        assert_eq!(decode_fields("LCDC", 0x91).join(", "),
+                   "LCD on, window map 9800, window off, tile data 8000, BG map 9800, OBJ 8x8, OBJ off, BG on");
+    }
+
+    #[test]
+    fn decode_palette_and_interrupts() {
+        assert_eq!(decode_fields("BGP", 0xFC), vec!["0=white", "1=black", "2=black", "3=black"]);
+        assert_eq!(decode_fields("IF", 0x05), vec!["VBlank Timer"]);
+        assert_eq!(decode_fields("IF", 0x00), vec!["none"]);
+        assert!(decode_fields("SCY", 0x12).is_empty());
+    }
+
+    #[test]
+    fn find_by_name_or_address() {
+        assert_eq!(find_register("lcdc").unwrap().address, 0xFF40);
+        assert_eq!(find_register("FF44").unwrap().name, "LY");
+        assert!(find_register("FF03").is_none());
+    }
+}
@@ -0,0 +1,703 @@
+pub mod apu;
+pub mod expression;
+pub mod io_registers;
+pub mod symbols;
+pub mod watchpoint;
+
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::cpu::register::DMGRegister;
+use crate::dmg::{DMG, StopReason};
+use crate::bus::ram_search::{game_shark_code, RamSearch, SearchFilter};
+use crate::movie::MovieStart;
+use crate::savestate;
+use crate::tas::{parse_buttons, TasSession};
+use expression::Expression;
+use symbols::SymbolTable;
+use watchpoint::{WatchKind, Watchpoint};
+
+const HELP: &str = "\
+Addresses are hex, or labels from a loaded .sym file such as Main or Main+3
+
+break <addr>     add a breakpoint (b)
+break <addr> if <condition>
+                 add a breakpoint that only stops when the condition holds,
+                 e.g. A == 0x3E && [0xFF44] > 0x90
+delete <addr>    remove a breakpoint (d)
+breakpoints      list breakpoints (bl)
+watch <addr>[-<end>] [read|write|change]
+                 stop on accesses to an address or inclusive range,
+                 writes by default (w)
+unwatch <addr>   remove the watchpoints starting at an address (uw)
+watchpoints      list watchpoints (wl)
+continue         run until a breakpoint is hit (c)
+step [n]         execute n instructions, 1 by default (s)
+frame            run until the end of the frame or a breakpoint (f)
+regs             show CPU registers (r)
+reset            power cycle, keeping the cartridge
+poke <addr> <byte>...
+                 write hex bytes starting at an address, ROM included
+display <expr>   show an expression after every step, continue or frame, e.g.
+                 HL, [0xFF44] or w[0xC000] + 2 for a 16-bit word (disp)
+undisplay <n>    stop showing display number n
+displays         show all displayed expressions now
+apu              show the sound channel settings
+sgb              show the Super Game Boy commands the game sent that are ignored
+savestate <file|slot>
+                 save the whole machine state to a file or a numbered slot
+loadstate <file|slot>
+                 restore a state saved from the same ROM
+timeline <frames> <file>
+                 run frames recording PPU modes, LY and interrupts, written
+                 as CSV for .csv files and as a text diagram otherwise
+tas start        start recording a movie frame by frame
+tas advance [buttons] [n]
+                 run n frames holding buttons such as A+RIGHT, - for none
+tas save <slot>  remember the state and the input recorded so far
+tas load <slot>  go back to a slot, recording continues from there
+tas write <file> save the movie for --play
+tas stop         stop recording
+cheat add <code> add a GameShark (01VVLLHH) or Game Genie (ABC-DEF[-GHI]) code
+cheat on|off|delete <n>
+                 enable, disable or remove cheat number n
+cheats           list cheats
+search start     snapshot work and high RAM to look for a value's address
+search eq <byte>|gt|lt|changed|unchanged
+                 keep the addresses equal to a byte, or greater, less,
+                 changed or unchanged since the last search command
+search list      show the addresses left with their values
+search freeze <addr> <byte>
+                 hold an address at a value with a GameShark cheat
+io [register]    show IO registers with decoded fields, all or one by name
+                 or address
+quit             exit (q)";
+
+const SEARCH_LIST_LIMIT: usize = 20;
+
+#[derive(Debug, PartialEq)]
+pub enum CommandOutcome {
+    Output(String),
+    Quit,
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    symbols: SymbolTable,
+    displays: Vec<Expression>,
+    tas: Option<TasSession>,
+    ram_search: Option<RamSearch>,
+}
+
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X").trim_start_matches('$');
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Bad address: {}", text))
+}
+
+fn parse_watch_kind(text: Option<&str>) -> Result<WatchKind, String> {
+    match text {
+        None | Some("write") => Ok(WatchKind::Write),
+        Some("read") => Ok(WatchKind::Read),
+        Some("change") => Ok(WatchKind::Change),
+        Some(other) => Err(format!("Bad watch kind: {} (read, write or change)", other)),
+    }
+}
+
+fn watch_kind_name(kind: WatchKind) -> &'static str {
+    match kind {
+        WatchKind::Read => "read",
+        WatchKind::Write => "write",
+        WatchKind::Change => "change",
+    }
+}
+
+pub fn format_registers(dmg: &DMG) -> String {
+    let cpu = &dmg.cpu;
+    format!("AF {:04X}  BC {:04X}  DE {:04X}  HL {:04X}  SP {:04X}  PC {:04X}",
+            cpu.reg_af.read(), cpu.reg_bc.read(), cpu.reg_de.read(), cpu.reg_hl.read(),
+            cpu.stack_pointer.read(), cpu.program_counter.read())
+}
+
+fn format_display(number: usize, expression: &Expression, dmg: &mut DMG) -> String {
+    let value = expression.evaluate(dmg);
+    format!("{}: {} = 0x{:X} ({})", number, expression, value, value)
+}
+
+impl Debugger {
+    pub fn new() -> Debugger { Debugger::default() }
+
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    // Accepts a label, a label with a decimal offset like Main+3, or a hex address
+    fn resolve_address(&self, text: &str) -> Result<u16, String> {
+        let (name, offset) = match text.split_once('+') {
+            Some((name, offset)) => (name, offset.parse::<u16>().map_err(|_| format!("Bad offset: {}", text))?),
+            None => (text, 0),
+        };
+        match self.symbols.address_of(name) {
+            Some(address) => Ok(address.wrapping_add(offset)),
+            None => parse_address(text),
+        }
+    }
+
+    fn resolve_range(&self, text: &str) -> Result<(u16, u16), String> {
+        match text.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (self.resolve_address(start)?, self.resolve_address(end)?);
+                if end < start { return Err(format!("Bad range: {}", text)); }
+                Ok((start, end))
+            }
+            None => self.resolve_address(text).map(|address| (address, address)),
+        }
+    }
+
+    fn format_address(&self, address: u16) -> String {
+        match self.symbols.describe(address) {
+            Some(label) => format!("{:04X} ({})", address, label),
+            None => format!("{:04X}", address),
+        }
+    }
+
+    fn format_watchpoint(&self, watchpoint: &Watchpoint) -> String {
+        let kind = watch_kind_name(watchpoint.kind);
+        if watchpoint.start == watchpoint.end {
+            format!("{} {}", self.format_address(watchpoint.start), kind)
+        } else {
+            format!("{:04X}-{:04X} {}", watchpoint.start, watchpoint.end, kind)
+        }
+    }
+
+    fn format_state(&self, dmg: &DMG) -> String {
+        let registers = format_registers(dmg);
+        match self.symbols.describe(dmg.cpu.program_counter.read()) {
+            Some(label) => format!("{}  ({})", registers, label),
+            None => registers,
+        }
+    }
+
+    fn describe_stop(&self, reason: StopReason) -> String {
+        match reason {
+            StopReason::FrameCompleted => "Frame completed".to_string(),
+            StopReason::Breakpoint(address) => format!("Breakpoint at {}", self.format_address(address)),
+            StopReason::Watchpoint(hit) => match self.symbols.describe(hit.pc) {
+                Some(label) => format!("Watchpoint: {} ({})", hit, label),
+                None => format!("Watchpoint: {}", hit),
+            },
+            StopReason::CpuStopped => "CPU stopped until a button is pressed".to_string(),
+        }
+    }
+
+    fn format_displays(&self, dmg: &mut DMG) -> String {
+        self.displays.iter().enumerate()
+            .map(|(index, expression)| format_display(index + 1, expression, dmg))
+            .collect::<Vec<_>>().join("\n")
+    }
+
+    // Appends the displayed expressions after commands that let the emulation run
+    fn with_displays(&self, dmg: &mut DMG, output: String) -> String {
+        if self.displays.is_empty() { return output; }
+        format!("{}\n{}", output, self.format_displays(dmg))
+    }
+
+    fn execute_search(&mut self, dmg: &mut DMG, subcommand: Option<&str>, arguments: &[&str]) -> Result<String, String> {
+        let usage = "Usage: search start|eq <byte>|gt|lt|changed|unchanged|list|freeze <addr> <byte>";
+        let parse_byte = |byte: Option<&&str>| -> Result<u8, String> {
+            let byte = byte.ok_or(usage)?;
+            u8::from_str_radix(byte.trim_start_matches("0x").trim_start_matches('$'), 16).map_err(|_| format!("Bad byte: {}", byte))
+        };
+        let bus = &mut dmg.cpu.bus;
+        let filter = match subcommand.ok_or(usage)? {
+            "start" => {
+                let search = self.ram_search.insert(RamSearch::start(|address| bus.peek(address)));
+                return Ok(format!("{} addresses", search.candidates().len()));
+            }
+            "list" => {
+                let search = self.ram_search.as_ref().ok_or("No search, use search start")?;
+                let mut lines: Vec<String> = search.candidates().iter().take(SEARCH_LIST_LIMIT)
+                    .map(|(address, value)| format!("{} = {:02X}", self.format_address(*address), value)).collect();
+                if search.candidates().len() > SEARCH_LIST_LIMIT {
+                    lines.push(format!("... {} more", search.candidates().len() - SEARCH_LIST_LIMIT));
+                }
+                return Ok(lines.join("\n"));
+            }
+            "freeze" => {
+                let address = self.resolve_address(arguments.first().ok_or(usage)?)?;
+                let code = game_shark_code(address, parse_byte(arguments.get(1))?);
+                let index = dmg.cheats_mut().add(&code)?;
+                return Ok(format!("Cheat {} added: {}", index, code));
+            }
+            "eq" => SearchFilter::Equal(parse_byte(arguments.first())?),
+            "gt" => SearchFilter::Greater,
+            "lt" => SearchFilter::Less,
+            "changed" => SearchFilter::Changed,
+            "unchanged" => SearchFilter::Unchanged,
+            _ => return Err(usage.to_string()),
+        };
+        let search = self.ram_search.as_mut().ok_or("No search, use search start")?;
+        Ok(format!("{} addresses left", search.filter(|address| bus.peek(address), filter)))
+    }
+
+    fn execute_tas(&mut self, dmg: &mut DMG, arguments: &[&str]) -> Result<String, String> {
+        let usage = "Usage: tas start|advance [buttons] [n]|save <slot>|load <slot>|write <file>|stop";
+        let (subcommand, arguments) = arguments.split_first().ok_or(usage)?;
+        if *subcommand == "start" {
+            let session = TasSession::new(dmg);
+            let output = match session.movie().start {
+                MovieStart::PowerOn => "Recording from power-on",
+                MovieStart::State(_) => "Recording from the current state",
+            };
+            self.tas = Some(session);
+            return Ok(output.to_string());
+        }
+        let session = self.tas.as_mut().ok_or("Not recording, use tas start")?;
+        let slot = || -> Result<u8, String> {
+            let slot = arguments.first().ok_or(usage)?;
+            slot.parse().map_err(|_| format!("Bad slot: {}", slot))
+        };
+        match *subcommand {
+            "advance" => {
+                let buttons = parse_buttons(arguments.first().unwrap_or(&"-"))?;
+                let count = match arguments.get(1) {
+                    Some(count) => count.parse::<u32>().map_err(|_| format!("Bad count: {}", count))?,
+                    None => 1,
+                };
+                let mut reason = StopReason::FrameCompleted;
+                for _ in 0..count {
+                    reason = session.frame_advance(dmg, buttons).map_err(|error| error.to_string())?;
+                    if reason != StopReason::FrameCompleted { break; }
+                }
+                let frame = session.movie().len();
+                let output = format!("{}, movie frame {}", self.describe_stop(reason), frame);
+                Ok(self.with_displays(dmg, output))
+            }
+            "save" => {
+                let slot = slot()?;
+                session.save_slot(dmg, slot);
+                Ok(format!("Slot {} saved at movie frame {}", slot, session.movie().len()))
+            }
+            "load" => {
+                let slot = slot()?;
+                let frame = session.load_slot(dmg, slot)?;
+                Ok(format!("Slot {} loaded, recording from movie frame {}", slot, frame))
+            }
+            "write" => {
+                let path = arguments.first().ok_or(usage)?;
+                session.movie().save(path).map_err(|error| format!("Cannot write {}: {}", path, error))?;
+                Ok(format!("{} frames and {} rerecords written to {}", session.movie().len(), session.movie().rerecords, path))
+            }
+            "stop" => {
+                self.tas = None;
+                Ok("Recording stopped".to_string())
+            }
+            _ => Err(usage.to_string()),
+        }
+    }
+
+    pub fn execute(&mut self, dmg: &mut DMG, line: &str) -> Result<CommandOutcome, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(CommandOutcome::Output(String::new())),
+        };
+        let argument = words.next();
+        let rest: Vec<&str> = words.collect();
+
+        let output = match command {
+            "b" | "break" => {
+                let address = self.resolve_address(argument.ok_or("break needs an address")?)?;
+                match rest.split_first() {
+                    None => {
+                        dmg.add_breakpoint(address);
+                        format!("Breakpoint set at {}", self.format_address(address))
+                    }
+                    Some((&"if", condition)) => {
+                        let condition = Expression::parse(&condition.join(" "))?;
+                        let output = format!("Breakpoint set at {} if {}", self.format_address(address), condition);
+                        dmg.add_conditional_breakpoint(address, condition);
+                        output
+                    }
+                    Some(_) => return Err("Usage: break <addr> [if <condition>]".to_string()),
+                }
+            }
+            "d" | "delete" => {
+                let address = self.resolve_address(argument.ok_or("delete needs an address")?)?;
+                if !dmg.remove_breakpoint(address) { return Err(format!("No breakpoint at {:04X}", address)); }
+                format!("Breakpoint at {:04X} removed", address)
+            }
+            "bl" | "breakpoints" => {
+                dmg.breakpoints().map(|(address, condition)| match condition {
+                    Some(condition) => format!("{} if {}", self.format_address(*address), condition),
+                    None => self.format_address(*address),
+                }).collect::<Vec<_>>().join("\n")
+            }
+            "w" | "watch" => {
+                let (start, end) = self.resolve_range(argument.ok_or("watch needs an address")?)?;
+                let kind = parse_watch_kind(rest.first().copied())?;
+                let watchpoint = Watchpoint { start, end, kind };
+                let output = format!("Watchpoint set at {}", self.format_watchpoint(&watchpoint));
+                dmg.add_watchpoint(watchpoint);
+                output
+            }
+            "uw" | "unwatch" => {
+                let address = self.resolve_address(argument.ok_or("unwatch needs an address")?)?;
+                if !dmg.remove_watchpoint(address) { return Err(format!("No watchpoint at {:04X}", address)); }
+                format!("Watchpoint at {:04X} removed", address)
+            }
+            "wl" | "watchpoints" => {
+                dmg.watchpoints().iter().map(|watchpoint| self.format_watchpoint(watchpoint)).collect::<Vec<_>>().join("\n")
+            }
+            "c" | "continue" => {
+                let output = self.describe_stop(dmg.run().map_err(|error| error.to_string())?);
+                self.with_displays(dmg, output)
+            }
+            "f" | "frame" => {
+                let output = self.describe_stop(dmg.run_frame().map_err(|error| error.to_string())?);
+                self.with_displays(dmg, output)
+            }
+            "s" | "step" => {
+                let count = match argument {
+                    Some(count) => count.parse::<u32>().map_err(|_| format!("Bad count: {}", count))?,
+                    None => 1,
+                };
+                for _ in 0..count { dmg.step().map_err(|error| error.to_string())?; }
+                let output = self.format_state(dmg);
+                self.with_displays(dmg, output)
+            }
+            "r" | "regs" => self.format_state(dmg),
+            "reset" => {
+                dmg.reset();
+                self.format_state(dmg)
+            }
+            "timeline" => {
+                let usage = "Usage: timeline <frames> <file>";
+                let frames = argument.ok_or(usage)?.parse::<u64>().map_err(|_| usage.to_string())?;
+                let path = *rest.first().ok_or(usage)?;
+                let (timeline, reason) = dmg.capture_ppu_timeline(frames).map_err(|error| error.to_string())?;
+                let written = File::create(path).and_then(|file| {
+                    let mut writer = io::BufWriter::new(file);
+                    if path.ends_with(".csv") { timeline.write_csv(&mut writer) } else { timeline.write_diagram(&mut writer) }
+                });
+                written.map_err(|error| format!("Cannot write {}: {}", path, error))?;
+                let output = format!("{}, {} timeline events written to {}",
+                                     self.describe_stop(reason), timeline.entries().len(), path);
+                self.with_displays(dmg, output)
+            }
+            "poke" => {
+                let usage = "Usage: poke <addr> <byte>...";
+                let address = self.resolve_address(argument.ok_or(usage)?)?;
+                if rest.is_empty() { return Err(usage.to_string()); }
+                let bytes = rest.iter()
+                    .map(|byte| u8::from_str_radix(byte.trim_start_matches("0x").trim_start_matches('$'), 16)
+                        .map_err(|_| format!("Bad byte: {}", byte)))
+                    .collect::<Result<Vec<u8>, String>>()?;
+                dmg.poke(address, &bytes)?;
+                format!("Wrote {} byte(s) at {}", bytes.len(), self.format_address(address))
+            }
+            "disp" | "display" => {
+                let expression = Expression::parse(&line.trim_start()[command.len()..])?;
+                let output = format_display(self.displays.len() + 1, &expression, dmg);
+                self.displays.push(expression);
+                output
+            }
+            "undisplay" => {
+                let number = argument.ok_or("undisplay needs a display number")?;
+                let index = number.parse::<usize>().ok()
+                    .filter(|index| (1..=self.displays.len()).contains(index))
+                    .ok_or_else(|| format!("No display {}", number))?;
+                let expression = self.displays.remove(index - 1);
+                format!("Display {} ({}) removed", index, expression)
+            }
+            "displays" => self.format_displays(dmg),
+            "savestate" => {
+                let target = argument.ok_or("savestate needs a file or slot")?;
+                let saved = match target.parse::<u8>() {
+                    Ok(slot) => dmg.save_state_slot(slot).map(|path| path.display().to_string()),
+                    Err(_) => savestate::save_to_file(dmg, target).map(|_| target.to_string()),
+                };
+                let path = saved.map_err(|error| format!("Cannot save {}: {}", target, error))?;
+                format!("State saved to {}", path)
+            }
+            "loadstate" => {
+                let target = argument.ok_or("loadstate needs a file or slot")?;
+                let loaded = match target.parse::<u8>() {
+                    Ok(slot) => dmg.load_state_slot(slot).map(|path| path.display().to_string()),
+                    Err(_) => savestate::load_from_file(dmg, target).map(|_| target.to_string()),
+                };
+                let path = loaded.map_err(|error| format!("Cannot load {}: {}", target, error))?;
+                format!("State loaded from {}\n{}", path, self.format_state(dmg))
+            }
+            "tas" => {
+                let arguments: Vec<&str> = argument.into_iter().chain(rest.iter().copied()).collect();
+                self.execute_tas(dmg, &arguments)?
+            }
+            "cheat" => {
+                let usage = "Usage: cheat add <code> | cheat on|off|delete <n>";
+                let value = *rest.first().ok_or(usage)?;
+                if argument == Some("add") {
+                    let index = dmg.cheats_mut().add(value)?;
+                    return Ok(CommandOutcome::Output(format!("Cheat {} added", index)));
+                }
+                let index = value.parse::<usize>().map_err(|_| format!("Bad cheat number: {}", value))?;
+                match argument {
+                    Some("on") => { dmg.cheats_mut().set_enabled(index, true)?; format!("Cheat {} enabled", index) }
+                    Some("off") => { dmg.cheats_mut().set_enabled(index, false)?; format!("Cheat {} disabled", index) }
+                    Some("delete") => format!("Cheat {} ({}) removed", index, dmg.cheats_mut().remove(index)?.code),
+                    _ => return Err(usage.to_string()),
+                }
+            }
+            "cheats" => {
+                dmg.cheats().list().iter().enumerate().map(|(index, cheat)| {
+                    format!("{}: {}{}", index, cheat.code, if cheat.enabled { "" } else { " (off)" })
+                }).collect::<Vec<_>>().join("\n")
+            }
+            "search" => self.execute_search(dmg, argument, &rest)?,
+            "apu" => {
+                let bus = &dmg.cpu.bus;
+                apu::describe_all(|address| bus.inspect_io(address))
+            }
+            "sgb" => {
+                let sgb = &dmg.cpu.bus.sgb;
+                match sgb.last_unsupported_command() {
+                    Some(code) => format!("{} unsupported commands, the last one {:02X}", sgb.unsupported_commands(), code),
+                    None => "No unsupported commands".to_string(),
+                }
+            }
+            "io" => match argument {
+                Some(name) => {
+                    let register = io_registers::find_register(name).ok_or_else(|| format!("Unknown IO register: {}", name))?;
+                    io_registers::describe_register(dmg, register)
+                }
+                None => io_registers::describe_all(dmg),
+            },
+            "h" | "help" => HELP.to_string(),
+            "q" | "quit" => return Ok(CommandOutcome::Quit),
+            _ => return Err(format!("Unknown command: {} (try help)", command)),
+        };
+        Ok(CommandOutcome::Output(output))
+    }
+
+    pub fn run_repl(&mut self, dmg: &mut DMG) {
+        let stdin = io::stdin();
+        let mut lines = stdin.lock().lines();
+        loop {
+            print!("({:04X}) > ", dmg.cpu.program_counter.read());
+            io::stdout().flush().unwrap();
+            let line = match lines.next() {
+                Some(Ok(line)) => line,
+                _ => return,
+            };
+            match self.execute(dmg, &line) {
+                Ok(CommandOutcome::Output(output)) => { if !output.is_empty() { println!("{}", output); } }
+                Ok(CommandOutcome::Quit) => return,
+                Err(message) => println!("{}", message),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::bus::sgb::SgbCommand;
+    use crate::cpu::CPU;
+
+    fn test_dmg<'a>() -> DMG<'a> {
+        // NOP; NOP; JR -4
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x00, 0x18, 0xFC], vec![])))
+    }
+
+    #[test]
+    fn parse_address_prefixes() {
+        assert_eq!(parse_address("150"), Ok(0x150));
+        assert_eq!(parse_address("0x150"), Ok(0x150));
+        assert_eq!(parse_address("$FF44"), Ok(0xFF44));
+        assert!(parse_address("xyz").is_err());
+    }
+
+    #[test]
+    fn break_and_continue() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        debugger.execute(&mut dmg, "break 0002").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, "c"), Ok(CommandOutcome::Output("Breakpoint at 0002".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "bl"), Ok(CommandOutcome::Output("0002".to_string())));
+        debugger.execute(&mut dmg, "d 2").unwrap();
+        assert!(debugger.execute(&mut dmg, "d 2").is_err());
+    }
+
+    #[test]
+    fn conditional_break() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "break 0 if A == 0x3E && [0xFF44] > 0x90"),
+                   Ok(CommandOutcome::Output("Breakpoint set at 0000 if (A == 0x3E) && ([0xFF44] > 0x90)".to_string())));
+        assert!(debugger.execute(&mut dmg, "break 0 when A == 1").is_err());
+        assert!(debugger.execute(&mut dmg, "break 0 if A ==").is_err());
+    }
+
+    #[test]
+    fn sgb_unsupported_commands() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "sgb"), Ok(CommandOutcome::Output("No unsupported commands".to_string())));
+        dmg.cpu.bus.sgb.apply(SgbCommand::decode(&[0x19 << 3 | 1]));
+        assert_eq!(debugger.execute(&mut dmg, "sgb"), Ok(CommandOutcome::Output("1 unsupported commands, the last one 19".to_string())));
+    }
+
+    #[test]
+    fn cheats() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "cheat add 0199A2C6"), Ok(CommandOutcome::Output("Cheat 0 added".to_string())));
+        debugger.execute(&mut dmg, "cheat add 3e1-23f").unwrap();
+        debugger.execute(&mut dmg, "cheat off 1").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, "cheats"), Ok(CommandOutcome::Output("0: 0199A2C6\n1: 3E1-23F (off)".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "cheat delete 0"), Ok(CommandOutcome::Output("Cheat 0 (0199A2C6) removed".to_string())));
+        assert!(debugger.execute(&mut dmg, "cheat on 1").is_err());
+        assert!(debugger.execute(&mut dmg, "cheat add 12").is_err());
+    }
+
+    #[test]
+    fn ram_search() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        dmg.cpu.bus.write(0xC010, 3);
+        assert!(debugger.execute(&mut dmg, "search lt").is_err());
+        assert_eq!(debugger.execute(&mut dmg, "search start"), Ok(CommandOutcome::Output("8319 addresses".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "search eq 3"), Ok(CommandOutcome::Output("1 addresses left".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "search list"), Ok(CommandOutcome::Output("C010 = 03".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "search freeze C010 9"), Ok(CommandOutcome::Output("Cheat 0 added: 010910C0".to_string())));
+    }
+
+    #[test]
+    fn watch_and_continue() {
+        // LD (HL),A; JR -3
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x77, 0x18, 0xFD], vec![])));
+        dmg.cpu.reg_hl.write(0xC010);
+        dmg.cpu.reg_af.write(0x0500);
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "watch C000-C0FF change"),
+                   Ok(CommandOutcome::Output("Watchpoint set at C000-C0FF change".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "c"),
+                   Ok(CommandOutcome::Output("Watchpoint: write to C010 at PC 0000: 00 -> 05".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "wl"), Ok(CommandOutcome::Output("C000-C0FF change".to_string())));
+        debugger.execute(&mut dmg, "unwatch C000").unwrap();
+        assert!(debugger.execute(&mut dmg, "unwatch C000").is_err());
+        assert!(debugger.execute(&mut dmg, "watch C0FF-C000").is_err());
+        assert!(debugger.execute(&mut dmg, "watch C000 sometimes").is_err());
+    }
+
+    #[test]
+    fn break_on_label() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        debugger.set_symbols(SymbolTable::parse("00:0000 Start\n00:0002 Loop\n").unwrap());
+        assert_eq!(debugger.execute(&mut dmg, "break Start+1"),
+                   Ok(CommandOutcome::Output("Breakpoint set at 0001 (Start+1)".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "c"),
+                   Ok(CommandOutcome::Output("Breakpoint at 0001 (Start+1)".to_string())));
+        debugger.execute(&mut dmg, "s").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, "r"), Ok(CommandOutcome::Output(
+            "AF 0000  BC 0000  DE 0000  HL 0000  SP 0000  PC 0002  (Loop)".to_string())));
+        assert!(debugger.execute(&mut dmg, "break Nowhere").is_err());
+    }
+
+    #[test]
+    fn displays_after_step() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        dmg.cpu.bus.write(0xC000, 0x34);
+        dmg.cpu.bus.write(0xC001, 0x12);
+        assert_eq!(debugger.execute(&mut dmg, "display PC + 1"),
+                   Ok(CommandOutcome::Output("1: PC + 1 = 0x1 (1)".to_string())));
+        debugger.execute(&mut dmg, "disp w[0xC000]").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, "s"), Ok(CommandOutcome::Output(
+            "AF 0000  BC 0000  DE 0000  HL 0000  SP 0000  PC 0001\n1: PC + 1 = 0x2 (2)\n2: w[0xC000] = 0x1234 (4660)".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "undisplay 1"),
+                   Ok(CommandOutcome::Output("Display 1 (PC + 1) removed".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "displays"),
+                   Ok(CommandOutcome::Output("1: w[0xC000] = 0x1234 (4660)".to_string())));
+        assert!(debugger.execute(&mut dmg, "undisplay 2").is_err());
+        assert!(debugger.execute(&mut dmg, "display").is_err());
+    }
+
+    #[test]
+    fn poke_rom() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "poke 2 00 $00"),
+                   Ok(CommandOutcome::Output("Wrote 2 byte(s) at 0002".to_string())));
+        debugger.execute(&mut dmg, "step 3").unwrap();
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0003);
+        assert!(debugger.execute(&mut dmg, "poke C000").is_err());
+        assert!(debugger.execute(&mut dmg, "poke C000 100").is_err());
+        assert!(debugger.execute(&mut dmg, "poke A000 01").is_err());
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        let path = std::env::temp_dir().join(format!("rustdmg-debugger-{}.state", std::process::id()));
+        let path = path.to_str().unwrap();
+        debugger.execute(&mut dmg, "s").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, &format!("savestate {}", path)),
+                   Ok(CommandOutcome::Output(format!("State saved to {}", path))));
+        debugger.execute(&mut dmg, "s 2").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, &format!("loadstate {}", path)), Ok(CommandOutcome::Output(
+            format!("State loaded from {}\nAF 0000  BC 0000  DE 0000  HL 0000  SP 0000  PC 0001", path))));
+        std::fs::remove_file(path).unwrap();
+        assert!(debugger.execute(&mut dmg, &format!("loadstate {}", path)).is_err());
+    }
+
+    #[test]
+    fn tas_recording() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert!(debugger.execute(&mut dmg, "tas save 1").is_err());
+        assert_eq!(debugger.execute(&mut dmg, "tas start"), Ok(CommandOutcome::Output("Recording from power-on".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "tas advance A+RIGHT"),
+                   Ok(CommandOutcome::Output("Frame completed, movie frame 1".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "tas save 1"),
+                   Ok(CommandOutcome::Output("Slot 1 saved at movie frame 1".to_string())));
+        debugger.execute(&mut dmg, "tas advance - 3").unwrap();
+        assert_eq!(debugger.execute(&mut dmg, "tas load 1"),
+                   Ok(CommandOutcome::Output("Slot 1 loaded, recording from movie frame 1".to_string())));
+        assert!(debugger.execute(&mut dmg, "tas advance Z").is_err());
+        assert!(debugger.execute(&mut dmg, "tas").is_err());
+        debugger.execute(&mut dmg, "tas stop").unwrap();
+        assert!(debugger.execute(&mut dmg, "tas advance").is_err());
+    }
+
+    #[test]
+    fn io_register() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        dmg.cpu.bus.write(0xFF47, 0xE4);
+        assert_eq!(debugger.execute(&mut dmg, "io BGP"),
+                   Ok(CommandOutcome::Output("FF47 BGP  E4  0=white, 1=light, 2=dark, 3=black".to_string())));
+        assert_eq!(debugger.execute(&mut dmg, "io FF42"), Ok(CommandOutcome::Output("FF42 SCY  00".to_string())));
+        assert!(debugger.execute(&mut dmg, "io").is_ok());
+        assert!(debugger.execute(&mut dmg, "io XYZ").is_err());
+    }
+
+    #[test]
+    fn step_count() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        debugger.execute(&mut dmg, "step 2").unwrap();
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0002);
+    }
+
+    #[test]
+    fn quit_and_unknown() {
+        let mut dmg = test_dmg();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.execute(&mut dmg, "quit"), Ok(CommandOutcome::Quit));
+        assert!(debugger.execute(&mut dmg, "frobnicate").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+
+// Labels from RGBDS or wla-dx .sym files, lines look like "00:0150 Main"
+#[derive(Default)]
+pub struct SymbolTable {
+    by_address: BTreeMap<u16, Vec<(u8, String)>>,
+    by_name: HashMap<String, u16>,
+}
+
+impl SymbolTable {
+    pub fn load(path: &str) -> io::Result<SymbolTable> {
+        SymbolTable::parse(&fs::read_to_string(path)?)
+            .map_err(|message| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, message)))
+    }
+
+    pub fn parse(text: &str) -> Result<SymbolTable, String> {
+        let mut symbols = SymbolTable::default();
+        // wla-dx splits the file into sections, only [labels] holds addresses
+        let mut in_labels = true;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() { continue; }
+            if line.starts_with('[') {
+                in_labels = line == "[labels]";
+                continue;
+            }
+            if !in_labels { continue; }
+
+            let bad_line = || format!("Bad symbol on line {}: {}", line_number + 1, line);
+            let (location, name) = line.split_once(char::is_whitespace).ok_or_else(bad_line)?;
+            let (bank, address) = location.split_once(':').ok_or_else(bad_line)?;
+            let bank = u8::from_str_radix(bank, 16).map_err(|_| bad_line())?;
+            let address = u16::from_str_radix(address, 16).map_err(|_| bad_line())?;
+            symbols.insert(bank, address, name.trim());
+        }
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, bank: u8, address: u16, name: &str) {
+        self.by_address.entry(address).or_default().push((bank, name.to_string()));
+        self.by_name.insert(name.to_string(), address);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.by_name.get(name).copied()
+    }
+
+    // Labels in the switchable area are looked up in bank 1, the bank MBCs start with, as the
+    // bank mapped at the time is not known here
+    pub fn label_at(&self, address: u16) -> Option<&str> {
+        let visible_bank = if (0x4000..0x8000).contains(&address) { 1 } else { 0 };
+        let labels = self.by_address.get(&address)?;
+        labels.iter()
+            .find(|(bank, _)| *bank == visible_bank)
+            .or_else(|| labels.first())
+            .map(|(_, name)| name.as_str())
+    }
+
+    // Closest label at or before the address, e.g. "Main+3"
+    pub fn describe(&self, address: u16) -> Option<String> {
+        let (label_address, _) = self.by_address.range(..=address).next_back()?;
+        let name = self.label_at(*label_address)?;
+        match address - label_address {
+            0 => Some(name.to_string()),
+            offset => Some(format!("{}+{}", name, offset)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RGBDS_SYM: &str = "\
+; File generated by rgblink
+00:0150 Main
+00:0160 Main.loop
+01:4000 BankedData
+00:c000 wBuffer
+";
+
+    #[test]
+    fn parse_rgbds() {
+        let symbols = SymbolTable::parse(RGBDS_SYM).unwrap();
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.address_of("Main.loop"), Some(0x0160));
+        assert_eq!(symbols.address_of("wBuffer"), Some(0xC000));
+        assert_eq!(symbols.label_at(0x4000), Some("BankedData"));
+        assert_eq!(symbols.address_of("Nope"), None);
+    }
+
+    #[test]
+    fn parse_wla_sections() {
+        let symbols = SymbolTable::parse("[labels]\n0000:0150 Start\n\n[definitions]\n00000010 _sizeof_x\n").unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(symbols.address_of("Start"), Some(0x0150));
+    }
+
+    #[test]
+    fn describe_with_offset() {
+        let symbols = SymbolTable::parse(RGBDS_SYM).unwrap();
+        assert_eq!(symbols.describe(0x0150), Some("Main".to_string()));
+        assert_eq!(symbols.describe(0x0153), Some("Main+3".to_string()));
+        assert_eq!(symbols.describe(0x0100), None);
+    }
+
+    #[test]
+    fn bad_line() {
+        assert!(SymbolTable::parse("00:01G0 Main").is_err());
+        assert!(SymbolTable::parse("Main").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use crate::bus::BusObserver;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Only writes that actually modify the stored value
+    Change,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchpointHit {
+    pub address: u16,
+    pub kind: WatchKind,
+    pub old_value: u8,
+    pub new_value: u8,
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            WatchKind::Read => write!(f, "read {:02X} from {:04X} at PC {:04X}",
+                                      self.new_value, self.address, self.pc),
+            _ => write!(f, "write to {:04X} at PC {:04X}: {:02X} -> {:02X}",
+                        self.address, self.pc, self.old_value, self.new_value),
+        }
+    }
+}
+
+// Collects the first matching access while an instruction runs, the DMG picks it up afterwards
+#[derive(Default)]
+pub struct Watchpoints {
+    watchpoints: Vec<Watchpoint>,
+    hit: Option<WatchpointHit>,
+}
+
+impl Watchpoints {
+    pub fn add(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove(&mut self, start: u16) -> bool {
+        let count_before = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.start != start);
+        self.watchpoints.len() != count_before
+    }
+
+    pub fn list(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn take_hit(&mut self) -> Option<WatchpointHit> {
+        self.hit.take()
+    }
+
+    fn record(&mut self, address: u16, access_is_write: bool, old_value: u8, new_value: u8) {
+        if self.hit.is_some() { return; }
+        let matching = self.watchpoints.iter().find(|watchpoint| {
+            address >= watchpoint.start && address <= watchpoint.end && match watchpoint.kind {
+                WatchKind::Read => !access_is_write,
+                WatchKind::Write => access_is_write,
+                WatchKind::Change => access_is_write && old_value != new_value,
+            }
+        });
+        if let Some(watchpoint) = matching {
+            self.hit = Some(WatchpointHit { address, kind: watchpoint.kind, old_value, new_value, pc: 0 });
+        }
+    }
+}
+
+impl BusObserver for Watchpoints {
+    fn on_read(&mut self, address: u16, value: u8) {
+        self.record(address, false, value, value);
+    }
+
+    fn on_write(&mut self, address: u16, old_value: u8, new_value: u8) {
+        self.record(address, true, old_value, new_value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn watching(start: u16, end: u16, kind: WatchKind) -> Watchpoints {
+        let mut watchpoints = Watchpoints::default();
+        watchpoints.add(Watchpoint { start, end, kind });
+        watchpoints
+    }
+
+    #[test]
+    fn read_watch() {
+        let mut watchpoints = watching(0xC0A3, 0xC0A3, WatchKind::Read);
+        watchpoints.on_write(0xC0A3, 0, 1);
+        assert_eq!(watchpoints.take_hit(), None);
+        watchpoints.on_read(0xC0A3, 7);
+        assert_eq!(watchpoints.take_hit().unwrap().new_value, 7);
+    }
+
+    #[test]
+    fn change_watch_ignores_same_value() {
+        let mut watchpoints = watching(0xC000, 0xC0FF, WatchKind::Change);
+        watchpoints.on_write(0xC010, 5, 5);
+        assert_eq!(watchpoints.take_hit(), None);
+        watchpoints.on_write(0xC010, 5, 6);
+        let hit = watchpoints.take_hit().unwrap();
+        assert_eq!((hit.address, hit.old_value, hit.new_value), (0xC010, 5, 6));
+    }
+
+    #[test]
+    fn write_watch_range() {
+        let mut watchpoints = watching(0xC000, 0xC0FF, WatchKind::Write);
+        watchpoints.on_write(0xC100, 5, 5);
+        assert_eq!(watchpoints.take_hit(), None);
+        watchpoints.on_write(0xC0FF, 5, 5);
+        assert!(watchpoints.take_hit().is_some());
+        assert!(watchpoints.remove(0xC000));
+        assert!(watchpoints.list().is_empty());
+    }
+}
@@ -1,27 +1,671 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
 use super::bus::cartridge::Cartridge;
-use super::bus::bootrom::BootROM;
+use super::bus::cheats::Cheats;
+use super::bus::bootrom::{BootROM, BootRomVariant};
+use super::bus::rtc::RtcClock;
 use super::bus;
-use super::cpu::CPU;
-use std::io;
+use super::bus::{HardwareMode, InfraredTransceiver, SerialSink};
+use super::cpu::{CpuState, CPU};
+use super::cpu::register::DMGRegister;
+use super::hash;
+use crate::error::EmulationError;
+use crate::debugger::expression::Expression;
+use crate::debugger::watchpoint::{Watchpoint, WatchpointHit, Watchpoints};
+use crate::input::{Button, Buttons};
 use crate::ppu::PPU;
+use crate::ppu::palettes::CompatibilityPalette;
+use crate::ppu::timeline::Timeline;
+use crate::profiler::{Location, Profiler};
+use crate::savestate;
+use crate::savestate::{SaveState, Snapshot};
+
+#[derive(Debug, PartialEq)]
+pub enum StopReason {
+    FrameCompleted,
+    Breakpoint(u16),
+    Watchpoint(WatchpointHit),
+    // STOP ran, only a button press brings the CPU back
+    CpuStopped,
+}
+
+// Gets the console after each frame, for frontends publishing frames elsewhere
+pub type FrameListener = Box<dyn FnMut(&DMG)>;
 
 pub struct DMG<'a> {
     pub cpu: CPU<'a>,
+    breakpoints: BTreeMap<u16, Option<Expression>>,
+    resuming_from_breakpoint: bool,
+    // Only hooked into the bus once the first watchpoint is set, so plain runs skip the observer
+    watchpoints: Option<Rc<RefCell<Watchpoints>>>,
+    trace: Option<Box<dyn Write>>,
+    profiler: Option<Profiler>,
+    // Where numbered save state slots are kept
+    state_directory: PathBuf,
+    // Resets go straight to the cartridge too
+    skip_boot: bool,
+    audio: bool,
 }
 
 impl<'a> DMG<'a> {
-    pub fn new(rom_file_path: &String) -> io::Result<DMG<'a>> {
+    pub fn new(rom_file_path: &str) -> io::Result<DMG<'a>> {
+        DMG::new_in_mode(rom_file_path, HardwareMode::Dmg)
+    }
+
+    pub fn new_in_mode(rom_file_path: &str, mode: HardwareMode) -> io::Result<DMG<'a>> {
+        DMG::new_with_boot_rom(rom_file_path, BootRomVariant::Dmg, mode)
+    }
+
+    // The boot ROM is read from the variant's file in the working directory
+    pub fn new_with_boot_rom(rom_file_path: &str, boot_rom: BootRomVariant, mode: HardwareMode) -> io::Result<DMG<'a>> {
         let cartridge = Cartridge::read_cartridge_from_romfile(rom_file_path)?;
-        let boot_rom = BootROM::new("DMG_ROM.bin")?;
+        DMG::new_from_cartridge(BootROM::load(boot_rom.file_name(), boot_rom)?, cartridge, mode)
+    }
+
+    pub fn new_from_reader<R: Read>(rom_reader: &mut R) -> io::Result<DMG<'a>> {
+        DMG::new_from_reader_in_mode(rom_reader, HardwareMode::Dmg)
+    }
+
+    pub fn new_from_reader_in_mode<R: Read>(rom_reader: &mut R, mode: HardwareMode) -> io::Result<DMG<'a>> {
+        DMG::new_from_reader_with_boot_rom(rom_reader, BootRomVariant::Dmg, mode)
+    }
+
+    pub fn new_from_reader_with_boot_rom<R: Read>(rom_reader: &mut R, boot_rom: BootRomVariant, mode: HardwareMode) -> io::Result<DMG<'a>> {
+        let cartridge = Cartridge::read_cartridge_from_reader(rom_reader)?;
+        DMG::new_from_cartridge(BootROM::load(boot_rom.file_name(), boot_rom)?, cartridge, mode)
+    }
+
+    // Boot ROM and cartridge from memory instead of files, e.g. for fuzzing
+    pub fn new_from_data(boot_rom_data: Vec<u8>, rom_data: &[u8]) -> io::Result<DMG<'a>> {
+        let invalid_data = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let boot_rom = BootROM::from_data(boot_rom_data).map_err(invalid_data)?;
+        let cartridge = Cartridge::from_data(rom_data.to_vec()).map_err(invalid_data)?;
+        DMG::new_from_cartridge(boot_rom, cartridge, HardwareMode::Dmg)
+    }
+
+    pub(crate) fn new_from_cartridge(boot_rom: BootROM, cartridge: Cartridge, mode: HardwareMode) -> io::Result<DMG<'a>> {
+        if !cartridge.runs_on(mode) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, EmulationError::CgbOnlyRom(cartridge.header().title)));
+        }
         let ppu = PPU::new();
-        let bus = bus::Bus::new(boot_rom, cartridge, ppu);
-        let cpu = CPU::new(bus);
-        Ok(DMG{cpu})
+        let mut bus = bus::Bus::new(boot_rom, cartridge, ppu);
+        bus.set_mode(mode);
+        Ok(DMG::new_from_cpu(CPU::new(bus)))
+    }
+
+    pub fn hardware_mode(&self) -> HardwareMode {
+        self.cpu.bus.mode()
+    }
+
+    pub(crate) fn new_from_cpu(cpu: CPU<'a>) -> DMG<'a> {
+        DMG {
+            cpu,
+            breakpoints: BTreeMap::new(),
+            resuming_from_breakpoint: false,
+            watchpoints: None,
+            trace: None,
+            profiler: None,
+            state_directory: PathBuf::from("."),
+            skip_boot: false,
+            audio: true,
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, None);
+    }
+
+    pub fn add_conditional_breakpoint(&mut self, address: u16, condition: Expression) {
+        self.breakpoints.insert(address, Some(condition));
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (&u16, &Option<Expression>)> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        let bus = &mut self.cpu.bus;
+        let watchpoints = self.watchpoints.get_or_insert_with(|| {
+            let watchpoints = Rc::new(RefCell::new(Watchpoints::default()));
+            bus.add_observer(watchpoints.clone());
+            watchpoints
+        });
+        watchpoints.borrow_mut().add(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, start: u16) -> bool {
+        match &self.watchpoints {
+            Some(watchpoints) => watchpoints.borrow_mut().remove(start),
+            None => false,
+        }
+    }
+
+    pub fn watchpoints(&self) -> Vec<Watchpoint> {
+        match &self.watchpoints {
+            Some(watchpoints) => watchpoints.borrow().list().to_vec(),
+            None => vec![],
+        }
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            cartridge: self.cpu.bus.cartridge.name.clone(),
+            cpu: self.cpu.save_state(),
+            bus: self.cpu.bus.save_state(),
+        }
+    }
+
+    pub fn load_state(&mut self, state: SaveState) -> Result<(), String> {
+        if state.cartridge != self.cpu.bus.cartridge.name {
+            return Err(format!("Save state is for {:?}, not {:?}",
+                               state.cartridge.trim_end_matches('\0'), self.cpu.bus.cartridge.name.trim_end_matches('\0')));
+        }
+        self.cpu.bus.restore_state(state.bus)?;
+        self.cpu.restore_state(&state.cpu);
+        self.resuming_from_breakpoint = false;
+        Ok(())
+    }
+
+    // Power cycles the console keeping the cartridge, RAM comes back zeroed
+    pub fn reset(&mut self) {
+        self.power_cycle(|| 0);
+    }
+
+    // Real RAM powers up with unpredictable contents, games that read it uninitialized behave
+    // differently. The seed keeps the garbage reproducible.
+    pub fn reset_with_random_ram(&mut self, seed: u64) {
+        // xorshift64, which needs a non-zero state
+        let mut state = seed | 1;
+        self.power_cycle(move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 32) as u8
+        });
+    }
+
+    fn power_cycle(&mut self, ram_fill: impl FnMut() -> u8) {
+        self.cpu.bus.reset(ram_fill);
+        self.cpu.reset();
+        if self.skip_boot { self.hand_over_to_cartridge(); }
+        self.resuming_from_breakpoint = false;
+    }
+
+    // Starts the cartridge right away, with the registers the boot ROM would have left behind
+    pub fn skip_boot_rom(&mut self) {
+        self.skip_boot = true;
+        self.hand_over_to_cartridge();
+    }
+
+    fn hand_over_to_cartridge(&mut self) {
+        let [af, bc, de, hl] = self.cpu.bus.boot_rom.variant.registers_at_handover();
+        self.cpu.restore_state(&CpuState { af, bc, de, hl, sp: 0xFFFE, pc: 0x0100, ..self.cpu.save_state() });
+        // LCD and background on with the usual palette, boot ROM unmapped
+        for (address, value) in [(0xFF40, 0x91), (0xFF47, 0xFC), (0xFF50, 0x01)] {
+            self.cpu.bus.poke(address, value).expect("IO registers can always be poked");
+        }
+        self.cpu.bus.boot_rom_active = false;
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot { state: self.save_state() }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.cpu.bus.copy_state_from(&snapshot.state.bus);
+        self.cpu.restore_state(&snapshot.state.cpu);
+        self.resuming_from_breakpoint = false;
+    }
+
+    // Frames run while skipping take as many cycles as ever but leave the framebuffer alone
+    pub fn set_skip_drawing(&mut self, skip: bool) {
+        self.cpu.bus.set_skip_drawing(skip);
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        if !(buttons - self.buttons()).is_empty() { self.cpu.resume(); }
+        self.cpu.bus.set_buttons(buttons);
+    }
+
+    pub fn set_button_state(&mut self, button: Button, pressed: bool) {
+        let buttons = self.buttons();
+        self.set_buttons(if pressed { buttons | button.into() } else { buttons - button.into() });
+    }
+
+    // Frames still complete while stopped, frontends keep running and can wake it with input
+    pub fn is_stopped(&self) -> bool {
+        self.cpu.is_stopped()
+    }
+
+    pub fn buttons(&self) -> Buttons {
+        self.cpu.bus.buttons()
+    }
+
+    // Whether the frontend asked for sound. There is no APU yet, so nothing produces any.
+    pub fn audio_enabled(&self) -> bool {
+        self.audio
+    }
+
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        self.audio = enabled;
+    }
+
+    pub fn set_state_directory(&mut self, directory: &Path) {
+        self.state_directory = directory.to_path_buf();
+    }
+
+    pub fn state_slot_path(&self, slot: u8) -> PathBuf {
+        let cartridge = &self.cpu.bus.cartridge;
+        self.state_directory.join(savestate::slot_file_name(&cartridge.name, cartridge.global_checksum(), slot))
+    }
+
+    pub fn save_state_slot(&self, slot: u8) -> io::Result<PathBuf> {
+        let path = self.state_slot_path(slot);
+        savestate::save_to_file(self, &path)?;
+        Ok(path)
+    }
+
+    pub fn load_state_slot(&mut self, slot: u8) -> io::Result<PathBuf> {
+        let path = self.state_slot_path(slot);
+        savestate::load_from_file(self, &path)?;
+        Ok(path)
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cpu.bus.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.cpu.bus.cheats
+    }
+
+    // Writes consecutive bytes starting at an address, ROM included, stopping at the first unmapped one
+    pub fn poke(&mut self, address: u16, bytes: &[u8]) -> Result<(), String> {
+        for (offset, value) in bytes.iter().enumerate() {
+            self.cpu.bus.poke(address.wrapping_add(offset as u16), *value)?;
+        }
+        Ok(())
+    }
+
+    // Logs the CPU state before every instruction executed after the boot ROM, like Gameboy Doctor expects
+    pub fn set_trace(&mut self, writer: Box<dyn Write>) {
+        self.trace = Some(writer);
+    }
+
+    fn write_trace_line(&mut self) {
+        if self.cpu.bus.boot_rom_active { return; }
+        if let Some(writer) = &mut self.trace {
+            let line = self.cpu.trace_line();
+            if let Err(error) = writeln!(writer, "{}", line) {
+                eprintln!("Trace disabled, cannot write: {}", error);
+                self.trace = None;
+            }
+        }
+    }
+
+    // Only matters for cartridges with an MBC3 clock, see RtcClock
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.cpu.bus.set_rtc_clock(clock);
     }
 
-    pub fn run(&mut self) {
+    // Faster for hot loops, see CPU::enable_block_cache
+    pub fn enable_cached_interpreter(&mut self) {
+        self.cpu.enable_block_cache();
+    }
+
+    // Like the trace, profiling starts once the boot ROM has handed over to the cartridge
+    pub fn enable_profiler(&mut self) {
+        self.profiler.get_or_insert_with(Profiler::default);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    fn breakpoint_triggers(&mut self, address: u16) -> bool {
+        match self.breakpoints.get(&address) {
+            None => false,
+            Some(None) => true,
+            Some(Some(condition)) => {
+                let condition = condition.clone();
+                condition.evaluate(self) != 0
+            }
+        }
+    }
+
+    // Executes one instruction unless the PC sits on a breakpoint. The instruction a breakpoint
+    // stopped on is executed by the next call, so resuming does not stop at the same place again.
+    // Watchpoints stop after the instruction that made the access has completed.
+    fn step_checking_breakpoints(&mut self) -> Result<Option<StopReason>, EmulationError> {
+        let pc = self.cpu.program_counter.read();
+        if !self.resuming_from_breakpoint && !self.idle() && self.breakpoint_triggers(pc) {
+            self.resuming_from_breakpoint = true;
+            return Ok(Some(StopReason::Breakpoint(pc)));
+        }
+        self.step()?;
+        let hit = self.watchpoints.as_ref().and_then(|watchpoints| watchpoints.borrow_mut().take_hit());
+        Ok(hit.map(|hit| StopReason::Watchpoint(WatchpointHit { pc, ..hit })))
+    }
+
+    pub fn step(&mut self) -> Result<(), EmulationError> {
+        self.resuming_from_breakpoint = false;
+        // Drop hits left over from single stepping, only the coming instruction should report one
+        if let Some(watchpoints) = &self.watchpoints { watchpoints.borrow_mut().take_hit(); }
+        if self.trace.is_some() && !self.idle() { self.write_trace_line(); }
+        if self.profiler.is_some() && !self.cpu.bus.boot_rom_active {
+            self.step_profiled()
+        } else {
+            self.cpu.step()
+        }
+    }
+
+    // Halted with nothing to wake up for or stopped, the next step only lets time pass. Taking an
+    // interrupt does not run an instruction either.
+    fn idle(&self) -> bool {
+        self.cpu.is_stopped() || self.cpu.is_locked() || self.cpu.takes_interrupt()
+            || (self.cpu.is_halted() && self.cpu.bus.pending_interrupts() == 0)
+    }
+
+    fn step_profiled(&mut self) -> Result<(), EmulationError> {
+        let address = self.cpu.program_counter.read();
+        let location = Location { bank: self.cpu.bus.rom_bank_at(address), address };
+        let cycles_before = self.cpu.cycle_count;
+        let result = self.cpu.step();
+        let cycles = self.cpu.cycle_count - cycles_before;
+        if let Some(profiler) = &mut self.profiler { profiler.record(location, cycles); }
+        result
+    }
+
+    // Nothing presses buttons during a run, so it ends when the CPU stops
+    pub fn run(&mut self) -> Result<StopReason, EmulationError> {
         loop {
-            self.cpu.step();
+            if let Some(reason) = self.step_checking_breakpoints()? { return Ok(reason); }
+            if self.cpu.is_stopped() { return Ok(StopReason::CpuStopped); }
+        }
+    }
+
+    pub fn run_frame(&mut self) -> Result<StopReason, EmulationError> {
+        let target_frame = self.frame_count() + 1;
+        while self.frame_count() < target_frame {
+            if let Some(reason) = self.step_checking_breakpoints()? { return Ok(reason); }
+        }
+        Ok(StopReason::FrameCompleted)
+    }
+
+    // Runs the given number of frames while recording PPU mode changes, stops early at breakpoints
+    pub fn capture_ppu_timeline(&mut self, frames: u64) -> Result<(Timeline, StopReason), EmulationError> {
+        self.cpu.bus.start_ppu_timeline(frames);
+        let mut reason = StopReason::FrameCompleted;
+        for _ in 0..frames {
+            reason = self.run_frame()?;
+            if reason != StopReason::FrameCompleted { break; }
+        }
+        Ok((self.cpu.bus.take_ppu_timeline().unwrap(), reason))
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.bus.frame_count()
+    }
+
+    pub fn framebuffer(&self) -> &[u8] {
+        self.cpu.bus.framebuffer()
+    }
+
+    // The same frame in RGB555 in CGB mode, DMG mode only has the shades
+    pub fn color_framebuffer(&self) -> Option<&[u16]> {
+        self.cpu.bus.color_framebuffer()
+    }
+
+    // The colors a DMG cartridge is shown in on a CGB
+    pub fn set_compatibility_palette(&mut self, palette: CompatibilityPalette) {
+        self.cpu.bus.set_compatibility_palette(palette);
+    }
+
+    // What the infrared port of a CGB sees, None for no signal
+    pub fn set_infrared_transceiver(&mut self, infrared: Option<Rc<RefCell<dyn InfraredTransceiver>>>) {
+        self.cpu.bus.set_infrared_transceiver(infrared);
+    }
+
+    // Whatever is on the other end of the link port, None to collect what is sent in
+    // serial_output
+    pub fn set_serial_sink(&mut self, sink: Option<Rc<RefCell<dyn SerialSink>>>) {
+        self.cpu.bus.set_serial_sink(sink);
+    }
+
+    pub fn serial_output(&self) -> &[u8] {
+        self.cpu.bus.serial_output()
+    }
+
+    // Whether OAM reads 0xFF and ignores writes while the PPU uses it, like on hardware
+    pub fn set_oam_access_blocking(&mut self, enabled: bool) {
+        self.cpu.bus.oam_access_blocking = enabled;
+    }
+
+    pub fn frame_hash(&self) -> u64 {
+        hash::fnv1a_64(self.framebuffer())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::debugger::watchpoint::WatchKind;
+
+    #[test]
+    fn run_stops_at_breakpoint() {
+        // NOP; NOP; NOP; JR -2
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x00, 0x00, 0x18, 0xFE], vec![])));
+        dmg.add_breakpoint(0x0002);
+        assert_eq!(dmg.run().unwrap(), StopReason::Breakpoint(0x0002));
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0002);
+        assert_eq!(dmg.cpu.instruction_count, 2);
+    }
+
+    #[test]
+    fn resume_executes_instruction_under_breakpoint() {
+        // NOP; JR -3
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x18, 0xFD], vec![])));
+        dmg.add_breakpoint(0x0000);
+        assert_eq!(dmg.run().unwrap(), StopReason::Breakpoint(0x0000));
+        assert_eq!(dmg.run().unwrap(), StopReason::Breakpoint(0x0000));
+        assert_eq!(dmg.cpu.instruction_count, 2);
+    }
+
+    #[test]
+    fn stop_ends_runs_until_a_button_is_pressed() {
+        // STOP; INC A; JR -2
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x10, 0x00, 0x3C, 0x18, 0xFE], vec![])));
+        dmg.set_buttons(Buttons::A);
+        assert_eq!(dmg.run().unwrap(), StopReason::CpuStopped);
+        assert!(dmg.is_stopped());
+        assert_eq!(dmg.run_frame().unwrap(), StopReason::FrameCompleted);
+        assert!(dmg.is_stopped());
+        // Releasing does not count, pressing does
+        dmg.set_buttons(Buttons::empty());
+        assert!(dmg.is_stopped());
+        dmg.set_buttons(Buttons::START);
+        assert!(!dmg.is_stopped());
+        dmg.step().unwrap();
+        assert_eq!(dmg.cpu.reg_af.read_higher(), 1);
+    }
+
+    #[test]
+    fn button_presses_reach_the_joypad_register() {
+        // LD A,$10; LDH ($00),A; HALT; LDH A,($00)
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x3E, 0x10, 0xE0, 0x00, 0x76, 0xF0, 0x00], vec![])));
+        dmg.cpu.bus.interrupt_enable = 0x10;
+        for _ in 0..3 { dmg.step().unwrap(); }
+        assert!(dmg.cpu.is_halted());
+        // Directions are not selected, pressing one does not wake it up
+        dmg.set_button_state(Button::Up, true);
+        dmg.step().unwrap();
+        assert!(dmg.cpu.is_halted());
+        dmg.set_button_state(Button::B, true);
+        dmg.step().unwrap();
+        assert_eq!(dmg.cpu.reg_af.read_higher(), 0xDD);
+        assert_eq!(dmg.buttons(), Buttons::UP | Buttons::B);
+        dmg.set_button_state(Button::Up, false);
+        assert_eq!(dmg.buttons(), Buttons::B);
+    }
+
+    #[test]
+    fn serial_transfers_reach_the_sink() {
+        #[derive(Default)]
+        struct Link { received: Vec<u8> }
+        impl SerialSink for Link {
+            fn receive(&mut self, byte: u8) { self.received.push(byte); }
         }
+
+        // LD A,$48; LDH ($01),A; LD A,$81; LDH ($02),A; HALT; LDH A,($01); JR -13
+        let program = vec![0x3E, 0x48, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x76, 0xF0, 0x01, 0x18, 0xF3];
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(program, vec![])));
+        dmg.cpu.bus.interrupt_enable = 0x08;
+        for _ in 0..7 { dmg.step().unwrap(); }
+        assert_eq!(dmg.serial_output(), b"H");
+        assert_eq!(dmg.cpu.reg_af.read_higher(), 0xFF);
+        let link = Rc::new(RefCell::new(Link::default()));
+        dmg.set_serial_sink(Some(link.clone()));
+        dmg.cpu.bus.write(0xFF0F, 0x00);
+        for _ in 0..7 { dmg.step().unwrap(); }
+        assert_eq!(link.borrow().received, b"H");
+        assert_eq!(dmg.serial_output(), b"H");
+    }
+
+    #[test]
+    fn run_frame_stops_at_breakpoint() {
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x18, 0xFD], vec![])));
+        dmg.add_breakpoint(0x0001);
+        assert_eq!(dmg.run_frame().unwrap(), StopReason::Breakpoint(0x0001));
+        assert!(dmg.remove_breakpoint(0x0001));
+        assert_eq!(dmg.run_frame().unwrap(), StopReason::FrameCompleted);
+        assert_eq!(dmg.frame_count(), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn conditional_breakpoint() {
+        // INC A; JR -3
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x3C, 0x18, 0xFD], vec![])));
+        dmg.add_conditional_breakpoint(0x0000, Expression::parse("A == 3").unwrap());
+        assert_eq!(dmg.run().unwrap(), StopReason::Breakpoint(0x0000));
+        assert_eq!(dmg.cpu.reg_af.read_a(), 3);
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> { self.0.borrow_mut().write(data) }
+        fn flush(&mut self) -> io::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn trace_skips_boot_rom() {
+        // NOP; JR -3, both in the boot ROM and the cartridge
+        let program = vec![0x00, 0x18, 0xFD, 0x00, 0x00];
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(program.clone(), program)));
+        let buffer = SharedBuffer::default();
+        dmg.set_trace(Box::new(buffer.clone()));
+        dmg.step().unwrap();
+        assert!(buffer.0.borrow().is_empty());
+
+        dmg.cpu.bus.boot_rom_active = false;
+        dmg.step().unwrap();
+        dmg.step().unwrap();
+        let log = String::from_utf8(buffer.0.borrow().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("PC:0001"));
+        assert!(lines[1].contains("PC:0000"));
+    }
+
+    #[test]
+    fn profiler_attributes_cycles() {
+        // NOP; JR -3
+        let program = vec![0x00, 0x18, 0xFD];
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(program.clone(), program)));
+        dmg.enable_profiler();
+        dmg.step().unwrap();
+        assert_eq!(dmg.profiler().unwrap().total_cycles(), 0);
+
+        dmg.cpu.bus.boot_rom_active = false;
+        for _ in 0..4 { dmg.step().unwrap(); }
+        let profiler = dmg.profiler().unwrap();
+        assert_eq!(profiler.hotspot(Location { bank: 0, address: 0x0001 }).unwrap().executions, 2);
+        assert_eq!(profiler.top(1)[0].0, Location { bank: 0, address: 0x0001 });
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        // LD (HL),A; INC A; JR -4
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x77, 0x3C, 0x18, 0xFC], vec![])));
+        dmg.cpu.reg_hl.write(0xC000);
+        dmg.run_frame().unwrap();
+        let snapshot = dmg.snapshot();
+        let (pc, memory) = (dmg.cpu.program_counter.read(), dmg.cpu.bus.peek(0xC000));
+        dmg.run_frame().unwrap();
+        assert_ne!(dmg.frame_count(), 1);
+        dmg.restore(&snapshot);
+        assert_eq!(dmg.frame_count(), 1);
+        assert_eq!(dmg.cpu.program_counter.read(), pc);
+        assert_eq!(dmg.cpu.bus.peek(0xC000), memory);
+    }
+
+    #[test]
+    fn reset_keeps_cartridge() {
+        // LD (HL),A; INC A; JR -4
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x77, 0x3C, 0x18, 0xFC], vec![0x12])));
+        dmg.cpu.reg_hl.write(0xC000);
+        dmg.run_frame().unwrap();
+        dmg.cpu.bus.write(0xFF50, 1);
+        dmg.reset();
+        assert_eq!(dmg.cpu.save_state(), CPU::new(Bus::new_from_vecs(vec![], vec![])).save_state());
+        assert_eq!(dmg.frame_count(), 0);
+        assert!(dmg.cpu.bus.boot_rom_active);
+        assert_eq!(dmg.cpu.bus.peek(0xC000), 0);
+        assert_eq!(dmg.cpu.bus.cartridge.rom_bank(0), Some(&[0x12][..]));
+
+        dmg.reset_with_random_ram(42);
+        let garbage: Vec<u8> = (0xC000..0xC010).map(|address| dmg.cpu.bus.peek(address)).collect();
+        assert!(garbage.iter().any(|byte| *byte != 0));
+        dmg.reset_with_random_ram(42);
+        assert_eq!((0xC000..0xC010).map(|address| dmg.cpu.bus.peek(address)).collect::<Vec<u8>>(), garbage);
+    }
+
+    #[test]
+    fn capture_timeline() {
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x18, 0xFD], vec![])));
+        dmg.cpu.bus.write(0xFF40, 0x80);
+        let (timeline, reason) = dmg.capture_ppu_timeline(2).unwrap();
+        assert_eq!(reason, StopReason::FrameCompleted);
+        assert_eq!(timeline.duration(), 2 * crate::ppu::timeline::FRAME_DURATION);
+        assert!(timeline.entries().len() > 2);
+    }
+
+    #[test]
+    fn write_watchpoint() {
+        // NOP; LD (HL),A; JR -3
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x00, 0x77, 0x18, 0xFD], vec![])));
+        dmg.cpu.reg_hl.write(0xC0A3);
+        dmg.cpu.reg_af.write(0x4200);
+        dmg.add_watchpoint(Watchpoint { start: 0xC0A3, end: 0xC0A3, kind: WatchKind::Change });
+        match dmg.run().unwrap() {
+            StopReason::Watchpoint(hit) => {
+                assert_eq!(hit, WatchpointHit { address: 0xC0A3, kind: WatchKind::Change, old_value: 0, new_value: 0x42, pc: 0x0001 });
+            }
+            reason => panic!("Unexpected stop: {:?}", reason),
+        }
+        assert_eq!(dmg.cpu.program_counter.read(), 0x0002);
+        assert!(dmg.remove_watchpoint(0xC0A3));
+        assert!(dmg.watchpoints().is_empty());
+    }
+}
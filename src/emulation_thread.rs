@@ -0,0 +1,127 @@
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::bench::frame_interval;
+use crate::dmg::{DMG, StopReason};
+use crate::input::Buttons;
+use crate::triple_buffer::{triple_buffer, Reader};
+
+enum Command {
+    SetButtons(Buttons),
+    Quit,
+}
+
+// Why emulation ended on its own
+#[derive(Debug)]
+pub enum Ended {
+    Stopped(StopReason),
+    Failed(String),
+}
+
+// Runs the console on its own thread at real-time speed, so a slow window or vsync never holds
+// emulation back. Frames come through a triple buffer, buttons go the other way over a channel.
+// The console is created on that thread as it is not Send.
+pub struct EmulationThread {
+    commands: Sender<Command>,
+    frames: Reader<Vec<u8>>,
+    ended: Receiver<Ended>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmulationThread {
+    pub fn spawn<F, E>(create: F) -> EmulationThread
+        where F: FnOnce() -> Result<DMG<'static>, E> + Send + 'static, E: fmt::Display {
+        let (commands, command_receiver) = mpsc::channel();
+        let (ended_sender, ended) = mpsc::channel();
+        let (mut frame_writer, frames) = triple_buffer(vec![]);
+        let handle = thread::spawn(move || {
+            let mut dmg = match create() {
+                Ok(dmg) => dmg,
+                Err(error) => { let _ = ended_sender.send(Ended::Failed(error.to_string())); return; }
+            };
+            let mut next_frame = Instant::now();
+            loop {
+                loop {
+                    match command_receiver.try_recv() {
+                        Ok(Command::SetButtons(buttons)) => dmg.set_buttons(buttons),
+                        Ok(Command::Quit) | Err(TryRecvError::Disconnected) => return,
+                        Err(TryRecvError::Empty) => break,
+                    }
+                }
+                let result = dmg.run_frame();
+                let back = frame_writer.back_mut();
+                back.clear();
+                back.extend_from_slice(dmg.framebuffer());
+                frame_writer.publish();
+                match result {
+                    Ok(StopReason::FrameCompleted) => {}
+                    Ok(reason) => { let _ = ended_sender.send(Ended::Stopped(reason)); return; }
+                    Err(error) => { let _ = ended_sender.send(Ended::Failed(error.to_string())); return; }
+                }
+                // Keep to real time, but do not try to catch up after falling behind
+                next_frame += frame_interval();
+                let now = Instant::now();
+                if next_frame > now { thread::sleep(next_frame - now); } else { next_frame = now; }
+            }
+        });
+        EmulationThread { commands, frames, ended, handle: Some(handle) }
+    }
+
+    pub fn set_buttons(&self, buttons: Buttons) {
+        let _ = self.commands.send(Command::SetButtons(buttons));
+    }
+
+    // Shades of the newest frame, None when no frame was completed since the last call
+    pub fn latest_frame(&mut self) -> Option<&[u8]> {
+        self.frames.latest().map(|frame| frame.as_slice())
+    }
+
+    pub fn ended(&self) -> Option<Ended> {
+        self.ended.try_recv().ok()
+    }
+}
+
+impl Drop for EmulationThread {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        if let Some(handle) = self.handle.take() { let _ = handle.join(); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::time::Duration;
+
+    fn dmg(rom: Vec<u8>) -> io::Result<DMG<'static>> {
+        let mut boot_rom = vec![0; 0x100];
+        // LD A,1; LDH (50),A
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        DMG::new_from_data(boot_rom, &rom)
+    }
+
+    #[test]
+    fn publishes_frames() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let mut emulation = EmulationThread::spawn(move || dmg(rom));
+        emulation.set_buttons(Buttons::START);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while emulation.latest_frame().is_none() {
+            assert!(Instant::now() < deadline, "No frame published");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(emulation.ended().is_none());
+    }
+
+    #[test]
+    fn reports_failures() {
+        let emulation = EmulationThread::spawn(|| dmg(vec![0; 10]));
+        let ended = emulation.ended.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert!(matches!(ended, Ended::Failed(_)));
+    }
+}
@@ -0,0 +1,223 @@
+use std::cell::RefCell;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::builder::DmgBuilder;
+use crate::bus::Peripheral;
+use crate::bus::ram_search::{RamSearch, SearchFilter};
+use crate::bus::bootrom::BootRomVariant;
+use crate::bus::cartridge::CartridgeHeader;
+use crate::bus::rtc::RtcClock;
+use crate::dmg::DMG;
+use crate::controller::{yield_now, Controller};
+use crate::error::EmulationError;
+use crate::input::{Button, Buttons};
+use crate::ppu::frame::Frame;
+
+// The console as a library user sees it: load a ROM, hold buttons, run frames and look at them
+pub struct Emulator {
+    dmg: DMG<'static>,
+}
+
+impl Emulator {
+    // The boot ROM is read from DMG_ROM.bin in the working directory, like the command line does
+    pub fn from_file<P: AsRef<Path>>(rom_path: P) -> Result<Emulator, EmulationError> {
+        Emulator::from_bytes(fs::read(BootRomVariant::Dmg.file_name())?, fs::read(rom_path)?)
+    }
+
+    pub fn from_bytes(boot_rom: Vec<u8>, rom: Vec<u8>) -> Result<Emulator, EmulationError> {
+        Ok(Emulator { dmg: DmgBuilder::new().boot_rom_bytes(boot_rom).rom_bytes(rom).build()? })
+    }
+
+    pub fn cartridge_header(&self) -> CartridgeHeader {
+        self.dmg.cpu.bus.cartridge.header()
+    }
+
+    // Stops at the first thing the emulator cannot do yet, the console can be run on from there
+    pub fn run_frame(&mut self) -> Result<(), EmulationError> {
+        self.dmg.run_frame().map(|_| ())
+    }
+
+    pub fn frame(&self) -> Frame {
+        Frame::new(self.dmg.framebuffer().to_vec())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.dmg.frame_count()
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.dmg.set_button_state(button, true);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.dmg.set_button_state(button, false);
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.dmg.buttons().contains(Buttons::from(button))
+    }
+
+    // Runs frames until the controller is stopped, yielding to the executor after each one so
+    // the emulator can share an async event loop. There is no pacing, the caller decides how
+    // often the task gets polled.
+    pub async fn run(&mut self, controller: &Controller) -> Result<(), EmulationError> {
+        while !controller.is_stopped() {
+            self.run_frame()?;
+            yield_now().await;
+        }
+        Ok(())
+    }
+
+    // Reads and writes in the range go to the peripheral instead of the console's own memory.
+    // Peripherals stay mapped across resets.
+    pub fn map_peripheral(&mut self, range: RangeInclusive<u16>, peripheral: Rc<RefCell<dyn Peripheral>>) -> Result<(), EmulationError> {
+        self.dmg.cpu.bus.map_peripheral(range, peripheral).map_err(EmulationError::InvalidPeripheral)
+    }
+
+    pub fn unmap_peripheral(&mut self, peripheral: &Rc<RefCell<dyn Peripheral>>) {
+        self.dmg.cpu.bus.unmap_peripheral(peripheral);
+    }
+
+    // GameShark or Game Genie code, enabled right away. Returns the number to toggle it with.
+    pub fn add_cheat(&mut self, code: &str) -> Result<usize, EmulationError> {
+        self.dmg.cheats_mut().add(code).map_err(EmulationError::InvalidCheat)
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), EmulationError> {
+        self.dmg.cheats_mut().set_enabled(index, enabled).map_err(EmulationError::InvalidCheat)
+    }
+
+    // Snapshots RAM to look for the address of a value, see RamSearch
+    pub fn start_ram_search(&mut self) -> RamSearch {
+        let bus = &mut self.dmg.cpu.bus;
+        RamSearch::start(|address| bus.peek(address))
+    }
+
+    // Keeps the candidates passing the filter against current RAM, returns how many are left
+    pub fn refine_ram_search(&mut self, search: &mut RamSearch, filter: SearchFilter) -> usize {
+        let bus = &mut self.dmg.cpu.bus;
+        search.filter(|address| bus.peek(address), filter)
+    }
+
+    // What runs the clock of MBC3 cartridges, the emulated CPU cycles unless told otherwise
+    pub fn set_rtc_clock(&mut self, clock: RtcClock) {
+        self.dmg.set_rtc_clock(clock);
+    }
+
+    // Power cycles the console keeping the cartridge
+    pub fn reset(&mut self) {
+        self.dmg.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn emulator() -> Emulator {
+        let mut boot_rom = vec![0; 0x100];
+        // LD A,1; LDH (50),A
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"DEMO");
+        // JR -2
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        Emulator::from_bytes(boot_rom, rom).unwrap()
+    }
+
+    #[test]
+    fn runs_and_reports() {
+        let mut emulator = emulator();
+        assert_eq!(emulator.cartridge_header().title, "DEMO");
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.frame_count(), 1);
+        assert_eq!(emulator.frame().shade(0, 0), 0);
+    }
+
+    #[test]
+    fn buttons() {
+        let mut emulator = emulator();
+        emulator.press(Button::A);
+        emulator.press(Button::Start);
+        emulator.release(Button::A);
+        assert!(!emulator.is_pressed(Button::A));
+        assert!(emulator.is_pressed(Button::Start));
+    }
+
+    #[test]
+    fn async_run() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::task::{Context, Poll, Waker};
+
+        let mut emulator = emulator();
+        let controller = Controller::new();
+        let mut context = Context::from_waker(Waker::noop());
+        {
+            let mut run = Box::pin(emulator.run(&controller));
+            for _ in 0..3 {
+                assert!(Pin::as_mut(&mut run).poll(&mut context).is_pending());
+            }
+            controller.stop();
+            assert!(matches!(Pin::as_mut(&mut run).poll(&mut context), Poll::Ready(Ok(()))));
+        }
+        assert_eq!(emulator.frame_count(), 3);
+    }
+
+    struct Counter { ticks: u8 }
+
+    impl Peripheral for Counter {
+        fn read(&mut self, _address: u16) -> u8 { self.ticks }
+        fn write(&mut self, _address: u16, _value: u8) { self.ticks = 0; }
+        fn tick(&mut self, cycles: u64) { self.ticks = self.ticks.wrapping_add(cycles as u8); }
+    }
+
+    #[test]
+    fn peripherals() {
+        let mut emulator = emulator();
+        let counter: Rc<RefCell<dyn Peripheral>> = Rc::new(RefCell::new(Counter { ticks: 0 }));
+        emulator.map_peripheral(0xA000..=0xA000, Rc::clone(&counter)).unwrap();
+        assert!(matches!(emulator.map_peripheral(0xA000..=0xA001, Rc::clone(&counter)),
+                         Err(EmulationError::InvalidPeripheral(_))));
+        emulator.run_frame().unwrap();
+        assert_ne!(counter.borrow_mut().read(0xA000), 0);
+        emulator.unmap_peripheral(&counter);
+    }
+
+    #[test]
+    fn cheats() {
+        let mut emulator = emulator();
+        let index = emulator.add_cheat("0199A2C6").unwrap();
+        emulator.run_frame().unwrap();
+        assert_eq!(emulator.dmg.cpu.bus.peek(0xC6A2), 0x99);
+        emulator.set_cheat_enabled(index, false).unwrap();
+        assert!(matches!(emulator.set_cheat_enabled(1, false), Err(EmulationError::InvalidCheat(_))));
+        assert!(matches!(emulator.add_cheat("nonsense"), Err(EmulationError::InvalidCheat(_))));
+    }
+
+    #[test]
+    fn ram_search() {
+        let mut emulator = emulator();
+        emulator.dmg.cpu.bus.write(0xC010, 5);
+        let mut search = emulator.start_ram_search();
+        emulator.dmg.cpu.bus.write(0xC010, 4);
+        assert_eq!(emulator.refine_ram_search(&mut search, SearchFilter::Less), 1);
+        assert_eq!(search.candidates(), &[(0xC010, 4)]);
+    }
+
+    #[test]
+    fn load_errors() {
+        assert!(matches!(Emulator::from_bytes(vec![0; 3], vec![]), Err(EmulationError::InvalidBootRom(_))));
+        assert!(matches!(Emulator::from_bytes(vec![0; 0x100], vec![0; 10]), Err(EmulationError::InvalidCartridge(_))));
+        assert_eq!(Emulator::from_file("does/not/exist.gb").err().unwrap().to_string(), "No such file or directory (os error 2)");
+        let mut rom = vec![0; 0x8000];
+        rom[0x0134..0x0138].copy_from_slice(b"DEMO");
+        rom[0x0143] = 0xC0;
+        let error = Emulator::from_bytes(vec![0; 0x100], rom).err().unwrap();
+        assert!(matches!(error, EmulationError::CgbOnlyRom(_)));
+        assert_eq!(error.to_string(), "DEMO only runs on a Game Boy Color");
+    }
+}
@@ -0,0 +1,37 @@
+use thiserror::Error;
+
+use crate::prelude::*;
+
+use crate::cpu::stats::Opcode;
+
+#[derive(Debug, Error)]
+pub enum EmulationError {
+    #[cfg(feature = "std")]
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid boot ROM: {0}")]
+    InvalidBootRom(String),
+    #[error("Invalid cartridge: {0}")]
+    InvalidCartridge(String),
+    #[error("Invalid peripheral: {0}")]
+    InvalidPeripheral(String),
+    #[error("Invalid cheat: {0}")]
+    InvalidCheat(String),
+    // The header says the cartridge needs a Game Boy Color, holds the title
+    #[error("{0} only runs on a Game Boy Color")]
+    CgbOnlyRom(String),
+    // The program ran something the emulator cannot do yet. The console stops at the first one,
+    // the instruction that ran into it has completed as far as it could.
+    #[error("Opcode {opcode} at {address:04X} is not implemented")]
+    UnimplementedOpcode { opcode: Opcode, address: u16 },
+    // The program ran one of the opcodes the SM83 does not have. The console locks up until it
+    // is reset, like the hardware does.
+    #[error("Illegal opcode {opcode:02X} at {address:04X} locked up the CPU")]
+    IllegalOpcode { opcode: u8, address: u16 },
+    #[error("{area} is not emulated, accessed at {address:04X}")]
+    UnemulatedMemory { area: &'static str, address: u16 },
+    #[error("Reading IO register {0:04X} is not supported")]
+    UnsupportedIoRead(u16),
+    #[error("Writing {value:02X} to IO register {address:04X} is not supported")]
+    UnsupportedIoWrite { address: u16, value: u8 },
+}
@@ -0,0 +1,108 @@
+use minifb::{Scale, Window, WindowOptions};
+
+use crate::debugger::apu::{decode_channels, ChannelState};
+use crate::dmg::DMG;
+use super::font::draw_text;
+use super::DebugWindow;
+
+const PLOT_WIDTH: usize = 256;
+const BAR_WIDTH: usize = 8;
+const ROW_HEIGHT: usize = 40;
+const PLOT_LEFT: usize = 12;
+pub const VIEWER_WIDTH: usize = PLOT_LEFT + PLOT_WIDTH + 3 * BAR_WIDTH;
+pub const VIEWER_HEIGHT: usize = 4 * ROW_HEIGHT;
+// Periods of each channel's waveform drawn across the plot
+const PERIODS_SHOWN: usize = 4;
+
+const BACKGROUND_COLOR: u32 = 0x0010_1010;
+const WAVE_COLOR: u32 = 0x0040_FF40;
+const MUTED_WAVE_COLOR: u32 = 0x0040_6040;
+const VOLUME_COLOR: u32 = 0x00FF_C040;
+const LENGTH_COLOR: u32 = 0x0040_A0FF;
+const TEXT_COLOR: u32 = 0x00FF_FFFF;
+
+fn fill_bar(buffer: &mut [u32], left: usize, top: usize, fraction: f64, color: u32) {
+    let height = ((ROW_HEIGHT - 4) as f64 * fraction.clamp(0.0, 1.0)) as usize;
+    for y in top + ROW_HEIGHT - 2 - height..top + ROW_HEIGHT - 2 {
+        buffer[y * VIEWER_WIDTH + left..y * VIEWER_WIDTH + left + BAR_WIDTH - 2].fill(color);
+    }
+}
+
+// Waveform on the left, then bars for volume and remaining length
+fn render_channel(buffer: &mut [u32], top: usize, channel: &ChannelState) {
+    draw_text(buffer, VIEWER_WIDTH, 2, top + 2, &channel.number.to_string(), TEXT_COLOR);
+
+    let color = if channel.dac_enabled { WAVE_COLOR } else { MUTED_WAVE_COLOR };
+    let samples = channel.waveform.len().max(1);
+    let plot_bottom = top + ROW_HEIGHT - 3;
+    for x in 0..PLOT_WIDTH {
+        let sample_index = x * samples * PERIODS_SHOWN / PLOT_WIDTH % samples;
+        let sample = *channel.waveform.get(sample_index).unwrap_or(&0) as usize;
+        let y = plot_bottom - sample * (ROW_HEIGHT - 6) / 15;
+        buffer[y * VIEWER_WIDTH + PLOT_LEFT + x] = color;
+    }
+
+    let bars_left = PLOT_LEFT + PLOT_WIDTH + BAR_WIDTH;
+    fill_bar(buffer, bars_left, top, channel.volume as f64 / 15.0, VOLUME_COLOR);
+    if channel.length_enabled {
+        let maximum_length = if channel.number == 3 { 256.0 } else { 64.0 };
+        fill_bar(buffer, bars_left + BAR_WIDTH, top, channel.length as f64 / maximum_length, LENGTH_COLOR);
+    }
+}
+
+pub fn render_channels(channels: &[ChannelState], buffer: &mut [u32]) {
+    buffer.fill(BACKGROUND_COLOR);
+    for (index, channel) in channels.iter().enumerate() {
+        render_channel(buffer, index * ROW_HEIGHT, channel);
+    }
+}
+
+pub struct ApuViewer {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+impl ApuViewer {
+    pub fn open() -> minifb::Result<Box<dyn DebugWindow>> {
+        let options = WindowOptions { scale: Scale::X2, ..WindowOptions::default() };
+        Ok(Box::new(ApuViewer {
+            window: Window::new("rustdmg - sound channels", VIEWER_WIDTH, VIEWER_HEIGHT, options)?,
+            buffer: vec![0; VIEWER_WIDTH * VIEWER_HEIGHT],
+        }))
+    }
+}
+
+impl DebugWindow for ApuViewer {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn update(&mut self, dmg: &DMG) -> minifb::Result<()> {
+        let bus = &dmg.cpu.bus;
+        render_channels(&decode_channels(|address| bus.inspect_io(address)), &mut self.buffer);
+        self.window.update_with_buffer(&self.buffer, VIEWER_WIDTH, VIEWER_HEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave_plot() {
+        let channels = decode_channels(|address| match address {
+            0xFF12 => 0xF0,
+            0xFF11 => 0x80,
+            _ => 0,
+        });
+        let mut buffer = vec![0; VIEWER_WIDTH * VIEWER_HEIGHT];
+        render_channels(&channels, &mut buffer);
+        let top_row = (ROW_HEIGHT - 3 - (ROW_HEIGHT - 6)) * VIEWER_WIDTH;
+        let bottom_row = (ROW_HEIGHT - 3) * VIEWER_WIDTH;
+        // A 50% duty wave starts high, then drops low for half the period
+        assert_eq!(buffer[top_row + PLOT_LEFT], WAVE_COLOR);
+        assert_eq!(buffer[bottom_row + PLOT_LEFT + 10], WAVE_COLOR);
+        // Channel 2 has its DAC off, so its flat line is drawn muted
+        assert_eq!(buffer[(ROW_HEIGHT + ROW_HEIGHT - 3) * VIEWER_WIDTH + PLOT_LEFT], MUTED_WAVE_COLOR);
+    }
+}
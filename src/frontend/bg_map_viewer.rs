@@ -0,0 +1,124 @@
+use minifb::{Scale, Window, WindowOptions};
+
+use crate::dmg::DMG;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::tiles::{decode_tile_map, TILE_MAP_0, TILE_MAP_1, TILE_MAP_PIXELS};
+use super::{DebugWindow, SHADES};
+
+const VIEWPORT_COLOR: u32 = 0x00FF_0000;
+const WINDOW_COLOR: u32 = 0x0000_80FF;
+
+// The registers that decide which part of the map ends up on screen
+#[derive(Clone, Copy, Default)]
+pub struct MapRegisters {
+    pub lcdc: u8,
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+    pub window_x: u8,
+    pub window_y: u8,
+}
+
+impl MapRegisters {
+    pub fn from_dmg(dmg: &DMG) -> MapRegisters {
+        let bus = &dmg.cpu.bus;
+        MapRegisters {
+            lcdc: bus.inspect_io(0xFF40),
+            scroll_y: bus.inspect_io(0xFF42),
+            scroll_x: bus.inspect_io(0xFF43),
+            window_y: bus.inspect_io(0xFF4A),
+            window_x: bus.inspect_io(0xFF4B),
+        }
+    }
+
+    fn window_enabled(&self) -> bool { self.lcdc & 0x20 != 0 }
+    fn unsigned_addressing(&self) -> bool { self.lcdc & 0x10 != 0 }
+    fn background_map(&self) -> usize { if self.lcdc & 0x08 != 0 { TILE_MAP_1 } else { TILE_MAP_0 } }
+}
+
+// Draws the outline of a screen area onto the map, wrapping around its edges like the PPU does
+fn outline(buffer: &mut [u32], left: usize, top: usize, width: usize, height: usize, color: u32) {
+    let mut plot = |x: usize, y: usize| {
+        buffer[(y % TILE_MAP_PIXELS) * TILE_MAP_PIXELS + x % TILE_MAP_PIXELS] = color;
+    };
+    for x in left..left + width {
+        plot(x, top);
+        plot(x, top + height - 1);
+    }
+    for y in top..top + height {
+        plot(left, y);
+        plot(left + width - 1, y);
+    }
+}
+
+pub fn render_background_map(video_ram: &[u8], registers: MapRegisters, buffer: &mut [u32]) {
+    let pixels = decode_tile_map(video_ram, registers.background_map(), registers.unsigned_addressing());
+    for (pixel, color) in buffer.iter_mut().zip(pixels.iter()) {
+        *pixel = SHADES[*color as usize];
+    }
+
+    let (scroll_x, scroll_y) = (registers.scroll_x as usize, registers.scroll_y as usize);
+    outline(buffer, scroll_x, scroll_y, SCREEN_WIDTH, SCREEN_HEIGHT, VIEWPORT_COLOR);
+
+    // WX is offset by 7, the window covers the screen from its position to the bottom right corner
+    let window_left = (registers.window_x as usize).saturating_sub(7);
+    let window_top = registers.window_y as usize;
+    if registers.window_enabled() && window_left < SCREEN_WIDTH && window_top < SCREEN_HEIGHT {
+        outline(buffer, scroll_x + window_left, scroll_y + window_top,
+                SCREEN_WIDTH - window_left, SCREEN_HEIGHT - window_top, WINDOW_COLOR);
+    }
+}
+
+pub struct BackgroundMapViewer {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+impl BackgroundMapViewer {
+    pub fn open() -> minifb::Result<Box<dyn DebugWindow>> {
+        let options = WindowOptions { scale: Scale::X2, ..WindowOptions::default() };
+        Ok(Box::new(BackgroundMapViewer {
+            window: Window::new("rustdmg - background map", TILE_MAP_PIXELS, TILE_MAP_PIXELS, options)?,
+            buffer: vec![0; TILE_MAP_PIXELS * TILE_MAP_PIXELS],
+        }))
+    }
+}
+
+impl DebugWindow for BackgroundMapViewer {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn update(&mut self, dmg: &DMG) -> minifb::Result<()> {
+        render_background_map(&dmg.cpu.bus.video_ram.data, MapRegisters::from_dmg(dmg), &mut self.buffer);
+        self.window.update_with_buffer(&self.buffer, TILE_MAP_PIXELS, TILE_MAP_PIXELS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(registers: MapRegisters) -> Vec<u32> {
+        let video_ram = vec![0; 0x2000];
+        let mut buffer = vec![0; TILE_MAP_PIXELS * TILE_MAP_PIXELS];
+        render_background_map(&video_ram, registers, &mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn viewport_wraps_around() {
+        let buffer = render(MapRegisters { scroll_x: 200, scroll_y: 10, ..MapRegisters::default() });
+        assert_eq!(buffer[10 * TILE_MAP_PIXELS + 200], VIEWPORT_COLOR);
+        // The right edge is at 200 + 159 = 359, which wraps to 103
+        assert_eq!(buffer[20 * TILE_MAP_PIXELS + 103], VIEWPORT_COLOR);
+        assert_eq!(buffer[20 * TILE_MAP_PIXELS + 104], SHADES[0]);
+    }
+
+    #[test]
+    fn window_marked_when_enabled() {
+        let registers = MapRegisters { lcdc: 0x20, window_x: 87, window_y: 72, ..MapRegisters::default() };
+        assert_eq!(render(registers)[72 * TILE_MAP_PIXELS + 80], WINDOW_COLOR);
+        let registers = MapRegisters { lcdc: 0x00, ..registers };
+        assert_eq!(render(registers)[72 * TILE_MAP_PIXELS + 80], SHADES[0]);
+    }
+}
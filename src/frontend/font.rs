@@ -0,0 +1,58 @@
+// A 3x5 pixel font for the labels in debug windows, one row per byte using the low three bits
+pub const GLYPH_WIDTH: usize = 3;
+pub const GLYPH_HEIGHT: usize = 5;
+pub const CHARACTER_WIDTH: usize = GLYPH_WIDTH + 1;
+
+fn glyph(character: char) -> [u8; GLYPH_HEIGHT] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+pub fn draw_text(buffer: &mut [u32], buffer_width: usize, left: usize, top: usize, text: &str, color: u32) {
+    for (index, character) in text.chars().enumerate() {
+        let glyph_left = left + index * CHARACTER_WIDTH;
+        for (y, row) in glyph(character).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if row & (0b100 >> x) != 0 {
+                    buffer[(top + y) * buffer_width + glyph_left + x] = color;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draw_glyphs() {
+        let mut buffer = vec![0; 8 * GLYPH_HEIGHT];
+        draw_text(&mut buffer, 8, 0, 0, "1-", 1);
+        // Top row of "1" is .X. and "-" starts one character further
+        assert_eq!(&buffer[0..8], &[0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&buffer[16..24], &[0, 1, 0, 0, 1, 1, 1, 0]);
+    }
+}
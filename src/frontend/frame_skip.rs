@@ -0,0 +1,79 @@
+use std::time::Instant;
+
+use crate::bench::frame_interval;
+
+// Consecutive frames left undrawn at most, so the picture keeps moving on very slow hosts
+const MAX_SKIPPED_FRAMES: u64 = 4;
+// Falling this far behind means emulation was paused (rewinding, a dragged window), start over
+const RESYNC_FRAMES: u64 = 60;
+
+// Compares emulated frames against the wall clock. Frames past due are run without drawing them,
+// keeping the console at real-time speed on hosts too slow to draw every frame.
+pub struct FrameSkip {
+    started: Option<Instant>,
+    frames_run: u64,
+}
+
+impl Default for FrameSkip {
+    fn default() -> FrameSkip { FrameSkip::new() }
+}
+
+impl FrameSkip {
+    pub fn new() -> FrameSkip {
+        FrameSkip { started: None, frames_run: 0 }
+    }
+
+    // Frames to run undrawn before the next drawn one, counting all of them as run
+    pub fn frames_to_skip(&mut self, now: Instant) -> u64 {
+        let started = *self.started.get_or_insert(now);
+        let due = (now.duration_since(started).as_nanos() / frame_interval().as_nanos()) as u64;
+        let behind = due.saturating_sub(self.frames_run);
+        if behind > RESYNC_FRAMES {
+            self.resync();
+            return self.frames_to_skip(now);
+        }
+        let skipped = behind.min(MAX_SKIPPED_FRAMES);
+        self.frames_run += skipped + 1;
+        skipped
+    }
+
+    // Forgets the time spent so far, e.g. after emulation was not running for a while
+    pub fn resync(&mut self) {
+        self.started = None;
+        self.frames_run = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeping_up_skips_nothing() {
+        let mut frame_skip = FrameSkip::new();
+        let start = Instant::now();
+        for frame in 0..10 {
+            assert_eq!(frame_skip.frames_to_skip(start + frame_interval() * frame), 0);
+        }
+    }
+
+    #[test]
+    fn catches_up_a_few_frames_at_a_time() {
+        let mut frame_skip = FrameSkip::new();
+        let start = Instant::now();
+        assert_eq!(frame_skip.frames_to_skip(start), 0);
+        assert_eq!(frame_skip.frames_to_skip(start + frame_interval() * 4), 3);
+        assert_eq!(frame_skip.frames_to_skip(start + frame_interval() * 12), MAX_SKIPPED_FRAMES);
+        assert_eq!(frame_skip.frames_to_skip(start + frame_interval() * 13), 3);
+    }
+
+    #[test]
+    fn resyncs_after_long_pauses() {
+        let mut frame_skip = FrameSkip::new();
+        let start = Instant::now();
+        assert_eq!(frame_skip.frames_to_skip(start), 0);
+        let later = start + frame_interval() * 500;
+        assert_eq!(frame_skip.frames_to_skip(later), 0);
+        assert_eq!(frame_skip.frames_to_skip(later + frame_interval()), 0);
+    }
+}
@@ -0,0 +1,169 @@
+pub mod apu_viewer;
+pub mod bg_map_viewer;
+pub mod font;
+pub mod frame_skip;
+pub mod oam_viewer;
+pub mod tile_viewer;
+
+use std::time::Instant;
+
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+
+use crate::dmg::{DMG, FrameListener, StopReason};
+use crate::emulation_thread::{EmulationThread, Ended};
+use crate::frontend::frame_skip::FrameSkip;
+use crate::input::Buttons;
+use crate::ppu::palettes::rgb555_to_rgb888;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::rewind::Rewind;
+
+const REWIND_INTERVAL_FRAMES: u64 = 2;
+const REWIND_MEMORY_BUDGET: usize = 64 * 1024 * 1024;
+
+// Colors for the four DMG shades, lightest first
+pub const SHADES: [u32; 4] = [0x00FF_FFFF, 0x00AA_AAAA, 0x0055_5555, 0x0000_0000];
+
+// A window showing emulator internals, refreshed once per emulated frame
+pub trait DebugWindow {
+    fn is_open(&self) -> bool;
+    fn update(&mut self, dmg: &DMG) -> minifb::Result<()>;
+}
+
+struct DebugWindowToggle {
+    key: Key,
+    open: fn() -> minifb::Result<Box<dyn DebugWindow>>,
+    window: Option<Box<dyn DebugWindow>>,
+}
+
+impl DebugWindowToggle {
+    fn new(key: Key, open: fn() -> minifb::Result<Box<dyn DebugWindow>>) -> DebugWindowToggle {
+        DebugWindowToggle { key, open, window: None }
+    }
+
+    fn update(&mut self, main_window: &Window, dmg: &DMG) -> minifb::Result<()> {
+        if main_window.is_key_pressed(self.key, KeyRepeat::No) {
+            self.window = match self.window {
+                Some(_) => None,
+                None => Some((self.open)()?),
+            };
+        }
+        if let Some(window) = &mut self.window {
+            if window.is_open() {
+                window.update(dmg)?;
+            } else {
+                self.window = None;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Arrows for the D-pad, Z for A, X for B, Enter for Start and Right Shift for Select
+fn held_buttons(window: &Window) -> Buttons {
+    let keys = [
+        (Key::Right, Buttons::RIGHT), (Key::Left, Buttons::LEFT), (Key::Up, Buttons::UP), (Key::Down, Buttons::DOWN),
+        (Key::Z, Buttons::A), (Key::X, Buttons::B), (Key::Enter, Buttons::START), (Key::RightShift, Buttons::SELECT),
+    ];
+    keys.iter()
+        .filter(|(key, _)| window.is_key_down(*key))
+        .fold(Buttons::empty(), |buttons, (_, button)| buttons | *button)
+}
+
+pub struct Frontend {
+    window: Window,
+    screen: Vec<u32>,
+    debug_windows: Vec<DebugWindowToggle>,
+    rewind: Rewind,
+    frame_skip: FrameSkip,
+    frame_listener: Option<FrameListener>,
+}
+
+impl Frontend {
+    pub fn new() -> minifb::Result<Frontend> {
+        let options = WindowOptions { scale: Scale::X4, ..WindowOptions::default() };
+        let mut window = Window::new("rustdmg", SCREEN_WIDTH, SCREEN_HEIGHT, options)?;
+        window.set_target_fps(60);
+        Ok(Frontend {
+            window,
+            screen: vec![SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            debug_windows: vec![
+                DebugWindowToggle::new(Key::F1, tile_viewer::TileViewer::open),
+                DebugWindowToggle::new(Key::F2, bg_map_viewer::BackgroundMapViewer::open),
+                DebugWindowToggle::new(Key::F3, oam_viewer::OamViewer::open),
+                DebugWindowToggle::new(Key::F4, apu_viewer::ApuViewer::open),
+            ],
+            rewind: Rewind::new(REWIND_INTERVAL_FRAMES, REWIND_MEMORY_BUDGET),
+            frame_skip: FrameSkip::new(),
+            frame_listener: None,
+        })
+    }
+
+    // Called with the console after every frame shown, rewound ones included
+    pub fn set_frame_listener(&mut self, listener: FrameListener) {
+        self.frame_listener = Some(listener);
+    }
+
+    // Runs a frame per window update until the window is closed, a breakpoint is hit or emulation
+    // fails. When the
+    // host falls behind real time, a few frames go by undrawn first. Holding Backspace goes back
+    // through the rewind snapshots instead.
+    pub fn run(&mut self, dmg: &mut DMG) -> minifb::Result<Option<Ended>> {
+        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+            if self.window.is_key_down(Key::Backspace) {
+                self.rewind.step_back(dmg);
+                self.frame_skip.resync();
+            } else {
+                for _ in 0..self.frame_skip.frames_to_skip(Instant::now()) {
+                    if let Some(ended) = self.run_frame(dmg, true) { return Ok(Some(ended)); }
+                }
+                if let Some(ended) = self.run_frame(dmg, false) { return Ok(Some(ended)); }
+            }
+            if let Some(listener) = &mut self.frame_listener { listener(dmg); }
+
+            match dmg.color_framebuffer() {
+                Some(colors) => self.draw_colors(colors),
+                None => self.draw(dmg.framebuffer()),
+            }
+            self.window.update_with_buffer(&self.screen, SCREEN_WIDTH, SCREEN_HEIGHT)?;
+            for debug_window in &mut self.debug_windows {
+                debug_window.update(&self.window, dmg)?;
+            }
+        }
+        Ok(None)
+    }
+
+    // Shows frames from an emulation thread, which keeps its own pace. Debug windows and rewind
+    // need the console itself and are not available.
+    pub fn run_threaded(&mut self, emulation: &mut EmulationThread) -> minifb::Result<Option<Ended>> {
+        while self.window.is_open() && !self.window.is_key_down(Key::Escape) {
+            emulation.set_buttons(held_buttons(&self.window));
+            if let Some(ended) = emulation.ended() { return Ok(Some(ended)); }
+            if let Some(frame) = emulation.latest_frame() { self.draw(frame); }
+            self.window.update_with_buffer(&self.screen, SCREEN_WIDTH, SCREEN_HEIGHT)?;
+        }
+        Ok(None)
+    }
+
+    fn run_frame(&mut self, dmg: &mut DMG, skip_drawing: bool) -> Option<Ended> {
+        self.rewind.record(dmg);
+        dmg.set_buttons(held_buttons(&self.window));
+        dmg.set_skip_drawing(skip_drawing);
+        match dmg.run_frame() {
+            Ok(StopReason::FrameCompleted) => None,
+            Ok(reason) => Some(Ended::Stopped(reason)),
+            Err(error) => Some(Ended::Failed(error.to_string())),
+        }
+    }
+
+    fn draw(&mut self, shades: &[u8]) {
+        for (pixel, shade) in self.screen.iter_mut().zip(shades) {
+            *pixel = SHADES[*shade as usize];
+        }
+    }
+
+    fn draw_colors(&mut self, colors: &[u16]) {
+        for (pixel, color) in self.screen.iter_mut().zip(colors) {
+            *pixel = rgb555_to_rgb888(*color);
+        }
+    }
+}
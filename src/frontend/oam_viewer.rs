@@ -0,0 +1,126 @@
+use minifb::{Scale, Window, WindowOptions};
+
+use crate::dmg::DMG;
+use crate::ppu::sprites::{decode_oam, dropped_by_line_limit, Sprite, OAM_ENTRIES};
+use crate::ppu::tiles::{decode_tile, TILE_SIZE};
+use super::font::{draw_text, GLYPH_HEIGHT};
+use super::{DebugWindow, SHADES};
+
+const COLUMNS: usize = 8;
+const CELL_WIDTH: usize = 32;
+const CELL_HEIGHT: usize = 26;
+pub const VIEWER_WIDTH: usize = COLUMNS * CELL_WIDTH;
+pub const VIEWER_HEIGHT: usize = OAM_ENTRIES / COLUMNS * CELL_HEIGHT;
+
+const CELL_COLOR: u32 = 0x0030_3030;
+const DROPPED_CELL_COLOR: u32 = 0x0080_2020;
+const TEXT_COLOR: u32 = 0x00FF_FFFF;
+const TEXT_LEFT: usize = 2 + TILE_SIZE + 3;
+
+fn flag_text(sprite: &Sprite) -> String {
+    format!("{}{}{}{}",
+            if sprite.behind_background() { 'P' } else { '-' },
+            if sprite.x_flip() { 'X' } else { '-' },
+            if sprite.y_flip() { 'Y' } else { '-' },
+            sprite.palette())
+}
+
+// Draws the sprite's tiles as they appear on screen, flips included, with color 0 left transparent
+fn draw_thumbnail(buffer: &mut [u32], left: usize, top: usize, video_ram: &[u8], sprite: &Sprite, tall: bool) {
+    let height = if tall { 2 * TILE_SIZE } else { TILE_SIZE };
+    let first_tile = if tall { sprite.tile & 0xFE } else { sprite.tile } as usize;
+    for y in 0..height {
+        let source_y = if sprite.y_flip() { height - 1 - y } else { y };
+        let tile = decode_tile(video_ram, first_tile + source_y / TILE_SIZE);
+        for x in 0..TILE_SIZE {
+            let source_x = if sprite.x_flip() { TILE_SIZE - 1 - x } else { x };
+            let color = tile[source_y % TILE_SIZE][source_x];
+            if color != 0 {
+                buffer[(top + y) * VIEWER_WIDTH + left + x] = SHADES[color as usize];
+            }
+        }
+    }
+}
+
+pub fn render_oam(video_ram: &[u8], oam: &[u8], lcdc: u8, buffer: &mut [u32]) {
+    let tall = lcdc & 0x04 != 0;
+    let sprites = decode_oam(oam);
+    let dropped = dropped_by_line_limit(&sprites, if tall { 16 } else { 8 });
+
+    for (index, sprite) in sprites.iter().enumerate() {
+        let left = (index % COLUMNS) * CELL_WIDTH;
+        let top = (index / COLUMNS) * CELL_HEIGHT;
+        let background = if dropped[index] { DROPPED_CELL_COLOR } else { CELL_COLOR };
+        for y in top..top + CELL_HEIGHT - 1 {
+            buffer[y * VIEWER_WIDTH + left..y * VIEWER_WIDTH + left + CELL_WIDTH - 1].fill(background);
+        }
+
+        draw_thumbnail(buffer, left + 2, top + 2, video_ram, sprite, tall);
+        let lines = [format!("X{:02X}", sprite.x), format!("Y{:02X}", sprite.y), format!("T{:02X}", sprite.tile), flag_text(sprite)];
+        for (line_index, line) in lines.iter().enumerate() {
+            draw_text(buffer, VIEWER_WIDTH, left + TEXT_LEFT, top + 1 + line_index * (GLYPH_HEIGHT + 1), line, TEXT_COLOR);
+        }
+    }
+}
+
+pub struct OamViewer {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+impl OamViewer {
+    pub fn open() -> minifb::Result<Box<dyn DebugWindow>> {
+        let options = WindowOptions { scale: Scale::X2, ..WindowOptions::default() };
+        Ok(Box::new(OamViewer {
+            window: Window::new("rustdmg - OAM", VIEWER_WIDTH, VIEWER_HEIGHT, options)?,
+            buffer: vec![0; VIEWER_WIDTH * VIEWER_HEIGHT],
+        }))
+    }
+}
+
+impl DebugWindow for OamViewer {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn update(&mut self, dmg: &DMG) -> minifb::Result<()> {
+        let bus = &dmg.cpu.bus;
+        render_oam(&bus.video_ram.data, &bus.oam.data, bus.inspect_io(0xFF40), &mut self.buffer);
+        self.window.update_with_buffer(&self.buffer, VIEWER_WIDTH, VIEWER_HEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags() {
+        assert_eq!(flag_text(&Sprite { flags: 0xB0, ..Sprite::default() }), "PX-1");
+        assert_eq!(flag_text(&Sprite::default()), "---0");
+    }
+
+    #[test]
+    fn dropped_entries_highlighted() {
+        let video_ram = vec![0; 0x2000];
+        let mut oam = vec![0; OAM_ENTRIES * 4];
+        for entry in 0..11 { oam[entry * 4] = 16; }
+        let mut buffer = vec![0; VIEWER_WIDTH * VIEWER_HEIGHT];
+        render_oam(&video_ram, &oam, 0, &mut buffer);
+        // Entry 10 is the third cell of the second row
+        assert_eq!(buffer[(CELL_HEIGHT + 20) * VIEWER_WIDTH + 2 * CELL_WIDTH + 1], DROPPED_CELL_COLOR);
+        assert_eq!(buffer[(CELL_HEIGHT + 20) * VIEWER_WIDTH + CELL_WIDTH + 1], CELL_COLOR);
+    }
+
+    #[test]
+    fn thumbnail_x_flip() {
+        let mut video_ram = vec![0; 0x2000];
+        // Tile 1, first row: only the leftmost pixel set
+        video_ram[16] = 0x80;
+        let sprite = Sprite { tile: 1, flags: 0x20, ..Sprite::default() };
+        let mut buffer = vec![0; VIEWER_WIDTH * VIEWER_HEIGHT];
+        draw_thumbnail(&mut buffer, 0, 0, &video_ram, &sprite, false);
+        assert_eq!(buffer[7], SHADES[1]);
+        assert_eq!(buffer[0], 0);
+    }
+}
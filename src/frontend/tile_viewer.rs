@@ -0,0 +1,68 @@
+use minifb::{Scale, Window, WindowOptions};
+
+use crate::dmg::DMG;
+use crate::ppu::tiles::{decode_tile, TILE_COUNT, TILE_SIZE};
+use super::{DebugWindow, SHADES};
+
+const TILES_PER_ROW: usize = 16;
+pub const SHEET_WIDTH: usize = TILES_PER_ROW * TILE_SIZE;
+pub const SHEET_HEIGHT: usize = TILE_COUNT / TILES_PER_ROW * TILE_SIZE;
+
+// Draws all tiles in video RAM as a 16 tile wide sheet, in the order they are stored
+pub fn render_tile_sheet(video_ram: &[u8], buffer: &mut [u32]) {
+    for tile_index in 0..TILE_COUNT {
+        let tile = decode_tile(video_ram, tile_index);
+        let sheet_x = (tile_index % TILES_PER_ROW) * TILE_SIZE;
+        let sheet_y = (tile_index / TILES_PER_ROW) * TILE_SIZE;
+        for (y, row) in tile.iter().enumerate() {
+            for (x, color) in row.iter().enumerate() {
+                buffer[(sheet_y + y) * SHEET_WIDTH + sheet_x + x] = SHADES[*color as usize];
+            }
+        }
+    }
+}
+
+pub struct TileViewer {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+impl TileViewer {
+    pub fn open() -> minifb::Result<Box<dyn DebugWindow>> {
+        let options = WindowOptions { scale: Scale::X2, ..WindowOptions::default() };
+        Ok(Box::new(TileViewer {
+            window: Window::new("rustdmg - tiles", SHEET_WIDTH, SHEET_HEIGHT, options)?,
+            buffer: vec![0; SHEET_WIDTH * SHEET_HEIGHT],
+        }))
+    }
+}
+
+impl DebugWindow for TileViewer {
+    fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    fn update(&mut self, dmg: &DMG) -> minifb::Result<()> {
+        render_tile_sheet(&dmg.cpu.bus.video_ram.data, &mut self.buffer);
+        self.window.update_with_buffer(&self.buffer, SHEET_WIDTH, SHEET_HEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::tiles::TILE_BYTES;
+
+    #[test]
+    fn tile_sheet_layout() {
+        let mut video_ram = vec![0; 0x2000];
+        // Tile 17 is the second tile of the second row, make its first pixel black
+        video_ram[17 * TILE_BYTES] = 0x80;
+        video_ram[17 * TILE_BYTES + 1] = 0x80;
+        let mut buffer = vec![0; SHEET_WIDTH * SHEET_HEIGHT];
+        render_tile_sheet(&video_ram, &mut buffer);
+        assert_eq!(buffer[8 * SHEET_WIDTH + 8], SHADES[3]);
+        assert_eq!(buffer[8 * SHEET_WIDTH + 9], SHADES[0]);
+        assert_eq!(SHEET_HEIGHT, 192);
+    }
+}
@@ -0,0 +1,162 @@
+// Screenshot regression tests: homebrew ROMs run for a fixed number of frames and the screen is
+// compared to a golden PNG checked in under golden/. When a screen differs, the actual screen and
+// a diff image are written to target/golden/ for review.
+//
+// The ROMs are not distributed with rustdmg, golden/<name>.png goes with <name>.gb in the homebrew
+// directory of $RUSTDMG_TEST_ROMS. Run with RUSTDMG_UPDATE_GOLDEN=1 to (re)write the goldens of every
+// ROM in there, then review the new PNGs like any other change.
+
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::batch::find_roms;
+use crate::dmg::DMG;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::test_roms::TEST_ROMS_ENV;
+
+pub const UPDATE_GOLDEN_ENV: &str = "RUSTDMG_UPDATE_GOLDEN";
+pub const GOLDEN_FRAMES: u64 = 60 * 5;
+
+// White, light grey, dark grey and black
+const SHADE_LEVELS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+const DIFF_COLOR: [u8; 3] = [0xFF, 0x00, 0x00];
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn golden_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn diff_directory() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden")
+}
+
+pub fn load_shades(path: &Path) -> io::Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|error| invalid_data(error.to_string()))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(|error| invalid_data(error.to_string()))?;
+    if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        return Err(invalid_data(format!("{} is {}x{}, expected {}x{}", path.display(), info.width, info.height,
+                                        SCREEN_WIDTH, SCREEN_HEIGHT)));
+    }
+    let channels = info.color_type.samples();
+    Ok(buffer[..info.buffer_size()].chunks(channels).map(|pixel| (255 - pixel[0]) / 85).collect())
+}
+
+fn write_png(path: &Path, color: png::ColorType, data: &[u8]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(File::create(path)?, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(color);
+    encoder.write_header().and_then(|mut writer| writer.write_image_data(data)).map_err(io::Error::from)
+}
+
+pub fn write_shades(path: &Path, shades: &[u8]) -> io::Result<()> {
+    let pixels: Vec<u8> = shades.iter().map(|shade| SHADE_LEVELS[*shade as usize & 3]).collect();
+    write_png(path, png::ColorType::Grayscale, &pixels)
+}
+
+// Matching pixels are kept faded so the differing ones stand out in red
+fn diff_image(actual: &[u8], expected: &[u8]) -> Vec<u8> {
+    actual.iter().zip(expected).flat_map(|(pixel, expected)| {
+        if pixel == expected { [0x80 + SHADE_LEVELS[*pixel as usize & 3] / 2; 3] } else { DIFF_COLOR }
+    }).collect()
+}
+
+// Compares a screen to golden_directory/<name>.png, writing <name>.actual.png and <name>.diff.png to
+// diff_directory when they differ. Returns how many pixels differ.
+pub fn compare_with_golden(golden_directory: &Path, diff_directory: &Path, name: &str, screen: &[u8]) -> io::Result<usize> {
+    let golden = load_shades(&golden_directory.join(format!("{}.png", name)))?;
+    let differing = screen.iter().zip(&golden).filter(|(pixel, expected)| pixel != expected).count();
+    if differing > 0 {
+        fs::create_dir_all(diff_directory)?;
+        write_shades(&diff_directory.join(format!("{}.actual.png", name)), screen)?;
+        write_png(&diff_directory.join(format!("{}.diff.png", name)), png::ColorType::Rgb, &diff_image(screen, &golden))?;
+    }
+    Ok(differing)
+}
+
+pub fn run_rom(rom_path: &Path, frames: u64) -> Vec<u8> {
+    let mut dmg = DMG::new(&rom_path.to_string_lossy()).unwrap();
+    for _ in 0..frames { dmg.run_frame().unwrap(); }
+    dmg.framebuffer().to_vec()
+}
+
+fn rom_name(rom_path: &Path) -> String {
+    rom_path.file_stem().unwrap().to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("rustdmg-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn stripes() -> Vec<u8> {
+        (0..SCREEN_WIDTH * SCREEN_HEIGHT).map(|index| (index % 4) as u8).collect()
+    }
+
+    #[test]
+    fn shades_round_trip() {
+        let directory = temp_directory("shades");
+        let path = directory.join("stripes.png");
+        write_shades(&path, &stripes()).unwrap();
+        assert_eq!(load_shades(&path).unwrap(), stripes());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn writes_diff_on_mismatch() {
+        let golden = temp_directory("golden");
+        let diffs = golden.join("diffs");
+        write_shades(&golden.join("stripes.png"), &stripes()).unwrap();
+        assert_eq!(compare_with_golden(&golden, &diffs, "stripes", &stripes()).unwrap(), 0);
+        assert!(!diffs.exists());
+
+        let mut screen = stripes();
+        screen[0] = 3;
+        screen[5] = 0;
+        assert_eq!(compare_with_golden(&golden, &diffs, "stripes", &screen).unwrap(), 2);
+        assert_eq!(load_shades(&diffs.join("stripes.actual.png")).unwrap(), screen);
+        assert!(diffs.join("stripes.diff.png").is_file());
+        fs::remove_dir_all(&golden).unwrap();
+    }
+
+    #[test]
+    fn diff_marks_differences() {
+        assert_eq!(diff_image(&[0, 3], &[0, 2]), vec![0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00]);
+    }
+
+    // Skips ROMs without a golden unless updating, a new golden is a reviewed change of its own
+    #[test]
+    #[ignore]
+    fn homebrew_goldens() {
+        let roms_directory = Path::new(&env::var(TEST_ROMS_ENV).expect("$RUSTDMG_TEST_ROMS is not set")).join("homebrew");
+        let update = env::var_os(UPDATE_GOLDEN_ENV).is_some();
+        let mut failures = vec![];
+        for rom in find_roms(&roms_directory).unwrap() {
+            let name = rom_name(&rom);
+            let golden = golden_directory().join(format!("{}.png", name));
+            if !update && !golden.exists() { continue; }
+            let screen = run_rom(&rom, GOLDEN_FRAMES);
+            if update {
+                fs::create_dir_all(golden_directory()).unwrap();
+                write_shades(&golden, &screen).unwrap();
+                continue;
+            }
+            let differing = compare_with_golden(&golden_directory(), &diff_directory(), &name, &screen).unwrap();
+            if differing > 0 {
+                failures.push(format!("{}: {} pixels differ, see {}", name, differing, diff_directory().join(format!("{}.diff.png", name)).display()));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+}
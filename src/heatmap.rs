@@ -0,0 +1,122 @@
+use std::io;
+use std::io::Write;
+
+use crate::bus::BusObserver;
+
+const ADDRESS_SPACE_SIZE: usize = 0x10000;
+
+// Name of the memory map area an address belongs to
+pub fn region_name(address: u16) -> &'static str {
+    match address {
+        0x0000..=0x3FFF => "ROM0",
+        0x4000..=0x7FFF => "ROMX",
+        0x8000..=0x9FFF => "VRAM",
+        0xA000..=0xBFFF => "SRAM",
+        0xC000..=0xDFFF => "WRAM",
+        0xE000..=0xFDFF => "ECHO",
+        0xFE00..=0xFE9F => "OAM",
+        0xFEA0..=0xFEFF => "UNUSED",
+        0xFF00..=0xFF7F => "IO",
+        0xFF80..=0xFFFE => "HRAM",
+        0xFFFF => "IE",
+    }
+}
+
+// Counts CPU reads and writes to every address, to help spot a game's important variables
+pub struct Heatmap {
+    reads: Vec<u64>,
+    writes: Vec<u64>,
+}
+
+impl Default for Heatmap {
+    fn default() -> Heatmap { Heatmap::new() }
+}
+
+impl Heatmap {
+    pub fn new() -> Heatmap {
+        Heatmap { reads: vec![0; ADDRESS_SPACE_SIZE], writes: vec![0; ADDRESS_SPACE_SIZE] }
+    }
+
+    pub fn reads(&self, address: u16) -> u64 {
+        self.reads[address as usize]
+    }
+
+    pub fn writes(&self, address: u16) -> u64 {
+        self.writes[address as usize]
+    }
+
+    fn touched(&self) -> impl Iterator<Item = (u16, u64, u64)> + '_ {
+        (0..ADDRESS_SPACE_SIZE)
+            .filter(move |&address| self.reads[address] > 0 || self.writes[address] > 0)
+            .map(move |address| (address as u16, self.reads[address], self.writes[address]))
+    }
+
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "address,region,reads,writes")?;
+        for (address, reads, writes) in self.touched() {
+            writeln!(writer, "{:04X},{},{},{}", address, region_name(address), reads, writes)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "[")?;
+        for (index, (address, reads, writes)) in self.touched().enumerate() {
+            if index > 0 { write!(writer, ",")?; }
+            write!(writer, "\n  {{\"address\": \"{:04X}\", \"region\": \"{}\", \"reads\": {}, \"writes\": {}}}",
+                   address, region_name(address), reads, writes)?;
+        }
+        writeln!(writer, "\n]")
+    }
+}
+
+impl BusObserver for Heatmap {
+    fn on_read(&mut self, address: u16, _value: u8) {
+        self.reads[address as usize] += 1;
+    }
+
+    fn on_write(&mut self, address: u16, _old_value: u8, _new_value: u8) {
+        self.writes[address as usize] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_heatmap() -> Heatmap {
+        let mut heatmap = Heatmap::new();
+        heatmap.on_read(0x0150, 0);
+        heatmap.on_read(0x0150, 0);
+        heatmap.on_write(0xC0A3, 0, 1);
+        heatmap
+    }
+
+    #[test]
+    fn regions() {
+        assert_eq!(region_name(0x0150), "ROM0");
+        assert_eq!(region_name(0xC0A3), "WRAM");
+        assert_eq!(region_name(0xFF44), "IO");
+        assert_eq!(region_name(0xFFFF), "IE");
+    }
+
+    #[test]
+    fn export_csv() {
+        let mut output = vec![];
+        sample_heatmap().write_csv(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "address,region,reads,writes\n0150,ROM0,2,0\nC0A3,WRAM,0,1\n");
+    }
+
+    #[test]
+    fn export_json() {
+        let mut output = vec![];
+        sample_heatmap().write_json(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "[\n  {\"address\": \"0150\", \"region\": \"ROM0\", \"reads\": 2, \"writes\": 0},\n  \
+                    {\"address\": \"C0A3\", \"region\": \"WRAM\", \"reads\": 0, \"writes\": 1}\n]\n");
+        let mut empty = vec![];
+        Heatmap::new().write_json(&mut empty).unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "[\n]\n");
+    }
+}
@@ -0,0 +1,49 @@
+use bitflags::bitflags;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+bitflags! {
+    // Pressed buttons, laid out like the two nibbles the joypad register selects between
+    #[derive(Default)]
+    pub struct Buttons: u8 {
+        const RIGHT = 0b0000_0001;
+        const LEFT = 0b0000_0010;
+        const UP = 0b0000_0100;
+        const DOWN = 0b0000_1000;
+        const A = 0b0001_0000;
+        const B = 0b0010_0000;
+        const SELECT = 0b0100_0000;
+        const START = 0b1000_0000;
+    }
+}
+
+// One of the eight buttons, the values are its bit in Buttons
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Right = 1,
+    Left = 2,
+    Up = 4,
+    Down = 8,
+    A = 16,
+    B = 32,
+    Select = 64,
+    Start = 128,
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Buttons {
+        Buttons::from_bits_truncate(button as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn button_bits() {
+        assert_eq!(Buttons::from(Button::Right), Buttons::RIGHT);
+        assert_eq!(Buttons::from(Button::Select) | Button::Start.into(), Buttons::SELECT | Buttons::START);
+    }
+}
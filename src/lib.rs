@@ -1,8 +1,122 @@
+#![allow(clippy::upper_case_acronyms)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+// Much of the core is only used by the debugger, save states and other std-only modules
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+extern crate alloc;
+#[cfg(feature = "std")]
 extern crate blit;
 extern crate bitflags;
 
+// Without std there is no console, messages printed by the emulation core go nowhere
+#[cfg(not(any(feature = "std", test)))]
+macro_rules! print {
+    ($($argument:tt)*) => { { let _ = format_args!($($argument)*); } };
+}
+#[cfg(not(any(feature = "std", test)))]
+macro_rules! println {
+    () => {};
+    ($($argument:tt)*) => { { let _ = format_args!($($argument)*); } };
+}
+
+// What the emulation core needs from alloc, which std builds get from the std prelude
+mod prelude {
+    pub use alloc::format;
+    pub use alloc::string::{String, ToString};
+    pub use alloc::vec;
+    pub use alloc::vec::Vec;
+}
+
+#[cfg(feature = "std")]
+#[doc(hidden)]
 pub mod dmg;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod builder;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod batch;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod debugger;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod profiler;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod heatmap;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod trace_diff;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod savestate;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod rewind;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod movie;
+#[doc(hidden)]
+pub mod input;
+#[doc(hidden)]
+pub mod machine;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod tas;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod test_roms;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod sm83_tests;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod watchdog;
+#[cfg(feature = "std")]
+#[doc(hidden)]
+pub mod emulation_thread;
+#[cfg(feature = "std")]
+mod triple_buffer;
+#[cfg(feature = "shared-frame")]
+#[doc(hidden)]
+pub mod shared_frame;
+#[cfg(feature = "gui")]
+#[doc(hidden)]
+pub mod frontend;
+#[cfg(feature = "wasm")]
+#[doc(hidden)]
+pub mod wasm;
 mod cpu;
 mod bus;
-mod ppu;
+#[doc(hidden)]
+pub mod ppu;
+mod hash;
+mod error;
+mod controller;
+#[cfg(feature = "std")]
+mod emulator;
+#[cfg(all(test, feature = "std"))]
+mod golden;
+#[cfg(all(test, feature = "std"))]
+mod smoke_tests;
 
+// The supported library API, the modules above stay public for the bundled binaries and tools
+// and may change at any time
+pub use bus::Peripheral;
+pub use bus::bootrom::BootRomVariant;
+pub use controller::Controller;
+pub use bus::cartridge::{CartridgeHeader, CgbSupport};
+pub use bus::{HardwareMode, InfraredTransceiver};
+pub use bus::ram_search::{RamSearch, SearchFilter};
+pub use bus::rtc::RtcClock;
+#[cfg(feature = "std")]
+pub use emulator::Emulator;
+pub use error::EmulationError;
+pub use cpu::stats::Opcode;
+pub use input::Button;
+pub use machine::Machine;
+pub use ppu::frame::Frame;
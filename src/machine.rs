@@ -0,0 +1,84 @@
+use core::cell::RefCell;
+use core::ops::RangeInclusive;
+use alloc::rc::Rc;
+
+use crate::prelude::*;
+
+use crate::bus::{Bus, HardwareMode, Peripheral};
+use crate::bus::bootrom::BootROM;
+use crate::bus::cartridge::Cartridge;
+use crate::cpu::CPU;
+use crate::error::EmulationError;
+use crate::ppu::PPU;
+use crate::ppu::frame::Frame;
+
+// The emulation core on its own, for builds without std: no files, debugger, tracing or save
+// states, just the console running a boot ROM and cartridge handed over as bytes. DMG wraps the
+// same CPU and bus with everything that needs an operating system.
+pub struct Machine<'a> {
+    cpu: CPU<'a>,
+}
+
+impl<'a> Machine<'a> {
+    pub fn new(boot_rom_data: Vec<u8>, rom_data: Vec<u8>) -> Result<Machine<'a>, EmulationError> {
+        let boot_rom = BootROM::from_data(boot_rom_data).map_err(EmulationError::InvalidBootRom)?;
+        let cartridge = Cartridge::from_data(rom_data).map_err(EmulationError::InvalidCartridge)?;
+        if !cartridge.runs_on(HardwareMode::Dmg) { return Err(EmulationError::CgbOnlyRom(cartridge.header().title)); }
+        Ok(Machine { cpu: CPU::new(Bus::new(boot_rom, cartridge, PPU::new())) })
+    }
+
+    pub fn step(&mut self) -> Result<(), EmulationError> {
+        self.cpu.step()
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), EmulationError> {
+        let target_frame = self.frame_count() + 1;
+        while self.frame_count() < target_frame { self.step()?; }
+        Ok(())
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.bus.frame_count()
+    }
+
+    pub fn frame(&self) -> Frame {
+        Frame::new(self.cpu.bus.framebuffer().to_vec())
+    }
+
+    pub fn map_peripheral(&mut self, range: RangeInclusive<u16>, peripheral: Rc<RefCell<dyn Peripheral>>) -> Result<(), EmulationError> {
+        self.cpu.bus.map_peripheral(range, peripheral).map_err(EmulationError::InvalidPeripheral)
+    }
+
+    pub fn unmap_peripheral(&mut self, peripheral: &Rc<RefCell<dyn Peripheral>>) {
+        self.cpu.bus.unmap_peripheral(peripheral);
+    }
+
+    // Power cycles the console keeping the cartridge, RAM comes back zeroed
+    pub fn reset(&mut self) {
+        self.cpu.bus.reset(|| 0);
+        self.cpu.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_frames_from_data() {
+        let mut rom = vec![0; 0x8000];
+        // JR -2 at the entry point, where the boot ROM below hands over
+        rom[0x0100..0x0102].copy_from_slice(&[0x18, 0xFE]);
+        let mut boot_rom = vec![0; 0x100];
+        // LD A,1; LDH (50),A
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        let mut machine = Machine::new(boot_rom, rom).unwrap();
+        machine.run_frame().unwrap();
+        machine.run_frame().unwrap();
+        assert_eq!(machine.frame_count(), 2);
+        assert!(!machine.cpu.bus.boot_rom_active);
+        machine.reset();
+        assert_eq!(machine.frame_count(), 0);
+        assert!(matches!(Machine::new(vec![], vec![]), Err(EmulationError::InvalidBootRom(_))));
+    }
+}
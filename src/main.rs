@@ -1,23 +1,402 @@
+use std::cell::RefCell;
 use std::env;
-use rustdmg::dmg;
+use std::fs::{self, File};
+use std::io;
+use std::io::{BufReader, BufWriter, Read};
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+use std::time::Duration;
+use rustdmg::{batch, bench, debugger, dmg, savestate, sm83_tests, test_roms, trace_diff, BootRomVariant, EmulationError, HardwareMode, RtcClock};
+use rustdmg::builder::DmgBuilder;
+use rustdmg::debugger::symbols::SymbolTable;
+use rustdmg::heatmap::Heatmap;
+use rustdmg::ppu::palettes::CompatibilityPalette;
+use rustdmg::movie::Movie;
+use rustdmg::watchdog::{Watchdog, WatchdogError};
 
+const DEFAULT_BATCH_FRAMES: u64 = 600;
+const BENCH_DURATION: Duration = Duration::from_secs(10);
+const STATS_TOP_OPCODES: usize = 20;
+const PROFILE_TOP_HOTSPOTS: usize = 30;
+
+// Assemblers write game.sym next to game.gb, pick it up unless a file was given
+fn load_symbols(symbol_file_path: Option<String>, rom_file_path: &str) -> Option<SymbolTable> {
+    let symbol_file_path = symbol_file_path.or_else(|| {
+        let default_path = Path::new(rom_file_path).with_extension("sym");
+        if rom_file_path != "-" && default_path.is_file() { Some(default_path.to_string_lossy().into_owned()) } else { None }
+    })?;
+    match SymbolTable::load(&symbol_file_path) {
+        Ok(symbols) => { println!("Loaded {} symbols from {}", symbols.len(), symbol_file_path); Some(symbols) }
+        Err(error) => { eprintln!("Cannot load symbols: {}", error); process::exit(1); }
+    }
+}
+
+fn export_heatmap(heatmap: &Heatmap, heatmap_file_path: &str) {
+    let export = File::create(heatmap_file_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        if heatmap_file_path.ends_with(".json") { heatmap.write_json(&mut writer) } else { heatmap.write_csv(&mut writer) }
+    });
+    match export {
+        Ok(()) => println!("Memory access heatmap written to {}", heatmap_file_path),
+        Err(error) => eprintln!("Cannot write {}: {}", heatmap_file_path, error),
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(dmg: &mut dmg::DMG, frame_listener: Option<dmg::FrameListener>) {
+    let result = rustdmg::frontend::Frontend::new().and_then(|mut frontend| {
+        if let Some(listener) = frame_listener { frontend.set_frame_listener(listener); }
+        frontend.run(dmg)
+    });
+    match result {
+        Ok(Some(rustdmg::emulation_thread::Ended::Stopped(reason))) => println!("Stopped: {:?}", reason),
+        Ok(Some(rustdmg::emulation_thread::Ended::Failed(error))) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+        Ok(None) => {}
+        Err(error) => { eprintln!("Graphical frontend failed: {}", error); process::exit(1); }
+    }
+}
+
+// "-" reads the ROM from standard input
+fn read_rom(rom_file_path: &str) -> Vec<u8> {
+    let rom = if rom_file_path == "-" {
+        let mut rom = vec![];
+        io::stdin().lock().read_to_end(&mut rom).map(|_| rom)
+    } else {
+        fs::read(rom_file_path)
+    };
+    match rom {
+        Ok(rom) => rom,
+        Err(error) => { eprintln!("Cannot read {}: {}", rom_file_path, error); process::exit(1); }
+    }
+}
+
+// Emulates on a thread of its own while this one only presents frames and reads the keyboard
+#[cfg(feature = "gui")]
+fn run_gui_threaded(rom: Vec<u8>, settings: ConsoleSettings) {
+    // The builder is not Send, so it is put together on the emulation thread
+    let mut emulation = rustdmg::emulation_thread::EmulationThread::spawn(move || settings.builder().rom_bytes(rom).build());
+    let result = rustdmg::frontend::Frontend::new().and_then(|mut frontend| frontend.run_threaded(&mut emulation));
+    match result {
+        Ok(Some(rustdmg::emulation_thread::Ended::Stopped(reason))) => println!("Stopped: {:?}", reason),
+        Ok(Some(rustdmg::emulation_thread::Ended::Failed(error))) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+        Ok(None) => {}
+        Err(error) => { eprintln!("Graphical frontend failed: {}", error); process::exit(1); }
+    }
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui_threaded(_rom: Vec<u8>, _settings: ConsoleSettings) {
+    eprintln!("rustdmg was built without the gui feature");
+    process::exit(2);
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_dmg: &mut dmg::DMG, _frame_listener: Option<dmg::FrameListener>) {
+    eprintln!("rustdmg was built without the gui feature");
+    process::exit(2);
+}
+
+// Runs headless, or in the window with --gui, publishing every frame for external viewers
+#[cfg(feature = "shared-frame")]
+fn run_with_shared_frame(dmg: &mut dmg::DMG, shared_frame_path: &str, gui: bool) {
+    let mut shared_frame = match rustdmg::shared_frame::SharedFrame::create(shared_frame_path) {
+        Ok(shared_frame) => shared_frame,
+        Err(error) => { eprintln!("Cannot map {}: {}", shared_frame_path, error); process::exit(1); }
+    };
+    if gui {
+        run_gui(dmg, Some(Box::new(move |dmg: &dmg::DMG| shared_frame.publish(dmg))));
+        return;
+    }
+    loop {
+        let result = dmg.run_frame();
+        shared_frame.publish(dmg);
+        match result {
+            Ok(dmg::StopReason::FrameCompleted) => {}
+            Ok(reason) => { println!("Stopped: {:?}", reason); return; }
+            Err(error) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+        }
+    }
+}
+
+#[cfg(not(feature = "shared-frame"))]
+fn run_with_shared_frame(_dmg: &mut dmg::DMG, _shared_frame_path: &str, _gui: bool) {
+    eprintln!("rustdmg was built without the shared-frame feature");
+    process::exit(2);
+}
+
+fn print_analysis(dmg: &dmg::DMG, stats: bool, symbols: Option<&SymbolTable>) {
+    if stats { println!("{}", dmg.cpu.opcode_report(STATS_TOP_OPCODES)); }
+    if let Some(profiler) = dmg.profiler() { println!("{}", profiler.report(PROFILE_TOP_HOTSPOTS, symbols)); }
+}
+
+
+// What the command line decides about the console before it is switched on
+#[derive(Clone, Copy)]
+struct ConsoleSettings {
+    boot_rom: BootRomVariant,
+    hardware_mode: Option<HardwareMode>,
+    skip_boot: bool,
+    palette: CompatibilityPalette,
+    cached_interpreter: bool,
+    rtc_host_clock: bool,
+}
+
+impl ConsoleSettings {
+    fn builder(self) -> DmgBuilder {
+        let mut builder = DmgBuilder::new()
+            .boot_rom(self.boot_rom)
+            .skip_boot(self.skip_boot)
+            .palette(self.palette)
+            .cached_interpreter(self.cached_interpreter)
+            .rtc_clock(if self.rtc_host_clock { RtcClock::host() } else { RtcClock::Emulated });
+        if let Some(mode) = self.hardware_mode { builder = builder.hardware_mode(mode); }
+        builder
+    }
+}
 
 fn main() {
     println!("rustdmg");
 
     let mut args = env::args();
     let mut rom_file_path: Option<String> = None;
+    let mut batch_directory: Option<String> = None;
+    let mut blargg_rom_path: Option<String> = None;
+    let mut mooneye_directory: Option<String> = None;
+    let mut sm83_directory: Option<String> = None;
+    let mut trace_file_path: Option<String> = None;
+    let mut symbol_file_path: Option<String> = None;
+    let mut heatmap_file_path: Option<String> = None;
+    let mut reference_trace_path: Option<String> = None;
+    let mut state_file_path: Option<String> = None;
+    let mut movie_file_path: Option<String> = None;
+    let mut shared_frame_path: Option<String> = None;
+    let mut frames = DEFAULT_BATCH_FRAMES;
+    let mut watchdog_frames: Option<u64> = None;
     let mut debug = false;
+    let mut bench = false;
+    let mut stats = false;
+    let mut profile = false;
+    let mut cached_interpreter = false;
+    let mut rtc_host_clock = false;
+    let mut skip_boot = false;
+    let mut interactive_debugger = false;
+    let mut gui = false;
+    let mut threaded = false;
+    let mut hardware_mode: Option<HardwareMode> = None;
+    let mut boot_rom = BootRomVariant::Dmg;
+    let mut palette = CompatibilityPalette::default();
+    let mut cheats: Vec<String> = vec![];
     args.next(); // skip first element as it's the called program name
     while let Some(argument) = args.next() {
         if argument == "--debug" {
             debug = true;
+        } else if argument == "--debugger" {
+            interactive_debugger = true;
+        } else if argument == "--gui" {
+            gui = true;
+        } else if argument == "--cgb" {
+            hardware_mode = Some(HardwareMode::Cgb);
+        } else if argument == "--sgb" {
+            hardware_mode = Some(HardwareMode::Sgb);
+        } else if argument == "--boot-rom" {
+            boot_rom = match args.next().and_then(|name| BootRomVariant::from_name(&name)) {
+                Some(value) => value,
+                None => {
+                    let names: Vec<&str> = BootRomVariant::ALL.iter().map(|variant| variant.name()).collect();
+                    eprintln!("--boot-rom expects one of {}", names.join(", "));
+                    process::exit(2);
+                }
+            };
+        } else if argument == "--palette" {
+            palette = match args.next().and_then(|name| CompatibilityPalette::from_name(&name)) {
+                Some(value) => value,
+                None => {
+                    let names: Vec<&str> = CompatibilityPalette::ALL.iter().map(|palette| palette.name()).collect();
+                    eprintln!("--palette expects one of {}", names.join(", "));
+                    process::exit(2);
+                }
+            };
+        } else if argument == "--cheat" {
+            cheats.extend(args.next());
+        } else if argument == "--threaded" {
+            threaded = true;
+        } else if argument == "--bench" {
+            bench = true;
+        } else if argument == "--cached" {
+            cached_interpreter = true;
+        } else if argument == "--rtc-host-clock" {
+            rtc_host_clock = true;
+        } else if argument == "--skip-boot" {
+            skip_boot = true;
+        } else if argument == "--stats" {
+            stats = true;
+        } else if argument == "--profile" {
+            profile = true;
+        } else if argument == "--batch" {
+            batch_directory = args.next();
+        } else if argument == "--blargg" {
+            blargg_rom_path = args.next();
+        } else if argument == "--mooneye" {
+            mooneye_directory = args.next();
+        } else if argument == "--sm83" {
+            sm83_directory = args.next();
+        } else if argument == "--symbols" {
+            symbol_file_path = args.next();
+        } else if argument == "--heatmap" {
+            heatmap_file_path = args.next();
+        } else if argument == "--trace-diff" {
+            reference_trace_path = args.next();
+        } else if argument == "--load-state" {
+            state_file_path = args.next();
+        } else if argument == "--play" {
+            movie_file_path = args.next();
+        } else if argument == "--shared-frame" {
+            shared_frame_path = args.next();
+        } else if argument == "--trace" {
+            trace_file_path = args.next();
+        } else if argument == "--frames" {
+            frames = match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => value,
+                _ => { eprintln!("--frames expects a number"); process::exit(2); }
+            };
+        } else if argument == "--watchdog" {
+            watchdog_frames = match args.next().map(|value| value.parse()) {
+                Some(Ok(value)) => Some(value),
+                _ => { eprintln!("--watchdog expects a number of frames"); process::exit(2); }
+            };
         } else {
             rom_file_path = Some(argument);
         }
     }
 
-    let mut dmg = dmg::DMG::new(&rom_file_path.unwrap()).unwrap();
+    if let Some(directory) = batch_directory {
+        match batch::run_directory(Path::new(&directory), frames, watchdog_frames) {
+            Ok(results) => batch::print_report(&results),
+            Err(error) => { eprintln!("Cannot read {}: {}", directory, error); process::exit(1); }
+        }
+        return;
+    }
+
+    if let Some(blargg_rom_path) = blargg_rom_path {
+        let outcome = test_roms::run_blargg(Path::new(&blargg_rom_path), frames, watchdog_frames);
+        println!("{}: {:?}", blargg_rom_path, outcome);
+        if outcome != test_roms::TestRomOutcome::Passed { process::exit(1); }
+        return;
+    }
+
+    if let Some(directory) = mooneye_directory {
+        match test_roms::run_mooneye_directory(Path::new(&directory), frames, watchdog_frames) {
+            Ok(results) => test_roms::print_report(Path::new(&directory), &results),
+            Err(error) => { eprintln!("Cannot read {}: {}", directory, error); process::exit(1); }
+        }
+        return;
+    }
+
+    if let Some(directory) = sm83_directory {
+        match sm83_tests::run_directory(Path::new(&directory)) {
+            Ok(results) => sm83_tests::print_report(&results),
+            Err(error) => { eprintln!("Cannot read {}: {}", directory, error); process::exit(1); }
+        }
+        return;
+    }
+
+    let rom_file_path = rom_file_path.unwrap();
+    let settings = ConsoleSettings { boot_rom, hardware_mode, skip_boot, palette, cached_interpreter, rtc_host_clock };
+    let rom = read_rom(&rom_file_path);
+    if gui && threaded {
+        run_gui_threaded(rom, settings);
+        return;
+    }
+    let builder = cheats.iter().fold(settings.builder().rom_bytes(rom).profiler(profile), |builder, code| builder.cheat(code));
+    let mut dmg = match builder.build() {
+        Ok(dmg) => dmg,
+        Err(EmulationError::InvalidCheat(error)) => { eprintln!("{}", error); process::exit(2); }
+        Err(error) => { eprintln!("Cannot load {}: {}", rom_file_path, error); process::exit(1); }
+    };
     dmg.cpu.debug = debug;
-    dmg.run();
+    if let Some(state_file_path) = state_file_path {
+        if let Err(error) = savestate::load_from_file(&mut dmg, &state_file_path) {
+            eprintln!("Cannot load {}: {}", state_file_path, error);
+            process::exit(1);
+        }
+    }
+    let symbols = load_symbols(symbol_file_path, &rom_file_path);
+    let heatmap = heatmap_file_path.map(|path| {
+        let heatmap = Rc::new(RefCell::new(Heatmap::new()));
+        dmg.cpu.bus.add_observer(heatmap.clone());
+        (heatmap, path)
+    });
+    if let Some(trace_file_path) = trace_file_path {
+        match File::create(&trace_file_path) {
+            Ok(file) => dmg.set_trace(Box::new(BufWriter::new(file))),
+            Err(error) => { eprintln!("Cannot create {}: {}", trace_file_path, error); process::exit(1); }
+        }
+    }
+    if let Some(reference_trace_path) = reference_trace_path {
+        let outcome = File::open(&reference_trace_path)
+            .and_then(|file| trace_diff::diff_against_reference(&mut dmg, BufReader::new(file)));
+        match outcome {
+            Ok(outcome) => trace_diff::print_report(&outcome),
+            Err(error) => { eprintln!("Cannot read {}: {}", reference_trace_path, error); process::exit(1); }
+        }
+        return;
+    }
+    if let Some(movie_file_path) = movie_file_path {
+        let played = Movie::load(&movie_file_path).map_err(|error| error.to_string())
+            .and_then(|movie| movie.play(&mut dmg));
+        match played {
+            Ok((frames, reason)) => println!("Played {} frames, stopped: {:?}, frame hash {:016X}", frames, reason, dmg.frame_hash()),
+            Err(error) => { eprintln!("Cannot play {}: {}", movie_file_path, error); process::exit(1); }
+        }
+        return;
+    }
+    if bench {
+        let result = bench::run_bench(&mut dmg, BENCH_DURATION);
+        if let Ok(result) = &result { bench::print_report(result); }
+        print_analysis(&dmg, stats, symbols.as_ref());
+        if let Some((heatmap, path)) = &heatmap { export_heatmap(&heatmap.borrow(), path); }
+        if let Err(error) = result { eprintln!("Emulation failed: {}", error); process::exit(1); }
+        return;
+    }
+    if interactive_debugger {
+        let mut debugger = debugger::Debugger::new();
+        if let Some(symbols) = symbols { debugger.set_symbols(symbols); }
+        debugger.run_repl(&mut dmg);
+        if let Some((heatmap, path)) = &heatmap { export_heatmap(&heatmap.borrow(), path); }
+        return;
+    }
+    if let Some(shared_frame_path) = shared_frame_path {
+        run_with_shared_frame(&mut dmg, &shared_frame_path, gui);
+        return;
+    }
+    if gui {
+        run_gui(&mut dmg, None);
+        return;
+    }
+    if stats || profile || heatmap.is_some() {
+        // Runs usually end at an unimplemented opcode, the statistics are most interesting right then
+        let run_result = dmg.run();
+        print_analysis(&dmg, stats, symbols.as_ref());
+        if let Some((heatmap, path)) = &heatmap { export_heatmap(&heatmap.borrow(), path); }
+        match run_result {
+            Ok(reason) => println!("Stopped: {:?}", reason),
+            Err(error) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+        }
+        return;
+    }
+    if let Some(budget_frames) = watchdog_frames {
+        let mut watchdog = Watchdog::new(&mut dmg, budget_frames);
+        loop {
+            match watchdog.run_frame(&mut dmg) {
+                Ok(()) => {}
+                Err(WatchdogError::Hang(hang)) => { eprint!("{}", hang); process::exit(1); }
+                Err(WatchdogError::Emulation(error)) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+            }
+        }
+    }
+    match dmg.run() {
+        Ok(reason) => println!("Stopped: {:?}", reason),
+        Err(error) => { eprintln!("Emulation failed: {}", error); process::exit(1); }
+    }
 }
@@ -0,0 +1,184 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dmg::{DMG, StopReason};
+use crate::error::EmulationError;
+use crate::input::Buttons;
+use crate::savestate::SaveState;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub enum MovieStart {
+    PowerOn,
+    State(Box<SaveState>),
+}
+
+// A recording of the buttons held on every frame. Emulation is deterministic, so replaying
+// them from the same start on the same ROM reproduces the run exactly.
+#[derive(Serialize, Deserialize)]
+pub struct Movie {
+    pub version: u32,
+    pub rom_hash: u64,
+    pub start: MovieStart,
+    // How many times a state was loaded while recording, movies from older builds have none
+    #[serde(default)]
+    pub rerecords: u32,
+    frames: Vec<u8>,
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn is_powered_on(dmg: &DMG) -> bool {
+    dmg.cpu.instruction_count == 0
+}
+
+impl Movie {
+    // Starts at power-on when nothing has run yet, from a snapshot of the current state otherwise
+    pub fn start_recording(dmg: &DMG) -> Movie {
+        let start = if is_powered_on(dmg) { MovieStart::PowerOn } else { MovieStart::State(Box::new(dmg.save_state())) };
+        Movie { version: VERSION, rom_hash: dmg.cpu.bus.cartridge.rom_hash(), start, rerecords: 0, frames: vec![] }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frame_buttons(&self, frame: usize) -> Option<Buttons> {
+        self.frames.get(frame).map(|bits| Buttons::from_bits_truncate(*bits))
+    }
+
+    pub fn input_log(&self) -> Vec<Buttons> {
+        self.frames.iter().map(|bits| Buttons::from_bits_truncate(*bits)).collect()
+    }
+
+    // Replaces the recorded input, used when re-recording goes back to an earlier branch
+    pub fn set_input_log(&mut self, frames: &[Buttons]) {
+        self.frames = frames.iter().map(|buttons| buttons.bits()).collect();
+    }
+
+    pub fn record_frame(&mut self, dmg: &mut DMG, buttons: Buttons) -> Result<StopReason, EmulationError> {
+        self.frames.push(buttons.bits());
+        dmg.set_buttons(buttons);
+        dmg.run_frame()
+    }
+
+    // Puts the DMG where the recording started
+    pub fn prepare_playback(&self, dmg: &mut DMG) -> Result<(), String> {
+        if self.rom_hash != dmg.cpu.bus.cartridge.rom_hash() {
+            return Err("The movie was recorded with a different ROM".to_string());
+        }
+        match &self.start {
+            MovieStart::PowerOn => {
+                dmg.reset();
+                Ok(())
+            }
+            MovieStart::State(state) => dmg.load_state(SaveState::clone(state)),
+        }
+    }
+
+    pub fn play_frame(&self, frame: usize, dmg: &mut DMG) -> Option<Result<StopReason, EmulationError>> {
+        dmg.set_buttons(self.frame_buttons(frame)?);
+        Some(dmg.run_frame())
+    }
+
+    // Plays every frame, stopping early at breakpoints. Returns the frames played and why it stopped.
+    pub fn play(&self, dmg: &mut DMG) -> Result<(usize, StopReason), String> {
+        self.prepare_playback(dmg)?;
+        let mut reason = StopReason::FrameCompleted;
+        for frame in 0..self.len() {
+            reason = self.play_frame(frame, dmg).unwrap().map_err(|error| error.to_string())?;
+            if reason != StopReason::FrameCompleted { return Ok((frame + 1, reason)); }
+        }
+        Ok((self.len(), reason))
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        serde_json::to_writer(writer, self).map_err(io::Error::from)
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<Movie> {
+        let movie: Movie = serde_json::from_reader(reader).map_err(|error| invalid_data(format!("Bad movie: {}", error)))?;
+        if movie.version != VERSION {
+            return Err(invalid_data(format!("Movie format version {} is not supported, this build reads version {}",
+                                            movie.version, VERSION)));
+        }
+        Ok(movie)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Movie> {
+        Movie::read(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::{CPU, CpuState};
+
+    fn counting_dmg<'a>() -> DMG<'a> {
+        // LD HL,C000; LD (HL),A; INC A; JR -4
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x21, 0x00, 0xC0, 0x77, 0x3C, 0x18, 0xFC], vec![])))
+    }
+
+    fn cpu_state(dmg: &DMG) -> CpuState {
+        dmg.cpu.save_state()
+    }
+
+    #[test]
+    fn record_and_play_from_power_on() {
+        let mut dmg = counting_dmg();
+        let mut movie = Movie::start_recording(&dmg);
+        for frame in 0..3 {
+            movie.record_frame(&mut dmg, if frame == 1 { Buttons::A | Buttons::START } else { Buttons::empty() }).unwrap();
+        }
+        let mut data = vec![];
+        movie.write(&mut data).unwrap();
+        let movie = Movie::read(&data[..]).unwrap();
+        assert_eq!(movie.frame_buttons(1), Some(Buttons::A | Buttons::START));
+
+        let mut replay = counting_dmg();
+        assert_eq!(movie.play(&mut replay), Ok((3, StopReason::FrameCompleted)));
+        assert_eq!(cpu_state(&replay), cpu_state(&dmg));
+        movie.prepare_playback(&mut replay).unwrap();
+        assert_eq!(replay.cpu.instruction_count, 0);
+    }
+
+    #[test]
+    fn record_from_state() {
+        let mut dmg = counting_dmg();
+        dmg.run_frame().unwrap();
+        let mut movie = Movie::start_recording(&dmg);
+        movie.record_frame(&mut dmg, Buttons::empty()).unwrap();
+
+        let mut replay = counting_dmg();
+        replay.run_frame().unwrap();
+        replay.run_frame().unwrap();
+        assert_eq!(movie.play(&mut replay), Ok((1, StopReason::FrameCompleted)));
+        assert_eq!(cpu_state(&replay), cpu_state(&dmg));
+    }
+
+    #[test]
+    fn rejects_other_rom() {
+        let mut movie = Movie::start_recording(&counting_dmg());
+        movie.rom_hash ^= 1;
+        assert!(movie.prepare_playback(&mut counting_dmg()).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use crate::prelude::*;
+
+use super::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// RGBA for the four DMG shades, lightest first
+const SHADES_RGBA: [[u8; 4]; 4] = [[0xFF, 0xFF, 0xFF, 0xFF], [0xAA, 0xAA, 0xAA, 0xFF], [0x55, 0x55, 0x55, 0xFF], [0x00, 0x00, 0x00, 0xFF]];
+
+// A whole screen, one shade per pixel row by row, 0 being the lightest and 3 the darkest
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    shades: Vec<u8>,
+}
+
+impl Frame {
+    pub(crate) fn new(shades: Vec<u8>) -> Frame {
+        assert_eq!(shades.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        Frame { shades }
+    }
+
+    pub fn width(&self) -> usize {
+        SCREEN_WIDTH
+    }
+
+    pub fn height(&self) -> usize {
+        SCREEN_HEIGHT
+    }
+
+    pub fn shade(&self, x: usize, y: usize) -> u8 {
+        self.shades[y * SCREEN_WIDTH + x]
+    }
+
+    pub fn shades(&self) -> &[u8] {
+        &self.shades
+    }
+
+    // Four bytes per pixel in a grey palette, e.g. for a canvas or an image encoder
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.shades.iter().flat_map(|shade| SHADES_RGBA[*shade as usize & 3]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixels() {
+        let mut shades = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        shades[SCREEN_WIDTH + 2] = 3;
+        let frame = Frame::new(shades);
+        assert_eq!(frame.shade(2, 1), 3);
+        assert_eq!(frame.shade(1, 2), 0);
+        assert_eq!(&frame.to_rgba()[..4], &[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(&frame.to_rgba()[(SCREEN_WIDTH + 2) * 4..][..4], &[0x00, 0x00, 0x00, 0xFF]);
+    }
+}
@@ -1,3 +1,23 @@
+pub mod frame;
+pub mod palettes;
+pub mod scanline;
+pub mod sprites;
+pub mod tiles;
+pub mod timeline;
+
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+use crate::bus::scheduler::Component;
+use crate::bus::{INTERRUPT_LCD_STATUS, INTERRUPT_VBLANK};
+
+use palettes::{ColorPalettes, RGB555_WHITE};
+use sprites::{ObjectPriority, SPRITES_PER_LINE};
+use timeline::{Timeline, FRAME_DURATION};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
 const OAM_SEARCH_DURATION: u16 = 20 * 4;
 const PIXEL_TRANSFER_DURATION: u16 = 43 * 4;
 const HBLANK_DURATION: u16 = 51 * 4;
@@ -5,8 +25,20 @@ const LINE_TOTAL_DURATION: u16 = OAM_SEARCH_DURATION + PIXEL_TRANSFER_DURATION +
 const DRAWN_LINES: u8 = 144;
 const VBLANK_LINES: u8 = 10;
 
-#[derive(PartialEq)]
-#[derive(Debug)]
+// STAT bits 3 to 6 pick what raises the STAT interrupt: HBlank, VBlank, OAM search and LY
+// matching LYC
+const STATUS_HBLANK_INTERRUPT: u8 = 0x08;
+const STATUS_VBLANK_INTERRUPT: u8 = 0x10;
+const STATUS_OAM_INTERRUPT: u8 = 0x20;
+const STATUS_LINE_COMPARE_INTERRUPT: u8 = 0x40;
+const STATUS_INTERRUPTS: u8 = 0x78;
+const STATUS_LINE_COMPARE: u8 = 0x04;
+
+// LCDC bit 7, the other bits are only read while drawing
+pub const LCDC_DISPLAY_ENABLE: u8 = 0x80;
+
+#[derive(Clone, Copy, PartialEq)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum PpuMode { OAM, PixelTransfer, HBlank, VBlank }
 
 fn mode_duration(mode: &PpuMode) -> u16 {
@@ -26,31 +58,341 @@ fn next_mode(mode: &PpuMode, current_line: u8) -> PpuMode {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct PPU {
     pub cycle_count: u64,
+    pub frame_count: u64,
     pub current_line: u8,
     pub bg_scroll_y: u8,
+    #[serde(default)]
+    pub bg_scroll_x: u8,
+    // LCDC, changed through set_lcd_control so turning the LCD on or off takes effect
+    #[serde(default)]
+    lcd_control: u8,
+    // Cycles into the current frame while the LCD is off. LY stays at 0 and no mode changes, but
+    // blank frames are still counted so frontends keep their pace.
+    #[serde(default)]
+    cycles_while_off: u64,
+    // BGP, the shade of each of the four background colors
+    #[serde(default)]
+    pub bg_palette: u8,
+    // OBP0 and OBP1, picked by bit 4 of the sprite flags. Color 0 is always transparent.
+    #[serde(default)]
+    pub object_palette_0: u8,
+    #[serde(default)]
+    pub object_palette_1: u8,
+    // OAM indices the OAM search picked for the line being drawn, topmost first
+    #[serde(default)]
+    selected_sprites: [u8; SPRITES_PER_LINE],
+    #[serde(default)]
+    selected_sprite_count: u8,
+    // WY and WX, the window's top left corner is at (WX - 7, WY)
+    #[serde(default)]
+    pub window_y: u8,
+    #[serde(default)]
+    pub window_x: u8,
+    // Window lines drawn so far this frame, the window only moves down on lines it shows on
+    #[serde(default)]
+    window_line: u8,
+    // LY matched WY at some point this frame, the window can show up from then on
+    #[serde(default)]
+    window_reached: bool,
+    // HBlanks entered so far, HBlank DMA copies a block on each
+    #[serde(default)]
+    pub hblank_count: u64,
     current_mode: PpuMode,
     cycles_in_current_mode: u16,
     cycles_in_current_line: u16,
+    #[serde(skip)]
+    timeline: Option<Timeline>,
+    // Shade index (0 lightest to 3 darkest) of every screen pixel of the last completed frame
+    #[serde(skip, default = "blank_framebuffer")]
+    framebuffer: Vec<u8>,
+    // The frame being drawn, swapped with the completed one at VBlank so neither is ever copied
+    #[serde(skip, default = "blank_framebuffer")]
+    back_buffer: Vec<u8>,
+    // Frames are timed as usual but not drawn, the last drawn one stays in the framebuffer
+    #[serde(skip)]
+    pub skip_drawing: bool,
+    // CGB mode only
+    #[serde(default)]
+    pub palettes: ColorPalettes,
+    #[serde(default)]
+    pub object_priority: ObjectPriority,
+    // A DMG cartridge on a CGB, its shades are shown in the colors of the compatibility palettes
+    #[serde(default)]
+    pub dmg_compatibility: bool,
+    #[serde(skip)]
+    color_buffers: Option<ColorBuffers>,
+    // The STAT interrupt sources enabled, as in STAT
+    #[serde(default)]
+    status_interrupts: u8,
+    // LYC
+    #[serde(default)]
+    pub line_compare: u8,
+    // Whether any enabled STAT source holds, the interrupt is only requested when this goes up
+    #[serde(default)]
+    status_line: bool,
+    // IF bits requested since the bus last took them
+    #[serde(default)]
+    requested_interrupts: u8,
+}
+
+// The CGB's true color output in RGB555, double buffered like the shades
+#[derive(Clone)]
+struct ColorBuffers {
+    front: Vec<u16>,
+    back: Vec<u16>,
+}
+
+fn blank_framebuffer() -> Vec<u8> {
+    vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]
+}
+
+impl Default for PPU {
+    fn default() -> PPU { PPU::new() }
 }
 
 impl PPU {
     pub fn new() -> PPU {
         PPU {
             cycle_count: 0,
+            frame_count: 0,
             current_line: 0,
             bg_scroll_y: 0,
-            current_mode: PpuMode::OAM, // FIXME CONFIRM
+            bg_scroll_x: 0,
+            lcd_control: 0,
+            cycles_while_off: 0,
+            bg_palette: 0,
+            object_palette_0: 0,
+            object_palette_1: 0,
+            selected_sprites: [0; SPRITES_PER_LINE],
+            selected_sprite_count: 0,
+            window_y: 0,
+            window_x: 0,
+            window_line: 0,
+            window_reached: false,
+            hblank_count: 0,
+            // The LCD is off at power on until the boot ROM turns it on
+            current_mode: PpuMode::HBlank,
             cycles_in_current_mode: 0,
             cycles_in_current_line: 0,
+            timeline: None,
+            framebuffer: blank_framebuffer(),
+            back_buffer: blank_framebuffer(),
+            skip_drawing: false,
+            palettes: ColorPalettes::default(),
+            object_priority: ObjectPriority::Coordinate,
+            dmg_compatibility: false,
+            color_buffers: None,
+            status_interrupts: 0,
+            line_compare: 0,
+            status_line: false,
+            requested_interrupts: 0,
+        }
+    }
+
+    // Draws in color from the CGB palettes from now on, besides the shades
+    pub fn enable_color(&mut self) {
+        let blank = vec![RGB555_WHITE; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.color_buffers = Some(ColorBuffers { front: blank.clone(), back: blank });
+    }
+
+    // The last completed frame in RGB555, in CGB mode
+    pub fn color_framebuffer(&self) -> Option<&[u16]> {
+        self.color_buffers.as_ref().map(|buffers| buffers.front.as_slice())
+    }
+
+    // The last completed frame, it stays put until the next VBlank
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    // Records mode and line changes for the given number of frames from now on
+    pub fn start_timeline(&mut self, frames: u64) {
+        self.timeline = Some(Timeline::new(self.cycle_count, frames, self.current_line, self.current_mode));
+    }
+
+    pub fn take_timeline(&mut self) -> Option<Timeline> {
+        self.timeline.take()
+    }
+
+    // Copy of the PPU state for save states, timelines being recorded are left out
+    pub fn snapshot(&self) -> PPU {
+        PPU {
+            timeline: None,
+            framebuffer: self.framebuffer.clone(),
+            back_buffer: self.back_buffer.clone(),
+            palettes: self.palettes.clone(),
+            color_buffers: self.color_buffers.clone(),
+            ..*self
         }
     }
 
     pub fn cycle(&mut self) {
-        self.cycle_count += 1;
-        self.cycles_in_current_mode += 1;
-        self.cycles_in_current_line += 1;
+        self.advance(1);
+    }
+
+    // Moves forward like advance, searching OAM as mode 2 ends and drawing every line out of video
+    // RAM as its pixel transfer ends
+    pub fn run(&mut self, cycles: u64, video_ram: &[u8], video_ram_bank_1: &[u8], oam: &[u8]) {
+        let mut remaining = cycles;
+        while remaining > 0 {
+            let step = self.cycles_until_event().min(remaining);
+            let mode_before = self.current_mode;
+            self.advance(step);
+            remaining -= step;
+            if self.skip_drawing || mode_before == self.current_mode { continue; }
+            match self.current_mode {
+                PpuMode::PixelTransfer => self.search_oam(oam),
+                PpuMode::HBlank => self.draw_line(video_ram, video_ram_bank_1, oam),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn lcd_control(&self) -> u8 {
+        self.lcd_control
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcd_control & LCDC_DISPLAY_ENABLE != 0
+    }
+
+    // Turning the LCD off stops it at the start of line 0 in HBlank, turning it on starts line 0
+    // over from its OAM search
+    pub fn set_lcd_control(&mut self, value: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.lcd_control = value;
+        if was_enabled == self.lcd_enabled() { return; }
+        self.current_line = 0;
+        self.current_mode = if was_enabled { PpuMode::HBlank } else { PpuMode::OAM };
+        self.cycles_in_current_mode = 0;
+        self.cycles_in_current_line = 0;
+        self.cycles_while_off = 0;
+        self.update_status_line();
+    }
+
+    fn cycles_into_frame(&self) -> u64 {
+        self.current_line as u64 * LINE_TOTAL_DURATION as u64 + self.cycles_in_current_line as u64
+    }
+
+    // Cycles until the next VBlank starts and the frame count goes up
+    pub fn cycles_until_vblank(&self) -> u64 {
+        if !self.lcd_enabled() { return FRAME_DURATION - self.cycles_while_off; }
+        let vblank_start = DRAWN_LINES as u64 * LINE_TOTAL_DURATION as u64;
+        let into_frame = self.cycles_into_frame();
+        if into_frame < vblank_start { vblank_start - into_frame } else { FRAME_DURATION - into_frame + vblank_start }
+    }
+
+    // Cycles until the PPU could next request an interrupt. Without STAT sources enabled that is
+    // only VBlank. With the LCD off nothing is requested, but the end of the blank frame still
+    // counts so the frame count stays up to date.
+    pub fn cycles_until_interrupt(&self) -> u64 {
+        if self.status_interrupts == 0 || !self.lcd_enabled() { self.cycles_until_vblank() } else { self.cycles_until_event() }
+    }
+
+    // STAT as the CPU reads it, the unused bit 7 reads as 1
+    pub fn status(&self) -> u8 {
+        let mode = match self.current_mode {
+            PpuMode::HBlank => 0,
+            PpuMode::VBlank => 1,
+            PpuMode::OAM => 2,
+            PpuMode::PixelTransfer => 3,
+        };
+        let line_compare = if self.current_line == self.line_compare { STATUS_LINE_COMPARE } else { 0 };
+        0x80 | self.status_interrupts | line_compare | mode
+    }
+
+    // Only the interrupt sources can be written, the mode and LYC flag are read only
+    pub fn set_status(&mut self, value: u8) {
+        self.status_interrupts = value & STATUS_INTERRUPTS;
+        self.update_status_line();
+    }
+
+    pub fn set_line_compare(&mut self, value: u8) {
+        self.line_compare = value;
+        self.update_status_line();
+    }
+
+    // IF bits for the bus to request, cleared once taken
+    pub fn take_interrupts(&mut self) -> u8 {
+        core::mem::take(&mut self.requested_interrupts)
+    }
+
+    fn update_status_line(&mut self) {
+        if !self.lcd_enabled() {
+            self.status_line = false;
+            return;
+        }
+        let sources = self.status_interrupts;
+        let status_line = match self.current_mode {
+            PpuMode::HBlank => sources & STATUS_HBLANK_INTERRUPT != 0,
+            PpuMode::VBlank => sources & STATUS_VBLANK_INTERRUPT != 0,
+            PpuMode::OAM => sources & STATUS_OAM_INTERRUPT != 0,
+            PpuMode::PixelTransfer => false,
+        } || (sources & STATUS_LINE_COMPARE_INTERRUPT != 0 && self.current_line == self.line_compare);
+        if status_line && !self.status_line { self.requested_interrupts |= INTERRUPT_LCD_STATUS; }
+        self.status_line = status_line;
+    }
+
+    // Swaps the frame just drawn in, the one shown until now becomes the one to draw next
+    fn present_frame(&mut self) {
+        core::mem::swap(&mut self.framebuffer, &mut self.back_buffer);
+        if let Some(buffers) = &mut self.color_buffers { core::mem::swap(&mut buffers.front, &mut buffers.back); }
+    }
+
+    // The screen shows white while the LCD is off, or the lightest compatibility color
+    fn advance_while_off(&mut self, cycles: u64) {
+        self.cycle_count += cycles;
+        self.cycles_while_off += cycles;
+        if self.cycles_while_off < FRAME_DURATION { return; }
+        self.cycles_while_off = 0;
+        self.frame_count += 1;
+        if self.skip_drawing { return; }
+        self.back_buffer.fill(0);
+        let white = if self.dmg_compatibility { self.palettes.background.color(0, 0) } else { RGB555_WHITE };
+        if let Some(buffers) = &mut self.color_buffers { buffers.back.fill(white); }
+        self.present_frame();
+    }
+
+    // OAM is searched in mode 2 and read for the sprites being drawn in mode 3
+    pub fn uses_oam(&self) -> bool {
+        matches!(self.current_mode, PpuMode::OAM | PpuMode::PixelTransfer)
+    }
+
+    // OAM indices of the sprites drawn on a line, as picked during its OAM search
+    pub fn sprites_on_line(&self, oam: &[u8], line: u8) -> Vec<usize> {
+        sprites::line_sprites(&sprites::decode_oam(oam), line, self.sprite_height(), self.object_priority)
+    }
+
+    // The line LY will show once the given cycles have passed
+    pub fn line_after(&self, cycles: u64) -> u8 {
+        if !self.lcd_enabled() { return 0; }
+        ((self.cycles_into_frame() + cycles) % FRAME_DURATION / LINE_TOTAL_DURATION as u64) as u8
+    }
+}
+
+impl Component for PPU {
+    // Next mode change or new line, whichever comes first
+    fn cycles_until_event(&self) -> u64 {
+        if !self.lcd_enabled() { return FRAME_DURATION - self.cycles_while_off; }
+        let line_remaining = LINE_TOTAL_DURATION - self.cycles_in_current_line;
+        let mode_remaining = mode_duration(&self.current_mode) - self.cycles_in_current_mode;
+        line_remaining.min(mode_remaining) as u64
+    }
+
+    fn advance(&mut self, cycles: u64) {
+        debug_assert!(cycles <= self.cycles_until_event());
+        if cycles == 0 { return; }
+        if !self.lcd_enabled() {
+            self.advance_while_off(cycles);
+            return;
+        }
+        let (line_before, mode_before) = (self.current_line, self.current_mode);
+        self.cycle_count += cycles;
+        self.cycles_in_current_mode += cycles as u16;
+        self.cycles_in_current_line += cycles as u16;
 
         let duration = mode_duration(&self.current_mode);
 
@@ -65,6 +407,17 @@ impl PPU {
         if duration > 0 && self.cycles_in_current_mode >= duration {
             self.current_mode = next_mode(&self.current_mode, self.current_line);
             self.cycles_in_current_mode = 0;
+            if self.current_mode == PpuMode::HBlank { self.hblank_count += 1; }
+            if self.current_mode == PpuMode::VBlank {
+                self.requested_interrupts |= INTERRUPT_VBLANK;
+                self.frame_count += 1;
+                if !self.skip_drawing { self.present_frame(); }
+            }
+        }
+
+        self.update_status_line();
+        if let Some(timeline) = &mut self.timeline {
+            timeline.observe(self.cycle_count, line_before, mode_before, self.current_line, self.current_mode);
         }
     }
 }
@@ -74,16 +427,153 @@ impl PPU {
 mod tests {
     use super::*;
 
+    // The LCD is off at power on
+    fn lcd_on_ppu() -> PPU {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(LCDC_DISPLAY_ENABLE);
+        ppu
+    }
+
     #[test]
     fn cycle() {
-        let mut ppu = PPU::new();
+        let mut ppu = lcd_on_ppu();
         ppu.cycle();
         assert_eq!(ppu.cycle_count, 1);
     }
 
+    #[test]
+    fn bulk_advance_matches_single_cycles() {
+        let mut stepped = lcd_on_ppu();
+        let mut scheduled = lcd_on_ppu();
+        for chunk in [3, 80, 1000, 70224, 17] {
+            for _ in 0..chunk { stepped.cycle(); }
+            crate::bus::scheduler::run_for(&mut [&mut scheduled], chunk);
+            assert_eq!(scheduled.cycle_count, stepped.cycle_count);
+            assert_eq!(scheduled.current_line, stepped.current_line);
+            assert_eq!(scheduled.current_mode, stepped.current_mode);
+            assert_eq!(scheduled.cycles_in_current_mode, stepped.cycles_in_current_mode);
+            assert_eq!(scheduled.frame_count, stepped.frame_count);
+        }
+    }
+
+    #[test]
+    fn frame_count_increments_on_vblank() {
+        let mut ppu = lcd_on_ppu();
+        for _ in 0..(LINE_TOTAL_DURATION as u32 * DRAWN_LINES as u32 - 1) { ppu.cycle(); }
+        assert_eq!(ppu.frame_count, 0);
+        ppu.cycle();
+        assert_eq!(ppu.current_mode, PpuMode::VBlank);
+        assert_eq!(ppu.frame_count, 1);
+    }
+
+    #[test]
+    fn vblank_is_requested_once_per_frame() {
+        let mut ppu = lcd_on_ppu();
+        crate::bus::scheduler::run_for(&mut [&mut ppu], LINE_TOTAL_DURATION as u64 * DRAWN_LINES as u64 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+        ppu.cycle();
+        assert_eq!(ppu.take_interrupts(), INTERRUPT_VBLANK);
+        crate::bus::scheduler::run_for(&mut [&mut ppu], LINE_TOTAL_DURATION as u64 * VBLANK_LINES as u64 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn stat_interrupt_sources() {
+        let mut ppu = lcd_on_ppu();
+        assert_eq!(ppu.status(), 0x80 | 0x04 | 2);
+        ppu.set_status(0xFF);
+        assert_eq!(ppu.status(), 0xFC | 2);
+        // LY already matches LYC, setting the sources raises the line
+        assert_eq!(ppu.take_interrupts(), INTERRUPT_LCD_STATUS);
+        ppu.set_status(STATUS_HBLANK_INTERRUPT);
+        crate::bus::scheduler::run_for(&mut [&mut ppu], (OAM_SEARCH_DURATION + PIXEL_TRANSFER_DURATION) as u64 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+        ppu.cycle();
+        assert_eq!(ppu.status() & 0x03, 0);
+        assert_eq!(ppu.take_interrupts(), INTERRUPT_LCD_STATUS);
+    }
+
+    #[test]
+    fn stat_interrupt_on_line_compare() {
+        let mut ppu = lcd_on_ppu();
+        ppu.set_line_compare(2);
+        ppu.set_status(STATUS_LINE_COMPARE_INTERRUPT);
+        crate::bus::scheduler::run_for(&mut [&mut ppu], LINE_TOTAL_DURATION as u64 * 2 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+        ppu.cycle();
+        assert_eq!(ppu.current_line, 2);
+        assert_eq!(ppu.take_interrupts(), INTERRUPT_LCD_STATUS);
+        // Held for the whole line, the STAT line does not go up again
+        crate::bus::scheduler::run_for(&mut [&mut ppu], LINE_TOTAL_DURATION as u64 - 1);
+        assert_eq!(ppu.take_interrupts(), 0);
+    }
+
+    #[test]
+    fn buffers_swap_on_vblank() {
+        let mut ppu = lcd_on_ppu();
+        ppu.back_buffer[0] = 3;
+        let drawn = ppu.back_buffer.as_ptr();
+        for _ in 0..(LINE_TOTAL_DURATION as u32 * DRAWN_LINES as u32 - 1) { ppu.cycle(); }
+        assert_eq!(ppu.framebuffer()[0], 0);
+        ppu.cycle();
+        assert_eq!(ppu.framebuffer()[0], 3);
+        assert_eq!(ppu.framebuffer().as_ptr(), drawn);
+    }
+
+    #[test]
+    fn dmg_compatibility_colorizes_shades() {
+        let mut ppu = lcd_on_ppu();
+        ppu.enable_color();
+        ppu.dmg_compatibility = true;
+        ppu.palettes.load_compatibility(palettes::CompatibilityPalette::Reverse);
+        ppu.set_lcd_control(0x91);
+        ppu.bg_palette = 0x0C;
+        // The second pixel of tile 0 has color 1, which BGP shows as shade 3
+        let mut video_ram = vec![0; 0x2000];
+        video_ram[0] = 0x40;
+        ppu.run(LINE_TOTAL_DURATION as u64 * DRAWN_LINES as u64, &video_ram, &[0; 0x2000], &[0; 0xA0]);
+        let colors = ppu.color_framebuffer().unwrap();
+        assert_eq!((colors[0], colors[1]), (0x0000, RGB555_WHITE));
+    }
+
+    #[test]
+    fn lcd_off_holds_line_0_and_on_restarts_it() {
+        let mut ppu = lcd_on_ppu();
+        ppu.set_status(STATUS_INTERRUPTS);
+        crate::bus::scheduler::run_for(&mut [&mut ppu], 10 * LINE_TOTAL_DURATION as u64 + 100);
+        ppu.take_interrupts();
+        ppu.set_lcd_control(0x11);
+        assert_eq!((ppu.current_line, ppu.status() & 0x03), (0, 0));
+        ppu.back_buffer[0] = 3;
+        crate::bus::scheduler::run_for(&mut [&mut ppu], FRAME_DURATION - 1);
+        assert_eq!((ppu.current_line, ppu.status() & 0x03, ppu.frame_count), (0, 0, 0));
+        assert_eq!(ppu.take_interrupts(), 0);
+        // Blank frames are still counted, without a VBlank interrupt
+        ppu.cycle();
+        assert_eq!(ppu.frame_count, 1);
+        assert_eq!(ppu.framebuffer()[0], 0);
+        assert_eq!(ppu.take_interrupts(), 0);
+        ppu.set_lcd_control(0x91);
+        assert_eq!((ppu.current_line, ppu.status() & 0x03, ppu.lcd_control()), (0, 2, 0x91));
+        // LY matches LYC as soon as the LCD is on
+        assert_eq!(ppu.take_interrupts(), INTERRUPT_LCD_STATUS);
+        crate::bus::scheduler::run_for(&mut [&mut ppu], OAM_SEARCH_DURATION as u64);
+        assert_eq!(ppu.status() & 0x03, 3);
+    }
+
+    #[test]
+    fn skipped_frames_keep_the_last_drawn_one() {
+        let mut ppu = lcd_on_ppu();
+        ppu.skip_drawing = true;
+        ppu.back_buffer[0] = 3;
+        for _ in 0..(LINE_TOTAL_DURATION as u32 * DRAWN_LINES as u32) { ppu.cycle(); }
+        assert_eq!(ppu.frame_count, 1);
+        assert_eq!(ppu.framebuffer()[0], 0);
+    }
+
     #[test]
     fn mode_timings() {
-        let mut ppu = PPU::new();
+        let mut ppu = lcd_on_ppu();
 
         for _frame in 0..2 {
             for line in 0..144 {
@@ -104,7 +594,7 @@ mod tests {
                     ppu.cycle();
                 }
             }
-            for line_in_vblank in 0..10 as u8 {
+            for line_in_vblank in 0..10_u8 {
                 assert_eq!(ppu.current_line, line_in_vblank + 144);
                 for cycles_per_vblank in 0..((20 + 43 + 51) * 4) {
                     println!("{} {}", cycles_per_vblank, ppu.current_line);
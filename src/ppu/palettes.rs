@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prelude::*;
+
+pub const PALETTE_MEMORY_SIZE: usize = 64;
+const COLORS_PER_PALETTE: usize = 4;
+pub const RGB555_WHITE: u16 = 0x7FFF;
+
+// 8 palettes of 4 colors, two bytes each in little endian RGB555. Accessed a byte at a time
+// through a specification register (BCPS/OCPS) holding the index and an auto-increment flag
+// and a data register (BCPD/OCPD).
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PaletteMemory {
+    data: Vec<u8>,
+    specification: u8,
+}
+
+impl Default for PaletteMemory {
+    fn default() -> PaletteMemory { PaletteMemory::new() }
+}
+
+impl PaletteMemory {
+    pub fn new() -> PaletteMemory {
+        PaletteMemory { data: vec![0xFF; PALETTE_MEMORY_SIZE], specification: 0 }
+    }
+
+    // Bit 6 is unused and reads as set
+    pub fn specification(&self) -> u8 {
+        self.specification | 0x40
+    }
+
+    pub fn set_specification(&mut self, value: u8) {
+        self.specification = value & 0xBF;
+    }
+
+    fn index(&self) -> usize {
+        (self.specification & 0x3F) as usize
+    }
+
+    pub fn data(&self) -> u8 {
+        self.data[self.index()]
+    }
+
+    pub fn write_data(&mut self, value: u8) {
+        let index = self.index();
+        self.data[index] = value;
+        if self.specification & 0x80 != 0 {
+            self.specification = 0x80 | ((index as u8 + 1) & 0x3F);
+        }
+    }
+
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let offset = (palette as usize % 8 * COLORS_PER_PALETTE + color as usize % COLORS_PER_PALETTE) * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    fn set_palette(&mut self, palette: u8, colors: &[u32; COLORS_PER_PALETTE]) {
+        for (color, rgb888) in colors.iter().enumerate() {
+            let offset = (palette as usize * COLORS_PER_PALETTE + color) * 2;
+            self.data[offset..offset + 2].copy_from_slice(&rgb888_to_rgb555(*rgb888).to_le_bytes());
+        }
+    }
+}
+
+// Background palettes picked by the tile attributes and object palettes picked by OAM flags
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ColorPalettes {
+    pub background: PaletteMemory,
+    pub objects: PaletteMemory,
+}
+
+impl ColorPalettes {
+    // What the CGB boot ROM sets up for a DMG cartridge: background palette 0 and object
+    // palettes 0 and 1, which the DMG palette registers then pick shades from
+    pub fn load_compatibility(&mut self, palette: CompatibilityPalette) {
+        let [background, object0, object1] = palette.colors();
+        self.background.set_palette(0, &background);
+        self.objects.set_palette(0, &object0);
+        self.objects.set_palette(1, &object1);
+    }
+}
+
+// The colorizations a player picks with a button combination while the CGB boot logo shows,
+// named as in Pan Docs. Dark green is what cartridges the boot ROM does not recognize get.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompatibilityPalette {
+    Brown,
+    Red,
+    DarkBrown,
+    Blue,
+    DarkBlue,
+    Gray,
+    PaleYellow,
+    Orange,
+    Yellow,
+    Green,
+    #[default]
+    DarkGreen,
+    Reverse,
+}
+
+const BROWN: [u32; 4] = [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000];
+const RED: [u32; 4] = [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000];
+const GREEN: [u32; 4] = [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000];
+const BLUE: [u32; 4] = [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000];
+
+impl CompatibilityPalette {
+    pub const ALL: [CompatibilityPalette; 12] = [
+        CompatibilityPalette::Brown, CompatibilityPalette::Red, CompatibilityPalette::DarkBrown,
+        CompatibilityPalette::Blue, CompatibilityPalette::DarkBlue, CompatibilityPalette::Gray,
+        CompatibilityPalette::PaleYellow, CompatibilityPalette::Orange, CompatibilityPalette::Yellow,
+        CompatibilityPalette::Green, CompatibilityPalette::DarkGreen, CompatibilityPalette::Reverse,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CompatibilityPalette::Brown => "brown",
+            CompatibilityPalette::Red => "red",
+            CompatibilityPalette::DarkBrown => "dark-brown",
+            CompatibilityPalette::Blue => "blue",
+            CompatibilityPalette::DarkBlue => "dark-blue",
+            CompatibilityPalette::Gray => "gray",
+            CompatibilityPalette::PaleYellow => "pale-yellow",
+            CompatibilityPalette::Orange => "orange",
+            CompatibilityPalette::Yellow => "yellow",
+            CompatibilityPalette::Green => "green",
+            CompatibilityPalette::DarkGreen => "dark-green",
+            CompatibilityPalette::Reverse => "reverse",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CompatibilityPalette> {
+        CompatibilityPalette::ALL.iter().copied().find(|palette| palette.name() == name)
+    }
+
+    // Background, first and second object palette, as 0x00RRGGBB from lightest to darkest shade
+    pub fn colors(self) -> [[u32; 4]; 3] {
+        match self {
+            CompatibilityPalette::Brown => [BROWN, BROWN, BROWN],
+            CompatibilityPalette::Red => [RED, GREEN, BLUE],
+            CompatibilityPalette::DarkBrown => [[0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108], RED, BROWN],
+            CompatibilityPalette::Blue => [BLUE, RED, GREEN],
+            CompatibilityPalette::DarkBlue => [[0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000], RED, BROWN],
+            CompatibilityPalette::Gray => [[0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000]; 3],
+            CompatibilityPalette::PaleYellow => [[0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000]; 3],
+            CompatibilityPalette::Orange => [[0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000]; 3],
+            CompatibilityPalette::Yellow => [[0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000], BLUE, GREEN],
+            CompatibilityPalette::Green => [[0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000]; 3],
+            CompatibilityPalette::DarkGreen => [[0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000], RED, RED],
+            CompatibilityPalette::Reverse => [[0x000000, 0x008484, 0xFFDE00, 0xFFFFFF]; 3],
+        }
+    }
+}
+
+// Each 8 bit channel of 0x00RRGGBB cut down to 5 bits
+pub fn rgb888_to_rgb555(color: u32) -> u16 {
+    let narrow = |channel: u32| ((channel & 0xFF) >> 3) as u16;
+    narrow(color >> 16) | (narrow(color >> 8) << 5) | (narrow(color) << 10)
+}
+
+// Each 5 bit channel widened to 8 bits, as 0x00RRGGBB
+pub fn rgb555_to_rgb888(color: u16) -> u32 {
+    let widen = |channel: u16| { let channel = (channel & 0x1F) as u32; (channel << 3) | (channel >> 2) };
+    (widen(color) << 16) | (widen(color >> 5) << 8) | widen(color >> 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_increment() {
+        let mut palettes = PaletteMemory::new();
+        palettes.set_specification(0x80 | 0x3E);
+        assert_eq!(palettes.specification(), 0xFE);
+        palettes.write_data(0x1F);
+        palettes.write_data(0x00);
+        assert_eq!(palettes.specification(), 0xC0);
+        assert_eq!(palettes.color(7, 3), 0x001F);
+        // Without auto-increment the same byte is written again
+        palettes.set_specification(0x02);
+        palettes.write_data(0xE0);
+        palettes.write_data(0x03);
+        assert_eq!(palettes.specification(), 0x42);
+        assert_eq!(palettes.data(), 0x03);
+        assert_eq!(palettes.color(0, 1), 0xFF03);
+    }
+
+    #[test]
+    fn rgb888() {
+        assert_eq!(rgb555_to_rgb888(RGB555_WHITE), 0x00FF_FFFF);
+        assert_eq!(rgb555_to_rgb888(0x001F), 0x00FF_0000);
+        assert_eq!(rgb555_to_rgb888(0x03E0), 0x0000_FF00);
+        assert_eq!(rgb555_to_rgb888(0x7C00), 0x0000_00FF);
+        assert_eq!(rgb555_to_rgb888(0x0000), 0);
+        assert_eq!(rgb888_to_rgb555(0x00FF_0000), 0x001F);
+        assert_eq!(rgb888_to_rgb555(0x0000_00FF), 0x7C00);
+    }
+
+    #[test]
+    fn compatibility_palettes() {
+        let mut palettes = ColorPalettes::default();
+        palettes.load_compatibility(CompatibilityPalette::Red);
+        assert_eq!(rgb555_to_rgb888(palettes.background.color(0, 1)), 0x00FF_8484);
+        assert_eq!(rgb555_to_rgb888(palettes.objects.color(0, 2)), 0x0000_8400);
+        assert_eq!(rgb555_to_rgb888(palettes.objects.color(1, 2)), 0x0000_00FF);
+        assert_eq!(palettes.background.color(1, 0), 0xFFFF);
+        for palette in CompatibilityPalette::ALL.iter() {
+            assert_eq!(CompatibilityPalette::from_name(palette.name()), Some(*palette));
+        }
+    }
+}
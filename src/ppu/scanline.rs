@@ -0,0 +1,510 @@
+use super::sprites::{decode_oam, Sprite};
+use super::tiles::{decode_row, tile_attributes, tile_data_index, TileAttributes, TILE_BYTES, TILE_MAP_0, TILE_MAP_1, TILE_MAP_SIZE, TILE_SIZE};
+use super::{PPU, SCREEN_WIDTH};
+
+// LCDC bits the layers depend on
+pub const LCDC_BACKGROUND_ENABLE: u8 = 0x01;
+pub const LCDC_OBJECT_ENABLE: u8 = 0x02;
+pub const LCDC_TALL_OBJECTS: u8 = 0x04;
+pub const LCDC_BACKGROUND_MAP: u8 = 0x08;
+pub const LCDC_UNSIGNED_TILE_DATA: u8 = 0x10;
+pub const LCDC_WINDOW_ENABLE: u8 = 0x20;
+pub const LCDC_WINDOW_MAP: u8 = 0x40;
+// WX is the window's left edge plus 7, anything past the screen hides it
+const WINDOW_X_OFFSET: u8 = 7;
+const WINDOW_X_MAX: u8 = SCREEN_WIDTH as u8 + WINDOW_X_OFFSET - 1;
+
+// Shade of a color index through a BGP/OBP style palette
+fn shade(palette: u8, color: u8) -> u8 {
+    (palette >> (color * 2)) & 3
+}
+
+// What the background or window left on a pixel, objects behind them only show over color 0
+#[derive(Clone, Copy, Default)]
+struct BackgroundPixel {
+    color: u8,
+    attributes: TileAttributes,
+}
+
+// The topmost object pixel, with OBP0 or OBP1 on the DMG and one of the 8 object palettes on
+// the CGB
+#[derive(Clone, Copy)]
+struct ObjectPixel {
+    color: u8,
+    palette: u8,
+}
+
+impl PPU {
+    // 8x16 objects with LCDC bit 2 set
+    pub fn sprite_height(&self) -> u8 {
+        if self.lcd_control & LCDC_TALL_OBJECTS != 0 { 2 * TILE_SIZE as u8 } else { TILE_SIZE as u8 }
+    }
+
+    // Mode 2: picks the sprites of the current line, topmost first, for the pixel transfer
+    pub(super) fn search_oam(&mut self, oam: &[u8]) {
+        let selected = self.sprites_on_line(oam, self.current_line);
+        self.selected_sprite_count = selected.len() as u8;
+        for (slot, index) in self.selected_sprites.iter_mut().zip(selected) { *slot = index as u8; }
+    }
+
+    // CGB mode proper: tiles have attributes in video RAM bank 1 and colors come straight from
+    // palette memory. DMG cartridges on a CGB are drawn like on a DMG and colorized afterwards.
+    fn cgb_rendering(&self) -> bool {
+        self.color_buffers.is_some() && !self.dmg_compatibility
+    }
+
+    // Draws the current line into the frame being built, once its pixel transfer is over
+    pub(super) fn draw_line(&mut self, video_ram: &[u8], video_ram_bank_1: &[u8], oam: &[u8]) {
+        if self.current_line == 0 {
+            self.window_line = 0;
+            self.window_reached = false;
+        }
+        // WY is compared on every line, so moving it mid-frame can still bring the window in
+        if self.current_line == self.window_y { self.window_reached = true; }
+        let banks = [video_ram, video_ram_bank_1];
+        let mut background = [BackgroundPixel::default(); SCREEN_WIDTH];
+        // With LCDC bit 0 clear the DMG shows neither background nor window, the CGB still draws
+        // them but lets objects cover them everywhere
+        if self.cgb_rendering() || self.lcd_control & LCDC_BACKGROUND_ENABLE != 0 {
+            self.draw_background(banks, &mut background);
+            if self.window_visible() {
+                self.draw_window(banks, &mut background);
+                self.window_line += 1;
+            }
+        }
+        let mut objects = [None; SCREEN_WIDTH];
+        if self.lcd_control & LCDC_OBJECT_ENABLE != 0 { self.draw_sprites(banks, oam, &background, &mut objects); }
+        let mut shades = [0; SCREEN_WIDTH];
+        let mut colors = [0; SCREEN_WIDTH];
+        for (((shade, color), background), object) in shades.iter_mut().zip(&mut colors).zip(&background).zip(&objects) {
+            (*shade, *color) = self.pixel_output(*background, *object);
+        }
+        let line_start = self.current_line as usize * SCREEN_WIDTH;
+        self.back_buffer[line_start..line_start + SCREEN_WIDTH].copy_from_slice(&shades);
+        if let Some(buffers) = &mut self.color_buffers {
+            buffers.back[line_start..line_start + SCREEN_WIDTH].copy_from_slice(&colors);
+        }
+    }
+
+    // The shade of a pixel and its RGB555 color. On the CGB the color index goes straight into
+    // the palette, the shade is just the index. A DMG cartridge on a CGB gets its shades colored
+    // by background palette 0 and object palettes 0 and 1.
+    fn pixel_output(&self, background: BackgroundPixel, object: Option<ObjectPixel>) -> (u8, u16) {
+        if self.cgb_rendering() {
+            return match object {
+                Some(object) => (object.color, self.palettes.objects.color(object.palette, object.color)),
+                None => (background.color, self.palettes.background.color(background.attributes.palette(), background.color)),
+            };
+        }
+        match object {
+            Some(object) => {
+                let palette = if object.palette == 0 { self.object_palette_0 } else { self.object_palette_1 };
+                let shade = shade(palette, object.color);
+                (shade, self.palettes.objects.color(object.palette, shade))
+            }
+            None => {
+                // With the background off the DMG shows white whatever BGP says
+                let background_enabled = self.lcd_control & LCDC_BACKGROUND_ENABLE != 0;
+                let shade = if background_enabled { shade(self.bg_palette, background.color) } else { 0 };
+                (shade, self.palettes.background.color(0, shade))
+            }
+        }
+    }
+
+    fn window_visible(&self) -> bool {
+        self.lcd_control & LCDC_WINDOW_ENABLE != 0 && self.window_reached && self.window_x <= WINDOW_X_MAX
+    }
+
+    // One row of the tile at a map entry. On the CGB its attributes pick the bank holding the
+    // tile and flip it.
+    fn fetch_tile_row(&self, banks: [&[u8]; 2], map: usize, map_x: usize, map_y: usize, row: usize) -> ([u8; TILE_SIZE], TileAttributes) {
+        let attributes = if self.cgb_rendering() { tile_attributes(banks[1], map, map_x, map_y) } else { TileAttributes::default() };
+        let tile_number = banks[0][map + map_y * TILE_MAP_SIZE + map_x];
+        let unsigned_addressing = self.lcd_control & LCDC_UNSIGNED_TILE_DATA != 0;
+        let row = if attributes.y_flip() { TILE_SIZE - 1 - row } else { row };
+        let tile_data = banks[attributes.bank()];
+        let row_start = tile_data_index(tile_number, unsigned_addressing) * TILE_BYTES + row * 2;
+        let mut pixels = decode_row(tile_data[row_start], tile_data[row_start + 1]);
+        if attributes.x_flip() { pixels.reverse(); }
+        (pixels, attributes)
+    }
+
+    // The window covers the background from WX - 7 to the right edge. With WX under 7 its first
+    // columns are cut off rather than moved.
+    fn draw_window(&self, banks: [&[u8]; 2], background: &mut [BackgroundPixel; SCREEN_WIDTH]) {
+        let map = if self.lcd_control & LCDC_WINDOW_MAP != 0 { TILE_MAP_1 } else { TILE_MAP_0 };
+        let y = self.window_line as usize;
+        let first_column = self.window_x.saturating_sub(WINDOW_X_OFFSET) as usize;
+        let skipped = WINDOW_X_OFFSET.saturating_sub(self.window_x) as usize;
+        let mut tile = ([0; TILE_SIZE], TileAttributes::default());
+        for (column, pixel) in background[first_column..].iter_mut().enumerate() {
+            let x = column + skipped;
+            if column == 0 || x.is_multiple_of(TILE_SIZE) { tile = self.fetch_tile_row(banks, map, x / TILE_SIZE, y / TILE_SIZE, y % TILE_SIZE); }
+            *pixel = BackgroundPixel { color: tile.0[x % TILE_SIZE], attributes: tile.1 };
+        }
+    }
+
+    // SCX and SCY pick where the screen sits in the 256x256 map, which wraps around
+    fn draw_background(&self, banks: [&[u8]; 2], background: &mut [BackgroundPixel; SCREEN_WIDTH]) {
+        let map = if self.lcd_control & LCDC_BACKGROUND_MAP != 0 { TILE_MAP_1 } else { TILE_MAP_0 };
+        let y = self.current_line.wrapping_add(self.bg_scroll_y) as usize;
+        let mut tile = ([0; TILE_SIZE], TileAttributes::default());
+        for (screen_x, pixel) in background.iter_mut().enumerate() {
+            let x = (screen_x as u8).wrapping_add(self.bg_scroll_x) as usize;
+            if screen_x == 0 || x.is_multiple_of(TILE_SIZE) { tile = self.fetch_tile_row(banks, map, x / TILE_SIZE, y / TILE_SIZE, y % TILE_SIZE); }
+            *pixel = BackgroundPixel { color: tile.0[x % TILE_SIZE], attributes: tile.1 };
+        }
+    }
+
+    // Background color 0 never covers objects, otherwise the object's own flag decides. On the
+    // CGB the tile attributes can put the background on top too, unless LCDC bit 0 takes the
+    // priority away from the background altogether.
+    fn background_over_object(&self, sprite: &Sprite, background: &BackgroundPixel) -> bool {
+        if background.color == 0 { return false; }
+        if !self.cgb_rendering() { return sprite.behind_background(); }
+        self.lcd_control & LCDC_BACKGROUND_ENABLE != 0 && (sprite.behind_background() || background.attributes.over_sprites())
+    }
+
+    // Sprites picked by the OAM search, topmost first. The topmost sprite with a visible pixel
+    // owns it even when it is behind the background there, lower ones do not show through.
+    fn draw_sprites(&self, banks: [&[u8]; 2], oam: &[u8], background: &[BackgroundPixel; SCREEN_WIDTH],
+                    objects: &mut [Option<ObjectPixel>; SCREEN_WIDTH]) {
+        let sprites = decode_oam(oam);
+        let height = self.sprite_height();
+        let cgb = self.cgb_rendering();
+        let mut owned = [false; SCREEN_WIDTH];
+        for index in &self.selected_sprites[..self.selected_sprite_count as usize] {
+            let sprite = sprites[*index as usize];
+            // OAM can change between the search and the drawing
+            if !sprite.covers_line(self.current_line, height) { continue; }
+            let mut sprite_row = (self.current_line as i16 - sprite.screen_y()) as u8;
+            if sprite.y_flip() { sprite_row = height - 1 - sprite_row; }
+            // Tall sprites use an even tile on top and the next one below
+            let tile = if height > TILE_SIZE as u8 { (sprite.tile & 0xFE) + sprite_row / TILE_SIZE as u8 } else { sprite.tile };
+            let tile_data = if cgb { banks[sprite.bank()] } else { banks[0] };
+            let row_start = tile as usize * TILE_BYTES + (sprite_row as usize % TILE_SIZE) * 2;
+            let mut pixels = decode_row(tile_data[row_start], tile_data[row_start + 1]);
+            if sprite.x_flip() { pixels.reverse(); }
+            let palette = if cgb { sprite.cgb_palette() } else { sprite.palette() };
+            for (offset, color) in pixels.iter().enumerate() {
+                let x = sprite.screen_x() + offset as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&x) || *color == 0 { continue; }
+                let x = x as usize;
+                if core::mem::replace(&mut owned[x], true) { continue; }
+                if self.background_over_object(&sprite, &background[x]) { continue; }
+                objects[x] = Some(ObjectPixel { color: *color, palette });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::SCREEN_HEIGHT;
+    use crate::ppu::timeline::FRAME_DURATION;
+    use crate::ppu::palettes::PaletteMemory;
+    use crate::ppu::sprites::ObjectPriority;
+    use crate::ppu::LINE_TOTAL_DURATION;
+
+    const LINE_DURATION: u64 = LINE_TOTAL_DURATION as u64;
+    const NO_SPRITES: [u8; 0xA0] = [0; 0xA0];
+    const NO_BANK_1: [u8; 0x2000] = [0; 0x2000];
+
+    // Tile 1 has a dark left half on every row and tile 2 is solid color 3, tile 1 sits at the top
+    // left corner of map 0 and tile 2 right of it
+    fn video_ram() -> Vec<u8> {
+        let mut video_ram = vec![0; 0x2000];
+        for row in 0..TILE_SIZE {
+            video_ram[TILE_BYTES + row * 2] = 0xF0;
+            video_ram[2 * TILE_BYTES + row * 2] = 0xFF;
+            video_ram[2 * TILE_BYTES + row * 2 + 1] = 0xFF;
+        }
+        video_ram[TILE_MAP_0] = 1;
+        video_ram[TILE_MAP_0 + 1] = 2;
+        video_ram
+    }
+
+    fn drawn_frame(ppu: &mut PPU, video_ram: &[u8]) -> Vec<u8> {
+        drawn_frame_with_sprites(ppu, video_ram, &[])
+    }
+
+    // Sprites are given as Y, X, tile and flags as in OAM
+    fn drawn_frame_with_sprites(ppu: &mut PPU, video_ram: &[u8], sprites: &[[u8; 4]]) -> Vec<u8> {
+        run_frame(ppu, video_ram, &NO_BANK_1, sprites);
+        ppu.framebuffer().to_vec()
+    }
+
+    fn run_frame(ppu: &mut PPU, video_ram: &[u8], video_ram_bank_1: &[u8], sprites: &[[u8; 4]]) {
+        let mut oam = NO_SPRITES;
+        for (entry, sprite) in oam.chunks_exact_mut(4).zip(sprites) { entry.copy_from_slice(sprite); }
+        ppu.run(FRAME_DURATION, video_ram, video_ram_bank_1, &oam);
+    }
+
+    fn set_color(palettes: &mut PaletteMemory, palette: u8, color: u8, value: u16) {
+        palettes.set_specification(0x80 | ((palette * 4 + color) * 2));
+        for byte in value.to_le_bytes() { palettes.write_data(byte); }
+    }
+
+    // Tile 1 in bank 1 has its first row start with color 1 and its last row end with it, tile 2
+    // in bank 0 is solid color 1. Background palette 0 and 2 and object palette 3 give color 1 a
+    // color of their own.
+    fn cgb_ppu() -> (PPU, Vec<u8>, Vec<u8>) {
+        let mut ppu = PPU::new();
+        ppu.enable_color();
+        ppu.set_lcd_control(0x93);
+        set_color(&mut ppu.palettes.background, 0, 1, 0x7C00);
+        set_color(&mut ppu.palettes.background, 2, 1, 0x001F);
+        set_color(&mut ppu.palettes.objects, 3, 1, 0x03E0);
+        let mut video_ram = vec![0; 0x2000];
+        let mut video_ram_bank_1 = vec![0; 0x2000];
+        video_ram_bank_1[TILE_BYTES] = 0x80;
+        video_ram_bank_1[TILE_BYTES + 14] = 0x01;
+        for row in 0..TILE_SIZE { video_ram[2 * TILE_BYTES + row * 2] = 0xFF; }
+        (ppu, video_ram, video_ram_bank_1)
+    }
+
+    // Tile 3 has a single color 1 pixel on every row, going down from the top left corner
+    fn sprite_video_ram() -> Vec<u8> {
+        let mut video_ram = video_ram();
+        for row in 0..TILE_SIZE {
+            video_ram[3 * TILE_BYTES + row * 2] = 0x80 >> row;
+        }
+        video_ram
+    }
+
+    fn sprite_ppu(lcd_control: u8) -> PPU {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(lcd_control);
+        ppu.bg_palette = 0xE4;
+        ppu.object_palette_0 = 0xE4;
+        ppu.object_palette_1 = 0x08;
+        ppu
+    }
+
+    #[test]
+    fn background_tiles() {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0x91);
+        ppu.bg_palette = 0xE4;
+        let frame = drawn_frame(&mut ppu, &video_ram());
+        assert_eq!(frame.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        assert_eq!(&frame[..20], &[1, 1, 1, 1, 0, 0, 0, 0, 3, 3, 3, 3, 3, 3, 3, 3, 0, 0, 0, 0]);
+        assert_eq!(frame[7 * SCREEN_WIDTH], 1);
+        assert_eq!(frame[8 * SCREEN_WIDTH], 0);
+    }
+
+    #[test]
+    fn scrolling_wraps_around_the_map() {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0x91);
+        ppu.bg_palette = 0xE4;
+        ppu.bg_scroll_x = 2;
+        ppu.bg_scroll_y = 0xFC;
+        let frame = drawn_frame(&mut ppu, &video_ram());
+        assert_eq!(&frame[4 * SCREEN_WIDTH..4 * SCREEN_WIDTH + 8], &[1, 1, 0, 0, 0, 0, 3, 3]);
+        assert_eq!(&frame[..4], &[0; 4]);
+        // The last column shows the start of the map again once 256 pixels have gone by
+        ppu.bg_scroll_x = 0x9A;
+        let frame = drawn_frame(&mut ppu, &video_ram());
+        assert_eq!(frame[4 * SCREEN_WIDTH + SCREEN_WIDTH - 1], 0);
+        assert_eq!(frame[4 * SCREEN_WIDTH + 0x66], 1);
+    }
+
+    // Map 1 holds tile 2 at its top left corner for the window
+    fn window_video_ram() -> Vec<u8> {
+        let mut video_ram = video_ram();
+        video_ram[TILE_MAP_1] = 2;
+        video_ram
+    }
+
+    #[test]
+    fn window_covers_the_background() {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0xF1);
+        ppu.bg_palette = 0xE4;
+        ppu.window_y = 4;
+        ppu.window_x = 7 + 2;
+        let frame = drawn_frame(&mut ppu, &window_video_ram());
+        assert_eq!(&frame[3 * SCREEN_WIDTH..3 * SCREEN_WIDTH + 4], &[1, 1, 1, 1]);
+        assert_eq!(&frame[4 * SCREEN_WIDTH..4 * SCREEN_WIDTH + 12], &[1, 1, 3, 3, 3, 3, 3, 3, 3, 3, 0, 0]);
+        // The window starts from its own first line, not from line WY of the map
+        assert_eq!(frame[11 * SCREEN_WIDTH + 2], 3);
+        assert_eq!(frame[12 * SCREEN_WIDTH + 2], 0);
+        ppu.set_lcd_control(0xD1);
+        let frame = drawn_frame(&mut ppu, &window_video_ram());
+        assert_eq!(frame[4 * SCREEN_WIDTH + 2], 1);
+    }
+
+    #[test]
+    fn window_x_under_7_cuts_off_columns() {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0xF1);
+        ppu.bg_palette = 0xE4;
+        ppu.window_x = 3;
+        let frame = drawn_frame(&mut ppu, &window_video_ram());
+        assert_eq!(&frame[..6], &[3, 3, 3, 3, 0, 0]);
+        ppu.window_x = WINDOW_X_MAX + 1;
+        let frame = drawn_frame(&mut ppu, &window_video_ram());
+        assert_eq!(&frame[..6], &[1, 1, 1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn window_line_only_counts_lines_showing_the_window() {
+        let mut video_ram = window_video_ram();
+        // Second row of window tiles, to tell which window line is drawn
+        video_ram[TILE_MAP_1 + TILE_MAP_SIZE] = 1;
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0xF1);
+        ppu.bg_palette = 0xE4;
+        ppu.window_x = 7;
+        // Hiding the window for 4 lines after its first 4 leaves its counter at 4
+        for line in 0..SCREEN_HEIGHT as u64 {
+            ppu.set_lcd_control(if (4..8).contains(&line) { 0xD1 } else { 0xF1 });
+            ppu.run(LINE_DURATION, &video_ram, &NO_BANK_1, &NO_SPRITES);
+        }
+        ppu.run(FRAME_DURATION - SCREEN_HEIGHT as u64 * LINE_DURATION, &video_ram, &NO_BANK_1, &NO_SPRITES);
+        let frame = ppu.framebuffer();
+        assert_eq!(frame[11 * SCREEN_WIDTH + 4], 3);
+        assert_eq!(frame[12 * SCREEN_WIDTH + 4], 0);
+        assert_eq!(frame[12 * SCREEN_WIDTH], 1);
+    }
+
+    #[test]
+    fn window_appears_once_wy_matches_mid_frame() {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0xF1);
+        ppu.bg_palette = 0xE4;
+        ppu.window_x = 7 + 8;
+        ppu.window_y = 200;
+        ppu.run(10 * LINE_DURATION, &window_video_ram(), &NO_BANK_1, &NO_SPRITES);
+        ppu.window_y = 10;
+        ppu.run(FRAME_DURATION - 10 * LINE_DURATION, &window_video_ram(), &NO_BANK_1, &NO_SPRITES);
+        let frame = ppu.framebuffer();
+        assert_eq!(frame[9 * SCREEN_WIDTH + 8], 0);
+        assert_eq!(frame[10 * SCREEN_WIDTH + 8], 3);
+        // It keeps going down from there, over the blank background
+        assert_eq!(frame[17 * SCREEN_WIDTH + 8], 3);
+    }
+
+    #[test]
+    fn sprites_flip_and_pick_their_palette() {
+        let mut ppu = sprite_ppu(0x93);
+        let sprites = [[16 + 20, 8 + 10, 3, 0x00], [16 + 40, 8 + 10, 3, 0x30], [16 + 60, 8 + 10, 3, 0x40]];
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(&frame[20 * SCREEN_WIDTH + 9..20 * SCREEN_WIDTH + 12], &[0, 1, 0]);
+        assert_eq!(frame[21 * SCREEN_WIDTH + 11], 1);
+        // Flipped horizontally and drawn through OBP1
+        assert_eq!(frame[40 * SCREEN_WIDTH + 17], 2);
+        assert_eq!(frame[40 * SCREEN_WIDTH + 10], 0);
+        // Flipped vertically, the last row comes first
+        assert_eq!(frame[60 * SCREEN_WIDTH + 17], 1);
+        ppu.set_lcd_control(0x91);
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(frame[20 * SCREEN_WIDTH + 10], 0);
+    }
+
+    #[test]
+    fn background_over_objects() {
+        let mut ppu = sprite_ppu(0x93);
+        ppu.object_palette_0 = 0x80;
+        ppu.object_palette_1 = 0xC0;
+        // The first sprite only shows over background color 0, and still hides the one below it
+        let sprites = [[16, 8, 2, 0x80], [16, 8 + 1, 2, 0x10]];
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(&frame[..8], &[1, 1, 1, 1, 2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn dmg_priority_and_line_limit() {
+        let mut ppu = sprite_ppu(0x93);
+        ppu.object_palette_0 = 0xC0;
+        ppu.object_palette_1 = 0x80;
+        let line = 30 * SCREEN_WIDTH;
+        // The sprite further left is on top even with a higher OAM index
+        let sprites = [[16 + 30, 8 + 4, 2, 0x00], [16 + 30, 8 + 2, 2, 0x10]];
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(&frame[line + 2..line + 12], &[2, 2, 2, 2, 2, 2, 2, 2, 3, 3]);
+        ppu.object_priority = ObjectPriority::OamIndex;
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(&frame[line + 2..line + 12], &[2, 2, 3, 3, 3, 3, 3, 3, 3, 3]);
+        // Only the first ten sprites of a line in OAM are drawn
+        let crowded: Vec<[u8; 4]> = (0..11).map(|index| [16 + 50, 8 + index * 10, 2, 0x00]).collect();
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &crowded);
+        assert_eq!(frame[50 * SCREEN_WIDTH + 90], 3);
+        assert_eq!(frame[50 * SCREEN_WIDTH + 100], 0);
+    }
+
+    #[test]
+    fn tall_sprites() {
+        let mut ppu = sprite_ppu(0x97);
+        // Tile 3 in 8x16 mode means tile 2 on top and tile 3 below
+        let sprites = [[16 + 20, 8 + 10, 3, 0x00], [16 + 60, 8 + 10, 3, 0x40]];
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(frame[20 * SCREEN_WIDTH + 15], 3);
+        assert_eq!(&frame[28 * SCREEN_WIDTH + 10..28 * SCREEN_WIDTH + 12], &[1, 0]);
+        // Flipping swaps the two tiles too
+        assert_eq!(frame[60 * SCREEN_WIDTH + 17], 1);
+        assert_eq!(frame[75 * SCREEN_WIDTH + 15], 3);
+        ppu.set_lcd_control(0x93);
+        let frame = drawn_frame_with_sprites(&mut ppu, &sprite_video_ram(), &sprites);
+        assert_eq!(frame[28 * SCREEN_WIDTH + 10], 0);
+    }
+
+    #[test]
+    fn signed_tile_data_and_palette() {
+        let mut video_ram = video_ram();
+        // Tile 1 counted from 0x9000
+        video_ram[0x1000 + TILE_BYTES..0x1000 + 2 * TILE_BYTES].fill(0xFF);
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(0x81);
+        ppu.bg_palette = 0x1B;
+        let frame = drawn_frame(&mut ppu, &video_ram);
+        assert_eq!(&frame[..8], &[0; 8]);
+        assert_eq!(frame[8], 3);
+        ppu.set_lcd_control(0x80);
+        let frame = drawn_frame(&mut ppu, &video_ram);
+        assert!(frame.iter().all(|shade| *shade == 0));
+    }
+
+    #[test]
+    fn cgb_tiles_use_their_attributes() {
+        let (mut ppu, mut video_ram, mut video_ram_bank_1) = cgb_ppu();
+        video_ram[TILE_MAP_0] = 1;
+        // Palette 2, tile data from bank 1
+        video_ram_bank_1[TILE_MAP_0] = 0x0A;
+        run_frame(&mut ppu, &video_ram, &video_ram_bank_1, &[]);
+        let colors = ppu.color_framebuffer().unwrap();
+        assert_eq!((colors[0], colors[1]), (0x001F, ppu.palettes.background.color(2, 0)));
+        assert_eq!(colors[7 * SCREEN_WIDTH + 7], 0x001F);
+        // The shades are the color indices
+        assert_eq!(&ppu.framebuffer()[..2], &[1, 0]);
+        // Flipped horizontally, then vertically too
+        video_ram_bank_1[TILE_MAP_0] = 0x2A;
+        run_frame(&mut ppu, &video_ram, &video_ram_bank_1, &[]);
+        let colors = ppu.color_framebuffer().unwrap();
+        assert_eq!((colors[0], colors[7]), (ppu.palettes.background.color(2, 0), 0x001F));
+        video_ram_bank_1[TILE_MAP_0] = 0x6A;
+        run_frame(&mut ppu, &video_ram, &video_ram_bank_1, &[]);
+        assert_eq!(ppu.color_framebuffer().unwrap()[0], 0x001F);
+    }
+
+    #[test]
+    fn cgb_objects_and_priority() {
+        let (mut ppu, mut video_ram, mut video_ram_bank_1) = cgb_ppu();
+        // Object palette 3, tile data from bank 1
+        let sprites = [[16 + 20, 8 + 8, 1, 0x0B], [16, 8, 1, 0x0B]];
+        // The top left tile is drawn over objects
+        video_ram[TILE_MAP_0] = 2;
+        video_ram_bank_1[TILE_MAP_0] = 0x80;
+        run_frame(&mut ppu, &video_ram, &video_ram_bank_1, &sprites);
+        let colors = ppu.color_framebuffer().unwrap();
+        assert_eq!(colors[20 * SCREEN_WIDTH + 8], 0x03E0);
+        assert_eq!(colors[0], 0x7C00);
+        // LCDC bit 0 takes the priority away from the background, which is still drawn
+        ppu.set_lcd_control(0x92);
+        run_frame(&mut ppu, &video_ram, &video_ram_bank_1, &sprites);
+        let colors = ppu.color_framebuffer().unwrap();
+        assert_eq!((colors[0], colors[1]), (0x03E0, 0x7C00));
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+use super::DRAWN_LINES;
+use crate::prelude::*;
+
+pub const OAM_ENTRIES: usize = 40;
+pub const SPRITES_PER_LINE: usize = 10;
+// Sprite positions are stored offset so they can be partially off screen at the top left
+pub const SPRITE_Y_OFFSET: i16 = 16;
+pub const SPRITE_X_OFFSET: i16 = 8;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Sprite {
+    pub y: u8,
+    pub x: u8,
+    pub tile: u8,
+    pub flags: u8,
+}
+
+impl Sprite {
+    pub fn behind_background(&self) -> bool { self.flags & 0x80 != 0 }
+    pub fn y_flip(&self) -> bool { self.flags & 0x40 != 0 }
+    pub fn x_flip(&self) -> bool { self.flags & 0x20 != 0 }
+    pub fn palette(&self) -> u8 { (self.flags >> 4) & 1 }
+    // In CGB mode the lower bits pick one of the 8 object palettes and the video RAM bank
+    pub fn cgb_palette(&self) -> u8 { self.flags & 0x07 }
+    pub fn bank(&self) -> usize { (self.flags >> 3) as usize & 1 }
+
+    pub fn screen_y(&self) -> i16 { self.y as i16 - SPRITE_Y_OFFSET }
+    pub fn screen_x(&self) -> i16 { self.x as i16 - SPRITE_X_OFFSET }
+
+    pub fn covers_line(&self, line: u8, height: u8) -> bool {
+        let top = self.screen_y();
+        (top..top + height as i16).contains(&(line as i16))
+    }
+}
+
+// Each entry takes four bytes: Y, X, tile number and flags
+pub fn decode_oam(oam: &[u8]) -> Vec<Sprite> {
+    oam.chunks_exact(4)
+        .take(OAM_ENTRIES)
+        .map(|entry| Sprite { y: entry[0], x: entry[1], tile: entry[2], flags: entry[3] })
+        .collect()
+}
+
+// Which sprite is drawn on top where several overlap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObjectPriority {
+    // The DMG's: leftmost first, the lower OAM index on ties
+    #[default]
+    Coordinate,
+    // The CGB's unless OPRI says otherwise: lower OAM index first
+    OamIndex,
+}
+
+// OAM indices of the sprites drawn on a line, topmost first. The first ten in OAM order
+// are picked regardless of priority.
+pub fn line_sprites(sprites: &[Sprite], line: u8, height: u8, priority: ObjectPriority) -> Vec<usize> {
+    let mut on_line: Vec<usize> = sprites.iter().enumerate()
+        .filter(|(_, sprite)| sprite.covers_line(line, height))
+        .map(|(index, _)| index)
+        .take(SPRITES_PER_LINE)
+        .collect();
+    if priority == ObjectPriority::Coordinate {
+        on_line.sort_by_key(|index| sprites[*index].x);
+    }
+    on_line
+}
+
+// Sprites that miss at least one line they cover because ten sprites with a lower OAM index
+// were already selected for it
+pub fn dropped_by_line_limit(sprites: &[Sprite], height: u8) -> Vec<bool> {
+    let mut dropped = vec![false; sprites.len()];
+    for line in 0..DRAWN_LINES {
+        let on_line = sprites.iter().enumerate().filter(|(_, sprite)| sprite.covers_line(line, height));
+        for (index, _) in on_line.skip(SPRITES_PER_LINE) {
+            dropped[index] = true;
+        }
+    }
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_entries() {
+        let mut oam = vec![0; OAM_ENTRIES * 4];
+        oam[4..8].copy_from_slice(&[32, 16, 0x42, 0xF0]);
+        let sprites = decode_oam(&oam);
+        assert_eq!(sprites.len(), OAM_ENTRIES);
+        let sprite = sprites[1];
+        assert_eq!((sprite.screen_x(), sprite.screen_y(), sprite.tile), (8, 16, 0x42));
+        assert!(sprite.behind_background() && sprite.y_flip() && sprite.x_flip());
+        assert_eq!(sprite.palette(), 1);
+    }
+
+    #[test]
+    fn priority_by_coordinate_or_oam_index() {
+        let sprites = vec![
+            Sprite { y: 16, x: 30, ..Sprite::default() },
+            Sprite { y: 100, x: 0, ..Sprite::default() },
+            Sprite { y: 16, x: 20, ..Sprite::default() },
+            Sprite { y: 16, x: 30, ..Sprite::default() },
+        ];
+        assert_eq!(line_sprites(&sprites, 0, 8, ObjectPriority::Coordinate), vec![2, 0, 3]);
+        assert_eq!(line_sprites(&sprites, 0, 8, ObjectPriority::OamIndex), vec![0, 2, 3]);
+        // The line limit picks by OAM index first, so a later sprite further left is not drawn
+        let mut crowded = vec![Sprite { y: 16, x: 50, ..Sprite::default() }; 10];
+        crowded.push(Sprite { y: 16, x: 8, ..Sprite::default() });
+        assert!(!line_sprites(&crowded, 0, 8, ObjectPriority::Coordinate).contains(&10));
+    }
+
+    #[test]
+    fn eleventh_sprite_on_a_line_is_dropped() {
+        let mut sprites = vec![Sprite { y: 16, x: 0, tile: 0, flags: 0 }; 11];
+        sprites.push(Sprite { y: 100, ..Sprite::default() });
+        let dropped = dropped_by_line_limit(&sprites, 8);
+        assert_eq!(dropped.iter().filter(|dropped| **dropped).count(), 1);
+        assert!(dropped[10]);
+        // In 8x16 mode the sprite 8 lines lower overlaps as well
+        sprites[11].y = 24;
+        assert!(dropped_by_line_limit(&sprites, 16)[11]);
+        assert!(!dropped_by_line_limit(&sprites, 8)[11]);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::prelude::*;
+
+pub const TILE_SIZE: usize = 8;
+pub const TILE_BYTES: usize = 16;
+// Tile data fills 0x8000-0x97FF, the rest of video RAM holds the tile maps
+pub const TILE_COUNT: usize = 384;
+
+pub const TILE_MAP_SIZE: usize = 32;
+pub const TILE_MAP_PIXELS: usize = TILE_MAP_SIZE * TILE_SIZE;
+// Offsets of the two tile maps from the start of video RAM
+pub const TILE_MAP_0: usize = 0x1800;
+pub const TILE_MAP_1: usize = 0x1C00;
+
+pub type Tile = [[u8; TILE_SIZE]; TILE_SIZE];
+
+// On the CGB, video RAM bank 1 holds an attribute byte for every tile map entry in bank 0
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TileAttributes(pub u8);
+
+impl TileAttributes {
+    pub fn palette(&self) -> u8 { self.0 & 0x07 }
+    pub fn bank(&self) -> usize { (self.0 >> 3) as usize & 1 }
+    pub fn x_flip(&self) -> bool { self.0 & 0x20 != 0 }
+    pub fn y_flip(&self) -> bool { self.0 & 0x40 != 0 }
+    // Drawn over sprites regardless of their own priority
+    pub fn over_sprites(&self) -> bool { self.0 & 0x80 != 0 }
+}
+
+// Attributes of a tile map entry, from the same offset in bank 1
+pub fn tile_attributes(video_ram_bank_1: &[u8], map_offset: usize, map_x: usize, map_y: usize) -> TileAttributes {
+    TileAttributes(video_ram_bank_1[map_offset + map_y * TILE_MAP_SIZE + map_x])
+}
+
+// Tile numbers in the maps index from 0x8000 unsigned, or from 0x9000 signed when LCDC bit 4 is clear
+pub fn tile_data_index(tile_number: u8, unsigned_addressing: bool) -> usize {
+    if unsigned_addressing {
+        tile_number as usize
+    } else {
+        (256 + tile_number as i8 as i16) as usize
+    }
+}
+
+const BYTE_LANES: u64 = 0x0101_0101_0101_0101;
+// Bit 7 of the input ends up in the first (lowest) byte, bit 0 in the last, leftmost pixel first
+const BIT_PER_LANE: u64 = 0x0102_0408_1020_4080;
+
+// One byte per pixel of a tile row, 1 where the pixel's bit is set and 0 elsewhere, as a u64
+// so whole rows go through the same few integer operations instead of a loop over pixels
+fn spread_bits(bits: u8) -> u64 {
+    let lanes = (bits as u64).wrapping_mul(BYTE_LANES) & BIT_PER_LANE;
+    // Any set lane is at most 0x80, adding 0x7F carries it into bit 7 without touching its neighbour
+    ((lanes + 0x7F * BYTE_LANES) >> 7) & BYTE_LANES
+}
+
+// Color indices (0-3) of the 8 pixels of a tile row, from its two bytes
+pub fn decode_row(low: u8, high: u8) -> [u8; TILE_SIZE] {
+    (spread_bits(low) | spread_bits(high) << 1).to_le_bytes()
+}
+
+// Shades of the 8 pixels of a tile row after going through a BGP style palette, which holds
+// the shade of color 0 in its lowest two bits and that of color 3 in its highest
+pub fn decode_row_with_palette(low: u8, high: u8, palette: u8) -> [u8; TILE_SIZE] {
+    let low_set = spread_bits(low) * 0xFF;
+    let high_set = spread_bits(high) * 0xFF;
+    let shade = |color: u8| ((palette >> (color * 2)) & 3) as u64 * BYTE_LANES;
+    ((!high_set & !low_set & shade(0))
+        | (!high_set & low_set & shade(1))
+        | (high_set & !low_set & shade(2))
+        | (high_set & low_set & shade(3))).to_le_bytes()
+}
+
+// Color indices (0-3) of every pixel of a tile, rows top to bottom and pixels left to right.
+// Each row takes two bytes, the first one holds the low bit of each pixel's color.
+pub fn decode_tile(video_ram: &[u8], tile_index: usize) -> Tile {
+    let mut tile = [[0; TILE_SIZE]; TILE_SIZE];
+    let tile_start = tile_index * TILE_BYTES;
+    for (y, row) in tile.iter_mut().enumerate() {
+        *row = decode_row(video_ram[tile_start + y * 2], video_ram[tile_start + y * 2 + 1]);
+    }
+    tile
+}
+
+// Color indices of the whole 256x256 pixel map starting at the given video RAM offset, row by row
+pub fn decode_tile_map(video_ram: &[u8], map_offset: usize, unsigned_addressing: bool) -> Vec<u8> {
+    let mut pixels = vec![0; TILE_MAP_PIXELS * TILE_MAP_PIXELS];
+    for map_y in 0..TILE_MAP_SIZE {
+        for map_x in 0..TILE_MAP_SIZE {
+            let tile_number = video_ram[map_offset + map_y * TILE_MAP_SIZE + map_x];
+            let tile = decode_tile(video_ram, tile_data_index(tile_number, unsigned_addressing));
+            for (y, row) in tile.iter().enumerate() {
+                let line_start = (map_y * TILE_SIZE + y) * TILE_MAP_PIXELS + map_x * TILE_SIZE;
+                pixels[line_start..line_start + TILE_SIZE].copy_from_slice(row);
+            }
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_tile_rows() {
+        let mut video_ram = vec![0; TILE_COUNT * TILE_BYTES];
+        // Second tile, first row: colors 0 1 2 3 0 1 2 3
+        video_ram[TILE_BYTES] = 0b0101_0101;
+        video_ram[TILE_BYTES + 1] = 0b0011_0011;
+        let tile = decode_tile(&video_ram, 1);
+        assert_eq!(tile[0], [0, 1, 2, 3, 0, 1, 2, 3]);
+        assert_eq!(tile[1], [0; 8]);
+        assert_eq!(decode_tile(&video_ram, 0), [[0; 8]; 8]);
+    }
+
+    #[test]
+    fn rows_match_bit_by_bit_decoding() {
+        for low in 0..=255_u8 {
+            for high in 0..=255_u8 {
+                let row = decode_row(low, high);
+                for (x, pixel) in row.iter().enumerate() {
+                    let bit = 7 - x;
+                    assert_eq!(*pixel, ((low >> bit) & 1) | (((high >> bit) & 1) << 1));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rows_through_palette() {
+        // Colors 0 1 2 3 0 1 2 3 with the boot ROM palette, then with an inverted one
+        assert_eq!(decode_row_with_palette(0b0101_0101, 0b0011_0011, 0xFC), [0, 3, 3, 3, 0, 3, 3, 3]);
+        assert_eq!(decode_row_with_palette(0b0101_0101, 0b0011_0011, 0b0001_1011), [3, 2, 1, 0, 3, 2, 1, 0]);
+        for palette in 0..=255_u8 {
+            let row = decode_row_with_palette(0xA5, 0x3C, palette);
+            for (pixel, color) in row.iter().zip(decode_row(0xA5, 0x3C).iter()) {
+                assert_eq!(*pixel, (palette >> (color * 2)) & 3);
+            }
+        }
+    }
+
+    #[test]
+    fn attributes_from_bank_1() {
+        let mut video_ram_bank_1 = vec![0; 0x2000];
+        video_ram_bank_1[TILE_MAP_0 + 2 * TILE_MAP_SIZE + 3] = 0b1010_1101;
+        let attributes = tile_attributes(&video_ram_bank_1, TILE_MAP_0, 3, 2);
+        assert_eq!(attributes.palette(), 5);
+        assert_eq!(attributes.bank(), 1);
+        assert!(attributes.x_flip());
+        assert!(!attributes.y_flip());
+        assert!(attributes.over_sprites());
+        assert_eq!(tile_attributes(&video_ram_bank_1, TILE_MAP_1, 3, 2), TileAttributes(0));
+    }
+
+    #[test]
+    fn signed_tile_addressing() {
+        assert_eq!(tile_data_index(0x00, true), 0);
+        assert_eq!(tile_data_index(0x80, true), 128);
+        assert_eq!(tile_data_index(0x00, false), 256);
+        assert_eq!(tile_data_index(0x7F, false), 383);
+        assert_eq!(tile_data_index(0x80, false), 128);
+    }
+
+    #[test]
+    fn decode_map() {
+        let mut video_ram = vec![0; 0x2000];
+        // Tile 1 is solid color 3, place it second in the second map row
+        for byte in &mut video_ram[TILE_BYTES..2 * TILE_BYTES] { *byte = 0xFF; }
+        video_ram[TILE_MAP_1 + TILE_MAP_SIZE + 1] = 1;
+        let pixels = decode_tile_map(&video_ram, TILE_MAP_1, true);
+        assert_eq!(pixels[8 * TILE_MAP_PIXELS + 8], 3);
+        assert_eq!(pixels[8 * TILE_MAP_PIXELS + 7], 0);
+        assert_eq!(pixels[15 * TILE_MAP_PIXELS + 15], 3);
+        assert_eq!(pixels[16 * TILE_MAP_PIXELS + 15], 0);
+    }
+}
@@ -0,0 +1,190 @@
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use crate::prelude::*;
+use super::{PpuMode, DRAWN_LINES, LINE_TOTAL_DURATION, VBLANK_LINES};
+
+pub const FRAME_DURATION: u64 = LINE_TOTAL_DURATION as u64 * (DRAWN_LINES + VBLANK_LINES) as u64;
+// The diagram shows one character per machine cycle
+#[cfg(feature = "std")]
+const DIAGRAM_STEP: u64 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimelineEvent {
+    ModeChange(PpuMode),
+    LineChange(u8),
+    Interrupt(Interrupt),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimelineEntry {
+    // PPU cycles since the capture started
+    pub cycle: u64,
+    pub line: u8,
+    pub event: TimelineEvent,
+}
+
+// STAT mode numbers as the CPU sees them
+#[cfg(feature = "std")]
+fn stat_mode(mode: PpuMode) -> u8 {
+    match mode {
+        PpuMode::HBlank => 0,
+        PpuMode::VBlank => 1,
+        PpuMode::OAM => 2,
+        PpuMode::PixelTransfer => 3,
+    }
+}
+
+#[cfg(feature = "std")]
+fn diagram_char(mode: PpuMode) -> char {
+    match mode {
+        PpuMode::HBlank => 'H',
+        PpuMode::VBlank => 'V',
+        PpuMode::OAM => 'O',
+        PpuMode::PixelTransfer => 'T',
+    }
+}
+
+// PPU events over a fixed number of frames, starting with the state the capture began in
+#[derive(Clone)]
+pub struct Timeline {
+    start_cycle: u64,
+    duration: u64,
+    entries: Vec<TimelineEntry>,
+}
+
+impl Timeline {
+    pub fn new(start_cycle: u64, frames: u64, line: u8, mode: PpuMode) -> Timeline {
+        Timeline {
+            start_cycle,
+            duration: frames * FRAME_DURATION,
+            entries: vec![
+                TimelineEntry { cycle: 0, line, event: TimelineEvent::LineChange(line) },
+                TimelineEntry { cycle: 0, line, event: TimelineEvent::ModeChange(mode) },
+            ],
+        }
+    }
+
+    pub fn entries(&self) -> &[TimelineEntry] {
+        &self.entries
+    }
+
+    pub fn duration(&self) -> u64 {
+        self.duration
+    }
+
+    pub fn is_complete(&self, cycle: u64) -> bool {
+        cycle - self.start_cycle >= self.duration
+    }
+
+    pub(super) fn observe(&mut self, cycle: u64, line_before: u8, mode_before: PpuMode, line: u8, mode: PpuMode) {
+        if self.is_complete(cycle) { return; }
+        let cycle = cycle - self.start_cycle;
+        if line != line_before {
+            self.entries.push(TimelineEntry { cycle, line, event: TimelineEvent::LineChange(line) });
+        }
+        if mode != mode_before {
+            self.entries.push(TimelineEntry { cycle, line, event: TimelineEvent::ModeChange(mode) });
+            if mode == PpuMode::VBlank {
+                self.entries.push(TimelineEntry { cycle, line, event: TimelineEvent::Interrupt(Interrupt::VBlank) });
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "cycle,line,event,value")?;
+        for entry in &self.entries {
+            let (event, value) = match entry.event {
+                TimelineEvent::ModeChange(mode) => ("mode", stat_mode(mode).to_string()),
+                TimelineEvent::LineChange(line) => ("ly", line.to_string()),
+                TimelineEvent::Interrupt(interrupt) => ("interrupt", format!("{:?}", interrupt)),
+            };
+            writeln!(writer, "{},{},{},{}", entry.cycle, entry.line, event, value)?;
+        }
+        Ok(())
+    }
+
+    // One row per line with a character per machine cycle: O OAM search, T pixel transfer,
+    // H HBlank, V VBlank, and * where an interrupt is requested
+    #[cfg(feature = "std")]
+    pub fn write_diagram<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut entries = self.entries.iter().peekable();
+        let mut line = self.entries[0].line;
+        let mut mode = PpuMode::OAM;
+        let mut row = String::new();
+        for slot_start in (0..self.duration).step_by(DIAGRAM_STEP as usize) {
+            let mut interrupt = false;
+            while let Some(entry) = entries.next_if(|entry| entry.cycle < slot_start + DIAGRAM_STEP) {
+                match entry.event {
+                    TimelineEvent::LineChange(new_line) => {
+                        if !row.is_empty() { writeln!(writer, "LY {:3} {}", line, row)?; }
+                        row.clear();
+                        line = new_line;
+                    }
+                    TimelineEvent::ModeChange(new_mode) => mode = new_mode,
+                    TimelineEvent::Interrupt(_) => interrupt = true,
+                }
+            }
+            row.push(if interrupt { '*' } else { diagram_char(mode) });
+        }
+        if !row.is_empty() { writeln!(writer, "LY {:3} {}", line, row)?; }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ppu::PPU;
+
+    fn capture_frame() -> Timeline {
+        let mut ppu = PPU::new();
+        ppu.set_lcd_control(crate::ppu::LCDC_DISPLAY_ENABLE);
+        ppu.start_timeline(1);
+        for _ in 0..FRAME_DURATION + 100 { ppu.cycle(); }
+        ppu.take_timeline().unwrap()
+    }
+
+    #[test]
+    fn capture_mode_changes() {
+        let timeline = capture_frame();
+        let entries = timeline.entries();
+        assert_eq!(entries[2], TimelineEntry { cycle: 80, line: 0, event: TimelineEvent::ModeChange(PpuMode::PixelTransfer) });
+        let vblank = entries.iter().find(|entry| entry.event == TimelineEvent::Interrupt(Interrupt::VBlank)).unwrap();
+        assert_eq!((vblank.cycle, vblank.line), (144 * 456, 144));
+        // Nothing is recorded after the requested frame
+        assert!(entries.iter().all(|entry| entry.cycle < FRAME_DURATION));
+        let mode_changes = entries.iter().filter(|entry| matches!(entry.event, TimelineEvent::ModeChange(_))).count();
+        assert_eq!(mode_changes, 1 + 144 * 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn diagram_rows() {
+        let mut output = vec![];
+        capture_frame().write_diagram(&mut output).unwrap();
+        let diagram = String::from_utf8(output).unwrap();
+        let rows: Vec<&str> = diagram.lines().collect();
+        assert_eq!(rows.len(), 154);
+        assert_eq!(rows[0], format!("LY   0 {}{}{}", "O".repeat(20), "T".repeat(43), "H".repeat(51)));
+        assert!(rows[144].starts_with("LY 144 *VVV"));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn csv_export() {
+        let mut output = vec![];
+        capture_frame().write_csv(&mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        assert!(csv.starts_with("cycle,line,event,value\n0,0,ly,0\n0,0,mode,2\n80,0,mode,3\n"));
+        assert!(csv.contains("65664,144,interrupt,VBlank\n"));
+    }
+}
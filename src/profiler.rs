@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::debugger::symbols::SymbolTable;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub bank: u8,
+    pub address: u16,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02X}:{:04X}", self.bank, self.address)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hotspot {
+    pub cycles: u64,
+    pub executions: u64,
+}
+
+// Attributes emulated cycles to the address of the instruction that spent them
+#[derive(Default)]
+pub struct Profiler {
+    hotspots: HashMap<Location, Hotspot>,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    pub fn record(&mut self, location: Location, cycles: u64) {
+        let hotspot = self.hotspots.entry(location).or_default();
+        hotspot.cycles += cycles;
+        hotspot.executions += 1;
+        self.total_cycles += cycles;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn hotspot(&self, location: Location) -> Option<Hotspot> {
+        self.hotspots.get(&location).copied()
+    }
+
+    pub fn top(&self, count: usize) -> Vec<(Location, Hotspot)> {
+        let mut hotspots: Vec<(Location, Hotspot)> = self.hotspots.iter()
+            .map(|(location, hotspot)| (*location, *hotspot))
+            .collect();
+        hotspots.sort_by(|(location_a, hotspot_a), (location_b, hotspot_b)|
+            hotspot_b.cycles.cmp(&hotspot_a.cycles).then(location_a.cmp(location_b)));
+        hotspots.truncate(count);
+        hotspots
+    }
+
+    pub fn report(&self, count: usize, symbols: Option<&SymbolTable>) -> String {
+        let mut lines = vec![format!("{:<9}{:>12}{:>8}{:>12}", "Location", "Cycles", "%", "Executions")];
+        for (location, hotspot) in self.top(count) {
+            let share = 100.0 * hotspot.cycles as f64 / self.total_cycles.max(1) as f64;
+            let label = symbols.and_then(|symbols| symbols.describe(location.address)).unwrap_or_default();
+            lines.push(format!("{:<9}{:>12}{:>8.2}{:>12}  {}",
+                               location.to_string(), hotspot.cycles, share, hotspot.executions, label)
+                .trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(address: u16) -> Location {
+        Location { bank: 0, address }
+    }
+
+    #[test]
+    fn top_sorted_by_cycles() {
+        let mut profiler = Profiler::default();
+        profiler.record(location(0x0150), 4);
+        profiler.record(location(0x0151), 12);
+        profiler.record(location(0x0150), 4);
+        assert_eq!(profiler.total_cycles(), 20);
+        assert_eq!(profiler.hotspot(location(0x0150)), Some(Hotspot { cycles: 8, executions: 2 }));
+        let top: Vec<Location> = profiler.top(1).into_iter().map(|(location, _)| location).collect();
+        assert_eq!(top, vec![location(0x0151)]);
+    }
+
+    #[test]
+    fn report_with_labels() {
+        let mut profiler = Profiler::default();
+        profiler.record(location(0x0152), 16);
+        let symbols = SymbolTable::parse("00:0150 Main").unwrap();
+        let report = profiler.report(10, Some(&symbols));
+        assert_eq!(report.lines().nth(1), Some("00:0152            16  100.00           1  Main+2"));
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use crate::dmg::DMG;
+use crate::savestate::Snapshot;
+
+struct RewindPoint {
+    frame: u64,
+    snapshot: Snapshot,
+    size: usize,
+}
+
+// Snapshots taken every few frames, the oldest ones are dropped to stay within the memory budget
+pub struct Rewind {
+    interval_frames: u64,
+    memory_budget: usize,
+    snapshots: VecDeque<RewindPoint>,
+    memory_used: usize,
+}
+
+impl Rewind {
+    pub fn new(interval_frames: u64, memory_budget: usize) -> Rewind {
+        Rewind { interval_frames: interval_frames.max(1), memory_budget, snapshots: VecDeque::new(), memory_used: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    // Call once per frame, a snapshot is only taken when the interval has passed since the last one
+    pub fn record(&mut self, dmg: &DMG) {
+        let frame = dmg.frame_count();
+        if let Some(newest) = self.snapshots.back() {
+            if frame < newest.frame + self.interval_frames { return; }
+        }
+        let snapshot = dmg.snapshot();
+        let size = snapshot.size_in_bytes();
+        self.snapshots.push_back(RewindPoint { frame, snapshot, size });
+        self.memory_used += size;
+        while self.memory_used > self.memory_budget {
+            match self.snapshots.pop_front() {
+                Some(oldest) => self.memory_used -= oldest.size,
+                None => break,
+            }
+        }
+    }
+
+    // Restores the newest snapshot and forgets it, so repeated calls go further back.
+    // Returns the frame the emulation went back to.
+    pub fn step_back(&mut self, dmg: &mut DMG) -> Option<u64> {
+        let point = self.snapshots.pop_back()?;
+        self.memory_used -= point.size;
+        dmg.restore(&point.snapshot);
+        Some(point.frame)
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.memory_used = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+
+    fn looping_dmg<'a>() -> DMG<'a> {
+        // JR -2
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x18, 0xFE], vec![])))
+    }
+
+    #[test]
+    fn records_at_interval_and_steps_back() {
+        let mut dmg = looping_dmg();
+        let mut rewind = Rewind::new(2, usize::MAX);
+        for _ in 0..6 {
+            rewind.record(&dmg);
+            dmg.run_frame().unwrap();
+        }
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.step_back(&mut dmg), Some(4));
+        assert_eq!(dmg.frame_count(), 4);
+        assert_eq!(rewind.step_back(&mut dmg), Some(2));
+        assert_eq!(rewind.step_back(&mut dmg), Some(0));
+        assert_eq!(rewind.step_back(&mut dmg), None);
+        assert_eq!(rewind.memory_used(), 0);
+    }
+
+    #[test]
+    fn drops_oldest_over_budget() {
+        let mut dmg = looping_dmg();
+        let one_snapshot = dmg.snapshot().size_in_bytes();
+        let mut rewind = Rewind::new(1, one_snapshot * 2);
+        for _ in 0..5 {
+            rewind.record(&dmg);
+            dmg.run_frame().unwrap();
+        }
+        assert_eq!(rewind.len(), 2);
+        assert_eq!(rewind.step_back(&mut dmg), Some(4));
+        assert_eq!(rewind.step_back(&mut dmg), Some(3));
+        assert!(rewind.is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::BusState;
+use crate::cpu::CpuState;
+use crate::dmg::DMG;
+
+// Bump whenever the saved data changes shape, and keep a loader for the previous version.
+// Version 1 files are a bare SaveState without the header.
+pub const VERSION: u32 = 2;
+const OLDEST_SUPPORTED_VERSION: u32 = 1;
+
+// Name and size of each saved memory area, so a state from a differently built machine is refused
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Component {
+    pub name: String,
+    pub size: usize,
+}
+
+// Written with a borrowed state and read back into an owned one
+#[derive(Serialize, Deserialize)]
+struct SaveStateFile<S> {
+    version: u32,
+    layout: Vec<Component>,
+    state: S,
+}
+
+// The whole machine state between two instructions, cartridge RAM and the MBC3 clock included.
+// There is no APU yet, so there is nothing of its to keep.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SaveState {
+    // Title from the cartridge header, states only make sense on the ROM they were saved from
+    pub cartridge: String,
+    pub cpu: CpuState,
+    pub bus: BusState,
+}
+
+// In-memory copy of the machine state for rewind and test harnesses. Restoring one copies the
+// memory back without any serialization, it is only meant for the DMG it was taken from.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) state: SaveState,
+}
+
+impl Snapshot {
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of::<Snapshot>() + self.state.layout().iter().map(|component| component.size).sum::<usize>()
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn bad_state(error: serde_json::Error) -> io::Error {
+    invalid_data(format!("Bad save state: {}", error))
+}
+
+impl SaveState {
+    pub fn layout(&self) -> Vec<Component> {
+        let bus = &self.bus;
+        let cartridge_ram = bus.mbc3.as_ref().map_or(&[][..], |mbc3| mbc3.ram());
+        [("work RAM", &bus.work_ram[..]), ("video RAM", &bus.video_ram), ("video RAM bank 1", &bus.video_ram_bank_1),
+         ("OAM", &bus.oam), ("IO ports", &bus.io_ports), ("high RAM", &bus.high_ram), ("cartridge RAM", cartridge_ram)].iter()
+            // Components only CGB saves and cartridges with RAM have
+            .filter(|(_, data)| !data.is_empty())
+            .map(|(name, data)| Component { name: name.to_string(), size: data.len() })
+            .collect()
+    }
+
+    pub fn write<W: Write>(&self, writer: W) -> io::Result<()> {
+        let file = SaveStateFile { version: VERSION, layout: self.layout(), state: self };
+        serde_json::to_writer(writer, &file).map_err(io::Error::from)
+    }
+
+    pub fn read<R: Read>(reader: R) -> io::Result<SaveState> {
+        let value: serde_json::Value = serde_json::from_reader(reader).map_err(bad_state)?;
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => version.as_u64()
+                .ok_or_else(|| invalid_data(format!("Bad save state version: {}", version)))? as u32,
+        };
+        match version {
+            1 => serde_json::from_value(value).map_err(bad_state),
+            VERSION => {
+                let file: SaveStateFile<SaveState> = serde_json::from_value(value).map_err(bad_state)?;
+                check_layout(&file.layout, &file.state.layout()).map_err(invalid_data)?;
+                Ok(file.state)
+            }
+            _ => Err(invalid_data(format!("Save state format version {} is not supported, this build reads versions {} to {}",
+                                          version, OLDEST_SUPPORTED_VERSION, VERSION))),
+        }
+    }
+}
+
+pub fn check_layout(saved: &[Component], expected: &[Component]) -> Result<(), String> {
+    for component in expected {
+        match saved.iter().find(|saved| saved.name == component.name) {
+            None => return Err(format!("Save state has no {}", component.name)),
+            Some(saved) if saved.size != component.size => {
+                return Err(format!("Save state has {} bytes of {}, expected {}", saved.size, component.name, component.size));
+            }
+            Some(_) => {}
+        }
+    }
+    match saved.iter().find(|saved| !expected.iter().any(|component| component.name == saved.name)) {
+        Some(unknown) => Err(format!("Save state has an unknown component: {}", unknown.name)),
+        None => Ok(()),
+    }
+}
+
+// Slot files are named after the ROM so states of different games never mix, e.g. TETRIS-6E1D.ss1
+pub fn slot_file_name(title: &str, checksum: u16, slot: u8) -> String {
+    let title: String = title.trim_end_matches('\0').trim()
+        .chars().map(|character| if character.is_ascii_alphanumeric() { character } else { '_' })
+        .collect();
+    let title = if title.is_empty() { "untitled".to_string() } else { title };
+    format!("{}-{:04X}.ss{}", title, checksum, slot)
+}
+
+pub fn save_to_file<P: AsRef<Path>>(dmg: &DMG, path: P) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    dmg.save_state().write(&mut writer)?;
+    writer.flush()
+}
+
+pub fn load_from_file<P: AsRef<Path>>(dmg: &mut DMG, path: P) -> io::Result<()> {
+    let state = SaveState::read(BufReader::new(File::open(path)?))?;
+    check_layout(&state.layout(), &dmg.save_state().layout()).map_err(invalid_data)?;
+    dmg.load_state(state).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::bus::cartridge::Cartridge;
+    use crate::cpu::CPU;
+    use crate::cpu::register::DMGRegister;
+
+    fn serialized(dmg: &DMG) -> Vec<u8> {
+        let mut data = vec![];
+        dmg.save_state().write(&mut data).unwrap();
+        data
+    }
+
+    fn counting_dmg<'a>() -> DMG<'a> {
+        // LD HL,C000; LD (HL),A; INC A; JR -4
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x21, 0x00, 0xC0, 0x77, 0x3C, 0x18, 0xFC], vec![])))
+    }
+
+    #[test]
+    fn restore_resumes_identically() {
+        let mut dmg = counting_dmg();
+        for _ in 0..100 { dmg.step().unwrap(); }
+        let saved = serialized(&dmg);
+        for _ in 0..500 { dmg.step().unwrap(); }
+        let expected = serialized(&dmg);
+
+        let mut restored = counting_dmg();
+        restored.load_state(SaveState::read(&saved[..]).unwrap()).unwrap();
+        assert_eq!(serialized(&restored), saved);
+        for _ in 0..500 { restored.step().unwrap(); }
+        assert_eq!(serialized(&restored), expected);
+        assert_eq!(restored.cpu.bus.peek(0xC000), dmg.cpu.bus.peek(0xC000));
+    }
+
+    #[test]
+    fn keeps_cartridge_ram_and_clock() {
+        // ROM+MBC3+TIMER+RAM+BATT with 8 KiB of RAM, the counting program runs from the boot ROM
+        let mut rom = vec![0; 2 * 0x4000];
+        rom[0x0147] = 0x10;
+        rom[0x0149] = 0x02;
+        let mbc3_dmg = || {
+            let mut dmg = counting_dmg();
+            dmg.cpu.bus.cartridge = Cartridge::from_data(rom.clone()).unwrap();
+            dmg
+        };
+        let mut dmg = mbc3_dmg();
+        let bus = &mut dmg.cpu.bus;
+        bus.write(0x0000, 0x0A);
+        bus.write(0xA123, 0x56);
+        bus.write(0x4000, 0x09);
+        bus.write(0xA000, 42);
+        let saved = serialized(&dmg);
+        assert!(dmg.save_state().layout().contains(&Component { name: "cartridge RAM".to_string(), size: 0x2000 }));
+
+        let mut restored = mbc3_dmg();
+        restored.load_state(SaveState::read(&saved[..]).unwrap()).unwrap();
+        let bus = &mut restored.cpu.bus;
+        bus.write(0x6000, 0);
+        bus.write(0x6000, 1);
+        assert_eq!(bus.read(0xA000), 42);
+        bus.write(0x4000, 0);
+        assert_eq!(bus.read(0xA123), 0x56);
+    }
+
+    #[test]
+    fn rejects_other_cartridge() {
+        let mut dmg = counting_dmg();
+        let mut state = dmg.save_state();
+        state.cartridge = "TETRIS".to_string();
+        assert!(dmg.load_state(state).is_err());
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let dmg = counting_dmg();
+        let version_1 = serde_json::to_vec(&dmg.save_state()).unwrap();
+        let state = SaveState::read(&version_1[..]).unwrap();
+        assert_eq!(state.cpu, dmg.save_state().cpu);
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let data = String::from_utf8(serialized(&counting_dmg())).unwrap().replacen("\"version\":2", "\"version\":99", 1);
+        let error = SaveState::read(data.as_bytes()).err().unwrap();
+        assert_eq!(error.to_string(), "Save state format version 99 is not supported, this build reads versions 1 to 2");
+    }
+
+    #[test]
+    fn layout_mismatch() {
+        let mut layout = counting_dmg().save_state().layout();
+        let expected = layout.clone();
+        assert_eq!(check_layout(&layout, &expected), Ok(()));
+        layout[0].size = 0x1000;
+        assert_eq!(check_layout(&layout, &expected), Err("Save state has 4096 bytes of work RAM, expected 8192".to_string()));
+        layout.remove(0);
+        assert_eq!(check_layout(&layout, &expected), Err("Save state has no work RAM".to_string()));
+    }
+
+    #[test]
+    fn slot_names() {
+        assert_eq!(slot_file_name("TETRIS\0\0\0", 0x6E1D, 1), "TETRIS-6E1D.ss1");
+        assert_eq!(slot_file_name("POKEMON RED", 0x91E6, 0), "POKEMON_RED-91E6.ss0");
+        assert_eq!(slot_file_name("", 0, 3), "untitled-0000.ss3");
+    }
+
+    #[test]
+    fn slots_round_trip() {
+        let directory = std::env::temp_dir().join(format!("rustdmg-slots-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut dmg = counting_dmg();
+        dmg.set_state_directory(&directory);
+        for _ in 0..10 { dmg.step().unwrap(); }
+        let path = dmg.save_state_slot(2).unwrap();
+        assert_eq!(path, directory.join("untitled-0000.ss2"));
+        let saved_pc = dmg.cpu.program_counter.read();
+        for _ in 0..10 { dmg.step().unwrap(); }
+        dmg.load_state_slot(2).unwrap();
+        assert_eq!(dmg.cpu.program_counter.read(), saved_pc);
+        assert_eq!(dmg.load_state_slot(3).unwrap_err().kind(), io::ErrorKind::NotFound);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(SaveState::read(&b"{\"cpu\": 3}"[..]).err().unwrap().kind(), io::ErrorKind::InvalidData);
+    }
+}
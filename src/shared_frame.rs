@@ -0,0 +1,88 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{fence, Ordering};
+
+use memmap2::MmapMut;
+
+use crate::dmg::DMG;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+// Layout of the region, little endian:
+//   0  magic "RDMG"
+//   4  layout version
+//   5  held buttons, bits as in input::Button
+//   6  screen width
+//   8  screen height
+//  10  reserved
+//  16  sequence number, odd while a frame is being written
+//  24  frame count
+//  32  one shade (0 lightest to 3 darkest) per pixel, row by row
+// Readers copy the frame when the sequence is even and check it did not change meanwhile.
+pub const MAGIC: &[u8; 4] = b"RDMG";
+pub const VERSION: u8 = 1;
+pub const HEADER_SIZE: usize = 32;
+pub const REGION_SIZE: usize = HEADER_SIZE + SCREEN_WIDTH * SCREEN_HEIGHT;
+const BUTTONS_OFFSET: usize = 5;
+const SEQUENCE_OFFSET: usize = 16;
+const FRAME_COUNT_OFFSET: usize = 24;
+
+pub struct SharedFrame {
+    map: MmapMut,
+    sequence: u64,
+}
+
+impl SharedFrame {
+    // Creates or truncates the file, on Linux a path under /dev/shm keeps it out of the disk
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<SharedFrame> {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(REGION_SIZE as u64)?;
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        map[0..4].copy_from_slice(MAGIC);
+        map[4] = VERSION;
+        map[6..8].copy_from_slice(&(SCREEN_WIDTH as u16).to_le_bytes());
+        map[8..10].copy_from_slice(&(SCREEN_HEIGHT as u16).to_le_bytes());
+        Ok(SharedFrame { map, sequence: 0 })
+    }
+
+    pub fn publish(&mut self, dmg: &DMG) {
+        self.write_sequence(self.sequence + 1);
+        fence(Ordering::Release);
+        self.map[BUTTONS_OFFSET] = dmg.buttons().bits();
+        self.map[FRAME_COUNT_OFFSET..HEADER_SIZE].copy_from_slice(&dmg.frame_count().to_le_bytes());
+        self.map[HEADER_SIZE..].copy_from_slice(dmg.framebuffer());
+        fence(Ordering::Release);
+        self.write_sequence(self.sequence + 2);
+        self.sequence += 2;
+    }
+
+    fn write_sequence(&mut self, sequence: u64) {
+        self.map[SEQUENCE_OFFSET..FRAME_COUNT_OFFSET].copy_from_slice(&sequence.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use crate::input::Buttons;
+
+    #[test]
+    fn publish_frames() {
+        let path = env::temp_dir().join(format!("rustdmg-shared-frame-{}", std::process::id()));
+        let mut dmg = DMG::new_from_data(vec![0; 0x100], &[0; 0x8000]).unwrap();
+        let mut shared_frame = SharedFrame::create(&path).unwrap();
+        dmg.set_buttons(Buttons::A | Buttons::START);
+        shared_frame.publish(&dmg);
+        shared_frame.publish(&dmg);
+
+        let region = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(region.len(), REGION_SIZE);
+        assert_eq!(&region[0..5], b"RDMG\x01");
+        assert_eq!(region[BUTTONS_OFFSET], (Buttons::A | Buttons::START).bits());
+        assert_eq!(&region[6..10], &[160, 0, 144, 0]);
+        assert_eq!(region[SEQUENCE_OFFSET], 4);
+    }
+}
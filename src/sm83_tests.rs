@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::batch::panic_message;
+use crate::bus::{Bus, BusObserver};
+use crate::cpu::{CPU, CpuState};
+
+// Runs the community single step tests for the SM83 (github.com/SingleStepTests/sm83): one JSON
+// file per opcode, each holding cases with the CPU and memory before and after one instruction
+// and the bus activity of every machine cycle.
+//
+// The tests model the SM83 fetching the next opcode during the last cycle of an instruction. The
+// initial PC is one past the opcode and the final PC one past the next one, while this CPU fetches
+// the opcode when the instruction starts. The PC is adjusted by one on both ends, and the opcode
+// fetch and the final prefetch are left out of the bus activity comparison.
+
+#[derive(Deserialize)]
+struct MachineState {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    #[serde(default)]
+    ime: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+#[derive(Deserialize)]
+struct TestCase {
+    name: String,
+    initial: MachineState,
+    #[serde(rename = "final")]
+    expected: MachineState,
+    cycles: Vec<Value>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct BusAccess {
+    address: u16,
+    value: u8,
+    write: bool,
+}
+
+#[derive(Default)]
+struct AccessRecorder {
+    accesses: Vec<BusAccess>,
+}
+
+impl BusObserver for AccessRecorder {
+    fn on_read(&mut self, address: u16, value: u8) {
+        self.accesses.push(BusAccess { address, value, write: false });
+    }
+    fn on_write(&mut self, address: u16, _old_value: u8, new_value: u8) {
+        self.accesses.push(BusAccess { address, value: new_value, write: true });
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OpcodeResult {
+    Passed(usize),
+    // Cases run, cases failed and the first failure
+    Failed(usize, usize, String),
+    Unimplemented,
+}
+
+pub struct FileResult {
+    pub path: PathBuf,
+    pub result: OpcodeResult,
+}
+
+fn cpu_state(state: &MachineState) -> CpuState {
+    CpuState {
+        af: u16::from_be_bytes([state.a, state.f]),
+        bc: u16::from_be_bytes([state.b, state.c]),
+        de: u16::from_be_bytes([state.d, state.e]),
+        hl: u16::from_be_bytes([state.h, state.l]),
+        sp: state.sp,
+        pc: state.pc.wrapping_sub(1),
+        interrupts_enabled: state.ime != 0,
+        cycle_count: 0,
+        instruction_count: 0,
+        halted: false,
+        halt_bug: false,
+        stopped: false,
+        locked: false,
+        enabling_interrupts: false,
+    }
+}
+
+// Cycle entries look like [address, value, "r-m"], with "-wm" for writes and null for idle cycles
+fn expected_accesses(cycles: &[Value]) -> Vec<BusAccess> {
+    cycles.iter().filter_map(|cycle| {
+        let address = cycle.get(0)?.as_u64()? as u16;
+        let value = cycle.get(1)?.as_u64()? as u8;
+        let kind = cycle.get(2)?.as_str()?;
+        match (kind.starts_with('r'), kind.chars().nth(1) == Some('w')) {
+            (true, _) => Some(BusAccess { address, value, write: false }),
+            (_, true) => Some(BusAccess { address, value, write: true }),
+            _ => None,
+        }
+    }).collect()
+}
+
+fn run_case(case: &TestCase) -> Result<(), String> {
+    let mut memory = vec![0; 0x10000];
+    for (address, value) in &case.initial.ram { memory[*address as usize] = *value; }
+    let mut cpu = CPU::new(Bus::new_flat(memory));
+    cpu.restore_state(&cpu_state(&case.initial));
+    let recorder = Rc::new(RefCell::new(AccessRecorder::default()));
+    cpu.bus.add_observer(recorder.clone());
+
+    cpu.step().map_err(|error| format!("{}: {}", case.name, error))?;
+
+    let mut errors = vec![];
+    let expected = cpu_state(&case.expected);
+    let actual = cpu.save_state();
+    let registers = [("AF", actual.af, expected.af), ("BC", actual.bc, expected.bc), ("DE", actual.de, expected.de),
+                     ("HL", actual.hl, expected.hl), ("SP", actual.sp, expected.sp), ("PC", actual.pc, expected.pc)];
+    for (name, actual, expected) in registers.iter() {
+        if actual != expected { errors.push(format!("{} {:04X} != {:04X}", name, actual, expected)); }
+    }
+    if actual.interrupts_enabled != expected.interrupts_enabled {
+        errors.push(format!("IME {} != {}", actual.interrupts_enabled, expected.interrupts_enabled));
+    }
+    for (address, value) in &case.expected.ram {
+        let actual = cpu.bus.peek(*address);
+        if actual != *value { errors.push(format!("[{:04X}] {:02X} != {:02X}", address, actual, value)); }
+    }
+    let expected_cycles = case.cycles.len() as u64 * 4;
+    if actual.cycle_count != expected_cycles {
+        errors.push(format!("{} cycles != {}", actual.cycle_count, expected_cycles));
+    }
+    let actual_accesses = recorder.borrow().accesses.iter().skip(1).copied().collect::<Vec<_>>();
+    let mut expected_accesses = expected_accesses(&case.cycles);
+    expected_accesses.pop();
+    if actual_accesses != expected_accesses {
+        errors.push(format!("bus activity {:?} != {:?}", actual_accesses, expected_accesses));
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(format!("{}: {}", case.name, errors.join(", "))) }
+}
+
+fn run_cases(cases: &[TestCase]) -> OpcodeResult {
+    let mut failed = 0;
+    let mut first_failure = None;
+    for case in cases {
+        let outcome = panic::catch_unwind(panic::AssertUnwindSafe(|| run_case(case)))
+            .unwrap_or_else(|payload| Err(format!("{}: {}", case.name, panic_message(payload))));
+        if let Err(message) = outcome {
+            if message.ends_with("is not implemented") { return OpcodeResult::Unimplemented; }
+            failed += 1;
+            first_failure.get_or_insert(message);
+        }
+    }
+    match first_failure {
+        None => OpcodeResult::Passed(cases.len()),
+        Some(message) => OpcodeResult::Failed(cases.len(), failed, message),
+    }
+}
+
+pub fn run_file(path: &Path) -> io::Result<OpcodeResult> {
+    let cases: Vec<TestCase> = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), error)))?;
+    Ok(run_cases(&cases))
+}
+
+pub fn run_directory(directory: &Path) -> io::Result<Vec<FileResult>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    paths.sort();
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results = paths.into_iter()
+        .map(|path| run_file(&path).map(|result| FileResult { path, result }))
+        .collect();
+    panic::set_hook(default_hook);
+    results
+}
+
+pub fn print_report(results: &[FileResult]) {
+    println!();
+    println!("==============");
+    println!("SM83 single step tests");
+    for file in results {
+        let name = file.path.file_stem().unwrap_or_default().to_string_lossy();
+        match &file.result {
+            OpcodeResult::Passed(cases) => println!("PASS {:<6} {} cases", name, cases),
+            OpcodeResult::Failed(cases, failed, first) => println!("FAIL {:<6} {} of {} cases failed, first {}", name, failed, cases, first),
+            OpcodeResult::Unimplemented => println!("---- {:<6} not implemented", name),
+        }
+    }
+    let passed = results.iter().filter(|file| matches!(file.result, OpcodeResult::Passed(_))).count();
+    let unimplemented = results.iter().filter(|file| file.result == OpcodeResult::Unimplemented).count();
+    println!("{} opcodes passed, {} failed, {} not implemented", passed, results.len() - passed - unimplemented, unimplemented);
+    println!("==============");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cases(json: &str) -> Vec<TestCase> {
+        serde_json::from_str(json).unwrap()
+    }
+
+    const LD_B_D8: &str = r#"[{
+        "name": "06 0000",
+        "initial": {"pc": 49153, "sp": 0, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0,
+                    "ime": 0, "ram": [[49152, 6], [49153, 66]]},
+        "final": {"pc": 49155, "sp": 0, "a": 0, "b": 66, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0,
+                  "ime": 0, "ram": [[49152, 6], [49153, 66]]},
+        "cycles": [[49153, 66, "r-m"], [49154, 0, "r-m"]]
+    }]"#;
+
+    #[test]
+    fn passing_case() {
+        assert_eq!(run_cases(&cases(LD_B_D8)), OpcodeResult::Passed(1));
+    }
+
+    #[test]
+    fn failing_case() {
+        let json = LD_B_D8.replace("\"b\": 66", "\"b\": 67");
+        match run_cases(&cases(&json)) {
+            OpcodeResult::Failed(1, 1, message) => assert_eq!(message, "06 0000: BC 4200 != 4300"),
+            other => panic!("Unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cycle_entries() {
+        let cycles: Vec<Value> = serde_json::from_str(r#"[[1, 2, "r-m"], null, [3, 4, "-wm"], [5, 6, "---"]]"#).unwrap();
+        assert_eq!(expected_accesses(&cycles), vec![BusAccess { address: 1, value: 2, write: false },
+                                                     BusAccess { address: 3, value: 4, write: true }]);
+    }
+}
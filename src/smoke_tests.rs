@@ -0,0 +1,162 @@
+// Fast end-to-end checks: two tiny homebrew ROMs written for rustdmg (public domain, like the rest
+// of this file) boot through a minimal boot ROM and run headlessly for a fixed number of frames.
+// Emulation is deterministic, so the frame hash and serial output must come out the same every time.
+// A changed hash is not necessarily a bug, but it has to be explained by the change that caused it.
+//
+// The ignored homebrew_smoke does the same with freely licensed games from the homebrew directory of
+// $RUSTDMG_TEST_ROMS, which are not distributed with rustdmg. Their hashes are kept in
+// golden/smoke_hashes.txt, run with RUSTDMG_UPDATE_GOLDEN=1 to record them. Without a recorded hash
+// the test fails instead of passing on a game it did not check.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::bus::cartridge::Cartridge;
+use crate::dmg::DMG;
+use crate::golden::{golden_directory, UPDATE_GOLDEN_ENV};
+use crate::test_roms::{SerialCapture, TEST_ROMS_ENV};
+
+const BOOT_ROM_SIZE: usize = 256;
+const ROM_SIZE: usize = 0x8000;
+const ENTRY_POINT: usize = 0x0100;
+const PROGRAM_START: usize = 0x0150;
+const TITLE_ADDRESS: usize = 0x0134;
+const SMOKE_FRAMES: u64 = 60;
+const SMOKE_HASHES_FILE: &str = "smoke_hashes.txt";
+
+// 2048-gb by Sanqui (zlib license), as <name>.gb. It is a 32 KiB ROM only cartridge that does not
+// send anything through the serial port.
+const HOMEBREW_ROMS: [&str; 1] = ["2048"];
+
+// Unmaps itself at the end, so the CPU continues at 0x0100 like after the real boot ROM
+fn boot_rom() -> Vec<u8> {
+    let mut boot_rom = vec![0x00; BOOT_ROM_SIZE];
+    // LD A,1; LDH (50),A
+    boot_rom[BOOT_ROM_SIZE - 4..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+    boot_rom
+}
+
+fn cartridge(title: &str, program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x00; ROM_SIZE];
+    // NOP; JR to the program right after the header
+    rom[ENTRY_POINT..ENTRY_POINT + 3].copy_from_slice(&[0x00, 0x18, (PROGRAM_START - ENTRY_POINT - 3) as u8]);
+    rom[TITLE_ADDRESS..TITLE_ADDRESS + title.len()].copy_from_slice(title.as_bytes());
+    rom[PROGRAM_START..PROGRAM_START + program.len()].copy_from_slice(program);
+    rom
+}
+
+// Sends a text through the serial port, then spins
+fn serial_hello() -> Vec<u8> {
+    let mut program = vec![];
+    for character in b"Hello from rustdmg\n" {
+        // LD A,character; LDH (01),A; LD A,81; LDH (02),A
+        program.extend_from_slice(&[0x3E, *character, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02]);
+    }
+    // JR -2
+    program.extend_from_slice(&[0x18, 0xFE]);
+    cartridge("SERIALHELLO", &program)
+}
+
+// Turns the LCD on and keeps writing a counter over the first 256 bytes of tile data
+fn vram_counter() -> Vec<u8> {
+    // LD A,91; LDH (40),A; LD A,FC; LDH (47),A; LD HL,8000; LD (HL),A; INC A; INC L; JR -5
+    cartridge("VRAMCOUNTER", &[0x3E, 0x91, 0xE0, 0x40, 0x3E, 0xFC, 0xE0, 0x47, 0x21, 0x00, 0x80, 0x77, 0x3C, 0x2C, 0x18, 0xFB])
+}
+
+fn homebrew_rom(name: &str) -> PathBuf {
+    let directory = env::var(TEST_ROMS_ENV).expect("$RUSTDMG_TEST_ROMS is not set");
+    Path::new(&directory).join("homebrew").join(format!("{}.gb", name))
+}
+
+// One "<name> <hash>" per line, the hash in hexadecimal
+fn load_smoke_hashes(path: &Path) -> BTreeMap<String, u64> {
+    let contents = fs::read_to_string(path).unwrap_or_default();
+    contents.lines().filter_map(|line| {
+        let (name, hash) = line.split_once(' ')?;
+        Some((name.to_string(), u64::from_str_radix(hash.trim(), 16).ok()?))
+    }).collect()
+}
+
+fn write_smoke_hashes(path: &Path, hashes: &BTreeMap<String, u64>) {
+    let contents: String = hashes.iter().map(|(name, hash)| format!("{} {:016X}\n", name, hash)).collect();
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn run(rom: &[u8]) -> (DMG<'static>, String) {
+    run_with(rom, false)
+}
+
+fn run_with(rom: &[u8], cached_interpreter: bool) -> (DMG<'static>, String) {
+    let mut dmg = DMG::new_from_data(boot_rom(), rom).unwrap();
+    if cached_interpreter { dmg.enable_cached_interpreter(); }
+    let serial = Rc::new(RefCell::new(SerialCapture::default()));
+    dmg.cpu.bus.add_observer(serial.clone());
+    for _ in 0..SMOKE_FRAMES { dmg.run_frame().unwrap(); }
+    let output = serial.borrow().output.clone();
+    (dmg, output)
+}
+
+#[test]
+fn serial_hello_smoke() {
+    let (dmg, output) = run(&serial_hello());
+    assert_eq!(output, "Hello from rustdmg\n");
+    assert_eq!(dmg.frame_count(), SMOKE_FRAMES);
+    // The LCD stays off, so every frame is blank
+    assert_eq!(dmg.frame_hash(), 0xECA4_7F65_4990_2B25);
+}
+
+#[test]
+fn vram_counter_smoke() {
+    let (dmg, output) = run(&vram_counter());
+    assert_eq!(output, "");
+    assert_eq!(dmg.frame_hash(), 0x9F01_8926_05F0_D205);
+}
+
+#[test]
+fn cached_interpreter_smoke() {
+    for rom in [serial_hello(), vram_counter()] {
+        let (plain, plain_output) = run(&rom);
+        let (cached, cached_output) = run_with(&rom, true);
+        assert_eq!(cached_output, plain_output);
+        assert_eq!(cached.frame_hash(), plain.frame_hash());
+        assert_eq!(cached.cpu.save_state(), plain.cpu.save_state());
+    }
+}
+
+#[test]
+fn smoke_hashes_round_trip() {
+    let path = env::temp_dir().join(format!("rustdmg-smoke-{}", std::process::id())).join(SMOKE_HASHES_FILE);
+    let hashes: BTreeMap<String, u64> = vec![("2048".to_string(), 0x0123_4567_89AB_CDEF), ("game".to_string(), 1)].into_iter().collect();
+    write_smoke_hashes(&path, &hashes);
+    assert_eq!(load_smoke_hashes(&path), hashes);
+    fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+#[ignore]
+fn homebrew_smoke() {
+    let hashes_path = golden_directory().join(SMOKE_HASHES_FILE);
+    let mut hashes = load_smoke_hashes(&hashes_path);
+    let update = env::var_os(UPDATE_GOLDEN_ENV).is_some();
+    let missing: Vec<&str> = HOMEBREW_ROMS.iter().copied().filter(|name| !hashes.contains_key(*name)).collect();
+    assert!(update || missing.is_empty(), "No hash for {} in {}, nothing was checked. Record them with {}=1",
+            missing.join(", "), hashes_path.display(), UPDATE_GOLDEN_ENV);
+    for name in HOMEBREW_ROMS {
+        let rom = fs::read(homebrew_rom(name)).unwrap_or_else(|error| panic!("{}.gb: {}", name, error));
+        let cartridge_type = Cartridge::from_data(rom.clone()).unwrap_or_else(|error| panic!("{}.gb: {}", name, error)).header().cartridge_type;
+        assert_eq!(cartridge_type, "ROM only", "{}.gb is not the ROM only cartridge the test is meant for", name);
+        let (dmg, output) = run(&rom);
+        assert_eq!(output, "", "{} sent something through the serial port", name);
+        if update {
+            hashes.insert(name.to_string(), dmg.frame_hash());
+            continue;
+        }
+        assert_eq!(dmg.frame_hash(), hashes[name], "{}", name);
+    }
+    if update { write_smoke_hashes(&hashes_path, &hashes); }
+}
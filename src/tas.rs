@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+
+use crate::dmg::{DMG, StopReason};
+use crate::error::EmulationError;
+use crate::input::Buttons;
+use crate::movie::Movie;
+use crate::savestate::Snapshot;
+
+struct Slot {
+    snapshot: Snapshot,
+    input_log: Vec<Buttons>,
+}
+
+// Tool-assisted recording: the game advances one frame at a time with chosen buttons, and
+// loading a slot rewinds the movie to the input that led to it, so recording continues from there.
+pub struct TasSession {
+    movie: Movie,
+    slots: BTreeMap<u8, Slot>,
+}
+
+impl TasSession {
+    pub fn new(dmg: &DMG) -> TasSession {
+        TasSession { movie: Movie::start_recording(dmg), slots: BTreeMap::new() }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn into_movie(self) -> Movie {
+        self.movie
+    }
+
+    pub fn frame_advance(&mut self, dmg: &mut DMG, buttons: Buttons) -> Result<StopReason, EmulationError> {
+        self.movie.record_frame(dmg, buttons)
+    }
+
+    pub fn save_slot(&mut self, dmg: &DMG, slot: u8) {
+        self.slots.insert(slot, Slot { snapshot: dmg.snapshot(), input_log: self.movie.input_log() });
+    }
+
+    // Returns the movie length after truncating to the slot
+    pub fn load_slot(&mut self, dmg: &mut DMG, slot: u8) -> Result<usize, String> {
+        let saved = self.slots.get(&slot).ok_or_else(|| format!("Slot {} is empty", slot))?;
+        dmg.restore(&saved.snapshot);
+        self.movie.set_input_log(&saved.input_log);
+        self.movie.rerecords += 1;
+        Ok(self.movie.len())
+    }
+}
+
+pub fn parse_buttons(text: &str) -> Result<Buttons, String> {
+    let mut buttons = Buttons::empty();
+    for name in text.split('+').filter(|name| !name.is_empty() && *name != "-") {
+        buttons |= match name.to_ascii_uppercase().as_str() {
+            "RIGHT" => Buttons::RIGHT,
+            "LEFT" => Buttons::LEFT,
+            "UP" => Buttons::UP,
+            "DOWN" => Buttons::DOWN,
+            "A" => Buttons::A,
+            "B" => Buttons::B,
+            "SELECT" => Buttons::SELECT,
+            "START" => Buttons::START,
+            _ => return Err(format!("Unknown button: {}", name)),
+        };
+    }
+    Ok(buttons)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+
+    fn counting_dmg<'a>() -> DMG<'a> {
+        // LD HL,C000; LD (HL),A; INC A; JR -4
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![0x21, 0x00, 0xC0, 0x77, 0x3C, 0x18, 0xFC], vec![])))
+    }
+
+    #[test]
+    fn loading_a_slot_truncates_the_movie() {
+        let mut dmg = counting_dmg();
+        let mut session = TasSession::new(&dmg);
+        session.frame_advance(&mut dmg, Buttons::A).unwrap();
+        session.save_slot(&dmg, 1);
+        session.frame_advance(&mut dmg, Buttons::B).unwrap();
+        session.frame_advance(&mut dmg, Buttons::B).unwrap();
+        assert_eq!(session.load_slot(&mut dmg, 1), Ok(1));
+        assert_eq!(dmg.frame_count(), 1);
+        session.frame_advance(&mut dmg, Buttons::START).unwrap();
+        assert!(session.load_slot(&mut dmg, 2).is_err());
+
+        let movie = session.into_movie();
+        assert_eq!(movie.input_log(), vec![Buttons::A, Buttons::START]);
+        assert_eq!(movie.rerecords, 1);
+        let final_state = dmg.cpu.save_state();
+        let mut replay = counting_dmg();
+        movie.play(&mut replay).unwrap();
+        assert_eq!(replay.cpu.save_state(), final_state);
+    }
+
+    #[test]
+    fn button_names() {
+        assert_eq!(parse_buttons("a+Start"), Ok(Buttons::A | Buttons::START));
+        assert_eq!(parse_buttons("-"), Ok(Buttons::empty()));
+        assert!(parse_buttons("A+Z").is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use std::io;
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use crate::batch::{find_roms, panic_message};
+use crate::bus::BusObserver;
+use crate::cpu::register::DMGRegister;
+use crate::dmg::DMG;
+use crate::watchdog::{Watchdog, WatchdogError};
+
+// The suites are not distributed with rustdmg, the ignored tests look for them in this directory
+pub const TEST_ROMS_ENV: &str = "RUSTDMG_TEST_ROMS";
+
+const SERIAL_DATA: u16 = 0xFF01;
+const SERIAL_CONTROL: u16 = 0xFF02;
+// Internal clock with the transfer start bit, what Blargg's ROMs write to send a byte
+const SERIAL_START_TRANSFER: u8 = 0x81;
+
+// Mooneye's ROMs execute LD B,B once done, with these in B, C, D, E, H and L when they passed
+const MOONEYE_BREAKPOINT_OPCODE: u8 = 0x40;
+const MOONEYE_PASS_FINGERPRINT: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAIL_VALUE: u8 = 0x42;
+
+#[derive(Debug, PartialEq)]
+pub enum TestRomOutcome {
+    Passed,
+    Failed(String),
+    TimedOut(String),
+    LoadFailed(String),
+    Crashed(String),
+    // No serial output or frame change within the watchdog budget, with the diagnostic
+    Hung(String),
+}
+
+// Collects the text Blargg's test ROMs print through the serial port
+#[derive(Default)]
+pub struct SerialCapture {
+    pending: u8,
+    pub output: String,
+}
+
+impl BusObserver for SerialCapture {
+    fn on_write(&mut self, address: u16, _old_value: u8, new_value: u8) {
+        match address {
+            SERIAL_DATA => self.pending = new_value,
+            SERIAL_CONTROL if new_value == SERIAL_START_TRANSFER => self.output.push(self.pending as char),
+            _ => {}
+        }
+    }
+}
+
+fn blargg_verdict(output: &str) -> Option<TestRomOutcome> {
+    if output.contains("Passed") {
+        Some(TestRomOutcome::Passed)
+    } else if output.contains("Failed") {
+        Some(TestRomOutcome::Failed(output.trim().to_string()))
+    } else {
+        None
+    }
+}
+
+// Runs one of Blargg's ROMs until it prints its verdict or the frames run out
+pub fn run_blargg(rom_path: &Path, max_frames: u64, watchdog_frames: Option<u64>) -> TestRomOutcome {
+    let mut dmg = match DMG::new(&rom_path.to_string_lossy()) {
+        Ok(dmg) => dmg,
+        Err(error) => return TestRomOutcome::LoadFailed(error.to_string()),
+    };
+    let mut watchdog = watchdog_frames.map(|budget_frames| Watchdog::new(&mut dmg, budget_frames));
+    let run_result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        for _frame in 0..max_frames {
+            match &mut watchdog {
+                Some(watchdog) => match watchdog.run_frame(&mut dmg) {
+                    Err(WatchdogError::Hang(hang)) => return Some(TestRomOutcome::Hung(hang.to_string())),
+                    Err(WatchdogError::Emulation(error)) => return Some(TestRomOutcome::Crashed(error.to_string())),
+                    Ok(()) => {}
+                },
+                None => if let Err(error) = dmg.run_frame() { return Some(TestRomOutcome::Crashed(error.to_string())); },
+            }
+            if let Some(verdict) = blargg_verdict(&String::from_utf8_lossy(dmg.serial_output())) { return Some(verdict); }
+        }
+        None
+    }));
+    let output = String::from_utf8_lossy(dmg.serial_output()).trim().to_string();
+    match run_result {
+        Ok(Some(verdict)) => verdict,
+        Ok(None) => TestRomOutcome::TimedOut(output),
+        Err(payload) => TestRomOutcome::Crashed(panic_message(payload)),
+    }
+}
+
+fn mooneye_verdict(registers: [u8; 6]) -> TestRomOutcome {
+    if registers == MOONEYE_PASS_FINGERPRINT {
+        TestRomOutcome::Passed
+    } else if registers.iter().all(|register| *register == MOONEYE_FAIL_VALUE) {
+        TestRomOutcome::Failed("Test failed".to_string())
+    } else {
+        TestRomOutcome::Failed(format!("Unexpected registers B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X}",
+                                       registers[0], registers[1], registers[2], registers[3], registers[4], registers[5]))
+    }
+}
+
+// Runs one of Mooneye's ROMs until it reaches its LD B,B breakpoint or the frames run out
+pub fn run_mooneye(rom_path: &Path, max_frames: u64, watchdog_frames: Option<u64>) -> TestRomOutcome {
+    let mut dmg = match DMG::new(&rom_path.to_string_lossy()) {
+        Ok(dmg) => dmg,
+        Err(error) => return TestRomOutcome::LoadFailed(error.to_string()),
+    };
+    let mut watchdog = watchdog_frames.map(|budget_frames| Watchdog::new(&mut dmg, budget_frames));
+
+    let run_result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        while dmg.frame_count() < max_frames {
+            let pc = dmg.cpu.program_counter.read();
+            let at_breakpoint = !dmg.cpu.bus.boot_rom_active && dmg.cpu.bus.peek(pc) == MOONEYE_BREAKPOINT_OPCODE;
+            let frame = dmg.frame_count();
+            match &mut watchdog {
+                Some(watchdog) => {
+                    if let Err(error) = watchdog.step(&mut dmg) { return Some(TestRomOutcome::Crashed(error.to_string())); }
+                    if dmg.frame_count() != frame {
+                        if let Err(hang) = watchdog.check(&dmg) { return Some(TestRomOutcome::Hung(hang.to_string())); }
+                    }
+                }
+                None => if let Err(error) = dmg.step() { return Some(TestRomOutcome::Crashed(error.to_string())); },
+            }
+            if at_breakpoint {
+                let cpu = &dmg.cpu;
+                return Some(mooneye_verdict([cpu.reg_bc.read_higher(), cpu.reg_bc.read_lower(),
+                                             cpu.reg_de.read_higher(), cpu.reg_de.read_lower(),
+                                             cpu.reg_hl.read_higher(), cpu.reg_hl.read_lower()]));
+            }
+        }
+        None
+    }));
+    match run_result {
+        Ok(Some(verdict)) => verdict,
+        Ok(None) => TestRomOutcome::TimedOut(String::new()),
+        Err(payload) => TestRomOutcome::Crashed(panic_message(payload)),
+    }
+}
+
+// The acceptance suite is split into subdirectories, every ROM below the given one is run
+pub fn run_mooneye_directory(directory: &Path, max_frames: u64, watchdog_frames: Option<u64>) -> io::Result<Vec<(PathBuf, TestRomOutcome)>> {
+    let mut roms = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        roms.extend(find_roms(&directory)?);
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() { directories.push(path); }
+        }
+    }
+    roms.sort();
+
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results = roms.into_iter().map(|rom| {
+        let outcome = run_mooneye(&rom, max_frames, watchdog_frames);
+        (rom, outcome)
+    }).collect();
+    panic::set_hook(default_hook);
+    Ok(results)
+}
+
+pub fn print_report(root: &Path, results: &[(PathBuf, TestRomOutcome)]) {
+    println!();
+    println!("==============");
+    println!("Test ROM results");
+    for (rom, outcome) in results {
+        let name = rom.strip_prefix(root).unwrap_or(rom).to_string_lossy();
+        match outcome {
+            TestRomOutcome::Passed => println!("PASS {}", name),
+            TestRomOutcome::Failed(message) => println!("FAIL {}: {}", name, message),
+            TestRomOutcome::TimedOut(_) => println!("FAIL {}: timed out", name),
+            TestRomOutcome::LoadFailed(message) => println!("FAIL {}: cannot load: {}", name, message),
+            TestRomOutcome::Crashed(message) => println!("FAIL {}: crashed: {}", name, message),
+            TestRomOutcome::Hung(report) => println!("FAIL {}: hung: {}", name, report.lines().next().unwrap_or_default()),
+        }
+    }
+    let passed = results.iter().filter(|(_, outcome)| *outcome == TestRomOutcome::Passed).count();
+    println!("{} of {} passed", passed, results.len());
+    println!("==============");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+    use crate::golden::load_shades;
+    use crate::watchdog::DEFAULT_BUDGET_FRAMES;
+
+    const BLARGG_TIMING_FRAMES: u64 = 60 * 30;
+    const MOONEYE_FRAMES: u64 = 60 * 20;
+    // Mooneye's ROMs report through registers only, most finish within a second
+    const MOONEYE_WATCHDOG_FRAMES: u64 = 60 * 5;
+    // dmg-acid2 draws its face once and then keeps it still
+    const ACID2_FRAMES: u64 = 60 * 3;
+
+    fn suite_path(relative_path: &str) -> Option<PathBuf> {
+        let path = Path::new(&env::var(TEST_ROMS_ENV).ok()?).join(relative_path);
+        if path.exists() { Some(path) } else { None }
+    }
+
+    #[test]
+    fn serial_capture() {
+        let mut capture = SerialCapture::default();
+        for byte in b"Passed" {
+            capture.on_write(SERIAL_DATA, 0, *byte);
+            capture.on_write(SERIAL_CONTROL, 0, SERIAL_START_TRANSFER);
+        }
+        capture.on_write(SERIAL_CONTROL, 0, 0x01);
+        assert_eq!(capture.output, "Passed");
+        assert_eq!(blargg_verdict(&capture.output), Some(TestRomOutcome::Passed));
+        assert_eq!(blargg_verdict("instr_timing\n\nFailed #255"),
+                   Some(TestRomOutcome::Failed("instr_timing\n\nFailed #255".to_string())));
+        assert_eq!(blargg_verdict("instr_timing"), None);
+    }
+
+    #[test]
+    fn mooneye_fingerprint() {
+        assert_eq!(mooneye_verdict([3, 5, 8, 13, 21, 34]), TestRomOutcome::Passed);
+        assert_eq!(mooneye_verdict([0x42; 6]), TestRomOutcome::Failed("Test failed".to_string()));
+        assert!(matches!(mooneye_verdict([0; 6]), TestRomOutcome::Failed(_)));
+    }
+
+    // Needs the timer and cycle accurate memory accesses, run with --ignored once they exist
+    #[test]
+    #[ignore]
+    fn blargg_instr_timing() {
+        let rom = suite_path("instr_timing/instr_timing.gb").expect("instr_timing.gb not found in $RUSTDMG_TEST_ROMS");
+        assert_eq!(run_blargg(&rom, BLARGG_TIMING_FRAMES, Some(DEFAULT_BUDGET_FRAMES)), TestRomOutcome::Passed);
+    }
+
+    #[test]
+    #[ignore]
+    fn blargg_mem_timing() {
+        let rom = suite_path("mem_timing/mem_timing.gb").expect("mem_timing.gb not found in $RUSTDMG_TEST_ROMS");
+        assert_eq!(run_blargg(&rom, BLARGG_TIMING_FRAMES, Some(DEFAULT_BUDGET_FRAMES)), TestRomOutcome::Passed);
+    }
+
+    #[test]
+    #[ignore]
+    fn mooneye_acceptance() {
+        let directory = suite_path("mooneye/acceptance").expect("mooneye/acceptance not found in $RUSTDMG_TEST_ROMS");
+        let results = run_mooneye_directory(&directory, MOONEYE_FRAMES, Some(MOONEYE_WATCHDOG_FRAMES)).unwrap();
+        print_report(&directory, &results);
+        assert!(results.iter().all(|(_, outcome)| *outcome == TestRomOutcome::Passed));
+    }
+
+    // Needs background, window and sprite rendering
+    #[test]
+    #[ignore]
+    fn dmg_acid2() {
+        let rom = suite_path("dmg-acid2/dmg-acid2.gb").expect("dmg-acid2.gb not found in $RUSTDMG_TEST_ROMS");
+        let reference = load_shades(&suite_path("dmg-acid2/reference-dmg.png").unwrap()).unwrap();
+        let mut dmg = DMG::new(&rom.to_string_lossy()).unwrap();
+        for _ in 0..ACID2_FRAMES { dmg.run_frame().unwrap(); }
+        let framebuffer = dmg.framebuffer();
+        let differing = framebuffer.iter().zip(&reference).filter(|(pixel, expected)| pixel != expected).count();
+        assert_eq!(differing, 0, "{} pixels differ from the reference", differing);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::BufRead;
+use std::panic;
+
+use crate::batch::panic_message;
+use crate::dmg::DMG;
+use crate::error::EmulationError;
+
+const CONTEXT_LINES: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub struct Divergence {
+    // 1-based line number in the reference log
+    pub line_number: usize,
+    pub expected: String,
+    pub actual: String,
+    // The matching lines right before the divergence, oldest first
+    pub context: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TraceDiffOutcome {
+    Matched(usize),
+    Diverged(Divergence),
+    EmulatorFailed { line_number: usize, message: String, context: Vec<String> },
+}
+
+// "A:01 F:B0 ..." fields whose values differ, as (name, expected, actual)
+pub fn differing_fields(expected: &str, actual: &str) -> Vec<(String, String, String)> {
+    let fields = |line: &str| -> Vec<(String, String)> {
+        line.split_whitespace()
+            .map(|field| match field.split_once(':') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => (String::new(), field.to_string()),
+            })
+            .collect()
+    };
+    fields(expected).into_iter().zip(fields(actual))
+        .filter(|((_, expected_value), (_, actual_value))| !expected_value.eq_ignore_ascii_case(actual_value))
+        .map(|((name, expected_value), (_, actual_value))| (name, expected_value, actual_value))
+        .collect()
+}
+
+fn lines_match(expected: &str, actual: &str) -> bool {
+    expected.trim().eq_ignore_ascii_case(actual.trim())
+}
+
+// Emulation errors and panics both end the comparison
+fn run_guarded<T>(run: impl FnOnce() -> Result<T, EmulationError>) -> Result<T, String> {
+    match panic::catch_unwind(panic::AssertUnwindSafe(run)) {
+        Ok(result) => result.map_err(|error| error.to_string()),
+        Err(payload) => Err(panic_message(payload)),
+    }
+}
+
+// Steps through the boot ROM, then compares the state before every instruction with the reference
+pub fn diff_against_reference<R: BufRead>(dmg: &mut DMG, reference: R) -> io::Result<TraceDiffOutcome> {
+    let mut context: VecDeque<String> = VecDeque::with_capacity(CONTEXT_LINES);
+    let mut line_number = 0;
+
+    for expected in reference.lines() {
+        let expected = expected?;
+        if expected.trim().is_empty() { continue; }
+        line_number += 1;
+
+        let result = run_guarded(|| {
+            while dmg.cpu.bus.boot_rom_active { dmg.step()?; }
+            Ok(dmg.cpu.trace_line())
+        });
+        let actual = match result {
+            Ok(actual) => actual,
+            Err(message) => return Ok(TraceDiffOutcome::EmulatorFailed {
+                line_number, message, context: context.into(),
+            }),
+        };
+
+        if !lines_match(&expected, &actual) {
+            return Ok(TraceDiffOutcome::Diverged(Divergence {
+                line_number, expected: expected.trim().to_string(), actual, context: context.into(),
+            }));
+        }
+
+        if context.len() == CONTEXT_LINES { context.pop_front(); }
+        context.push_back(actual);
+        if let Err(message) = run_guarded(|| dmg.step()) {
+            return Ok(TraceDiffOutcome::EmulatorFailed {
+                line_number, message, context: context.into(),
+            });
+        }
+    }
+    Ok(TraceDiffOutcome::Matched(line_number))
+}
+
+pub fn print_report(outcome: &TraceDiffOutcome) {
+    println!();
+    println!("==============");
+    println!("Trace diff");
+    match outcome {
+        TraceDiffOutcome::Matched(lines) => println!("All {} reference lines match", lines),
+        TraceDiffOutcome::Diverged(divergence) => {
+            println!("First divergence at reference line {}", divergence.line_number);
+            for line in &divergence.context { println!("    {}", line); }
+            println!("  - {}", divergence.expected);
+            println!("  + {}", divergence.actual);
+            for (name, expected, actual) in differing_fields(&divergence.expected, &divergence.actual) {
+                println!("{} expected {} got {}", name, expected, actual);
+            }
+        }
+        TraceDiffOutcome::EmulatorFailed { line_number, message, context } => {
+            for line in context { println!("    {}", line); }
+            println!("Emulator failed after reference line {}: {}", line_number, message);
+        }
+    }
+    println!("==============");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+
+    fn test_dmg<'a>() -> DMG<'a> {
+        // XOR A; INC A; JR -3, with the boot ROM already handed over
+        let program = vec![0xAF, 0x3C, 0x18, 0xFD, 0x00, 0x00];
+        let mut dmg = DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(vec![], program)));
+        dmg.cpu.bus.boot_rom_active = false;
+        dmg
+    }
+
+    const REFERENCE: &str = "\
+A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:0000 PC:0000 PCMEM:AF,3C,18,FD
+A:00 F:80 B:00 C:00 D:00 E:00 H:00 L:00 SP:0000 PC:0001 PCMEM:3C,18,FD,00
+A:01 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:0000 PC:0002 PCMEM:18,FD,00,00
+";
+
+    #[test]
+    fn matching_trace() {
+        let outcome = diff_against_reference(&mut test_dmg(), REFERENCE.as_bytes()).unwrap();
+        assert_eq!(outcome, TraceDiffOutcome::Matched(3));
+    }
+
+    #[test]
+    fn first_divergence_with_context() {
+        let reference = REFERENCE.replace("A:01 F:00", "A:02 F:00");
+        match diff_against_reference(&mut test_dmg(), reference.as_bytes()).unwrap() {
+            TraceDiffOutcome::Diverged(divergence) => {
+                assert_eq!(divergence.line_number, 3);
+                assert_eq!(divergence.context.len(), 2);
+                assert_eq!(differing_fields(&divergence.expected, &divergence.actual),
+                           vec![("A".to_string(), "02".to_string(), "01".to_string())]);
+            }
+            outcome => panic!("Unexpected outcome {:?}", outcome),
+        }
+    }
+}
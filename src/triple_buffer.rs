@@ -0,0 +1,83 @@
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+// Hands the latest value from one thread to another without either waiting on the other: the
+// writer fills its own buffer and swaps it into the middle, the reader swaps the middle out
+// when something new arrived. Values the reader never picked up are dropped.
+struct Shared<T> {
+    middle: T,
+    fresh: bool,
+}
+
+pub struct Writer<T> {
+    back: T,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub struct Reader<T> {
+    front: T,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub fn triple_buffer<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Mutex::new(Shared { middle: initial.clone(), fresh: false }));
+    (Writer { back: initial.clone(), shared: Arc::clone(&shared) }, Reader { front: initial, shared })
+}
+
+impl<T> Writer<T> {
+    // Buffer to fill before publishing, its previous contents are stale
+    pub fn back_mut(&mut self) -> &mut T {
+        &mut self.back
+    }
+
+    pub fn publish(&mut self) {
+        let mut shared = self.shared.lock().unwrap();
+        mem::swap(&mut self.back, &mut shared.middle);
+        shared.fresh = true;
+    }
+}
+
+impl<T> Reader<T> {
+    // The newest published value, or None when nothing was published since the last call
+    pub fn latest(&mut self) -> Option<&T> {
+        {
+            let mut shared = self.shared.lock().unwrap();
+            if !shared.fresh { return None; }
+            mem::swap(&mut self.front, &mut shared.middle);
+            shared.fresh = false;
+        }
+        Some(&self.front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reader_gets_the_latest_value() {
+        let (mut writer, mut reader) = triple_buffer(0);
+        assert_eq!(reader.latest(), None);
+        *writer.back_mut() = 1;
+        writer.publish();
+        *writer.back_mut() = 2;
+        writer.publish();
+        assert_eq!(reader.latest(), Some(&2));
+        assert_eq!(reader.latest(), None);
+    }
+
+    #[test]
+    fn across_threads() {
+        let (mut writer, mut reader) = triple_buffer(vec![0u8; 4]);
+        let producer = thread::spawn(move || {
+            for value in 1..=100u8 {
+                writer.back_mut().iter_mut().for_each(|byte| *byte = value);
+                writer.publish();
+            }
+        });
+        producer.join().unwrap();
+        let frame = reader.latest().unwrap();
+        assert_eq!(frame, &vec![100; 4]);
+    }
+}
@@ -0,0 +1,69 @@
+use wasm_bindgen::prelude::*;
+
+use crate::dmg::DMG;
+use crate::input::Buttons;
+// Exported to JavaScript as well, for set_buttons
+pub use crate::input::Button;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::ppu::frame::Frame;
+
+// The emulator as seen from JavaScript. Everything comes in as bytes, there are no files in the browser.
+//
+//     const emulator = new Emulator(bootRomBytes, romBytes);
+//     emulator.set_buttons(Button.A | Button.Start);
+//     emulator.run_frame();
+//     context.putImageData(new ImageData(new Uint8ClampedArray(emulator.framebuffer_rgba()), 160, 144), 0, 0);
+#[wasm_bindgen]
+pub struct Emulator {
+    dmg: DMG<'static>,
+}
+
+#[wasm_bindgen]
+impl Emulator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(boot_rom: Vec<u8>, rom: Vec<u8>) -> Result<Emulator, JsValue> {
+        let dmg = DMG::new_from_data(boot_rom, &rom).map_err(|error| JsValue::from_str(&error.to_string()))?;
+        Ok(Emulator { dmg })
+    }
+
+    pub fn screen_width() -> usize {
+        SCREEN_WIDTH
+    }
+
+    pub fn screen_height() -> usize {
+        SCREEN_HEIGHT
+    }
+
+    // Throws when the game runs into something not emulated yet
+    pub fn run_frame(&mut self) -> Result<(), JsValue> {
+        self.dmg.run_frame().map(|_| ()).map_err(|error| JsValue::from_str(&error.to_string()))
+    }
+
+    pub fn frame_count(&self) -> f64 {
+        self.dmg.frame_count() as f64
+    }
+
+    // One shade per pixel, 0 is the lightest and 3 the darkest
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.dmg.framebuffer().to_vec()
+    }
+
+    // Ready for ImageData, four bytes per pixel
+    pub fn framebuffer_rgba(&self) -> Vec<u8> {
+        Frame::new(self.dmg.framebuffer().to_vec()).to_rgba()
+    }
+
+    // All held buttons at once, as Button values or'ed together
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.dmg.set_buttons(Buttons::from_bits_truncate(buttons));
+    }
+
+    // Samples produced since the last call. There is no APU yet, so there are never any.
+    pub fn audio_samples(&mut self) -> Vec<f32> {
+        vec![]
+    }
+
+    pub fn reset(&mut self) {
+        self.dmg.reset();
+    }
+}
@@ -0,0 +1,195 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::bus::BusObserver;
+use crate::cpu::register::DMGRegister;
+use crate::cpu::stats::Opcode;
+use crate::dmg::DMG;
+use crate::error::EmulationError;
+
+pub const HISTORY_LENGTH: usize = 100;
+// Ten seconds of emulated time
+pub const DEFAULT_BUDGET_FRAMES: u64 = 600;
+
+const SERIAL_CONTROL: u16 = 0xFF02;
+const SERIAL_START_TRANSFER: u8 = 0x81;
+
+#[derive(Default)]
+struct SerialActivity {
+    transfers: u64,
+}
+
+impl BusObserver for SerialActivity {
+    fn on_write(&mut self, address: u16, _old_value: u8, new_value: u8) {
+        if address == SERIAL_CONTROL && new_value == SERIAL_START_TRANSFER { self.transfers += 1; }
+    }
+}
+
+// What the CPU was doing when the watchdog gave up
+#[derive(Debug)]
+pub struct Hang {
+    pub frames_without_progress: u64,
+    pub pc: u16,
+    // Oldest first, with the mnemonic of each instruction
+    pub recent_instructions: Vec<(u16, String)>,
+    // Lowest and highest address of a loop the last instructions keep repeating, and its length
+    pub loop_range: Option<(u16, u16, usize)>,
+}
+
+impl fmt::Display for Hang {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(formatter, "No serial output or frame change for {} frames, PC {:04X}", self.frames_without_progress, self.pc)?;
+        if let Some((low, high, length)) = self.loop_range {
+            writeln!(formatter, "Stuck in a loop of {} instructions between {:04X} and {:04X}", length, low, high)?;
+        }
+        writeln!(formatter, "Last {} instructions:", self.recent_instructions.len())?;
+        for (address, mnemonic) in &self.recent_instructions {
+            writeln!(formatter, "  {:04X} {}", address, mnemonic)?;
+        }
+        Ok(())
+    }
+}
+
+// Why a watched run ended before its frames were done
+#[derive(Debug, thiserror::Error)]
+pub enum WatchdogError {
+    #[error("{0}")]
+    Hang(Hang),
+    #[error(transparent)]
+    Emulation(#[from] EmulationError),
+}
+
+// Smallest period the whole history repeats with, a CPU polling forever shows one right away
+fn find_loop(history: &[u16]) -> Option<usize> {
+    (1..=history.len() / 2).find(|period| (*period..history.len()).all(|index| history[index] == history[index - period]))
+}
+
+// Stops headless runs that stopped making progress: nothing sent through the serial port and no
+// change to the frame for a number of frames
+pub struct Watchdog {
+    budget_frames: u64,
+    history: VecDeque<(u16, Opcode)>,
+    serial: Rc<RefCell<SerialActivity>>,
+    last_progress_frame: u64,
+    last_serial_transfers: u64,
+    last_frame_hash: u64,
+}
+
+impl Watchdog {
+    pub fn new(dmg: &mut DMG, budget_frames: u64) -> Watchdog {
+        let serial = Rc::new(RefCell::new(SerialActivity::default()));
+        dmg.cpu.bus.add_observer(serial.clone());
+        Watchdog {
+            budget_frames,
+            history: VecDeque::with_capacity(HISTORY_LENGTH),
+            serial,
+            last_progress_frame: dmg.frame_count(),
+            last_serial_transfers: 0,
+            last_frame_hash: dmg.frame_hash(),
+        }
+    }
+
+    // Executes one instruction, remembering it for the diagnostic
+    pub fn step(&mut self, dmg: &mut DMG) -> Result<(), EmulationError> {
+        let pc = dmg.cpu.program_counter.read();
+        let opcode = match dmg.cpu.bus.peek(pc) {
+            0xCB => Opcode::CB(dmg.cpu.bus.peek(pc.wrapping_add(1))),
+            opcode => Opcode::Base(opcode),
+        };
+        if self.history.len() == HISTORY_LENGTH { self.history.pop_front(); }
+        self.history.push_back((pc, opcode));
+        dmg.step()
+    }
+
+    // Breakpoints are not checked, this is meant for runs without a debugger
+    pub fn run_frame(&mut self, dmg: &mut DMG) -> Result<(), WatchdogError> {
+        let target_frame = dmg.frame_count() + 1;
+        while dmg.frame_count() < target_frame { self.step(dmg)?; }
+        self.check(dmg).map_err(WatchdogError::Hang)
+    }
+
+    pub fn check(&mut self, dmg: &DMG) -> Result<(), Hang> {
+        let frame = dmg.frame_count();
+        let serial_transfers = self.serial.borrow().transfers;
+        let frame_hash = dmg.frame_hash();
+        if serial_transfers != self.last_serial_transfers || frame_hash != self.last_frame_hash {
+            self.last_serial_transfers = serial_transfers;
+            self.last_frame_hash = frame_hash;
+            self.last_progress_frame = frame;
+            return Ok(());
+        }
+        let frames_without_progress = frame - self.last_progress_frame;
+        if frames_without_progress < self.budget_frames { return Ok(()); }
+        Err(self.hang(dmg, frames_without_progress))
+    }
+
+    fn hang(&self, dmg: &DMG, frames_without_progress: u64) -> Hang {
+        let addresses: Vec<u16> = self.history.iter().map(|(address, _)| *address).collect();
+        let loop_range = find_loop(&addresses).map(|length| {
+            let looping = &addresses[addresses.len() - length..];
+            (*looping.iter().min().unwrap(), *looping.iter().max().unwrap(), length)
+        });
+        Hang {
+            frames_without_progress,
+            pc: dmg.cpu.program_counter.read(),
+            recent_instructions: self.history.iter().map(|(address, opcode)| (*address, dmg.cpu.mnemonic(*opcode).to_string())).collect(),
+            loop_range,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPU;
+
+    fn dmg_running<'a>(program: Vec<u8>) -> DMG<'a> {
+        DMG::new_from_cpu(CPU::new(Bus::new_from_vecs(program, vec![])))
+    }
+
+    #[test]
+    fn detects_tight_loop() {
+        // NOP; NOP; NOP; JR -3, looping over the last NOP and the JR
+        let mut dmg = dmg_running(vec![0x00, 0x00, 0x00, 0x18, 0xFD]);
+        let mut watchdog = Watchdog::new(&mut dmg, 3);
+        assert!(watchdog.run_frame(&mut dmg).is_ok());
+        assert!(watchdog.run_frame(&mut dmg).is_ok());
+        let hang = match watchdog.run_frame(&mut dmg) {
+            Err(WatchdogError::Hang(hang)) => hang,
+            other => panic!("Unexpected {:?}", other),
+        };
+        assert_eq!(hang.frames_without_progress, 3);
+        assert_eq!(hang.loop_range, Some((0x0002, 0x0003, 2)));
+        assert_eq!(hang.recent_instructions.len(), HISTORY_LENGTH);
+        let report = hang.to_string();
+        assert!(report.contains("Stuck in a loop of 2 instructions between 0002 and 0003"), "{}", report);
+        assert!(report.contains("  0003 JR"), "{}", report);
+    }
+
+    #[test]
+    fn frame_changes_are_progress() {
+        // LD A,91; LDH (40),A; LD A,FC; LDH (47),A; LD HL,8000; LD (HL),A; INC A; JR -4
+        // Tile 0 fills the screen, so its first row keeps changing on every frame
+        let mut dmg = dmg_running(vec![0x3E, 0x91, 0xE0, 0x40, 0x3E, 0xFC, 0xE0, 0x47, 0x21, 0x00, 0x80, 0x77, 0x3C, 0x18, 0xFC]);
+        let mut watchdog = Watchdog::new(&mut dmg, 1);
+        for _ in 0..5 { assert!(watchdog.run_frame(&mut dmg).is_ok()); }
+    }
+
+    #[test]
+    fn emulation_errors_stop_the_run() {
+        // An opcode the CPU does not have
+        let mut dmg = dmg_running(vec![0x00, 0xD3]);
+        let mut watchdog = Watchdog::new(&mut dmg, 3);
+        assert!(matches!(watchdog.run_frame(&mut dmg), Err(WatchdogError::Emulation(EmulationError::IllegalOpcode { address: 0x0001, .. }))));
+    }
+
+    #[test]
+    fn loop_periods() {
+        assert_eq!(find_loop(&[1, 2, 3, 1, 2, 3, 1]), Some(3));
+        assert_eq!(find_loop(&[5, 5, 5]), Some(1));
+        assert_eq!(find_loop(&[1, 2, 3, 4]), None);
+    }
+}